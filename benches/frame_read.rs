@@ -0,0 +1,162 @@
+//! Parsing throughput for continuous-mode data frames, read from a socket so each read is a real
+//! syscall as it would be on a serial port.
+//!
+//! Compare against a previous build with
+//!
+//! ```sh
+//! git stash && cargo bench --features unstable --bench frame_read -- --save-baseline before
+//! git stash pop && cargo bench --features unstable --bench frame_read -- --baseline before
+//! ```
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+#[cfg(unix)]
+mod port {
+    use serialport::{ClearBuffer, DataBits, FlowControl, Parity, SerialPort, StopBits};
+    use std::io::{self, Read, Write};
+    use std::os::unix::net::UnixStream;
+    use std::time::Duration;
+
+    /// One end of a socket pair posing as a serial port
+    pub struct SocketPort(pub UnixStream);
+
+    impl Read for SocketPort {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.0.read(buf)
+        }
+    }
+
+    impl Write for SocketPort {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.0.flush()
+        }
+    }
+
+    impl SerialPort for SocketPort {
+        fn name(&self) -> Option<String> {
+            Some("socket".to_string())
+        }
+        fn baud_rate(&self) -> serialport::Result<u32> {
+            Ok(38400)
+        }
+        fn data_bits(&self) -> serialport::Result<DataBits> {
+            Ok(DataBits::Eight)
+        }
+        fn flow_control(&self) -> serialport::Result<FlowControl> {
+            Ok(FlowControl::None)
+        }
+        fn parity(&self) -> serialport::Result<Parity> {
+            Ok(Parity::None)
+        }
+        fn stop_bits(&self) -> serialport::Result<StopBits> {
+            Ok(StopBits::One)
+        }
+        fn timeout(&self) -> Duration {
+            self.0.read_timeout().ok().flatten().unwrap_or_default()
+        }
+        fn set_baud_rate(&mut self, _: u32) -> serialport::Result<()> {
+            Ok(())
+        }
+        fn set_data_bits(&mut self, _: DataBits) -> serialport::Result<()> {
+            Ok(())
+        }
+        fn set_flow_control(&mut self, _: FlowControl) -> serialport::Result<()> {
+            Ok(())
+        }
+        fn set_parity(&mut self, _: Parity) -> serialport::Result<()> {
+            Ok(())
+        }
+        fn set_stop_bits(&mut self, _: StopBits) -> serialport::Result<()> {
+            Ok(())
+        }
+        fn set_timeout(&mut self, timeout: Duration) -> serialport::Result<()> {
+            Ok(self.0.set_read_timeout(Some(timeout))?)
+        }
+        fn write_request_to_send(&mut self, _: bool) -> serialport::Result<()> {
+            Ok(())
+        }
+        fn write_data_terminal_ready(&mut self, _: bool) -> serialport::Result<()> {
+            Ok(())
+        }
+        fn read_clear_to_send(&mut self) -> serialport::Result<bool> {
+            Ok(true)
+        }
+        fn read_data_set_ready(&mut self) -> serialport::Result<bool> {
+            Ok(true)
+        }
+        fn read_ring_indicator(&mut self) -> serialport::Result<bool> {
+            Ok(false)
+        }
+        fn read_carrier_detect(&mut self) -> serialport::Result<bool> {
+            Ok(true)
+        }
+        fn bytes_to_read(&self) -> serialport::Result<u32> {
+            Ok(0)
+        }
+        fn bytes_to_write(&self) -> serialport::Result<u32> {
+            Ok(0)
+        }
+        fn clear(&self, _: ClearBuffer) -> serialport::Result<()> {
+            Ok(())
+        }
+        fn try_clone(&self) -> serialport::Result<Box<dyn SerialPort>> {
+            Ok(Box::new(SocketPort(self.0.try_clone()?)))
+        }
+        fn set_break(&self) -> serialport::Result<()> {
+            Ok(())
+        }
+        fn clear_break(&self) -> serialport::Result<()> {
+            Ok(())
+        }
+    }
+}
+
+/// GetDataResp frame carrying heading, pitch, roll, temperature, and accel and mag XYZ
+fn data_frame() -> Vec<u8> {
+    let mut payload = vec![10u8];
+    for id in [5u8, 24, 25, 7, 21, 22, 23, 27, 28, 29] {
+        payload.push(id);
+        payload.extend_from_slice(&(id as f32 * 1.5).to_be_bytes());
+    }
+    let mut frame = vec![0u8; payload.len() + pni_sdk::codec::FRAME_OVERHEAD];
+    let len = pni_sdk::codec::encode_frame(0x05, &payload, &mut frame).unwrap();
+    frame.truncate(len);
+    frame
+}
+
+#[cfg(unix)]
+fn continuous_mode(c: &mut Criterion) {
+    use std::io::Write;
+    use std::os::unix::net::UnixStream;
+
+    const FRAMES_PER_BATCH: usize = 256;
+
+    let frame = data_frame();
+    let batch = frame.repeat(FRAMES_PER_BATCH);
+    let (mut sensor, host) = UnixStream::pair().unwrap();
+    let port: Box<dyn serialport::SerialPort> = Box::new(port::SocketPort(host));
+    let mut device = pni_sdk::Device::new(port);
+
+    let mut group = c.benchmark_group("continuous_mode");
+    group.throughput(Throughput::Bytes(batch.len() as u64));
+    group.bench_function("data_frames", |b| {
+        b.iter(|| {
+            // the socket buffer comfortably holds a batch, so the write never blocks
+            sensor.write_all(&batch).unwrap();
+            for _ in 0..FRAMES_PER_BATCH {
+                device.read_response().unwrap();
+            }
+        })
+    });
+    group.finish();
+}
+
+#[cfg(not(unix))]
+fn continuous_mode(_: &mut Criterion) {}
+
+criterion_group!(benches, continuous_mode);
+criterion_main!(benches);
@@ -20,10 +20,10 @@ fn main() {
     println!("Get Data Components: \n{:?}", tp3.get_data());
     println!("Set Cont Mode: \n{:?}", tp3.start_continuous_mode());
     println!("Save config: \n{:?}", tp3.save());
-    println!("Power down: \n{:?}", tp3.power_down());
+    let asleep = tp3.power_down().expect("Power down TP3");
+    println!("Powered down on {:?}", asleep.port_name());
 
-    tp3 = Device::connect(None).expect("Connect to TP3");
-    println!("Power up result {:?}", tp3.power_up());
+    let mut tp3 = asleep.wake().expect("Wake TP3");
     println!("S/N result {:?}", tp3.serial_number());
     for data in tp3.iter() {
         println!("{:?}", data)
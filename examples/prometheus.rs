@@ -0,0 +1,84 @@
+// `prometheus [PORT] [--listen ADDRESS]` streams from a device and serves its health for
+// Prometheus to scrape at http://ADDRESS/metrics (default 0.0.0.0:9898): sample rate, checksum
+// errors, magnetic accuracy and distortion, labelled with the device's serial number.
+
+use pni_sdk::acquisition::DataID;
+use pni_sdk::prometheus::Metrics;
+use pni_sdk::Device;
+
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+const SAMPLE_DELAY: f32 = 0.1;
+const LINK_STATS_INTERVAL: Duration = Duration::from_secs(5);
+
+fn main() -> ExitCode {
+    let mut port = None;
+    let mut listen = "0.0.0.0:9898".to_owned();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--listen" => match args.next() {
+                Some(address) => listen = address,
+                None => return usage(),
+            },
+            _ if arg.starts_with('-') => return usage(),
+            _ => port = Some(arg),
+        }
+    }
+
+    let mut builder = Device::builder();
+    if let Some(port) = port {
+        builder = builder.port(port);
+    }
+    let mut device = match builder.open() {
+        Ok(device) => device,
+        Err(e) => {
+            eprintln!("couldn't open the device: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let label = match device.serial_number() {
+        Ok(serial_number) => serial_number.to_string(),
+        Err(e) => {
+            eprintln!("couldn't read the serial number: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let metrics = Metrics::new();
+    let server = match metrics.serve(listen.as_str()) {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!("couldn't listen on {}: {}", listen, e);
+            return ExitCode::FAILURE;
+        }
+    };
+    println!("serving metrics on http://{}/metrics", server.local_addr());
+
+    let components = vec![DataID::Heading, DataID::MagAccuracy, DataID::Distortion];
+    if let Err(e) = device.start_continuous_mode_live(SAMPLE_DELAY, components) {
+        eprintln!("couldn't start streaming: {}", e);
+        return ExitCode::FAILURE;
+    }
+
+    let mut last_stats = Instant::now();
+    loop {
+        // the iterator ends on a read timeout; keep going, the sample rate gauge shows the gap
+        for sample in device.iter() {
+            metrics.record(&label, &sample);
+            if last_stats.elapsed() >= LINK_STATS_INTERVAL {
+                break;
+            }
+        }
+        if last_stats.elapsed() >= LINK_STATS_INTERVAL {
+            last_stats = Instant::now();
+            metrics.record_link_stats(&label, device.stats());
+        }
+    }
+}
+
+fn usage() -> ExitCode {
+    eprintln!("usage: prometheus [PORT] [--listen ADDRESS]");
+    ExitCode::from(2)
+}
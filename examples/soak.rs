@@ -0,0 +1,176 @@
+// `soak [PORT] [--hours HOURS] [--ping-minutes MINUTES]` streams from a device for hours, the way
+// an unattended deployment would, and reports whether it kept going on its own.
+//
+// The stream runs under Device::watchdog, which resyncs and reconnects by itself when data stops.
+// Every few minutes streaming is paused to ping the device in polled mode, which catches a unit
+// that streams but no longer answers commands. A summary with sample counts, recoveries, link
+// errors and memory use is printed every minute; memory should level off within the first few.
+// Exits nonzero if any ping failed or the watchdog gave up.
+
+use pni_sdk::acquisition::DataID;
+use pni_sdk::health::{Recovery, WatchdogConfig, WatchdogStats};
+use pni_sdk::Device;
+
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+const SAMPLE_DELAY: f32 = 0.1;
+const PING_TIMEOUT: Duration = Duration::from_secs(1);
+const REPORT_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Default, Clone, Copy)]
+struct Totals {
+    watchdog: WatchdogStats,
+    pings: u64,
+    failed_pings: u64,
+    worst_ping: Duration,
+    gave_up: bool,
+}
+
+impl Totals {
+    fn add(&mut self, stats: WatchdogStats) {
+        self.watchdog.samples += stats.samples;
+        self.watchdog.errors += stats.errors;
+        self.watchdog.resyncs += stats.resyncs;
+        self.watchdog.reconnects += stats.reconnects;
+        self.watchdog.failed_recoveries += stats.failed_recoveries;
+    }
+}
+
+fn main() -> ExitCode {
+    let mut port = None;
+    let mut hours = 24.0;
+    let mut ping_minutes = 5.0;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = match arg.as_str() {
+            "--hours" => &mut hours,
+            "--ping-minutes" => &mut ping_minutes,
+            _ if arg.starts_with('-') => return usage(),
+            _ => {
+                port = Some(arg);
+                continue;
+            }
+        };
+        match args.next().and_then(|v| v.parse::<f64>().ok()) {
+            Some(v) if v > 0.0 => *value = v,
+            _ => return usage(),
+        }
+    }
+
+    let mut builder = Device::builder();
+    if let Some(port) = port {
+        builder = builder.port(port);
+    }
+    let mut device = match builder.open() {
+        Ok(device) => device,
+        Err(e) => {
+            eprintln!("couldn't open the device: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let duration = Duration::from_secs_f64(hours * 3600.0);
+    let ping_interval = Duration::from_secs_f64(ping_minutes * 60.0);
+    let totals = soak(&mut device, duration, ping_interval);
+
+    println!("after {:.1} h: {}", hours, summary(&device, &totals));
+    if totals.failed_pings > 0 || totals.gave_up {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn usage() -> ExitCode {
+    eprintln!("usage: soak [PORT] [--hours HOURS] [--ping-minutes MINUTES]");
+    ExitCode::from(2)
+}
+
+fn soak(device: &mut Device, duration: Duration, ping_interval: Duration) -> Totals {
+    let components = vec![DataID::Heading, DataID::Pitch, DataID::Roll, DataID::Temperature];
+    let started = Instant::now();
+    let mut last_report = started;
+    let mut totals = Totals::default();
+
+    while started.elapsed() < duration {
+        if let Err(e) = device.start_continuous_mode_live(SAMPLE_DELAY, components.clone()) {
+            // the watchdog reconnects if this left the device silent
+            eprintln!("couldn't start streaming: {}", e);
+        }
+
+        let pause_at = Instant::now() + ping_interval;
+        let mut watchdog = device
+            .watchdog(WatchdogConfig::default())
+            .on_recovery(|recovery, stats| match recovery {
+                Recovery::Resync => eprintln!("stream stalled, resyncing ({})", stats),
+                Recovery::Reconnect => eprintln!("stream still stalled, reconnecting ({})", stats),
+            });
+        while Instant::now() < pause_at && started.elapsed() < duration {
+            match watchdog.next() {
+                Some(Ok(_)) => {}
+                Some(Err(e)) => eprintln!("bad frame: {}", e),
+                None => {
+                    totals.gave_up = true;
+                    break;
+                }
+            }
+            if last_report.elapsed() >= REPORT_INTERVAL {
+                last_report = Instant::now();
+                let mut so_far = totals;
+                so_far.add(watchdog.stats());
+                println!(
+                    "{:>6.2} h: {}",
+                    started.elapsed().as_secs_f64() / 3600.0,
+                    summary_of(&so_far)
+                );
+            }
+        }
+        totals.add(watchdog.stats());
+        drop(watchdog);
+        if totals.gave_up {
+            break;
+        }
+
+        if let Err(e) = device.stop_continuous_mode_live() {
+            eprintln!("couldn't stop streaming to ping: {}", e);
+        }
+        totals.pings += 1;
+        match device.ping(PING_TIMEOUT) {
+            Ok(round_trip) => totals.worst_ping = totals.worst_ping.max(round_trip),
+            Err(e) => {
+                totals.failed_pings += 1;
+                eprintln!("ping failed: {}", e);
+            }
+        }
+    }
+    totals
+}
+
+fn summary(device: &Device, totals: &Totals) -> String {
+    let stats = device.stats();
+    format!(
+        "{}; {} checksum errors, {} dropped frames",
+        summary_of(totals),
+        stats.checksum_errors,
+        stats.dropped_frames
+    )
+}
+
+fn summary_of(totals: &Totals) -> String {
+    format!(
+        "{}; {}/{} pings failed, worst {:?}; resident {}",
+        totals.watchdog,
+        totals.failed_pings,
+        totals.pings,
+        totals.worst_ping,
+        memory_kib().map_or("unknown".to_owned(), |kib| format!("{} KiB", kib))
+    )
+}
+
+/// Resident memory of this process, where the OS reports it
+fn memory_kib() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    line.split_whitespace().nth(1)?.parse().ok()
+}
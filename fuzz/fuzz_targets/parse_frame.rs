@@ -0,0 +1,9 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|bytes: &[u8]| {
+    if let Ok((_, len)) = pni_sdk::dispatch::parse_frame(bytes) {
+        assert!(len <= bytes.len());
+    }
+});
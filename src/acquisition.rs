@@ -1,9 +1,23 @@
+use crate::catalog::{Message, MessageId};
+use crate::capabilities::{Feature, SupportLevel};
 use crate::command::Command;
+use crate::connection::ConnectionEvent;
+use crate::constants::{FLAG_VALUE_LEN, FLOAT_VALUE_LEN, QUATERNION_VALUE_LEN};
+use crate::processing::{wrap_heading, StabilityDetector};
 use crate::responses::Get;
 use crate::{RWError, ReadError, Device};
 
 use std::error::Error;
+use std::time::{Duration, Instant, SystemTime};
 
+/// How long [Device::drain_continuous] listens for a stream. Covers SampleDelays of up to a second
+const STREAM_DETECT_PERIOD: Duration = Duration::from_secs(1);
+
+/// Rough time a TargetPoint3 spends measuring each sample in continuous mode, from its maximum
+/// output rate of about 30 Hz. Used by [Device::set_sample_rate_hz]
+const TYPICAL_ACQUISITION_TIME: Duration = Duration::from_millis(28);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DataID {
     /// The heading range is 0.0˚ to +359.9˚
     Heading = 5,
@@ -43,6 +57,44 @@ pub enum DataID {
 
     /// This value represents (in degrees) the approximate current magnetic accuracy of the system.  This should correspond to the RMS heading accuracy expected in a given location at a given time. When no user cal has been performed, the accuracy of this measurement is significantly reduced. This value combines the estimated accuracy of the most recent magnetic user calibration (cal score), change in the magnetic field since the last user cal, and any observed short-term transients observed in the background. This measurement is more accurate if the system is held somewhat still (as opposed to waving the unit around quickly), and may take some time to learn the ambient field (5-10s). Allowing the unit to see different orientations and pitch/rolls in an area will give a better background measurement of relative accuracy. Values are in degrees of heading. Because this measurement is based on post-fit residual measurements, it is not always a perfect indicator of true accuracy.  This score should be a good indicator of relative accuracy, i.e., if one location has a high score, and a second location has a lower score, the second location is more likely to have a clean field.  
     MagAccuracy = 88,
+
+    /// Gyro sensor data in radians per second. TRAX/TRAX2 only
+    GyroX = 74,
+
+    /// Gyro sensor data in radians per second. TRAX/TRAX2 only
+    GyroY = 75,
+
+    /// Gyro sensor data in radians per second. TRAX/TRAX2 only
+    GyroZ = 76,
+
+    /// Orientation as a unit quaternion, in the order w, x, y, z. TRAX/TRAX2 only
+    Quaternion = 77,
+
+    /// Confidence in the reported heading, see [HeadingStatus]. TRAX/TRAX2 only
+    HeadingStatus = 79,
+}
+
+impl DataID {
+    /// The [Feature] a device must support to report this component, if it isn't available on
+    /// every device
+    pub fn required_feature(&self) -> Option<Feature> {
+        match self {
+            DataID::MagAccuracy => Some(Feature::MagAccuracy),
+            DataID::GyroX | DataID::GyroY | DataID::GyroZ => Some(Feature::Gyro),
+            DataID::Quaternion => Some(Feature::Quaternion),
+            DataID::HeadingStatus => Some(Feature::HeadingStatus),
+            _ => None,
+        }
+    }
+
+    /// Size in bytes of this component's value in a GetDataResp, not counting its ID byte
+    pub fn value_len(&self) -> usize {
+        match self {
+            DataID::Distortion | DataID::CalStatus | DataID::HeadingStatus => FLAG_VALUE_LEN,
+            DataID::Quaternion => QUATERNION_VALUE_LEN,
+            _ => FLOAT_VALUE_LEN,
+        }
+    }
 }
 
 impl TryFrom<u8> for DataID {
@@ -63,8 +115,82 @@ impl TryFrom<u8> for DataID {
             28 => Ok(MagY),
             29 => Ok(MagZ),
             88 => Ok(MagAccuracy),
-            79 => Err(ReadError::ParseError("Unknown DataID from device: 79. This ID is usually detected when set_data_components is not called before calling get_data. You must specify what data you want from the device before parsing data back from the device.".to_string())),
-            _ => Err(ReadError::ParseError(format!("Unknown DataID from device: {}", value)))
+            74 => Ok(GyroX),
+            75 => Ok(GyroY),
+            76 => Ok(GyroZ),
+            77 => Ok(Quaternion),
+            79 => Ok(HeadingStatus),
+            _ => Err(ReadError::ParseError(
+                Message::new(MessageId::UnknownDataId).with("id", value),
+            )),
+        }
+    }
+}
+
+/// [Data::mag_accuracy] at or below this is as good as [HeadingStatus::Good]
+const GOOD_MAG_ACCURACY: f32 = 2.0;
+
+/// [Data::mag_accuracy] above this is as bad as [HeadingStatus::Poor]
+const FAIR_MAG_ACCURACY: f32 = 10.0;
+
+/// How far a sample's heading can be trusted, judged from the quality components it carries.
+/// See [Data::quality]
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+pub enum DataQuality {
+    /// User calibrated, no distortion, and heading accuracy within 2˚ where it's reported
+    Good,
+
+    /// Usable with care: not user calibrated, or heading accuracy between 2˚ and 10˚
+    Degraded,
+
+    /// Magnetic distortion, or heading accuracy worse than 10˚
+    Unreliable,
+
+    /// The sample has none of Distortion, CalStatus, MagAccuracy or HeadingStatus to judge by
+    Unknown,
+}
+
+/// Heading accuracy reported by the HeadingStatus component of AHRS devices
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+pub enum HeadingStatus {
+    /// Heading error is expected to be less than 2˚
+    Good = 1,
+
+    /// Heading error is expected to be between 2˚ and 10˚
+    Fair = 2,
+
+    /// Heading error is expected to be greater than 10˚, e.g. because of magnetic distortion
+    Poor = 3,
+}
+
+impl TryFrom<u8> for HeadingStatus {
+    type Error = ReadError;
+    fn try_from(value: u8) -> Result<Self, ReadError> {
+        match value {
+            1 => Ok(HeadingStatus::Good),
+            2 => Ok(HeadingStatus::Fair),
+            3 => Ok(HeadingStatus::Poor),
+            _ => Err(ReadError::ParseError(
+                Message::new(MessageId::UnknownHeadingStatus).with("value", value),
+            )),
+        }
+    }
+}
+
+impl Device {
+    /// Parses a DataID from a GetDataResp, rejecting components the connected device is known
+    /// not to produce. HeadingStatus is only accepted once the device has identified itself as
+    /// supporting it, since on a TP3 its ID shows up when the payload isn't what we think it is
+    fn data_id_for_device(&self, value: u8) -> Result<DataID, ReadError> {
+        let id = DataID::try_from(value)?;
+        match id.required_feature().map(|feature| self.supports(feature)) {
+            Some(SupportLevel::KnownNo | SupportLevel::Unknown) if value == 79 => Err(ReadError::ParseError(
+                Message::new(MessageId::DataComponentsNotSet).with("id", value),
+            )),
+            Some(SupportLevel::KnownNo) => Err(ReadError::ParseError(
+                Message::new(MessageId::UnsupportedDataId).with("id", value),
+            )),
+            _ => Ok(id),
         }
     }
 }
@@ -73,9 +199,9 @@ impl TryFrom<u8> for DataID {
 // DataComponent's. Ths is memory inefficient.
 /// Represents a data record from TP3. Use [TargetPoint3::set_data_components] to control which
 /// fields to populate
-#[derive(Debug, Display)]
+#[derive(Debug, Display, Default, Clone, PartialEq)]
 #[display(
-    fmt = "Data {{ heading: {:?}, pitch: {:?}, roll: {:?}, temperature: {:?}, distortion: {:?}, cal_status: {:?}, accel_x: {:?}, accel_y: {:?}, accel_z: {:?}, mag_x: {:?}, mag_y: {:?}, mag_z: {:?}, mag_accuracy: {:?} }}",
+    fmt = "Data {{ heading: {:?}, pitch: {:?}, roll: {:?}, temperature: {:?}, distortion: {:?}, cal_status: {:?}, accel_x: {:?}, accel_y: {:?}, accel_z: {:?}, mag_x: {:?}, mag_y: {:?}, mag_z: {:?}, mag_accuracy: {:?}, gyro_x: {:?}, gyro_y: {:?}, gyro_z: {:?}, quaternion: {:?}, heading_status: {:?} }}",
     heading,
     pitch,
     roll,
@@ -88,7 +214,12 @@ impl TryFrom<u8> for DataID {
     mag_x,
     mag_y,
     mag_z,
-    mag_accuracy
+    mag_accuracy,
+    gyro_x,
+    gyro_y,
+    gyro_z,
+    quaternion,
+    heading_status
 )]
 pub struct Data {
     /// The heading range is 0.0˚ to +359.9˚
@@ -129,75 +260,429 @@ pub struct Data {
 
     /// This value represents (in degrees) the approximate current magnetic accuracy of the system.  This should correspond to the RMS heading accuracy expected in a given location at a given time. When no user cal has been performed, the accuracy of this measurement is significantly reduced. This value combines the estimated accuracy of the most recent magnetic user calibration (cal score), change in the magnetic field since the last user cal, and any observed short-term transients observed in the background. This measurement is more accurate if the system is held somewhat still (as opposed to waving the unit around quickly), and may take some time to learn the ambient field (5-10s). Allowing the unit to see different orientations and pitch/rolls in an area will give a better background measurement of relative accuracy. Values are in degrees of heading. Because this measurement is based on post-fit residual measurements, it is not always a perfect indicator of true accuracy.  This score should be a good indicator of relative accuracy, i.e., if one location has a high score, and a second location has a lower score, the second location is more likely to have a clean field.  
     pub mag_accuracy: Option<f32>,
+
+    /// Gyro sensor data in radians per second. TRAX/TRAX2 only
+    pub gyro_x: Option<f32>,
+
+    /// Gyro sensor data in radians per second. TRAX/TRAX2 only
+    pub gyro_y: Option<f32>,
+
+    /// Gyro sensor data in radians per second. TRAX/TRAX2 only
+    pub gyro_z: Option<f32>,
+
+    /// Orientation as a unit quaternion, in the order w, x, y, z. TRAX/TRAX2 only
+    pub quaternion: Option<[f32; 4]>,
+
+    /// Confidence in the reported heading. TRAX/TRAX2 only
+    pub heading_status: Option<HeadingStatus>,
+
+    /// Units and north reference of heading, pitch and roll, as configured when the sample was
+    /// read. [None] if the [Device] hadn't seen the MilOut and TrueNorth settings yet; see
+    /// [Device::angle_format]
+    pub angle_format: Option<AngleFormat>,
 }
 
-impl Get<Data> for Device {
-    fn get(&mut self) -> Result<Data, ReadError> {
-        let mut data_struct = Data {
-            heading: None,
-            pitch: None,
-            roll: None,
-            temperature: None,
-            distortion: None,
-            cal_status: None,
-            accel_x: None,
-            accel_y: None,
-            accel_z: None,
-            mag_x: None,
-            mag_y: None,
-            mag_z: None,
-            mag_accuracy: None,
-        };
+/// Mils per full circle, as output with [crate::config::ConfigPair::MilOut]
+pub(crate) const MILS_PER_CIRCLE: f32 = 6400.0;
 
-        let id_count = Get::<u8>::get(self)?;
+/// Units of heading, pitch and roll, set by [crate::config::ConfigPair::MilOut]
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+pub enum AngleUnits {
+    Degrees,
 
-        for _ in 0..id_count {
-            let data_id = Get::<u8>::get(self)?;
+    /// 6400 per circle
+    Mils,
+}
 
-            match DataID::try_from(data_id)? {
-                DataID::Heading => {
-                    data_struct.heading = Some(Get::<f32>::get(self)?);
-                }
-                DataID::Pitch => {
-                    data_struct.pitch = Some(Get::<f32>::get(self)?);
-                }
-                DataID::Roll => {
-                    data_struct.roll = Some(Get::<f32>::get(self)?);
-                }
-                DataID::Temperature => {
-                    data_struct.temperature = Some(Get::<f32>::get(self)?);
-                }
-                DataID::Distortion => {
-                    data_struct.distortion = Some(Get::<bool>::get(self)?);
-                }
-                DataID::CalStatus => {
-                    data_struct.cal_status = Some(Get::<bool>::get(self)?);
-                }
-                DataID::AccelX => {
-                    data_struct.accel_x = Some(Get::<f32>::get(self)?);
-                }
-                DataID::AccelY => {
-                    data_struct.accel_y = Some(Get::<f32>::get(self)?);
-                }
-                DataID::AccelZ => {
-                    data_struct.accel_z = Some(Get::<f32>::get(self)?);
-                }
-                DataID::MagX => {
-                    data_struct.mag_x = Some(Get::<f32>::get(self)?);
-                }
-                DataID::MagY => {
-                    data_struct.mag_y = Some(Get::<f32>::get(self)?);
-                }
-                DataID::MagZ => {
-                    data_struct.mag_z = Some(Get::<f32>::get(self)?);
-                }
-                DataID::MagAccuracy => {
-                    data_struct.mag_accuracy = Some(Get::<f32>::get(self)?);
+impl AngleUnits {
+    pub fn to_degrees(&self, angle: f32) -> f32 {
+        match self {
+            AngleUnits::Degrees => angle,
+            AngleUnits::Mils => angle * 360.0 / MILS_PER_CIRCLE,
+        }
+    }
+}
+
+/// What heading 0 points at, set by [crate::config::ConfigPair::TrueNorth]
+#[derive(Debug, Display, Clone, Copy, PartialEq)]
+pub enum NorthReference {
+    Magnetic,
+
+    /// Magnetic heading plus the declination, in degrees
+    #[display(fmt = "True {{ declination: {} }}", declination)]
+    True { declination: f32 },
+}
+
+/// How a [Data] sample's angles are expressed
+#[derive(Debug, Display, Clone, Copy, PartialEq)]
+#[display(fmt = "{} from {} north", units, north)]
+pub struct AngleFormat {
+    pub units: AngleUnits,
+    pub north: NorthReference,
+}
+
+impl Data {
+    /// Whether the device flagged magnetic distortion: a magnetometer axis beyond ±150 µT.
+    /// False if the Distortion component wasn't requested
+    pub fn is_distorted(&self) -> bool {
+        self.distortion == Some(true)
+    }
+
+    /// Whether a user calibration is in effect, if the CalStatus component was requested
+    pub fn is_user_calibrated(&self) -> Option<bool> {
+        self.cal_status
+    }
+
+    /// Combines the manual's rules for the Distortion, CalStatus, MagAccuracy and HeadingStatus
+    /// components into one verdict. The worst of them wins; components that weren't requested
+    /// don't count, so request all the ones the device supports for a meaningful answer
+    pub fn quality(&self) -> DataQuality {
+        let mut verdicts = Vec::with_capacity(4);
+        if let Some(distortion) = self.distortion {
+            verdicts.push(if distortion { DataQuality::Unreliable } else { DataQuality::Good });
+        }
+        if let Some(calibrated) = self.cal_status {
+            verdicts.push(if calibrated { DataQuality::Good } else { DataQuality::Degraded });
+        }
+        if let Some(accuracy) = self.mag_accuracy {
+            verdicts.push(match accuracy {
+                a if a <= GOOD_MAG_ACCURACY => DataQuality::Good,
+                a if a <= FAIR_MAG_ACCURACY => DataQuality::Degraded,
+                _ => DataQuality::Unreliable,
+            });
+        }
+        if let Some(status) = self.heading_status {
+            verdicts.push(match status {
+                HeadingStatus::Good => DataQuality::Good,
+                HeadingStatus::Fair => DataQuality::Degraded,
+                HeadingStatus::Poor => DataQuality::Unreliable,
+            });
+        }
+        let worst = [DataQuality::Unreliable, DataQuality::Degraded, DataQuality::Good];
+        worst
+            .into_iter()
+            .find(|quality| verdicts.contains(quality))
+            .unwrap_or(DataQuality::Unknown)
+    }
+
+    /// Whether [Data::quality] is [DataQuality::Good]
+    pub fn is_reliable(&self) -> bool {
+        self.quality() == DataQuality::Good
+    }
+
+    /// Length in bytes of the GetDataResp frame that carried this sample, including framing
+    pub fn frame_len(&self) -> usize {
+        let floats = [
+            self.heading,
+            self.pitch,
+            self.roll,
+            self.temperature,
+            self.accel_x,
+            self.accel_y,
+            self.accel_z,
+            self.mag_x,
+            self.mag_y,
+            self.mag_z,
+            self.mag_accuracy,
+            self.gyro_x,
+            self.gyro_y,
+            self.gyro_z,
+        ];
+        let bytes = [self.distortion.is_some(), self.cal_status.is_some()];
+        // each component is its ID byte followed by its value, after a count byte
+        1 + floats.iter().flatten().count() * (1 + FLOAT_VALUE_LEN)
+            + bytes.iter().filter(|present| **present).count() * (1 + FLAG_VALUE_LEN)
+            + self.quaternion.map_or(0, |_| 1 + QUATERNION_VALUE_LEN)
+            + self.heading_status.map_or(0, |_| 1 + FLAG_VALUE_LEN)
+            + crate::codec::FRAME_OVERHEAD
+    }
+
+    /// IDs of the components present in the sample, in [DataID] declaration order (the order
+    /// they arrived in isn't kept)
+    pub fn components(&self) -> Vec<DataID> {
+        let present = [
+            (DataID::Heading, self.heading.is_some()),
+            (DataID::Pitch, self.pitch.is_some()),
+            (DataID::Roll, self.roll.is_some()),
+            (DataID::Temperature, self.temperature.is_some()),
+            (DataID::Distortion, self.distortion.is_some()),
+            (DataID::CalStatus, self.cal_status.is_some()),
+            (DataID::AccelX, self.accel_x.is_some()),
+            (DataID::AccelY, self.accel_y.is_some()),
+            (DataID::AccelZ, self.accel_z.is_some()),
+            (DataID::MagX, self.mag_x.is_some()),
+            (DataID::MagY, self.mag_y.is_some()),
+            (DataID::MagZ, self.mag_z.is_some()),
+            (DataID::MagAccuracy, self.mag_accuracy.is_some()),
+            (DataID::GyroX, self.gyro_x.is_some()),
+            (DataID::GyroY, self.gyro_y.is_some()),
+            (DataID::GyroZ, self.gyro_z.is_some()),
+            (DataID::Quaternion, self.quaternion.is_some()),
+            (DataID::HeadingStatus, self.heading_status.is_some()),
+        ];
+        present.into_iter().filter(|(_, present)| *present).map(|(id, _)| id).collect()
+    }
+
+    /// Encodes the sample as a GetDataResp payload: a component count, then each present
+    /// component's ID and value. The inverse of [parse_data_payload], for building frames in
+    /// tests and simulators. [Data::angle_format] isn't part of the payload
+    pub fn to_payload(&self) -> Vec<u8> {
+        let floats = [
+            (DataID::Heading, self.heading),
+            (DataID::Pitch, self.pitch),
+            (DataID::Roll, self.roll),
+            (DataID::Temperature, self.temperature),
+            (DataID::AccelX, self.accel_x),
+            (DataID::AccelY, self.accel_y),
+            (DataID::AccelZ, self.accel_z),
+            (DataID::MagX, self.mag_x),
+            (DataID::MagY, self.mag_y),
+            (DataID::MagZ, self.mag_z),
+            (DataID::MagAccuracy, self.mag_accuracy),
+            (DataID::GyroX, self.gyro_x),
+            (DataID::GyroY, self.gyro_y),
+            (DataID::GyroZ, self.gyro_z),
+        ];
+        let flags = [
+            (DataID::Distortion, self.distortion),
+            (DataID::CalStatus, self.cal_status),
+        ];
+
+        // count byte is filled in once the components are written
+        let mut payload = vec![0u8];
+        let mut count = 0u8;
+        for (id, value) in floats {
+            if let Some(value) = value {
+                payload.push(id as u8);
+                payload.extend_from_slice(&value.to_be_bytes());
+                count += 1;
+            }
+        }
+        for (id, value) in flags {
+            if let Some(value) = value {
+                payload.extend_from_slice(&[id as u8, value as u8]);
+                count += 1;
+            }
+        }
+        if let Some(quaternion) = self.quaternion {
+            payload.push(DataID::Quaternion as u8);
+            for component in quaternion {
+                payload.extend_from_slice(&component.to_be_bytes());
+            }
+            count += 1;
+        }
+        if let Some(status) = self.heading_status {
+            payload.extend_from_slice(&[DataID::HeadingStatus as u8, status as u8]);
+            count += 1;
+        }
+        payload[0] = count;
+        payload
+    }
+
+    /// Heading in degrees, whatever the configured units. [None] if there's no heading or the
+    /// [Data::angle_format] isn't known
+    pub fn heading_degrees(&self) -> Option<f32> {
+        Some(self.angle_format?.units.to_degrees(self.heading?))
+    }
+
+    /// Pitch in degrees, whatever the configured units
+    pub fn pitch_degrees(&self) -> Option<f32> {
+        Some(self.angle_format?.units.to_degrees(self.pitch?))
+    }
+
+    /// Roll in degrees, whatever the configured units
+    pub fn roll_degrees(&self) -> Option<f32> {
+        Some(self.angle_format?.units.to_degrees(self.roll?))
+    }
+
+    /// Heading in degrees from magnetic north, taking the declination back out if the device
+    /// applied it
+    pub fn magnetic_heading_degrees(&self) -> Option<f32> {
+        let heading = self.heading_degrees()?;
+        Some(match self.angle_format?.north {
+            NorthReference::Magnetic => heading,
+            NorthReference::True { declination } => wrap_heading(heading - declination),
+        })
+    }
+
+    /// Heading in degrees from true north. [None] if the device outputs magnetic headings, since
+    /// it doesn't report the declination it would use
+    pub fn true_heading_degrees(&self) -> Option<f32> {
+        match self.angle_format?.north {
+            NorthReference::True { .. } => self.heading_degrees(),
+            NorthReference::Magnetic => None,
+        }
+    }
+}
+
+/// One component's value from a GetDataResp, typed by its [DataID]
+#[derive(Debug, Display, Clone, Copy, PartialEq)]
+pub enum DataValue {
+    Float(f32),
+    Flag(bool),
+    #[display(fmt = "{:?}", _0)]
+    Quaternion([f32; 4]),
+    HeadingStatus(HeadingStatus),
+}
+
+/// A sample both ways: the [Data] struct, and its components in the order the device sent them,
+/// which is the order they were given to [Device::set_data_components]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DataRecord {
+    pub data: Data,
+    pub components: Vec<(DataID, DataValue)>,
+}
+
+impl Data {
+    /// Builds a sample from components, as read by [Device::get_data_record]. A component given
+    /// twice keeps its last value
+    pub fn from_components(components: &[(DataID, DataValue)]) -> Data {
+        let mut data = Data::default();
+        for &(id, value) in components {
+            match (id, value) {
+                (DataID::Heading, DataValue::Float(v)) => data.heading = Some(v),
+                (DataID::Pitch, DataValue::Float(v)) => data.pitch = Some(v),
+                (DataID::Roll, DataValue::Float(v)) => data.roll = Some(v),
+                (DataID::Temperature, DataValue::Float(v)) => data.temperature = Some(v),
+                (DataID::Distortion, DataValue::Flag(v)) => data.distortion = Some(v),
+                (DataID::CalStatus, DataValue::Flag(v)) => data.cal_status = Some(v),
+                (DataID::AccelX, DataValue::Float(v)) => data.accel_x = Some(v),
+                (DataID::AccelY, DataValue::Float(v)) => data.accel_y = Some(v),
+                (DataID::AccelZ, DataValue::Float(v)) => data.accel_z = Some(v),
+                (DataID::MagX, DataValue::Float(v)) => data.mag_x = Some(v),
+                (DataID::MagY, DataValue::Float(v)) => data.mag_y = Some(v),
+                (DataID::MagZ, DataValue::Float(v)) => data.mag_z = Some(v),
+                (DataID::MagAccuracy, DataValue::Float(v)) => data.mag_accuracy = Some(v),
+                (DataID::GyroX, DataValue::Float(v)) => data.gyro_x = Some(v),
+                (DataID::GyroY, DataValue::Float(v)) => data.gyro_y = Some(v),
+                (DataID::GyroZ, DataValue::Float(v)) => data.gyro_z = Some(v),
+                (DataID::Quaternion, DataValue::Quaternion(v)) => data.quaternion = Some(v),
+                (DataID::HeadingStatus, DataValue::HeadingStatus(v)) => {
+                    data.heading_status = Some(v)
                 }
-            };
+                // a value of the wrong type for its ID can't come from a payload
+                _ => {}
+            }
+        }
+        data
+    }
+
+    /// The components present in the sample with their values, in [DataID] declaration order.
+    /// For the order the device sent them in, read a [DataRecord]
+    pub fn values(&self) -> Vec<(DataID, DataValue)> {
+        self.components()
+            .into_iter()
+            .map(|id| {
+                let value = match id {
+                    DataID::Distortion => DataValue::Flag(self.distortion.unwrap_or_default()),
+                    DataID::CalStatus => DataValue::Flag(self.cal_status.unwrap_or_default()),
+                    DataID::Quaternion => DataValue::Quaternion(self.quaternion.unwrap_or_default()),
+                    DataID::HeadingStatus => {
+                        DataValue::HeadingStatus(self.heading_status.unwrap_or(HeadingStatus::Poor))
+                    }
+                    _ => DataValue::Float(self.float(id).unwrap_or_default()),
+                };
+                (id, value)
+            })
+            .collect()
+    }
+
+    /// The value of a float component
+    fn float(&self, id: DataID) -> Option<f32> {
+        match id {
+            DataID::Heading => self.heading,
+            DataID::Pitch => self.pitch,
+            DataID::Roll => self.roll,
+            DataID::Temperature => self.temperature,
+            DataID::AccelX => self.accel_x,
+            DataID::AccelY => self.accel_y,
+            DataID::AccelZ => self.accel_z,
+            DataID::MagX => self.mag_x,
+            DataID::MagY => self.mag_y,
+            DataID::MagZ => self.mag_z,
+            DataID::MagAccuracy => self.mag_accuracy,
+            DataID::GyroX => self.gyro_x,
+            DataID::GyroY => self.gyro_y,
+            DataID::GyroZ => self.gyro_z,
+            _ => None,
         }
+    }
+}
+
+/// Reads a GetDataResp payload's components from `source` in the order they were sent,
+/// identifying each with `data_id_for`
+fn read_components<S>(
+    source: &mut S,
+    data_id_for: fn(&S, u8) -> Result<DataID, ReadError>,
+) -> Result<Vec<(DataID, DataValue)>, ReadError>
+where
+    S: Get<u8> + Get<f32> + Get<bool> + ?Sized,
+{
+    let id_count = Get::<u8>::get(source)?;
+    let mut components = Vec::with_capacity(id_count as usize);
+
+    for _ in 0..id_count {
+        let data_id = Get::<u8>::get(source)?;
+        let id = data_id_for(source, data_id)?;
+        let value = match id {
+            DataID::Distortion | DataID::CalStatus => DataValue::Flag(Get::<bool>::get(source)?),
+            DataID::Quaternion => {
+                let mut quaternion = [0f32; 4];
+                for component in quaternion.iter_mut() {
+                    *component = Get::<f32>::get(source)?;
+                }
+                DataValue::Quaternion(quaternion)
+            }
+            DataID::HeadingStatus => {
+                DataValue::HeadingStatus(HeadingStatus::try_from(Get::<u8>::get(source)?)?)
+            }
+            _ => DataValue::Float(Get::<f32>::get(source)?),
+        };
+        components.push((id, value));
+    }
+
+    Ok(components)
+}
+
+/// Reads a GetDataResp payload from `source`, identifying each component with `data_id_for`
+fn read_data<S>(
+    source: &mut S,
+    data_id_for: fn(&S, u8) -> Result<DataID, ReadError>,
+) -> Result<DataRecord, ReadError>
+where
+    S: Get<u8> + Get<f32> + Get<bool> + ?Sized,
+{
+    let components = read_components(source, data_id_for)?;
+    Ok(DataRecord { data: Data::from_components(&components), components })
+}
+
+impl Get<DataRecord> for Device {
+    fn get(&mut self) -> Result<DataRecord, ReadError> {
+        let mut record = read_data(self, Device::data_id_for_device)?;
+        record.data.angle_format = self.angle_config.format();
+        Ok(record)
+    }
+
+    fn get_string(&mut self) -> Result<String, ReadError> {
+        Ok(Get::<Data>::get(self)?.to_string())
+    }
+}
 
-        Ok(data_struct)
+impl Get<Data> for Device {
+    fn get(&mut self) -> Result<Data, ReadError> {
+        Ok(Get::<DataRecord>::get(self)?.data)
+    }
+
+    fn get_string(&mut self) -> Result<String, ReadError> {
+        Ok(Get::<Data>::get(self)?.to_string())
+    }
+}
+
+/// Parses a GetDataResp payload captured elsewhere. Without a device to ask, every known
+/// component is accepted and [Data::angle_format] is left unknown
+impl Get<DataRecord> for &[u8] {
+    fn get(&mut self) -> Result<DataRecord, ReadError> {
+        read_data(self, |_, value| DataID::try_from(value))
     }
 
     fn get_string(&mut self) -> Result<String, ReadError> {
@@ -205,6 +690,37 @@ impl Get<Data> for Device {
     }
 }
 
+impl Get<Data> for &[u8] {
+    fn get(&mut self) -> Result<Data, ReadError> {
+        Ok(Get::<DataRecord>::get(self)?.data)
+    }
+
+    fn get_string(&mut self) -> Result<String, ReadError> {
+        Ok(Get::<Data>::get(self)?.to_string())
+    }
+}
+
+/// Parses a GetDataResp payload (the bytes between the command byte and the checksum) on its
+/// own, e.g. from a capture or a fuzzer. Every byte must be used: a component count that claims
+/// more fields than the payload holds, or trailing bytes after the last field, are errors
+pub fn parse_data_payload(payload: &[u8]) -> Result<Data, ReadError> {
+    let mut cursor = payload;
+    let data = Get::<Data>::get(&mut cursor)?;
+    if !cursor.is_empty() {
+        return Err(ReadError::SizeMismatch {
+            expected: frame_len_of(payload.len() - cursor.len()),
+            actual: frame_len_of(payload.len()),
+        });
+    }
+    Ok(data)
+}
+
+/// Frame length for a payload of `payload_len` bytes, saturating rather than wrapping
+fn frame_len_of(payload_len: usize) -> u16 {
+    (payload_len + crate::codec::FRAME_OVERHEAD).min(u16::MAX as usize) as u16
+}
+
+#[derive(Debug, Clone)]
 pub struct AcqParamsReserved {
     /// This flag sets whether output will be presented in Continuous or Polled Acquisition Mode. Poll Mode is TRUE and should be selected when the host system will poll the TargetPoint3 for each data set. Continuous Mode is FALSE and should be selected if the user will have the TargetPoint3 output data to the host system at a relatively fixed rate. Poll Mode is the default.
     pub acquisition_mode: bool,
@@ -219,6 +735,30 @@ pub struct AcqParamsReserved {
     pub sample_delay: f32,
 }
 
+impl AcqParamsReserved {
+    /// Encodes the parameters as a SetAcqParams or GetAcqParamsResp payload, which share a layout
+    pub fn to_payload(&self) -> Vec<u8> {
+        let mut payload = Vec::<u8>::new();
+        payload.push(if self.acquisition_mode { 1 } else { 0 });
+        payload.push(if self.flush_filter { 1 } else { 0 });
+        payload.extend_from_slice(&self.reserved.to_be_bytes());
+        payload.extend_from_slice(&self.sample_delay.to_be_bytes());
+        payload
+    }
+}
+
+impl From<AcqParams> for AcqParamsReserved {
+    /// With the reserved field zeroed, as [Device::set_acq_params] sends it
+    fn from(value: AcqParams) -> Self {
+        AcqParamsReserved {
+            acquisition_mode: value.acquisition_mode,
+            flush_filter: value.flush_filter,
+            reserved: 0.0,
+            sample_delay: value.sample_delay,
+        }
+    }
+}
+
 impl From<AcqParamsReserved> for AcqParams {
     fn from(value: AcqParamsReserved) -> Self {
         AcqParams {
@@ -229,6 +769,7 @@ impl From<AcqParamsReserved> for AcqParams {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct AcqParams {
     /// This flag sets whether output will be presented in Continuous or Polled Acquisition Mode. Poll Mode is TRUE and should be selected when the host system will poll the TargetPoint3 for each data set. Continuous Mode is FALSE and should be selected if the user will have the TargetPoint3 output data to the host system at a relatively fixed rate. Poll Mode is the default.
     pub acquisition_mode: bool,
@@ -246,38 +787,36 @@ impl Device {
     /// # Arguments
     /// * `acq_params` - Parameters to set for next acquisition
     pub fn set_acq_params(&mut self, acq_params: AcqParams) -> Result<(), RWError> {
-        self.set_acq_params_impl(AcqParamsReserved {
-            acquisition_mode: acq_params.acquisition_mode,
-            flush_filter: acq_params.flush_filter,
-            reserved: f32::from_be_bytes([0u8, 0u8, 0u8, 0u8]),
-            sample_delay: acq_params.sample_delay,
-        })
+        let acq_params = AcqParamsReserved::from(acq_params);
+        self.set_acq_params_impl(acq_params.clone())?;
+        self.acq_params = Some(acq_params);
+        self.settings.mark_other_unsaved();
+        Ok(())
     }
 
-    /// Like set_acq_parameters, but gives the user the ability to write to the PNI reserved
-    /// fields. Note different parameter ordering (done to reflect order inside payload)
-    /// Confused? Just use set_acq_parameters
-    pub fn set_acq_params_impl(
-        &mut self,
-        acq_params: AcqParamsReserved,
-    ) -> Result<(), RWError> {
-        let mut payload = Vec::<u8>::new();
-        payload.push(if acq_params.acquisition_mode { 1 } else { 0 });
-        payload.push(if acq_params.flush_filter { 1 } else { 0 });
-        payload.extend_from_slice(&acq_params.reserved.to_be_bytes());
-        payload.extend_from_slice(&acq_params.sample_delay.to_be_bytes());
-        self.write_frame(Command::SetAcqParams, Some(&payload))?;
-
-        let expected_size = Get::<u16>::get(self)?;
-        if Get::<u8>::get(self)? == Command::SetAcqParamsDone.discriminant() {
-            self.end_frame(expected_size)?;
-            Ok(())
-        } else {
-            let _ = self.end_frame(expected_size);
-            Err(RWError::ReadError(ReadError::ParseError(
-                "Unexpected response type".to_string(),
-            )))
-        }
+    requests! {
+        /// Like set_acq_parameters, but gives the user the ability to write to the PNI reserved
+        /// fields. Note different parameter ordering (done to reflect order inside payload)
+        /// Confused? Just use set_acq_parameters
+        pub fn set_acq_params_impl(acq_params: AcqParamsReserved) -> ()
+            = SetAcqParams(acq_params.to_payload())
+            => || Ok(());
+
+        /// Same as get_acq_params, but instead returns a tuple whose first value are the AcqParams and
+        /// whose second value are the reserved bits
+        pub fn get_acq_params_impl() -> AcqParamsReserved = GetAcqParams => |
+            acquisition_mode: bool,
+            flush_filter: bool,
+            reserved: f32,
+            sample_delay: f32,
+        | {
+            Ok(AcqParamsReserved {
+                acquisition_mode,
+                flush_filter,
+                reserved,
+                sample_delay,
+            })
+        };
     }
     
     /// Like set_acq_parameters, but gives the user the ability to write to the PNI reserved
@@ -288,7 +827,10 @@ impl Device {
         &mut self,
         acq_params: AcqParamsReserved,
     ) -> Result<(), RWError> {
-        self.set_acq_params_impl(acq_params)
+        self.set_acq_params_impl(acq_params.clone())?;
+        self.acq_params = Some(acq_params);
+        self.settings.mark_other_unsaved();
+        Ok(())
     }
     
     /// Same as get_acq_params, but instead returns a tuple whose first value are the AcqParams and
@@ -298,32 +840,6 @@ impl Device {
         self.get_acq_params_impl()
     }
 
-    /// Same as get_acq_params, but instead returns a tuple whose first value are the AcqParams and
-    /// whose second value are the reserved bits
-    pub fn get_acq_params_impl(&mut self) -> Result<AcqParamsReserved, RWError> {
-        self.write_frame(Command::GetAcqParams, None)?;
-
-        let expected_size = Get::<u16>::get(self)?;
-        if Get::<u8>::get(self)? == Command::GetAcqParamsResp.discriminant() {
-            let acquisition_mode = Get::<bool>::get(self)?;
-            let flush_filter = Get::<bool>::get(self)?;
-            let reserved = Get::<f32>::get(self)?;
-            let sample_delay = Get::<f32>::get(self)?;
-            self.end_frame(expected_size)?;
-            Ok(AcqParamsReserved {
-                acquisition_mode,
-                flush_filter,
-                reserved,
-                sample_delay,
-            })
-        } else {
-            let _ = self.end_frame(expected_size);
-            Err(RWError::ReadError(ReadError::ParseError(
-                "Unexpected response type".to_string(),
-            )))
-        }
-    }
-
     /// This frame queries the unit for acquisition parameters.
     pub fn get_acq_params(&mut self) -> Result<AcqParams, RWError> {
         Ok(self.get_acq_params_impl()?.into())
@@ -334,32 +850,111 @@ impl Device {
     /// # Arguments
     ///
     /// * `components` - List of dimensions (measurements) to get back on subsequent get_data
-    /// responses, or during continuous mode after the device is rebooted
+    ///   responses, or during continuous mode after the device is rebooted
+    ///
+    /// Components the device is known not to produce return [RWError::UnsupportedByDevice]
+    /// without sending anything
     pub fn set_data_components(&mut self, components: Vec<DataID>) -> Result<(), RWError> {
+        for feature in components.iter().filter_map(DataID::required_feature) {
+            self.require(feature)?;
+        }
         let mut payload = Vec::<u8>::new();
         payload.push(components.len() as u8);
-        for component in components.into_iter() {
-            payload.push(component as u8);
+        for component in components.iter() {
+            payload.push(*component as u8);
         }
-        self.write_frame(Command::SetDataComponents, Some(&payload))?;
+        self.send(Command::SetDataComponents, Some(&payload))?;
+        self.data_components = Some(components);
+        self.settings.mark_other_unsaved();
         Ok(())
     }
 
-    /// If the TargetPoint3 is configured to operate in Polled Acquisition Mode (see SetAcqParams), then this frame requests a single measurement data set. The frame has no payload.
-    pub fn get_data(&mut self) -> Result<Data, RWError> {
-        self.write_frame(Command::GetData, None)?;
+    /// Components last set with [Device::set_data_components] on this connection, in the order
+    /// they were sent. The device has no command to query them, so this is [None] until they're
+    /// set, even if components saved earlier are in effect
+    pub fn get_data_components(&self) -> Option<&[DataID]> {
+        self.data_components.as_deref()
+    }
 
-        let expected_size = Get::<u16>::get(self)?;
-        if Get::<u8>::get(self)? == Command::GetDataResp.discriminant() {
-            let data = Get::<Data>::get(self)?;
-            self.end_frame(expected_size)?;
-            Ok(data)
-        } else {
-            let _ = self.end_frame(expected_size);
-            Err(RWError::ReadError(ReadError::ParseError(
-                "Unexpected response type".to_string(),
-            )))
+    /// Like [Device::set_data_components], but since the device doesn't acknowledge
+    /// SetDataComponents, follows it with a GetData to check the sample carries exactly the
+    /// requested components. Returns that sample.
+    ///
+    /// A mismatch, e.g. from a device that ignored the frame, is a [RWError::DeviceError]. Needs
+    /// the device in polled mode, not streaming
+    pub fn set_data_components_verified(
+        &mut self,
+        components: Vec<DataID>,
+    ) -> Result<Data, RWError> {
+        self.set_data_components(components)?;
+        let data = self.get_data()?;
+        let mut requested = self.data_components.clone().unwrap_or_default();
+        requested.sort_by_key(|&id| id as u8);
+        requested.dedup();
+        let mut received = data.components();
+        received.sort_by_key(|&id| id as u8);
+        if requested != received {
+            return Err(RWError::DeviceError(
+                Message::new(MessageId::ComponentsMismatch)
+                    .with("requested", format!("{:?}", requested))
+                    .with("received", format!("{:?}", received)),
+            ));
         }
+        Ok(data)
+    }
+
+    requests! {
+        /// If the TargetPoint3 is configured to operate in Polled Acquisition Mode (see SetAcqParams), then this frame requests a single measurement data set. The frame has no payload.
+        pub fn get_data() -> Data = GetData => |data: Data| Ok(data);
+
+        /// Like [Device::get_data], but also returns the components in the order the device
+        /// sent them
+        pub fn get_data_record() -> DataRecord = GetData => |record: DataRecord| Ok(record);
+    }
+
+    /// Polls until the output has settled after power-up, so automated tests and captures skip
+    /// the transient first seconds while the FIR filter fills and the mag accuracy converges.
+    /// Uses [StabilityDetector::default]; the data components must include heading, and
+    /// stability is also judged on mag accuracy if it's included.
+    ///
+    /// Returns how long it took, or [RWError::NeverStabilized] after `timeout`
+    pub fn wait_until_stable(&mut self, timeout: Duration) -> Result<Duration, RWError> {
+        let mut detector = StabilityDetector::default();
+        let start = self.clock.now();
+        loop {
+            let data = self.get_data()?;
+            if data.heading.is_none() {
+                return Err(RWError::DeviceError(Message::new(MessageId::HeadingRequired)));
+            }
+            let elapsed = self.clock.now().saturating_duration_since(start);
+            if detector.update(&data) {
+                return Ok(elapsed);
+            }
+            if elapsed >= timeout {
+                return Err(RWError::NeverStabilized { elapsed });
+            }
+        }
+    }
+
+    /// Like [Device::get_data], but wraps the sample with the time its response started arriving.
+    /// [TimestampedData::estimated_sample_time] is left empty, since a polled sample is taken
+    /// when the poll arrives rather than on a schedule
+    pub fn get_data_timestamped(&mut self) -> Result<TimestampedData, RWError> {
+        self.send(Command::GetData, None)?;
+        let frame = self.begin_frame()?;
+        let received_at = frame.started();
+        let mut frame = frame.expect(Command::GetDataResp)?;
+        let data = frame.get::<Data>()?;
+        frame.finish()?;
+        let received_at_system =
+            SystemTime::now() - self.clock.now().duration_since(received_at);
+        Ok(TimestampedData {
+            data,
+            provenance: Provenance::Polled,
+            received_at,
+            received_at_system,
+            estimated_sample_time: None,
+        })
     }
 
     /// If the TargetPoint3 is configured to operate in Continuous Acquisition Mode (see SetAcqParams), then this frame initiates the outputting of data at a relatively fixed data rate, where the data rate is established by the SampleDelay parameter. The frame has no payload.
@@ -376,25 +971,25 @@ impl Device {
     /// tp3.set_data_components(vec![DataID::AccelX]).unwrap();
     /// tp3.save().unwrap();
     /// tp3.start_continuous_mode().unwrap();
-    /// tp3.power_down().unwrap();
-    /// let mut tp3 = TargetPoint3::connect(None).unwrap();
-    /// tp3.power_up().unwrap();
+    /// let mut tp3 = tp3.power_down().unwrap().wake().unwrap();
     /// tp3.stop_continuous_mode().unwrap();
     /// tp3.save().unwrap();
-    /// tp3.power_down().unwrap();
-    /// tp3 = TargetPoint3::connect(None).unwrap();
-    /// tp3.power_up().unwrap();
+    /// tp3 = tp3.power_down().unwrap().wake().unwrap();
     /// # }
     /// ```
     pub fn start_continuous_mode(&mut self) -> Result<(), RWError> {
-        self.write_frame(Command::StartContinuousMode, None)?;
+        self.send(Command::StartContinuousMode, None)?;
+        self.streaming = true;
+        self.set_connection_state(ConnectionEvent::Streaming);
         Ok(())
     }
 
     /// This frame commands the TargetPoint3 to stop data output when in Continuous Acquisition Mode. The frame has no payload.
     /// You must call [TargetPoint3::save] and power cycle the device after calling [TargetPoint3::stop_continuous_mode] to stop continuous output
     pub fn stop_continuous_mode(&mut self) -> Result<(), RWError> {
-        self.write_frame(Command::StopContinuousMode, None)?;
+        self.send(Command::StopContinuousMode, None)?;
+        self.streaming = false;
+        self.set_connection_state(ConnectionEvent::Identified);
         Ok(())
     }
 
@@ -406,17 +1001,14 @@ impl Device {
     /// Calling this will freely change several configuration settings (including AcqParams) to
     /// sensible defaults and save them, along with any other device settings currently in volatile memory to non-volatile memory.
     ///
-    /// This function will also re-construct [TargetPoint3] by auto-detecting the serial port,
-    /// meaning it is not compatible with your use case if you have multiple devices connected at the same time, or if auto-detection failed and you manually provided a [SerialPort] or provided a serial port descriptor string to the constructor
-    ///
     /// # For predictable behavior
     /// If you do not want more predictable behavior that doesn't violate these contracts, you may
     /// use [TargetPoint3::set_acq_params], [TargetPoint3::set_data_components], [TargetPoint3::start_continuous_mode_raw], [TargetPoint3::power_down], and
-    /// [TargetPoint3::power_up] in that order. See user manual for more help.
+    /// [crate::power::PoweredDown::wake] in that order. See user manual for more help.
     ///
     /// # Arguments
     /// * `sample_delay` - Time, in seconds, between samples. See SetAcqParams command in user
-    /// manual for nuances
+    ///   manual for nuances
     /// * `data_components` - List of data types to acquire from device
     pub fn continuous_mode_easy(
         mut self,
@@ -431,11 +1023,7 @@ impl Device {
         self.set_data_components(data_components)?;
         self.save()?;
         self.start_continuous_mode()?;
-        self.power_down()?;
-        let mut newtp3 = Device::connect(None)?;
-        newtp3.power_up()?;
-
-        Ok(newtp3)
+        Ok(self.power_down()?.wake()?)
     }
 
     /// Convenience wrapper around several functions to make it easier to take the device out of continuous mode. See [TargetPoint3::easy_continuous_mode]
@@ -444,26 +1032,172 @@ impl Device {
     /// Calling this may freely change several configuration settings (including AcqParams) to
     /// sensible defaults and save them, along with any other device settings currently in volatile memory to non-volatile memory.
     ///
-    /// This function will also re-construct [TargetPoint3] by auto-detecting the serial port,
-    /// meaning it is not compatible with your use case if you have multiple devices connected at the same time, or if auto-detection failed and you manually provided a [SerialPort] or provided a serial port descriptor string to the constructor
-    ///
     /// # For predictable behavior
     /// If you do not want more predictable behavior that doesn't violate these contracts, you may
     /// use [TargetPoint3::set_acq_params], TargetPoint3::stop_continuous_mode_raw], [TargetPoint3::power_down], and
-    /// [TargetPoint3::power_up] in that order. See user manual for more help.
+    /// [crate::power::PoweredDown::wake] in that order. See user manual for more help.
     pub fn stop_continuous_mode_easy(mut self) -> Result<Self, Box<dyn Error>> {
         //self.set_acq_params(AcqParams { acquisition_mode: true, flush_filter: false, sample_delay: 0f32 })?;
         self.stop_continuous_mode()?;
         self.save()?;
-        self.power_down()?;
-        let mut newtp3 = Device::connect(None)?;
-        newtp3.power_up()?;
-        Ok(newtp3)
+        Ok(self.power_down()?.wake()?)
+    }
+
+    /// Puts the device in continuous mode on the current connection, without saving to
+    /// non-volatile memory or power cycling. Any frames already streaming from a previous session
+    /// are drained first. Call [Device::iter] afterwards to read the data.
+    ///
+    /// Unlike [Device::continuous_mode_easy], this keeps the serial port open and doesn't wear
+    /// the device's flash, but the streaming configuration is lost when the device powers down.
+    ///
+    /// # Arguments
+    /// * `sample_delay` - Time, in seconds, between samples. See SetAcqParams command in user
+    ///   manual for nuances
+    /// * `data_components` - List of data types to acquire from device
+    pub fn start_continuous_mode_live(
+        &mut self,
+        sample_delay: f32,
+        data_components: Vec<DataID>,
+    ) -> Result<(), RWError> {
+        self.stop_continuous_mode_live()?;
+        self.set_acq_params(AcqParams {
+            acquisition_mode: false,
+            flush_filter: false,
+            sample_delay,
+        })?;
+        self.set_data_components(data_components)?;
+        self.start_continuous_mode()
+    }
+
+    /// Stops continuous output on the current connection, discarding any data frames that were
+    /// already in flight so the next command's response is read from a frame boundary. See
+    /// [Device::start_continuous_mode_live]
+    pub fn stop_continuous_mode_live(&mut self) -> Result<(), RWError> {
+        self.stop_continuous_mode()?;
+        self.drain_frames(Duration::from_millis(250))?;
+        Ok(())
+    }
+
+    /// Recovers a device that was left in continuous mode, e.g. by a previous program that
+    /// exited without stopping it. Listens for unsolicited GetDataResp frames and, if any arrive,
+    /// sends StopContinuousMode (without saving) and drains the rest so the next command's
+    /// response is read from a frame boundary. Otherwise the first command after connecting to a
+    /// streaming device fails with checksum or parse errors.
+    ///
+    /// Returns the number of data frames discarded; zero means the device wasn't streaming.
+    /// See also [Device::connect_recovering]
+    pub fn drain_continuous(&mut self) -> Result<usize, RWError> {
+        let streamed = self.drain_frames_counting(STREAM_DETECT_PERIOD, |command| {
+            command == u8::from(Command::GetDataResp)
+        })?;
+        if streamed > 0 {
+            log::warn!(
+                "Device was already in continuous mode ({} frames received unprompted), stopping it",
+                streamed
+            );
+            self.stop_continuous_mode_live()?;
+        }
+        Ok(streamed)
     }
 
     pub fn iter<'a>(&'a mut self) -> impl Iterator<Item = Result<Data, ReadError>> + 'a {
         ContinuousModeIterator(self)
     }
+
+    /// Like [Device::iter], but attaches host timestamps to every sample. Timestamps are read from
+    /// the device's clock (see [Device::set_clock]) as soon as a frame's length field arrives,
+    /// before its payload is read, so they aren't skewed by the time spent reading and parsing.
+    ///
+    /// # Arguments
+    /// * `sample_delay` - If [Some], the SampleDelay the device was configured with, used to fill
+    ///   [TimestampedData::estimated_sample_time]
+    pub fn iter_timestamped<'a>(
+        &'a mut self,
+        sample_delay: Option<f32>,
+    ) -> impl Iterator<Item = Result<TimestampedData, ReadError>> + 'a {
+        self.iter_timestamped_impl(sample_delay)
+    }
+
+    /// Like [Device::iter_timestamped], but ends the session on its own once `stop` is reached:
+    /// the device is sent StopContinuousMode (without saving) and remaining frames are drained
+    /// before iteration ends. Iteration also ends, without stopping the device, if the stream goes
+    /// quiet for longer than the port timeout.
+    ///
+    /// Dropping the iterator early leaves the device streaming
+    pub fn capture<'a>(&'a mut self, stop: StopAfter, sample_delay: Option<f32>) -> Capture<'a> {
+        let started = self.clock.now();
+        Capture {
+            samples: self.iter_timestamped_impl(sample_delay),
+            stop,
+            started,
+            count: 0,
+            finished: false,
+        }
+    }
+
+    fn iter_timestamped_impl(&mut self, sample_delay: Option<f32>) -> TimestampedIterator<'_> {
+        TimestampedIterator {
+            device: self,
+            sample_delay: sample_delay.map(Duration::from_secs_f32),
+            previous_frame_end: None,
+        }
+    }
+
+    /// Reads one GetDataResp frame. Returns [None] if the read timed out before the frame began,
+    /// otherwise the data along with when its length field arrived, on the device's clock and
+    /// before the rest of the frame was read. Other frames arriving in between, including ones
+    /// with unknown command bytes, are queued for [Device::take_unsolicited]
+    pub(crate) fn next_data_frame(&mut self) -> Option<Result<(Data, Instant), ReadError>> {
+        if self.protocol == crate::connection::Protocol::Ascii {
+            return self.next_ascii_sentence();
+        }
+        loop {
+            // with a reconnect policy, the stream picks up again once the port is back
+            let reconnects = self.reconnect_policy.is_some();
+            if self.port_lost && reconnects && !self.recover_port() {
+                return Some(Err(ReadError::PipeError(std::io::ErrorKind::NotConnected.into())));
+            }
+            let frame = match self.begin_frame() {
+                Ok(frame) => frame,
+                Err(ReadError::PipeError(ioerr))
+                    if ioerr.kind() == std::io::ErrorKind::TimedOut =>
+                {
+                    return None;
+                }
+                Err(ReadError::PipeError(ioerr))
+                    if reconnects && crate::connection::is_port_gone(&ioerr) =>
+                {
+                    continue;
+                }
+                Err(e) => {
+                    return Some(Err(e));
+                }
+            };
+            let frame_start = frame.started();
+
+            if frame.command() != u8::from(Command::GetDataResp) {
+                match frame.dispatch() {
+                    Ok(response) => {
+                        log::debug!("Queued {:?} received in continuous mode", response);
+                        self.queue_unsolicited(response);
+                        continue;
+                    }
+                    Err(e) if self.tolerate(&e) => continue,
+                    Err(e) => return Some(Err(e)),
+                }
+            }
+
+            let result = frame.expect(Command::GetDataResp).and_then(|mut frame| {
+                let data = frame.get::<Data>()?;
+                frame.finish()?;
+                Ok((data, frame_start))
+            });
+            match result {
+                Err(e) if self.tolerate(&e) => continue,
+                result => return Some(result),
+            }
+        }
+    }
 }
 
 pub struct ContinuousModeIterator<'a>(&'a mut Device);
@@ -472,43 +1206,327 @@ impl<'a> Iterator for ContinuousModeIterator<'a> {
     type Item = Result<Data, ReadError>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let expected_size = match Get::<u16>::get(self.0) {
-            Ok(size) => size,
-            Err(ReadError::PipeError(ioerr)) if ioerr.kind() == std::io::ErrorKind::TimedOut => {
-                return None;
-            }
-            Err(e) => {
-                return Some(Err(e));
-            }
+        self.0
+            .next_data_frame()
+            .map(|frame| frame.map(|(data, _)| data))
+    }
+}
+
+/// Where a sample came from, so downstream pipelines and logs can tell live data from replayed or
+/// simulated data
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Provenance {
+    /// Live, answering a GetData poll
+    Polled,
+
+    /// Live, streamed in continuous mode
+    Continuous,
+
+    /// Read back from a recording
+    Replayed,
+
+    /// Generated without a device, e.g. in tests
+    Simulated,
+}
+
+/// A [Data] sample along with when it was received
+#[derive(Debug, Clone)]
+pub struct TimestampedData {
+    pub data: Data,
+
+    pub provenance: Provenance,
+
+    /// Time on the device's clock at which the frame's length field arrived
+    pub received_at: Instant,
+
+    /// Wall-clock time at which the frame's length field arrived
+    pub received_at_system: SystemTime,
+
+    /// Estimate of when the device started acquiring this sample: the end of the previous frame
+    /// plus the SampleDelay. [None] for the first sample, or if no SampleDelay was given
+    pub estimated_sample_time: Option<Instant>,
+}
+
+pub struct TimestampedIterator<'a> {
+    device: &'a mut Device,
+    sample_delay: Option<Duration>,
+
+    /// When the previous frame finished arriving
+    previous_frame_end: Option<Instant>,
+}
+
+impl<'a> Iterator for TimestampedIterator<'a> {
+    type Item = Result<TimestampedData, ReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Some(frame) = self.device.next_data_frame() else {
+            // stream went quiet, so the next frame can't be timed off this one
+            self.previous_frame_end = None;
+            return None;
         };
+        let frame_end = self.device.clock.now();
+        let previous_frame_end = self.previous_frame_end.replace(frame_end);
+
+        Some(frame.map(|(data, received_at)| {
+            // translate the monotonic receive time into wall-clock time, rather than sampling the
+            // system clock late
+            let received_at_system = SystemTime::now() - frame_end.duration_since(received_at);
+            let estimated_sample_time = match (previous_frame_end, self.sample_delay) {
+                (Some(previous_end), Some(delay)) => Some((previous_end + delay).min(received_at)),
+                _ => None,
+            };
 
-        let resp_command = match Get::<u8>::get(self.0) {
-            Ok(command) => command,
-            Err(e) => {
-                return Some(Err(e));
+            TimestampedData {
+                data,
+                provenance: Provenance::Continuous,
+                received_at,
+                received_at_system,
+                estimated_sample_time,
             }
+        }))
+    }
+}
+
+/// Model of the delay between the device acquiring a sample and the host seeing its frame, for
+/// placing samples on the host clock more accurately than their receive time. See
+/// [Device::sample_timing]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SampleTiming {
+    /// SampleDelay, from the acquisition parameters
+    pub sample_delay: Duration,
+
+    /// FIR filter length. The filter delays its output by half its span
+    pub fir_taps: usize,
+
+    pub baud_rate: u32,
+
+    /// Time the device spends measuring each sample, on top of SampleDelay and sending the
+    /// frame. The manual doesn't give it; measure the real period with
+    /// `Device::measure_continuous_rate` (`unstable` feature) and set
+    /// [SampleTiming::set_measured_period]. Zero by default
+    pub acquisition_time: Duration,
+
+    /// Fixed delay in the host's serial stack, e.g. a USB adapter's latency timer (16 ms by
+    /// default on FTDI chips). Zero by default
+    pub host_latency: Duration,
+}
+
+impl SampleTiming {
+    /// Time to send a frame of `frame_len` bytes, at 10 bits per byte
+    pub fn transmission_time(&self, frame_len: usize) -> Duration {
+        Duration::from_secs_f64((frame_len * 10) as f64 / self.baud_rate.max(1) as f64)
+    }
+
+    /// Time between samples in continuous mode, as modelled
+    pub fn sample_period(&self, frame_len: usize) -> Duration {
+        self.sample_delay + self.acquisition_time + self.transmission_time(frame_len)
+    }
+
+    /// Highest rate continuous mode can run at, with a SampleDelay of 0
+    pub fn max_rate_hz(&self, frame_len: usize) -> f32 {
+        let period = self.acquisition_time + self.transmission_time(frame_len);
+        1.0 / period.as_secs_f32().max(f32::EPSILON)
+    }
+
+    /// SampleDelay giving a rate of `rate_hz`, once acquisition and transmission time are
+    /// taken off the period. [None] if the rate is above [SampleTiming::max_rate_hz]
+    pub fn sample_delay_for(&self, rate_hz: f32, frame_len: usize) -> Option<Duration> {
+        let period = Duration::try_from_secs_f64(1.0 / rate_hz as f64).ok()?;
+        period.checked_sub(self.acquisition_time + self.transmission_time(frame_len))
+    }
+
+    /// Sets [SampleTiming::acquisition_time] so the modelled period matches a measured one
+    pub fn set_measured_period(&mut self, period: Duration, frame_len: usize) {
+        self.acquisition_time = period
+            .saturating_sub(self.sample_delay)
+            .saturating_sub(self.transmission_time(frame_len));
+    }
+
+    /// How long before its frame's first byte reaches the host a sample was taken, for a frame of
+    /// `frame_len` bytes (see [Data::frame_len]). The sum of the FIR filter's group delay, the
+    /// frame's transmission time (USB adapters pass on a short frame in one burst once it's all
+    /// arrived) and [SampleTiming::host_latency]
+    pub fn sample_time_offset(&self, frame_len: usize) -> Duration {
+        let fir_delay = self
+            .sample_period(frame_len)
+            .mul_f64(self.fir_taps.saturating_sub(1) as f64 / 2.0);
+        fir_delay + self.transmission_time(frame_len) + self.host_latency
+    }
+
+    /// Replaces [TimestampedData::estimated_sample_time] with the receive time less
+    /// [SampleTiming::sample_time_offset]
+    pub fn correct(&self, sample: &mut TimestampedData) {
+        let offset = self.sample_time_offset(sample.data.frame_len());
+        sample.estimated_sample_time = sample.received_at.checked_sub(offset);
+    }
+}
+
+impl Device {
+    /// Reads the settings that determine sample timing (acquisition parameters, FIR filter
+    /// length and baud rate) to build a [SampleTiming]. Uses request/response commands, so call
+    /// it before starting continuous mode
+    pub fn sample_timing(&mut self) -> Result<SampleTiming, RWError> {
+        let sample_delay = self.get_acq_params()?.sample_delay;
+        let fir_taps = self.get_fir_filters()?.len();
+        let baud_rate = self
+            .transport
+            .baud_rate()
+            .map_err(ReadError::from)?;
+        Ok(SampleTiming {
+            sample_delay: Duration::from_secs_f32(sample_delay.max(0.0)),
+            fir_taps,
+            baud_rate,
+            acquisition_time: Duration::ZERO,
+            host_latency: Duration::ZERO,
+        })
+    }
+
+    /// Sets SampleDelay so continuous mode outputs about `rate_hz` samples a second, instead of
+    /// working the delay out from the manual. The other acquisition parameters are kept.
+    ///
+    /// The delay is the period less the time to send each frame at the port's baud rate (sized
+    /// from the components set with [Device::set_data_components], or heading, pitch and roll if
+    /// none were set on this connection) and a typical acquisition time. A rate above what the
+    /// device can reach logs a warning and sets a delay of 0, the fastest it goes.
+    ///
+    /// Returns the rate the model expects. It's an estimate: measure the real one with
+    /// `Device::measure_continuous_rate` (`unstable` feature) and, if it's off, set the delay
+    /// from [SampleTiming::sample_delay_for] after [SampleTiming::set_measured_period]
+    pub fn set_sample_rate_hz(&mut self, rate_hz: f32) -> Result<f32, RWError> {
+        if !(rate_hz.is_finite() && rate_hz > 0.0) {
+            return Err(RWError::InvalidArgument(
+                Message::new(MessageId::OutOfRange)
+                    .with("name", "sample rate")
+                    .with("value", rate_hz)
+                    .with("range", "(0, ∞) Hz"),
+            ));
+        }
+        let baud_rate = self
+            .transport
+            .baud_rate()
+            .map_err(ReadError::from)?;
+        let mut timing = SampleTiming {
+            sample_delay: Duration::ZERO,
+            fir_taps: 0,
+            baud_rate,
+            acquisition_time: TYPICAL_ACQUISITION_TIME,
+            host_latency: Duration::ZERO,
         };
+        let frame_len = self.expected_data_frame_len();
+        timing.sample_delay = timing.sample_delay_for(rate_hz, frame_len).unwrap_or_else(|| {
+            log::warn!(
+                "{} Hz is faster than the {:.1} Hz the device can reach at {} baud, using a \
+                 SampleDelay of 0",
+                rate_hz,
+                timing.max_rate_hz(frame_len),
+                baud_rate
+            );
+            Duration::ZERO
+        });
 
-        if resp_command == Command::GetDataResp.discriminant() {
-            let data = match Get::<Data>::get(self.0) {
-                Ok(command) => command,
-                Err(e) => {
-                    return Some(Err(e));
-                }
-            };
-            match self.0.end_frame(expected_size) {
-                Ok(_) => (),
-                Err(e) => {
-                    return Some(Err(e));
-                }
-            };
+        let mut acq_params = self.get_acq_params()?;
+        acq_params.sample_delay = timing.sample_delay.as_secs_f32();
+        self.set_acq_params(acq_params)?;
+        Ok(1.0 / timing.sample_period(frame_len).as_secs_f32())
+    }
+
+    /// Length of the GetDataResp frames the current data components give
+    fn expected_data_frame_len(&self) -> usize {
+        let default = [DataID::Heading, DataID::Pitch, DataID::Roll];
+        let components = self.data_components.as_deref().unwrap_or(&default);
+        let values = components.iter().map(|id| 1 + id.value_len()).sum::<usize>();
+        crate::codec::FRAME_OVERHEAD + 1 + values
+    }
+
+    /// Shorthand for [SampleTiming::sample_time_offset] with the device's current settings, for
+    /// frames of `frame_len` bytes
+    pub fn sample_time_offset(&mut self, frame_len: usize) -> Result<Duration, RWError> {
+        Ok(self.sample_timing()?.sample_time_offset(frame_len))
+    }
+}
+
+/// When [Device::capture] should end a session. Whichever limit is reached first applies; with
+/// neither set, the session runs until the stream stops
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StopAfter {
+    /// Time since the capture started
+    pub duration: Option<Duration>,
+
+    /// Samples returned, including errors
+    pub samples: Option<u64>,
+}
+
+impl StopAfter {
+    pub fn duration(duration: Duration) -> Self {
+        Self {
+            duration: Some(duration),
+            samples: None,
+        }
+    }
+
+    pub fn samples(samples: u64) -> Self {
+        Self {
+            duration: None,
+            samples: Some(samples),
+        }
+    }
+
+    fn reached(&self, elapsed: Duration, samples: u64) -> bool {
+        self.duration.is_some_and(|duration| elapsed >= duration)
+            || self.samples.is_some_and(|limit| samples >= limit)
+    }
+}
+
+/// Continuous-mode session that stops itself. See [Device::capture]
+pub struct Capture<'a> {
+    samples: TimestampedIterator<'a>,
+    stop: StopAfter,
+    started: Instant,
+    count: u64,
+    finished: bool,
+}
+
+impl Capture<'_> {
+    /// Samples returned so far
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Ends the session now, the same way reaching the limit would
+    pub fn stop(&mut self) -> Result<(), RWError> {
+        if self.finished {
+            return Ok(());
+        }
+        self.finished = true;
+        self.samples.device.stop_continuous_mode_live()
+    }
+}
+
+impl Iterator for Capture<'_> {
+    type Item = Result<TimestampedData, ReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        let elapsed = self
+            .samples
+            .device
+            .clock
+            .now()
+            .saturating_duration_since(self.started);
+        if self.stop.reached(elapsed, self.count) {
+            if let Err(e) = self.stop() {
+                log::warn!("Couldn't stop continuous mode at the end of a capture: {}", e);
+            }
+            return None;
+        }
 
-            Some(Ok(data))
-        } else {
-            let _ = self.0.end_frame(expected_size);
-            Some(Err(ReadError::ParseError(
-                "Unexpected response type".to_string(),
-            )))
+        let sample = self.samples.next();
+        match sample {
+            Some(_) => self.count += 1,
+            None => self.finished = true,
         }
+        sample
     }
 }
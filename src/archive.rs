@@ -0,0 +1,217 @@
+use crate::acquisition::AcqParams;
+use crate::config::{Baud, ConfigID, ConfigPair, MountingRef};
+use crate::{Device, RWError};
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+const ARCHIVE_HEADER: &str = "# pni-sdk device archive v1";
+
+/// Settings that are archived. BigEndian is left out because this library requires it to be
+/// true, and BaudRate because changing it would drop the connection mid-restore
+const ARCHIVED_CONFIG: &[ConfigID] = &[
+    ConfigID::Declination,
+    ConfigID::TrueNorth,
+    ConfigID::MountingRef,
+    ConfigID::UserCalNumPoints,
+    ConfigID::UserCalAutoSampling,
+    ConfigID::MilOut,
+    ConfigID::HPRDuringCal,
+    ConfigID::MagCoeffSet,
+    ConfigID::AccelCoeffSet,
+];
+
+/// Everything about a device's setup that can be read back over the protocol, for cloning onto
+/// replacement units.
+///
+/// The TP3 protocol has no command to read calibration coefficients (see
+/// [crate::capabilities::Feature::CoefficientExport]), so the archive records which coefficient
+/// sets are selected but not their contents. A replacement unit still needs its own user
+/// calibration, but everything else can be restored with [Device::restore_archive]. Keep
+/// archives on the host with [DeviceArchive::save] and [DeviceArchive::load]
+#[derive(Debug)]
+pub struct DeviceArchive {
+    /// Device type of the unit the archive was taken from
+    pub device_type: String,
+
+    /// Firmware revision of the unit the archive was taken from
+    pub revision: String,
+
+    /// Serial number of the unit the archive was taken from
+    pub serial_number: u32,
+
+    pub config: Vec<ConfigPair>,
+
+    pub acq_params: AcqParams,
+
+    pub fir_taps: Vec<f64>,
+}
+
+impl DeviceArchive {
+    /// Writes the archive as `name=value` lines, one setting per line
+    pub fn write_to(&self, mut out: impl Write) -> io::Result<()> {
+        writeln!(out, "{}", ARCHIVE_HEADER)?;
+        writeln!(out, "device_type={}", self.device_type)?;
+        writeln!(out, "revision={}", self.revision)?;
+        writeln!(out, "serial_number={}", self.serial_number)?;
+        for pair in &self.config {
+            let (name, value) = match pair {
+                ConfigPair::Declination(v) => ("Declination", v.to_string()),
+                ConfigPair::TrueNorth(v) => ("TrueNorth", v.to_string()),
+                ConfigPair::BigEndian(v) => ("BigEndian", v.to_string()),
+                ConfigPair::MountingRef(v) => ("MountingRef", u8::from(*v).to_string()),
+                ConfigPair::UserCalNumPoints(v) => ("UserCalNumPoints", v.to_string()),
+                ConfigPair::UserCalAutoSampling(v) => ("UserCalAutoSampling", v.to_string()),
+                ConfigPair::BaudRate(v) => ("BaudRate", u8::from(*v).to_string()),
+                ConfigPair::MilOut(v) => ("MilOut", v.to_string()),
+                ConfigPair::HPRDuringCal(v) => ("HPRDuringCal", v.to_string()),
+                ConfigPair::MagCoeffSet(v) => ("MagCoeffSet", v.to_string()),
+                ConfigPair::AccelCoeffSet(v) => ("AccelCoeffSet", v.to_string()),
+            };
+            writeln!(out, "{}={}", name, value)?;
+        }
+        writeln!(out, "acquisition_mode={}", self.acq_params.acquisition_mode)?;
+        writeln!(out, "flush_filter={}", self.acq_params.flush_filter)?;
+        writeln!(out, "sample_delay={}", self.acq_params.sample_delay)?;
+        let taps: Vec<String> = self.fir_taps.iter().map(|t| t.to_string()).collect();
+        writeln!(out, "fir_taps={}", taps.join(","))?;
+        Ok(())
+    }
+
+    /// Writes the archive to a file, replacing any file already there
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.write_to(&mut out)?;
+        out.flush()
+    }
+
+    /// Reads an archive file written by [DeviceArchive::save]
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::read_from(BufReader::new(File::open(path)?))
+    }
+
+    /// Reads an archive written by [DeviceArchive::write_to]
+    pub fn read_from(input: impl BufRead) -> io::Result<Self> {
+        let mut archive = DeviceArchive {
+            device_type: String::new(),
+            revision: String::new(),
+            serial_number: 0,
+            config: Vec::new(),
+            acq_params: AcqParams {
+                acquisition_mode: true,
+                flush_filter: false,
+                sample_delay: 0.0,
+            },
+            fir_taps: Vec::new(),
+        };
+
+        let mut lines = input.lines();
+        if lines.next().transpose()?.as_deref() != Some(ARCHIVE_HEADER) {
+            return Err(invalid("not a device archive".to_string()));
+        }
+
+        for line in lines {
+            let line = line?;
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, value) = line
+                .split_once('=')
+                .ok_or_else(|| invalid(format!("expected name=value, got {:?}", line)))?;
+            match name {
+                "device_type" => archive.device_type = value.to_string(),
+                "revision" => archive.revision = value.to_string(),
+                "serial_number" => archive.serial_number = parse(name, value)?,
+                "acquisition_mode" => archive.acq_params.acquisition_mode = parse(name, value)?,
+                "flush_filter" => archive.acq_params.flush_filter = parse(name, value)?,
+                "sample_delay" => archive.acq_params.sample_delay = parse(name, value)?,
+                "fir_taps" => {
+                    archive.fir_taps = value
+                        .split(',')
+                        .filter(|tap| !tap.is_empty())
+                        .map(|tap| parse(name, tap))
+                        .collect::<io::Result<_>>()?
+                }
+                _ => archive.config.push(parse_config(name, value)?),
+            }
+        }
+
+        Ok(archive)
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn parse<T: std::str::FromStr>(name: &str, value: &str) -> io::Result<T> {
+    value
+        .parse()
+        .map_err(|_| invalid(format!("invalid value for {}: {:?}", name, value)))
+}
+
+fn parse_config(name: &str, value: &str) -> io::Result<ConfigPair> {
+    Ok(match name {
+        "Declination" => ConfigPair::Declination(parse(name, value)?),
+        "TrueNorth" => ConfigPair::TrueNorth(parse(name, value)?),
+        "BigEndian" => ConfigPair::BigEndian(parse(name, value)?),
+        "MountingRef" => ConfigPair::MountingRef(
+            MountingRef::try_from(parse::<u8>(name, value)?)
+                .map_err(|e| invalid(e.to_string()))?,
+        ),
+        "UserCalNumPoints" => ConfigPair::UserCalNumPoints(parse(name, value)?),
+        "UserCalAutoSampling" => ConfigPair::UserCalAutoSampling(parse(name, value)?),
+        "BaudRate" => ConfigPair::BaudRate(
+            Baud::try_from(parse::<u8>(name, value)?).map_err(|e| invalid(e.to_string()))?,
+        ),
+        "MilOut" => ConfigPair::MilOut(parse(name, value)?),
+        "HPRDuringCal" => ConfigPair::HPRDuringCal(parse(name, value)?),
+        "MagCoeffSet" => ConfigPair::MagCoeffSet(parse(name, value)?),
+        "AccelCoeffSet" => ConfigPair::AccelCoeffSet(parse(name, value)?),
+        _ => return Err(invalid(format!("unknown setting {:?}", name))),
+    })
+}
+
+impl Device {
+    /// Reads every archivable setting from the device. See [DeviceArchive] for what is and isn't
+    /// included
+    pub fn export_archive(&mut self) -> Result<DeviceArchive, RWError> {
+        let mod_info = self.get_mod_info()?;
+        let serial_number = self.serial_number()?;
+        let config = ARCHIVED_CONFIG
+            .iter()
+            .map(|id| self.get_config(*id))
+            .collect::<Result<_, _>>()?;
+        let acq_params = self.get_acq_params()?;
+        let fir_taps = self.get_fir_filters()?;
+
+        Ok(DeviceArchive {
+            device_type: mod_info.device_type,
+            revision: mod_info.revision,
+            serial_number,
+            config,
+            acq_params,
+            fir_taps,
+        })
+    }
+
+    /// Applies an archive taken from another (or the same) unit and saves it to non-volatile
+    /// memory. The archive's serial number is informational only, so restoring onto a different
+    /// unit is expected. BigEndian and BaudRate lines in the archive are skipped, for the reasons
+    /// they aren't exported
+    pub fn restore_archive(&mut self, archive: &DeviceArchive) -> Result<(), RWError> {
+        for pair in archive.config.iter().filter(|pair| ARCHIVED_CONFIG.contains(&pair.id())) {
+            self.set_config(pair.clone())?;
+        }
+        self.set_acq_params(AcqParams {
+            acquisition_mode: archive.acq_params.acquisition_mode,
+            flush_filter: archive.acq_params.flush_filter,
+            sample_delay: archive.acq_params.sample_delay,
+        })?;
+        if !archive.fir_taps.is_empty() {
+            self.set_fir_filters(archive.fir_taps.clone())?;
+        }
+        self.save()
+    }
+}
@@ -0,0 +1,152 @@
+// TCM-style ASCII output, as sent by older PNI compasses and some data logger bridges: one sentence
+// per line, e.g. `$C328.3P-2.5R0.3X27.23Y-11.60Z-43.81T22.5E000*3F`. Each field is a letter then
+// a number; the optional `*hh` trailer is the XOR of every byte between `$` and `*`, in hex.
+
+use crate::acquisition::Data;
+use crate::catalog::{Message, MessageId};
+use crate::{Device, ReadError};
+
+use std::io::{BufRead, ErrorKind};
+use std::time::Instant;
+
+/// Longest line read from the port before it's treated as garbage rather than a sentence
+const MAX_SENTENCE_LEN: usize = 256;
+
+fn malformed(sentence: &str) -> ReadError {
+    ReadError::ParseError(Message::new(MessageId::MalformedSentence).with("sentence", sentence))
+}
+
+/// Parses one sentence, with or without the trailing line ending, into the fields it carries:
+///
+/// * `C` heading, `P` pitch, `R` roll, in degrees
+/// * `X`, `Y`, `Z` magnetometer readings, in µT
+/// * `T` temperature, in °C
+/// * `E` error code, in hex. A nonzero code is reported as [Data::distortion]
+///
+/// Unknown field letters are skipped, so units that add fields still parse. A checksum, if
+/// present, must match
+pub fn parse_sentence(sentence: &str) -> Result<Data, ReadError> {
+    let line = sentence.trim_end_matches(['\r', '\n']);
+    let body = line.strip_prefix('$').ok_or_else(|| malformed(line))?;
+    let body = match body.split_once('*') {
+        Some((body, checksum)) => {
+            let actual = u8::from_str_radix(checksum, 16).map_err(|_| malformed(line))?;
+            let expected = body.bytes().fold(0u8, |sum, byte| sum ^ byte);
+            if expected != actual {
+                return Err(ReadError::ChecksumMismatch {
+                    expected: expected.into(),
+                    actual: actual.into(),
+                });
+            }
+            body
+        }
+        None => body,
+    };
+
+    let mut data = Data::default();
+    let mut fields = body.char_indices().filter(|(_, c)| c.is_ascii_alphabetic()).peekable();
+    while let Some((start, letter)) = fields.next() {
+        let end = fields.peek().map_or(body.len(), |(next, _)| *next);
+        let value = &body[start + 1..end];
+        if letter == 'E' {
+            let code = u16::from_str_radix(value, 16).map_err(|_| malformed(line))?;
+            data.distortion = Some(code != 0);
+            continue;
+        }
+        let number = || value.parse::<f32>().map_err(|_| malformed(line));
+        match letter {
+            'C' => data.heading = Some(number()?),
+            'P' => data.pitch = Some(number()?),
+            'R' => data.roll = Some(number()?),
+            'X' => data.mag_x = Some(number()?),
+            'Y' => data.mag_y = Some(number()?),
+            'Z' => data.mag_z = Some(number()?),
+            'T' => data.temperature = Some(number()?),
+            _ => log::trace!("Skipped unknown field {} in ASCII sentence", letter),
+        }
+    }
+    if data == Data::default() {
+        return Err(malformed(line));
+    }
+    Ok(data)
+}
+
+/// Samples from a stream of ASCII sentences, e.g. a log file or a socket from a bridge. Lines
+/// that aren't sentences, such as banners, are skipped. See [parse_sentence]
+pub struct Sentences<R> {
+    reader: R,
+    line: String,
+}
+
+impl<R: BufRead> Sentences<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            line: String::new(),
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for Sentences<R> {
+    type Item = Result<Data, ReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.line.clear();
+            match self.reader.read_line(&mut self.line) {
+                Ok(0) => return None,
+                Ok(_) => {}
+                Err(e) => return Some(Err(e.into())),
+            }
+            if let Some(start) = self.line.find('$') {
+                return Some(parse_sentence(&self.line[start..]));
+            }
+        }
+    }
+}
+
+impl Device {
+    /// Reads the next sentence from a device opened with [crate::connection::Protocol::Ascii].
+    /// Returns [None] if the port times out, same as for binary frames
+    pub(crate) fn next_ascii_sentence(&mut self) -> Option<Result<(Data, Instant), ReadError>> {
+        let mut line = Vec::new();
+        let mut started = None;
+        let mut byte = [0u8];
+        loop {
+            match self.port_read_exact(&mut byte) {
+                Ok(()) => {}
+                Err(e) if e.kind() == ErrorKind::TimedOut => return None,
+                Err(e) => {
+                    self.note_port_error(&e);
+                    return Some(Err(e.into()));
+                }
+            }
+            match byte[0] {
+                b'$' => {
+                    line.clear();
+                    line.push(b'$');
+                    started = Some(self.clock.now());
+                }
+                b'\n' | b'\r' if !line.is_empty() => break,
+                _ if line.is_empty() => {}
+                _ if line.len() >= MAX_SENTENCE_LEN => {
+                    line.clear();
+                    self.stats.resyncs += 1;
+                }
+                other => line.push(other),
+            }
+        }
+
+        let result = match std::str::from_utf8(&line) {
+            Ok(sentence) => parse_sentence(sentence),
+            Err(_) => Err(malformed(&String::from_utf8_lossy(&line))),
+        };
+        match &result {
+            Ok(_) => self.stats.frames_ok += 1,
+            Err(ReadError::ChecksumMismatch { .. }) => self.stats.checksum_errors += 1,
+            Err(_) => self.stats.size_errors += 1,
+        }
+        let received_at = started.unwrap_or_else(Instant::now);
+        Some(result.map(|data| (data, received_at)))
+    }
+}
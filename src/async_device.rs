@@ -0,0 +1,341 @@
+// Device's command set on tokio, for async stacks that can't spare a thread for a blocking port.
+// Frames are encoded, checked and parsed by the same codec and response parsers as Device, so the
+// two only differ in how bytes get to and from the port.
+
+use crate::acquisition::{AcqParams, AcqParamsReserved, Data, DataID};
+use crate::catalog::{Message, MessageId};
+use crate::codec::{self, FRAME_OVERHEAD, MAX_FRAME_LEN};
+use crate::command::Command;
+use crate::config::{read_setting, ConfigID, ConfigPair};
+use crate::dispatch::{parse_frame, Response};
+use crate::responses::ModInfoResp;
+use crate::{RWError, ReadError, WriteError};
+
+use std::io;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio_serial::{SerialPortBuilderExt, SerialStream};
+
+/// Frames of other types a request reads past before giving up on its response, as on [Device]
+///
+/// [Device]: crate::Device
+const MAX_INTERLEAVED_FRAMES: usize = 8;
+
+/// A device on an async byte stream: a `tokio-serial` port from [AsyncDevice::open], or anything
+/// else that reads and writes asynchronously, such as a TCP connection to a serial server.
+///
+/// Mirrors the everyday commands of [crate::Device]. Frames that arrive while a request waits
+/// for its response are skipped rather than queued
+pub struct AsyncDevice<S = SerialStream> {
+    stream: S,
+    timeout: Duration,
+    streaming: bool,
+
+    /// Bytes read towards the next frame, kept when a read times out part way through so the
+    /// next one carries on from them
+    partial: Vec<u8>,
+
+    /// Whether `partial` may not start on a frame boundary, after a bad length or checksum, so
+    /// the next frame has to be searched for
+    resyncing: bool,
+}
+
+impl AsyncDevice<SerialStream> {
+    /// Opens a serial port for the device. Must be called from within a tokio runtime
+    pub fn open(port: &str, baud: u32) -> Result<Self, tokio_serial::Error> {
+        Ok(Self::new(tokio_serial::new(port, baud).open_native_async()?))
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncDevice<S> {
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream,
+            timeout: Duration::from_secs(1),
+            streaming: false,
+            partial: Vec::new(),
+            resyncing: false,
+        }
+    }
+
+    /// How long a read waits for the device before failing with [io::ErrorKind::TimedOut].
+    /// One second by default
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Gives back the stream
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    /// Sends the given command and payload to the device, with appropriate CRC and sizing
+    pub async fn write_frame(
+        &mut self,
+        command: Command,
+        payload: Option<&[u8]>,
+    ) -> Result<(), WriteError> {
+        let payload = payload.unwrap_or_default();
+        let mut frame = vec![0u8; payload.len() + FRAME_OVERHEAD];
+        codec::encode_frame(u8::from(command), payload, &mut frame)?;
+        self.stream.write_all(&frame).await?;
+        self.stream.flush().await?;
+        Ok(())
+    }
+
+    /// Reads one whole frame, checking its length and checksum. A read that times out part way
+    /// through a frame keeps what arrived, and the next read finishes the frame. After a frame
+    /// with a bad length or checksum, the next read skips ahead to the next valid frame
+    pub async fn read_frame(&mut self) -> Result<Vec<u8>, ReadError> {
+        match tokio::time::timeout(self.timeout, self.read_frame_untimed()).await {
+            Ok(frame) => frame,
+            Err(_) => Err(ReadError::PipeError(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "device didn't answer in time",
+            ))),
+        }
+    }
+
+    // cancel safe: the only await is a single read, and its bytes go straight into `partial`
+    async fn read_frame_untimed(&mut self) -> Result<Vec<u8>, ReadError> {
+        let mut buf = [0u8; 256];
+        loop {
+            if let Some(frame) = self.take_frame()? {
+                return Ok(frame);
+            }
+            let read = self.stream.read(&mut buf).await?;
+            if read == 0 {
+                return Err(ReadError::PipeError(io::ErrorKind::UnexpectedEof.into()));
+            }
+            self.partial.extend_from_slice(&buf[..read]);
+        }
+    }
+
+    /// Takes the next frame off `partial` if it's all there
+    fn take_frame(&mut self) -> Result<Option<Vec<u8>>, ReadError> {
+        if self.resyncing && !self.resync() {
+            return Ok(None);
+        }
+        let Some(header) = self.partial.get(..2) else {
+            return Ok(None);
+        };
+        let len = u16::from_be_bytes([header[0], header[1]]) as usize;
+        if !(FRAME_OVERHEAD..=MAX_FRAME_LEN).contains(&len) {
+            self.resyncing = true;
+            return Err(codec::decode_frame(header).map(drop).unwrap_err().into());
+        }
+        if self.partial.len() < len {
+            return Ok(None);
+        }
+        let frame: Vec<u8> = self.partial.drain(..len).collect();
+        if let Err(e) = codec::decode_frame(&frame) {
+            // a corrupt length can pass the range check, so don't trust where the next frame starts
+            self.resyncing = true;
+            return Err(e.into());
+        }
+        Ok(Some(frame))
+    }
+
+    /// Drops bytes from the front of `partial` until it starts with a frame of valid length and
+    /// checksum, as [crate::Device::sync_to_frame_boundary] does. Returns whether one was found;
+    /// if not, the bytes from the first position that might still begin one are kept for the
+    /// next read
+    fn resync(&mut self) -> bool {
+        // a stray byte can read as the length of a frame longer than anything that follows, so
+        // keep looking past candidates that aren't all there yet
+        let mut first_incomplete = None;
+        let mut start = 0;
+        while start + 2 <= self.partial.len() {
+            let len = u16::from_be_bytes([self.partial[start], self.partial[start + 1]]) as usize;
+            if !(FRAME_OVERHEAD..=MAX_FRAME_LEN).contains(&len) {
+                start += 1;
+                continue;
+            }
+            if self.partial.len() - start < len {
+                first_incomplete.get_or_insert(start);
+                start += 1;
+                continue;
+            }
+            if codec::decode_frame(&self.partial[start..start + len]).is_ok() {
+                log::debug!("Skipped {} byte(s) to find the next frame", start);
+                self.partial.drain(..start);
+                self.resyncing = false;
+                return true;
+            }
+            start += 1;
+        }
+        self.partial.drain(..first_incomplete.unwrap_or(start));
+        false
+    }
+
+    /// Reads the next frame, whatever it is. In continuous mode that's usually a sample
+    pub async fn read_response(&mut self) -> Result<Response, ReadError> {
+        let frame = self.read_frame().await?;
+        Ok(parse_frame(&frame)?.0)
+    }
+
+    /// Sends `command` and returns the whole frame of the response it gets
+    async fn request(
+        &mut self,
+        command: Command,
+        payload: Option<&[u8]>,
+    ) -> Result<Vec<u8>, RWError> {
+        if self.streaming && !command.allowed_while_streaming() {
+            return Err(RWError::Streaming { command });
+        }
+        let response = command
+            .response()
+            .expect("request() is only used for commands with a single response");
+        self.write_frame(command, payload).await?;
+        for _ in 0..=MAX_INTERLEAVED_FRAMES {
+            let frame = self.read_frame().await?;
+            if frame[2] == u8::from(response) {
+                return Ok(frame);
+            }
+            log::debug!("Skipped frame {:#04x} while waiting for {:?}", frame[2], response);
+        }
+        Err(RWError::ReadError(ReadError::ParseError(
+            Message::new(MessageId::ResponseNotReceived)
+                .with("response", format!("{:?}", response))
+                .with("frames", MAX_INTERLEAVED_FRAMES + 1),
+        )))
+    }
+
+    /// Sends `command` and parses its response with the same parsers as [crate::Device]
+    async fn request_parsed(
+        &mut self,
+        command: Command,
+        payload: Option<&[u8]>,
+    ) -> Result<Response, RWError> {
+        let frame = self.request(command, payload).await?;
+        Ok(parse_frame(&frame)?.0)
+    }
+
+    /// Returns device type and revision. See [crate::Device::get_mod_info]
+    pub async fn get_mod_info(&mut self) -> Result<ModInfoResp, RWError> {
+        match self.request_parsed(Command::GetModInfo, None).await? {
+            Response::ModInfo(info) => Ok(info),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    /// Returns device serial number, which can also be found on the front sticker
+    pub async fn serial_number(&mut self) -> Result<u32, RWError> {
+        match self.request_parsed(Command::SerialNumber, None).await? {
+            Response::SerialNumber(serial_number) => Ok(serial_number),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    /// Selects the components GetData and continuous mode return. See
+    /// [crate::Device::set_data_components]
+    pub async fn set_data_components(&mut self, components: Vec<DataID>) -> Result<(), RWError> {
+        let mut payload = vec![components.len() as u8];
+        payload.extend(components.iter().map(|component| *component as u8));
+        if self.streaming {
+            return Err(RWError::Streaming {
+                command: Command::SetDataComponents,
+            });
+        }
+        self.write_frame(Command::SetDataComponents, Some(&payload)).await?;
+        Ok(())
+    }
+
+    /// Requests a single sample, in polled mode
+    pub async fn get_data(&mut self) -> Result<Data, RWError> {
+        match self.request_parsed(Command::GetData, None).await? {
+            Response::Data(data) => Ok(data),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    /// Sets a configuration value, without saving it. See [crate::Device::set_config]
+    pub async fn set_config(&mut self, config: ConfigPair) -> Result<(), RWError> {
+        let payload = Vec::<u8>::from(config);
+        self.request(Command::SetConfig, Some(&payload)).await?;
+        Ok(())
+    }
+
+    /// Reads a configuration value. See [crate::Device::get_config]
+    pub async fn get_config(&mut self, id: ConfigID) -> Result<ConfigPair, RWError> {
+        let frame = self.request(Command::GetConfig, Some(&[u8::from(id)])).await?;
+        let mut cursor = &frame[3..frame.len() - 2];
+        let setting = read_setting(&mut cursor, id)?;
+        if !cursor.is_empty() {
+            return Err(ReadError::SizeMismatch {
+                expected: (frame.len() - cursor.len()) as u16,
+                actual: frame.len() as u16,
+            }
+            .into());
+        }
+        Ok(setting)
+    }
+
+    /// Saves configuration and user calibration to non-volatile memory. See
+    /// [crate::Device::save]
+    pub async fn save(&mut self) -> Result<(), RWError> {
+        match self.request_parsed(Command::Save, None).await? {
+            Response::SaveDone { error_code: 0 } => Ok(()),
+            Response::SaveDone { error_code } => Err(RWError::DeviceError(
+                Message::new(MessageId::SaveFailed).with("code", error_code),
+            )),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    pub async fn get_acq_params(&mut self) -> Result<AcqParams, RWError> {
+        match self.request_parsed(Command::GetAcqParams, None).await? {
+            Response::AcqParams(params) => Ok(params),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    pub async fn set_acq_params(&mut self, params: AcqParams) -> Result<(), RWError> {
+        let payload = AcqParamsReserved::from(params).to_payload();
+        self.request(Command::SetAcqParams, Some(&payload)).await?;
+        Ok(())
+    }
+
+    /// Starts streaming samples, read with [AsyncDevice::next_data]. Set acquisition parameters
+    /// and data components first. Until [AsyncDevice::stop_continuous_mode], other commands
+    /// return [RWError::Streaming]
+    pub async fn start_continuous_mode(&mut self) -> Result<(), RWError> {
+        self.write_frame(Command::StartContinuousMode, None).await?;
+        self.streaming = true;
+        Ok(())
+    }
+
+    /// Stops streaming, then discards samples that were already in flight so the next
+    /// response is read from a frame boundary
+    pub async fn stop_continuous_mode(&mut self) -> Result<(), RWError> {
+        self.write_frame(Command::StopContinuousMode, None).await?;
+        self.streaming = false;
+        let timeout = std::mem::replace(&mut self.timeout, Duration::from_millis(250));
+        let drained = loop {
+            match self.read_frame().await {
+                Ok(_) => continue,
+                Err(ReadError::PipeError(e)) if e.kind() == io::ErrorKind::TimedOut => break Ok(()),
+                Err(e) => break Err(e),
+            }
+        };
+        self.timeout = timeout;
+        Ok(drained?)
+    }
+
+    /// Waits for the next sample in continuous mode, skipping any other frames
+    pub async fn next_data(&mut self) -> Result<Data, ReadError> {
+        loop {
+            if let Response::Data(data) = self.read_response().await? {
+                return Ok(data);
+            }
+        }
+    }
+}
+
+/// A response parsed as something other than its command byte promised, which the parsers
+/// don't produce
+fn unexpected(response: Response) -> RWError {
+    RWError::ReadError(ReadError::ParseError(
+        Message::new(MessageId::UnexpectedResponse).with("command", format!("{:?}", response)),
+    ))
+}
@@ -0,0 +1,341 @@
+// `pni-monitor [PORT] [--duration SECONDS]` polls a device and shows its output live in the
+// terminal: a heading dial, pitch/roll, accelerometer bars, accuracy and link error counters.
+// Press q or Esc to quit.
+//
+// It doubles as a hardware acceptance test on the production line: with `--duration` it quits on
+// its own and exits nonzero if the unit failed any check (no samples, read errors, a noisy link,
+// unreliable output or an accelerometer that doesn't read 1 g at rest). Only volatile settings
+// are changed; nothing is saved to the device.
+
+use pni_sdk::acquisition::{AcqParams, Data, DataID, DataQuality};
+use pni_sdk::capabilities::{Feature, SupportLevel};
+use pni_sdk::frame::LinkStats;
+use pni_sdk::Device;
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Style};
+use ratatui::text::Line;
+use ratatui::widgets::canvas::{Canvas, Circle, Line as CanvasLine};
+use ratatui::widgets::{Block, Gauge, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+const PORT_TIMEOUT: Duration = Duration::from_millis(250);
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Accelerometer bars span ±this many g
+const ACCEL_RANGE: f32 = 2.0;
+
+/// A unit at rest must read 1 g within this
+const GRAVITY_TOLERANCE: f32 = 0.1;
+
+/// Checksum errors above this fraction of frames fail the acceptance test
+const CRC_ERROR_THRESHOLD: f64 = 0.01;
+
+#[derive(Default)]
+struct Monitor {
+    identity: String,
+    latest: Option<Data>,
+    samples: u64,
+    read_errors: u64,
+    last_error: Option<String>,
+    stats: LinkStats,
+    started: Option<Instant>,
+}
+
+impl Monitor {
+    fn rate_hz(&self) -> f64 {
+        match self.started {
+            Some(started) => self.samples as f64 / started.elapsed().as_secs_f64().max(1e-3),
+            None => 0.0,
+        }
+    }
+
+    fn accel_magnitude(&self) -> Option<f32> {
+        let data = self.latest.as_ref()?;
+        let (x, y, z) = (data.accel_x?, data.accel_y?, data.accel_z?);
+        Some((x * x + y * y + z * z).sqrt())
+    }
+
+    /// Acceptance checks, as (description, passed)
+    fn checks(&self) -> Vec<(String, bool)> {
+        let quality = self.latest.as_ref().map(Data::quality);
+        let magnitude = self.accel_magnitude();
+        vec![
+            (format!("{} samples received", self.samples), self.samples > 0),
+            (format!("{} read errors", self.read_errors), self.read_errors == 0),
+            (
+                format!("{:.2}% checksum errors", self.stats.checksum_error_rate() * 100.0),
+                self.stats.checksum_error_rate() <= CRC_ERROR_THRESHOLD,
+            ),
+            (
+                format!("output quality {}", quality.map_or("unknown".into(), |q| q.to_string())),
+                quality.is_some_and(|q| q != DataQuality::Unreliable),
+            ),
+            (
+                format!("accel magnitude {:.3} g", magnitude.unwrap_or(f32::NAN)),
+                magnitude.is_some_and(|g| (g - 1.0).abs() <= GRAVITY_TOLERANCE),
+            ),
+        ]
+    }
+}
+
+fn main() -> ExitCode {
+    let mut port = None;
+    let mut duration = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--duration" => match args.next().and_then(|secs| secs.parse::<f64>().ok()) {
+                Some(secs) => duration = Some(Duration::from_secs_f64(secs)),
+                None => return usage(),
+            },
+            _ if arg.starts_with('-') => return usage(),
+            _ => port = Some(arg),
+        }
+    }
+
+    let mut monitor = Monitor::default();
+    let mut device = match open(port, &mut monitor) {
+        Ok(device) => device,
+        Err(e) => {
+            eprintln!("couldn't set up the device: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut terminal = ratatui::init();
+    let result = run(&mut terminal, &mut device, &mut monitor, duration);
+    ratatui::restore();
+    if let Err(e) = result {
+        eprintln!("terminal error: {}", e);
+        return ExitCode::FAILURE;
+    }
+
+    println!("{}", monitor.identity);
+    let mut failed = false;
+    for (check, passed) in monitor.checks() {
+        println!("  {}  {}", if passed { "ok  " } else { "FAIL" }, check);
+        failed |= !passed;
+    }
+    if let Some(error) = &monitor.last_error {
+        println!("  last error: {}", error);
+    }
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn usage() -> ExitCode {
+    eprintln!("usage: pni-monitor [PORT] [--duration SECONDS]");
+    ExitCode::from(2)
+}
+
+/// Connects, identifies the device and sets up polling of the components it supports
+fn open(port: Option<String>, monitor: &mut Monitor) -> Result<Device, Box<dyn std::error::Error>> {
+    let mut builder = Device::builder().timeout(PORT_TIMEOUT);
+    if let Some(port) = port {
+        builder = builder.port(port);
+    }
+    let mut device = builder.open()?;
+    let info = device.get_mod_info()?;
+    monitor.identity = format!(
+        "{} {} (serial {})",
+        info.device_type.trim(),
+        info.revision.trim(),
+        device.serial_number()?
+    );
+
+    device.set_acq_params(AcqParams {
+        acquisition_mode: true,
+        flush_filter: false,
+        sample_delay: 0.0,
+    })?;
+    let mut components = vec![
+        DataID::Heading,
+        DataID::Pitch,
+        DataID::Roll,
+        DataID::Temperature,
+        DataID::Distortion,
+        DataID::CalStatus,
+        DataID::AccelX,
+        DataID::AccelY,
+        DataID::AccelZ,
+    ];
+    if device.supports(Feature::MagAccuracy) == SupportLevel::KnownYes {
+        components.push(DataID::MagAccuracy);
+    }
+    device.set_data_components(components)?;
+    Ok(device)
+}
+
+fn run(
+    terminal: &mut DefaultTerminal,
+    device: &mut Device,
+    monitor: &mut Monitor,
+    duration: Option<Duration>,
+) -> std::io::Result<()> {
+    let started = Instant::now();
+    monitor.started = Some(started);
+    loop {
+        match device.get_data() {
+            Ok(data) => {
+                monitor.samples += 1;
+                monitor.latest = Some(data);
+            }
+            Err(e) => {
+                monitor.read_errors += 1;
+                monitor.last_error = Some(e.to_string());
+            }
+        }
+        monitor.stats = device.stats();
+        terminal.draw(|frame| draw(frame, monitor))?;
+
+        if duration.is_some_and(|duration| started.elapsed() >= duration) {
+            return Ok(());
+        }
+        if event::poll(POLL_INTERVAL)? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press
+                    && matches!(key.code, KeyCode::Char('q') | KeyCode::Esc)
+                {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+fn draw(frame: &mut Frame, monitor: &Monitor) {
+    let [title, body, footer] = Layout::vertical([
+        Constraint::Length(1),
+        Constraint::Min(10),
+        Constraint::Length(7),
+    ])
+    .areas(frame.area());
+    frame.render_widget(
+        Paragraph::new(format!("{}   q to quit", monitor.identity)),
+        title,
+    );
+
+    let [dial, readings] =
+        Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(body);
+    draw_dial(frame, dial, monitor.latest.as_ref().and_then(|data| data.heading));
+    draw_readings(frame, readings, monitor);
+    draw_link(frame, footer, monitor);
+}
+
+fn draw_dial(frame: &mut Frame, area: Rect, heading: Option<f32>) {
+    let title = match heading {
+        Some(heading) => format!("Heading {:.1}°", heading),
+        None => "Heading".to_owned(),
+    };
+    let canvas = Canvas::default()
+        .block(Block::bordered().title(title))
+        .x_bounds([-1.3, 1.3])
+        .y_bounds([-1.3, 1.3])
+        .paint(move |ctx| {
+            ctx.draw(&Circle {
+                x: 0.0,
+                y: 0.0,
+                radius: 1.0,
+                color: Color::Gray,
+            });
+            ctx.print(-0.03, 1.12, "N");
+            ctx.print(1.12, -0.03, "E");
+            ctx.print(-0.03, -1.18, "S");
+            ctx.print(-1.18, -0.03, "W");
+            if let Some(heading) = heading {
+                let radians = (heading as f64).to_radians();
+                ctx.draw(&CanvasLine {
+                    x1: 0.0,
+                    y1: 0.0,
+                    x2: 0.9 * radians.sin(),
+                    y2: 0.9 * radians.cos(),
+                    color: Color::Yellow,
+                });
+            }
+        });
+    frame.render_widget(canvas, area);
+}
+
+fn draw_readings(frame: &mut Frame, area: Rect, monitor: &Monitor) {
+    let rows = Layout::vertical([
+        Constraint::Length(3),
+        Constraint::Length(3),
+        Constraint::Length(3),
+        Constraint::Min(4),
+    ])
+    .split(area);
+    let data = monitor.latest.clone().unwrap_or_default();
+    for (row, (axis, value)) in rows
+        .iter()
+        .zip([("X", data.accel_x), ("Y", data.accel_y), ("Z", data.accel_z)])
+    {
+        let value = value.unwrap_or(0.0);
+        let ratio = ((value + ACCEL_RANGE) / (2.0 * ACCEL_RANGE)).clamp(0.0, 1.0);
+        let gauge = Gauge::default()
+            .block(Block::bordered().title(format!("Accel {}", axis)))
+            .gauge_style(Style::default().fg(Color::Cyan))
+            .ratio(ratio as f64)
+            .label(format!("{:+.3} g", value));
+        frame.render_widget(gauge, *row);
+    }
+
+    let show = |value: Option<f32>, unit: &str| match value {
+        Some(value) => format!("{:.2}{}", value, unit),
+        None => "-".to_owned(),
+    };
+    let quality = data.quality();
+    let color = match quality {
+        DataQuality::Good => Color::Green,
+        DataQuality::Degraded => Color::Yellow,
+        DataQuality::Unreliable => Color::Red,
+        DataQuality::Unknown => Color::Gray,
+    };
+    let lines = vec![
+        Line::from(format!(
+            "Pitch {}  Roll {}  Temp {}",
+            show(data.pitch, "°"),
+            show(data.roll, "°"),
+            show(data.temperature, "°C")
+        )),
+        Line::from(format!(
+            "Mag accuracy {}  Distortion {}  User cal {}",
+            show(data.mag_accuracy, "°"),
+            data.distortion.map_or("-".into(), |d| d.to_string()),
+            data.cal_status.map_or("-".into(), |c| c.to_string())
+        )),
+        Line::styled(format!("Quality {}", quality), Style::default().fg(color)),
+    ];
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::bordered().title("Orientation")),
+        rows[3],
+    );
+}
+
+fn draw_link(frame: &mut Frame, area: Rect, monitor: &Monitor) {
+    let stats = &monitor.stats;
+    let lines = vec![
+        Line::from(format!(
+            "Samples {}  Rate {:.1} Hz  Read errors {}",
+            monitor.samples,
+            monitor.rate_hz(),
+            monitor.read_errors
+        )),
+        Line::from(format!(
+            "Frames ok {}  Checksum errors {}  Size errors {}  Resyncs {}",
+            stats.frames_ok, stats.checksum_errors, stats.size_errors, stats.resyncs
+        )),
+        Line::from(format!(
+            "Last error: {}",
+            monitor.last_error.as_deref().unwrap_or("none")
+        )),
+    ];
+    frame.render_widget(Paragraph::new(lines).block(Block::bordered().title("Link")), area);
+}
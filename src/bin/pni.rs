@@ -0,0 +1,445 @@
+// `pni doctor [PORT]` runs the checks we'd otherwise walk users through by hand when a device
+// won't talk, and prints what looks wrong along with what to try next.
+//
+// `pni declination SOURCE LAT LON [PORT]` looks up the declination at a location during
+// provisioning and, given a port, sets it on the device. SOURCE is `manual:DEGREES`, `wmm:FILE`,
+// `igrf:FILE` or `online:API_KEY`, or several separated by commas to fall back in order.
+
+use pni_sdk::acquisition::DataID;
+use pni_sdk::capabilities::{Feature, SupportLevel};
+use pni_sdk::command::Command;
+use pni_sdk::config::{Baud, ConfigID, ConfigPair};
+use pni_sdk::{Device, RWError, ReadError};
+
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+const PROBE_TIMEOUT: Duration = Duration::from_millis(300);
+const SAMPLE_WINDOW: Duration = Duration::from_secs(2);
+
+/// Checksum errors above this fraction of frames suggest a noisy or marginal link
+const CRC_ERROR_THRESHOLD: f64 = 0.01;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("doctor") => doctor(args.get(1).cloned()),
+        #[cfg(feature = "unstable")]
+        Some("declination") if args.len() >= 4 => declination(&args[1..]),
+        #[cfg(feature = "unstable")]
+        Some("bridge") if args.len() >= 4 => bridge(&args[1..]),
+        _ => {
+            eprintln!("usage: pni doctor [PORT]");
+            #[cfg(feature = "unstable")]
+            eprintln!("       pni declination SOURCE LAT LON [PORT]");
+            #[cfg(feature = "unstable")]
+            eprintln!("       pni bridge tcp|udp ADDRESS raw|json [PORT]");
+            ExitCode::from(2)
+        }
+    }
+}
+
+#[derive(Default)]
+struct Report {
+    failures: usize,
+    warnings: usize,
+}
+
+impl Report {
+    fn ok(&mut self, message: impl AsRef<str>) {
+        println!("  ok    {}", message.as_ref());
+    }
+
+    fn warn(&mut self, message: impl AsRef<str>) {
+        self.warnings += 1;
+        println!("  WARN  {}", message.as_ref());
+    }
+
+    fn fail(&mut self, message: impl AsRef<str>) {
+        self.failures += 1;
+        println!("  FAIL  {}", message.as_ref());
+    }
+
+    fn section(&self, name: &str) {
+        println!("\n{}", name);
+    }
+}
+
+fn doctor(port: Option<String>) -> ExitCode {
+    let mut report = Report::default();
+
+    report.section("Port discovery");
+    let Some(port) = discover_port(&mut report, port) else {
+        return finish(report);
+    };
+
+    report.section("Baud probe");
+    let Some((mut device, streamed)) = probe_baud(&mut report, &port) else {
+        return finish(report);
+    };
+
+    if streamed > 0 {
+        // measure while it's streaming, then quiet it so the remaining checks get answers
+        report.section("Sample rate and link quality");
+        measure_stream(&mut report, &mut device);
+        if let Err(e) = device.stop_continuous_mode_live() {
+            report.fail(format!("couldn't stop continuous mode: {}", e));
+            return finish(report);
+        }
+    }
+
+    report.section("Identity");
+    check_identity(&mut report, &mut device);
+
+    report.section("Configuration");
+    check_config(&mut report, &mut device);
+
+    if streamed == 0 {
+        report.section("Sample rate and link quality");
+        measure_polling(&mut report, &mut device);
+    }
+
+    finish(report)
+}
+
+fn finish(report: Report) -> ExitCode {
+    println!(
+        "\n{} failure(s), {} warning(s)",
+        report.failures, report.warnings
+    );
+    if report.failures > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn discover_port(report: &mut Report, requested: Option<String>) -> Option<String> {
+    let ports = match serialport::available_ports() {
+        Ok(ports) => ports,
+        Err(e) => {
+            report.fail(format!("couldn't list serial ports: {}", e));
+            return None;
+        }
+    };
+    for port in &ports {
+        println!("        found {} ({:?})", port.port_name, port.port_type);
+    }
+
+    if let Some(port) = requested {
+        if !ports.iter().any(|p| p.port_name == port) {
+            report.warn(format!(
+                "{} isn't in the list of detected ports; trying it anyway",
+                port
+            ));
+        }
+        return Some(port);
+    }
+
+    match ports.iter().find(|p| p.port_name.contains("usb")) {
+        Some(port) => {
+            report.ok(format!("using {}", port.port_name));
+            Some(port.port_name.clone())
+        }
+        None if ports.is_empty() => {
+            report.fail("no serial ports found: check the cable and USB-serial driver");
+            None
+        }
+        None => {
+            report.fail(
+                "no USB serial port found to auto-select: pass the port name, e.g. `pni doctor /dev/ttyS0`",
+            );
+            None
+        }
+    }
+}
+
+/// Finds the baud rate the device answers or streams at with [Device::connect_probe]. Also
+/// returns the number of frames that arrived unprompted
+fn probe_baud(report: &mut Report, port: &str) -> Option<(Device, usize)> {
+    let mut device = match Device::connect_probe(Some(port.to_string())) {
+        Ok(device) => device,
+        Err(e) => {
+            report.fail(format!(
+                "no response at any baud rate ({}): check power and wiring (TX and RX swapped?), \
+                 and that nothing else has the port open",
+                e
+            ));
+            return None;
+        }
+    };
+
+    let default = Baud::B38400;
+    let baud = Baud::ALL
+        .into_iter()
+        .find(|baud| device.baud_rate() == Some(baud.bits_per_second()))
+        .unwrap_or(default);
+    if baud == default {
+        report.ok(format!("device answers at {} baud", baud.bits_per_second()));
+    } else {
+        report.warn(format!(
+            "baud mismatch suspected: device answers at {} baud, not the {} that \
+             Device::connect uses. Connect with Device::connect_probe or \
+             Device::builder().baud(Baud::{:?}), or set ConfigPair::BaudRate back to {}",
+            baud.bits_per_second(),
+            default.bits_per_second(),
+            baud,
+            default
+        ));
+    }
+
+    // a streaming device keeps sending after the probe found it
+    let streamed = device.drain_frames(PROBE_TIMEOUT).unwrap_or(0);
+    if streamed > 0 {
+        report.warn(format!(
+            "device stuck in continuous mode: {} frames arrived without being asked for. \
+             It will be stopped (without saving) for the remaining checks; call \
+             Device::stop_continuous_mode_easy to stop it for good",
+            streamed
+        ));
+    }
+    Some((device, streamed))
+}
+
+fn check_identity(report: &mut Report, device: &mut Device) {
+    match device.get_mod_info() {
+        Ok(info) => report.ok(format!("{}", info)),
+        Err(e) => report.fail(format!("GetModInfo failed: {}", e)),
+    }
+    if device.supports(Feature::PolledData) == SupportLevel::Unknown {
+        report.warn("device type isn't in this library's capability table; some features may not work");
+    }
+    match device.serial_number() {
+        Ok(serial) => report.ok(format!("serial number {}", serial)),
+        Err(e) => report.warn(format!("couldn't read serial number: {}", e)),
+    }
+}
+
+fn check_config(report: &mut Report, device: &mut Device) {
+    match device.get_config(ConfigID::BigEndian) {
+        Ok(ConfigPair::BigEndian(false)) => report.fail(
+            "device is set to little-endian, which this library can't parse: set ConfigPair::BigEndian(true) and save",
+        ),
+        Ok(_) => report.ok("big-endian output"),
+        Err(e) => report.fail(format!("couldn't read config: {}", e)),
+    }
+
+    let declination = device.get_config(ConfigID::Declination);
+    let true_north = device.get_config(ConfigID::TrueNorth);
+    if let (Ok(ConfigPair::Declination(d)), Ok(ConfigPair::TrueNorth(false))) =
+        (&declination, &true_north)
+    {
+        if *d != 0.0 {
+            report.warn(format!(
+                "declination is set to {}˚ but TrueNorth is off, so headings are magnetic",
+                d
+            ));
+        }
+    }
+
+    if let Ok(ConfigPair::MilOut(true)) = device.get_config(ConfigID::MilOut) {
+        report.warn("MilOut is on: angles are reported in mils, not degrees");
+    }
+
+    match device.get_acq_params() {
+        Ok(params) if !params.acquisition_mode => report.warn(format!(
+            "acquisition mode is continuous (sample delay {}s): GetData polls won't be answered",
+            params.sample_delay
+        )),
+        Ok(_) => report.ok("polled acquisition mode"),
+        Err(e) => report.warn(format!("couldn't read acquisition parameters: {}", e)),
+    }
+}
+
+/// Counts frames already streaming for [SAMPLE_WINDOW]
+fn measure_stream(report: &mut Report, device: &mut Device) {
+    let start = Instant::now();
+    let (mut frames, mut crc_errors) = (0usize, 0usize);
+    while start.elapsed() < SAMPLE_WINDOW {
+        match device.read_frame() {
+            Ok(frame) if frame.command() == u8::from(Command::GetDataResp) => frames += 1,
+            Ok(_) => {}
+            Err(ReadError::ChecksumMismatch { .. }) => {
+                crc_errors += 1;
+                let _ = device.sync_to_frame_boundary();
+            }
+            Err(ReadError::PipeError(e)) if e.kind() == std::io::ErrorKind::TimedOut => break,
+            Err(_) => {
+                let _ = device.sync_to_frame_boundary();
+            }
+        }
+    }
+    summarize(report, frames, crc_errors, start.elapsed());
+}
+
+/// Polls heading for [SAMPLE_WINDOW]. This changes the data components in volatile memory only
+fn measure_polling(report: &mut Report, device: &mut Device) {
+    if let Err(e) = device.set_data_components(vec![DataID::Heading]) {
+        report.fail(format!("couldn't set data components: {}", e));
+        return;
+    }
+
+    let start = Instant::now();
+    let (mut frames, mut crc_errors, mut other_errors) = (0usize, 0usize, 0usize);
+    while start.elapsed() < SAMPLE_WINDOW {
+        match device.get_data() {
+            Ok(_) => frames += 1,
+            Err(RWError::ReadError(ReadError::ChecksumMismatch { .. })) => {
+                crc_errors += 1;
+                let _ = device.drain_frames(PROBE_TIMEOUT);
+            }
+            Err(_) => {
+                other_errors += 1;
+                let _ = device.drain_frames(PROBE_TIMEOUT);
+            }
+        }
+    }
+    if other_errors > 0 {
+        report.warn(format!("{} polls failed for reasons other than checksums", other_errors));
+    }
+    summarize(report, frames, crc_errors, start.elapsed());
+}
+
+fn summarize(report: &mut Report, frames: usize, crc_errors: usize, elapsed: Duration) {
+    if frames == 0 {
+        report.fail("no data received");
+        return;
+    }
+    report.ok(format!(
+        "{:.1} samples/s over {:.1}s",
+        frames as f64 / elapsed.as_secs_f64(),
+        elapsed.as_secs_f64()
+    ));
+
+    let error_rate = crc_errors as f64 / (frames + crc_errors) as f64;
+    if error_rate > CRC_ERROR_THRESHOLD {
+        report.warn(format!(
+            "{:.1}% of frames failed their checksum: check grounding and cable length, or try a lower baud rate",
+            error_rate * 100.0
+        ));
+    } else {
+        report.ok(format!("{} checksum errors", crc_errors));
+    }
+}
+
+#[cfg(feature = "unstable")]
+fn declination(args: &[String]) -> ExitCode {
+    use pni_sdk::declination::{DeclinationSource, FirstAvailable, Location};
+
+    let sources: Result<Vec<_>, String> = args[0].split(',').map(parse_declination_source).collect();
+    let source = match sources {
+        Ok(sources) => FirstAvailable(sources),
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::from(2);
+        }
+    };
+    let (Ok(latitude), Ok(longitude)) = (args[1].parse(), args[2].parse()) else {
+        eprintln!("latitude and longitude must be in decimal degrees");
+        return ExitCode::from(2);
+    };
+    let location = Location::new(latitude, longitude);
+
+    let Some(port) = args.get(3) else {
+        return match source.declination(&location, std::time::SystemTime::now()) {
+            Ok(declination) => {
+                println!("{:.2}", declination);
+                ExitCode::SUCCESS
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                ExitCode::FAILURE
+            }
+        };
+    };
+    let result = Device::connect(Some(port.clone()))
+        .map_err(|e| e.to_string())
+        .and_then(|mut device| {
+            device
+                .set_declination_from(&source, &location)
+                .map_err(|e| e.to_string())
+        });
+    match result {
+        Ok(declination) => {
+            println!(
+                "declination set to {:.2}˚ (not saved; enable TrueNorth and save to keep it)",
+                declination
+            );
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Serves the device over the network until killed. `tcp` listens on ADDRESS; `udp` sends to it.
+/// In json mode the device is polled ten times a second
+#[cfg(feature = "unstable")]
+fn bridge(args: &[String]) -> ExitCode {
+    use pni_sdk::bridge::{Bridge, BridgeFormat};
+
+    let format = match args[2].as_str() {
+        "raw" => BridgeFormat::RawFrames,
+        "json" => BridgeFormat::Json,
+        other => {
+            eprintln!("format must be raw or json, got {:?}", other);
+            return ExitCode::from(2);
+        }
+    };
+    let device = match Device::connect(args.get(3).cloned()) {
+        Ok(device) => device,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut bridge = Bridge::new(device, format);
+    if format == BridgeFormat::Json {
+        bridge = bridge.poll_every(std::time::Duration::from_millis(100));
+    }
+    let result = match args[0].as_str() {
+        "tcp" => bridge.serve_tcp(args[1].as_str()),
+        "udp" => bridge.serve_udp(args[1].as_str()),
+        other => {
+            eprintln!("transport must be tcp or udp, got {:?}", other);
+            return ExitCode::from(2);
+        }
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(feature = "unstable")]
+fn parse_declination_source(
+    spec: &str,
+) -> Result<Box<dyn pni_sdk::declination::DeclinationSource>, String> {
+    let (kind, value) = spec.split_once(':').unwrap_or((spec, ""));
+    match kind {
+        "manual" => value
+            .parse()
+            .map(|degrees| Box::new(pni_sdk::declination::Manual(degrees)) as Box<_>)
+            .map_err(|_| format!("manual declination must be in degrees, got {:?}", value)),
+        #[cfg(feature = "wmm")]
+        "wmm" => pni_sdk::wmm::MagneticModel::load(value)
+            .map(|model| Box::new(model) as Box<_>)
+            .map_err(|e| format!("couldn't load {}: {}", value, e)),
+        #[cfg(feature = "wmm")]
+        "igrf" => pni_sdk::wmm::Igrf::load(value)
+            .map(|model| Box::new(model) as Box<_>)
+            .map_err(|e| format!("couldn't load {}: {}", value, e)),
+        #[cfg(feature = "online-declination")]
+        "online" => Ok(Box::new(pni_sdk::declination::OnlineLookup::new(value))),
+        _ => Err(format!(
+            "unknown or disabled declination source {:?}: wmm, igrf and online need the wmm and \
+             online-declination features",
+            kind
+        )),
+    }
+}
@@ -0,0 +1,65 @@
+// `tp3-sim [--heading DEG] [--pitch DEG] [--roll DEG] [--noise DEG] [--serial-number N]
+// [--seed N]` opens a pseudo-terminal and answers on it like a TargetPoint3, so programs built on
+// this crate can be integration-tested without the hardware. It prints the terminal's path; open
+// that like any serial port, e.g. `Device::connect(Some(path))`. Runs until interrupted.
+
+#[cfg(unix)]
+fn main() -> std::process::ExitCode {
+    use pni_sdk::simulator::{Simulator, SimulatorConfig};
+    use serialport::{SerialPort, TTYPort};
+    use std::process::ExitCode;
+
+    let mut config = SimulatorConfig::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let Some(value) = args.next() else {
+            return usage();
+        };
+        let parsed = match arg.as_str() {
+            "--heading" => value.parse().map(|v| config.orientation.heading = v).is_ok(),
+            "--pitch" => value.parse().map(|v| config.orientation.pitch = v).is_ok(),
+            "--roll" => value.parse().map(|v| config.orientation.roll = v).is_ok(),
+            "--noise" => value.parse().map(|v| config.noise = v).is_ok(),
+            "--serial-number" => value.parse().map(|v| config.serial_number = v).is_ok(),
+            "--seed" => value.parse().map(|v| config.seed = v).is_ok(),
+            _ => false,
+        };
+        if !parsed {
+            return usage();
+        }
+    }
+
+    // the simulator answers on the master side; the host opens the other end by its path, which
+    // stays open here so the terminal outlives the host closing and reopening it
+    let (master, terminal) = match TTYPort::pair() {
+        Ok(pair) => pair,
+        Err(e) => {
+            eprintln!("couldn't open a pseudo-terminal: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    println!("{}", terminal.name().unwrap_or_default());
+
+    let mut port: Box<dyn SerialPort> = Box::new(master);
+    let mut simulator = Simulator::new(config);
+    if let Err(e) = simulator.serve(&mut port) {
+        eprintln!("simulator stopped: {}", e);
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
+
+#[cfg(unix)]
+fn usage() -> std::process::ExitCode {
+    eprintln!(
+        "usage: tp3-sim [--heading DEG] [--pitch DEG] [--roll DEG] [--noise DEG] \
+         [--serial-number N] [--seed N]"
+    );
+    std::process::ExitCode::from(2)
+}
+
+#[cfg(not(unix))]
+fn main() -> std::process::ExitCode {
+    eprintln!("tp3-sim needs a Unix pseudo-terminal; use pni_sdk::simulator::Simulator directly");
+    std::process::ExitCode::FAILURE
+}
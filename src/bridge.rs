@@ -0,0 +1,302 @@
+// Serving the device over the network, so software on another machine can use it. Bridging the
+// serial port byte for byte (as with socat) loses framing whenever a client connects mid-frame or
+// two clients write at once; the bridge only ever forwards whole, checksummed frames.
+
+use crate::acquisition::Data;
+use crate::codec::{self, FrameDecoder, MAX_FRAME_LEN};
+use crate::dispatch::Response;
+use crate::command::Command;
+use crate::{Device, ReadError, WriteError};
+
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// How long each read from the device waits before the bridge checks on its clients
+const DEVICE_POLL_TIMEOUT: Duration = Duration::from_millis(20);
+
+/// What a [Bridge] sends to its clients
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+pub enum BridgeFormat {
+    /// Every frame from the device, re-encoded exactly as the protocol specifies. Frames from
+    /// clients are checked and forwarded to the device, so a client can drive it as if it were
+    /// attached locally
+    RawFrames,
+
+    /// One JSON object per line (or per datagram over UDP) for each [Data] sample, with the
+    /// components the device sent plus `time`, the host's receive time in seconds since the Unix
+    /// epoch. Other frames are dropped, and anything clients send is ignored
+    Json,
+}
+
+/// Stops a running [Bridge] from another thread. See [Bridge::stop_handle]
+#[derive(Debug, Clone)]
+pub struct BridgeStop(Arc<AtomicBool>);
+
+impl BridgeStop {
+    pub fn stop(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+struct Client {
+    stream: TcpStream,
+    peer: SocketAddr,
+    decoder: Box<FrameDecoder<MAX_FRAME_LEN>>,
+}
+
+/// Serves a [Device] to network clients over TCP or UDP.
+///
+/// ```no_run
+/// # use pni_sdk::Device;
+/// # use pni_sdk::bridge::{Bridge, BridgeFormat};
+/// let device = Device::connect(None).expect("Couldn't connect");
+/// let mut bridge = Bridge::new(device, BridgeFormat::Json)
+///     .poll_every(std::time::Duration::from_millis(100));
+/// bridge.serve_tcp("0.0.0.0:4001").expect("Bridge failed");
+/// ```
+pub struct Bridge {
+    device: Device,
+    format: BridgeFormat,
+    poll_interval: Option<Duration>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Bridge {
+    pub fn new(device: Device, format: BridgeFormat) -> Self {
+        Self {
+            device,
+            format,
+            poll_interval: None,
+            stop: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// In [BridgeFormat::Json], sends GetData at this interval rather than relying on the device
+    /// being in continuous mode
+    pub fn poll_every(mut self, interval: Duration) -> Self {
+        self.poll_interval = Some(interval);
+        self
+    }
+
+    pub fn stop_handle(&self) -> BridgeStop {
+        BridgeStop(self.stop.clone())
+    }
+
+    /// Gives back the device once the bridge has stopped
+    pub fn into_inner(self) -> Device {
+        self.device
+    }
+
+    /// Accepts any number of TCP clients at `addr` and serves them until stopped with a
+    /// [BridgeStop] or the device fails. Clients that disconnect or fall behind are dropped
+    pub fn serve_tcp(&mut self, addr: impl ToSocketAddrs) -> io::Result<()> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        log::info!("Serving {} on tcp://{}", self.format, listener.local_addr()?);
+
+        let mut clients = Vec::<Client>::new();
+        self.run(|bridge, output| {
+            loop {
+                match listener.accept() {
+                    Ok((stream, peer)) => {
+                        log::info!("Bridge client {} connected", peer);
+                        stream.set_nonblocking(true)?;
+                        clients.push(Client {
+                            stream,
+                            peer,
+                            decoder: Box::default(),
+                        });
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                    Err(e) => return Err(e),
+                }
+            }
+
+            let raw = bridge.format == BridgeFormat::RawFrames;
+            let mut incoming = Vec::new();
+            clients.retain_mut(|client| {
+                let result = output
+                    .iter()
+                    .try_for_each(|bytes| client.stream.write_all(bytes))
+                    .and_then(|()| client.receive(raw, &mut incoming));
+                match result {
+                    Ok(()) => true,
+                    Err(e) => {
+                        log::info!("Dropping bridge client {}: {}", client.peer, e);
+                        false
+                    }
+                }
+            });
+            for frame in incoming {
+                bridge.device.transport.write_all(&frame)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Sends each frame or JSON sample as a datagram to `target`, e.g. a broadcast address, until
+    /// stopped with a [BridgeStop] or the device fails. UDP is one way: nothing is read back
+    pub fn serve_udp(&mut self, target: impl ToSocketAddrs) -> io::Result<()> {
+        let socket = UdpSocket::bind(("0.0.0.0", 0))?;
+        socket.set_broadcast(true)?;
+        socket.connect(target)?;
+        log::info!("Sending {} to udp://{}", self.format, socket.peer_addr()?);
+
+        self.run(|_, output| {
+            for bytes in output {
+                // a datagram nobody is listening for isn't an error worth stopping for
+                if let Err(e) = socket.send(bytes) {
+                    log::debug!("Bridge datagram not sent: {}", e);
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Reads from the device until stopped, handing each batch of encoded output to `service`
+    fn run(
+        &mut self,
+        mut service: impl FnMut(&mut Self, &[Vec<u8>]) -> io::Result<()>,
+    ) -> io::Result<()> {
+        let previous_timeout = self.device.transport.timeout();
+        self.device.transport.set_timeout(DEVICE_POLL_TIMEOUT)?;
+        let mut next_poll = Instant::now();
+
+        let result = (|| {
+            while !self.stop.load(Ordering::Relaxed) {
+                let mut output = Vec::new();
+                if let Some(bytes) = self.read_device(&mut next_poll)? {
+                    output.push(bytes);
+                }
+                service(self, &output)?;
+            }
+            Ok(())
+        })();
+
+        let _ = self.device.transport.set_timeout(previous_timeout);
+        result
+    }
+
+    /// Next piece of output for clients, or [None] if the device had nothing to say. Only errors
+    /// that mean the port itself is gone are returned; bad frames are logged and skipped
+    fn read_device(&mut self, next_poll: &mut Instant) -> io::Result<Option<Vec<u8>>> {
+        let result = match self.format {
+            BridgeFormat::RawFrames => self.device.read_frame().and_then(|frame| {
+                let mut bytes = vec![0u8; frame.payload().len() + codec::FRAME_OVERHEAD];
+                codec::encode_frame(frame.command(), frame.payload(), &mut bytes)
+                    .map_err(|e| io::Error::new(ErrorKind::InvalidData, e.to_string()))?;
+                Ok(bytes)
+            }),
+            BridgeFormat::Json => {
+                if let Some(interval) = self.poll_interval.filter(|_| Instant::now() >= *next_poll)
+                {
+                    *next_poll = Instant::now() + interval;
+                    match self.device.write_frame(Command::GetData, None) {
+                        Err(WriteError::PipeError(e)) => return Err(e),
+                        Err(e) => log::warn!("Bridge couldn't poll: {}", e),
+                        Ok(()) => {}
+                    }
+                }
+                self.device.read_response().map(|response| match response {
+                    Response::Data(data) => {
+                        let mut line = data_json(&data, SystemTime::now());
+                        line.push('\n');
+                        line.into_bytes()
+                    }
+                    _ => Vec::new(),
+                })
+            }
+        };
+
+        match result {
+            Ok(bytes) if bytes.is_empty() => Ok(None),
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(ReadError::PipeError(e)) if e.kind() == ErrorKind::TimedOut => Ok(None),
+            Err(ReadError::PipeError(e)) => Err(e),
+            Err(e) => {
+                log::warn!("Bridge skipped a frame: {}", e);
+                if self.device.sync_to_frame_boundary().is_err() {
+                    self.device.reset_frame();
+                }
+                Ok(None)
+            }
+        }
+    }
+}
+
+impl Client {
+    /// Takes whatever the client has sent without blocking. In raw mode, whole frames are added
+    /// to `frames` for the device
+    fn receive(&mut self, raw: bool, frames: &mut Vec<Vec<u8>>) -> io::Result<()> {
+        let mut buffer = [0u8; 512];
+        loop {
+            let count = match self.stream.read(&mut buffer) {
+                Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+                Ok(count) => count,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(e),
+            };
+            if !raw {
+                continue;
+            }
+            for &byte in &buffer[..count] {
+                match self.decoder.push(byte) {
+                    Ok(Some(frame)) => {
+                        let mut bytes = vec![0u8; frame.payload.len() + codec::FRAME_OVERHEAD];
+                        if codec::encode_frame(frame.command, frame.payload, &mut bytes).is_ok() {
+                            frames.push(bytes);
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(e) => log::warn!("Bad frame from bridge client {}: {}", self.peer, e),
+                }
+            }
+        }
+    }
+}
+
+/// One sample as a flat JSON object
+pub(crate) fn data_json(data: &Data, received_at: SystemTime) -> String {
+    let time = received_at
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64();
+    let mut fields = vec![format!("\"time\":{}", time)];
+    let numbers = [
+        ("heading", data.heading),
+        ("pitch", data.pitch),
+        ("roll", data.roll),
+        ("temperature", data.temperature),
+        ("accel_x", data.accel_x),
+        ("accel_y", data.accel_y),
+        ("accel_z", data.accel_z),
+        ("mag_x", data.mag_x),
+        ("mag_y", data.mag_y),
+        ("mag_z", data.mag_z),
+        ("mag_accuracy", data.mag_accuracy),
+        ("gyro_x", data.gyro_x),
+        ("gyro_y", data.gyro_y),
+        ("gyro_z", data.gyro_z),
+    ];
+    for (name, value) in numbers {
+        // JSON has no NaN or infinity
+        if let Some(value) = value.filter(|value| value.is_finite()) {
+            fields.push(format!("\"{}\":{}", name, value));
+        }
+    }
+    for (name, value) in [("distortion", data.distortion), ("cal_status", data.cal_status)] {
+        if let Some(value) = value {
+            fields.push(format!("\"{}\":{}", name, value));
+        }
+    }
+    if let Some([w, x, y, z]) = data.quaternion {
+        fields.push(format!("\"quaternion\":[{},{},{},{}]", w, x, y, z));
+    }
+    if let Some(status) = data.heading_status {
+        fields.push(format!("\"heading_status\":\"{}\"", status));
+    }
+    format!("{{{}}}", fields.join(","))
+}
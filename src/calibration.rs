@@ -1,33 +1,34 @@
+use crate::acquisition::{Data, DataID};
+use crate::capabilities::Feature;
+use crate::catalog::{Message, MessageId};
 use crate::command::Command;
-use crate::responses::Get;
+use crate::config::{ConfigID, ConfigPair};
+use crate::constants::MAX_COEFF_SET;
+use crate::dispatch::Response;
+use crate::processing::{heading_std_dev, mean_heading, HeadingOffset};
 use crate::{RWError, ReadError, Device, WriteError};
 
+use std::time::Duration;
+
+/// How long [AutoCal] waits without a sample being taken before giving up, by default
+const AUTO_CAL_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
 impl Device {
-    /// First, note that in order to perform a user calibration, it is necessary to place the TargetPoint3 in Compass Mode, as discussed in User Manual Section 7.7. Note that TargetPoint3 allows for a maximum of 18 calibration points.
-    /// See User Manual for calibration instructions.
-    /// This frame commands the TargetPoint3 to start user calibration with the current sensor acquisition parameters, internal configurations, and FIR filter settings.
-    ///
-    /// Returns the sample count, which should be 0 when starting a calibration
-    pub fn start_cal(&mut self, calibration_type: CalOption) -> Result<u32, RWError> {
-        self.write_frame(
-            Command::StartCal,
-            Some(&(calibration_type as u32).to_be_bytes()),
-        )?;
-
-        let expected_size = Get::<u16>::get(self)?;
-        let resp_command = Get::<u8>::get(self)?;
-
-        if resp_command == Command::UserCalSampleCount.discriminant() {
-            let sample_count = Get::<u32>::get(self)?;
-            self.end_frame(expected_size)?;
-            Ok(sample_count)
-        } else {
-            let _ = self.end_frame(expected_size);
-            Err(RWError::ReadError(ReadError::ParseError(format!(
-                "Unexpected response type. Got {}",
-                resp_command
-            ))))
-        }
+    requests! {
+        /// First, note that in order to perform a user calibration, it is necessary to place the TargetPoint3 in Compass Mode, as discussed in User Manual Section 7.7. Note that TargetPoint3 allows for a maximum of 18 calibration points.
+        /// See User Manual for calibration instructions.
+        /// This frame commands the TargetPoint3 to start user calibration with the current sensor acquisition parameters, internal configurations, and FIR filter settings.
+        ///
+        /// Returns the sample count, which should be 0 when starting a calibration
+        pub fn start_cal(calibration_type: CalOption) -> u32
+            = StartCal(u32::from(u8::from(calibration_type)).to_be_bytes())
+            => |sample_count: u32| Ok(sample_count);
+
+        /// This frame clears the magnetometer calibration coefficients and loads the original factory-generated coefficients. The frame has no payload. This frame must be followed by the kSave frame to save the change in non-volatile memory.
+        pub fn factory_mag_coeff() -> () = FactoryMagCoeff => || Ok(());
+
+        /// This frame clears the accelerometer calibration coefficients and loads the original factory-generated coefficients. The frame has no payload. This frame must be followed by the kSave frame to save the change in non-volatile memory.
+        pub fn factory_accel_coeff() -> () = FactorylAccelCoeff => || Ok(());
     }
 
     /// This frame commands the TargetPoint3 to take a sample during user calibration.
@@ -36,32 +37,28 @@ impl Device {
     /// If the sample was succesful, calibration should return 1 more
     /// than the previous sample count (or return the score)
     fn take_user_cal_sample_impl(&mut self) -> Result<UserCalResponseReserved, RWError> {
-        self.write_frame(Command::TakeUserCalSample, None)?;
+        self.send(Command::TakeUserCalSample, None)?;
 
-        let expected_size = Get::<u16>::get(self)?;
-        let resp_command = Get::<u8>::get(self)?;
-
-        if resp_command == Command::UserCalSampleCount.discriminant() {
-            let sample_count = Get::<u32>::get(self)?;
-            self.end_frame(expected_size)?;
+        let mut frame = self.begin_frame()?;
+        if frame.command() == u8::from(Command::UserCalSampleCount) {
+            let sample_count = frame.get::<u32>()?;
+            frame.finish()?;
             Ok(UserCalResponseReserved::SampleCount(sample_count))
-        } else if resp_command == Command::UserCalScore.discriminant() {
+        } else if frame.command() == u8::from(Command::UserCalScore) {
             let ret = UserCalResponseReserved::UserCalScore {
-                mag_cal_score: Get::<f32>::get(self)?,
-                reserved: Get::<f32>::get(self)?,
-                accel_cal_score: Get::<f32>::get(self)?,
-                distribution_error: Get::<f32>::get(self)?,
-                tilt_error: Get::<f32>::get(self)?,
-                tilt_range: Get::<f32>::get(self)?,
+                mag_cal_score: frame.get::<f32>()?,
+                reserved: frame.get::<f32>()?,
+                accel_cal_score: frame.get::<f32>()?,
+                distribution_error: frame.get::<f32>()?,
+                tilt_error: frame.get::<f32>()?,
+                tilt_range: frame.get::<f32>()?,
             };
-            self.end_frame(expected_size)?;
+            frame.finish()?;
             Ok(ret)
         } else {
-            let _ = self.end_frame(expected_size);
-            Err(RWError::ReadError(ReadError::ParseError(format!(
-                "Unexpected response type. Got {}",
-                resp_command
-            ))))
+            Err(RWError::ReadError(ReadError::ParseError(
+                Message::new(MessageId::UnexpectedResponse).with("command", frame.command()),
+            )))
         }
     }
 
@@ -79,137 +76,296 @@ impl Device {
         Ok(self.take_user_cal_sample_impl()?.into())
     }
 
-    /// This command aborts the calibration process. The prior calibration results are retained.
-    pub fn stop_cal(&mut self) -> Result<(), WriteError> {
-        self.write_frame(Command::StopCal, None)?;
-        Ok(())
+    /// Starts a user calibration with [ConfigPair::UserCalAutoSampling] turned on (in volatile
+    /// memory), so the device takes sample points by itself as it's moved, and returns an
+    /// iterator over its progress. Without this the sample counts the device sends have nowhere
+    /// to go: nothing is waiting for them, so they'd only show up as [Device::events].
+    ///
+    /// Iteration ends after the score. Dropping the iterator before then aborts the calibration
+    /// with [Device::stop_cal], keeping the prior calibration. Either way UserCalAutoSampling is
+    /// set back to what it was
+    pub fn start_auto_cal(&mut self, calibration_type: CalOption) -> Result<AutoCal<'_>, RWError> {
+        // calibrating while streaming is how HPRDuringCal output is seen
+        let (auto_sampling, samples) = self.while_streaming(|device| {
+            let auto_sampling = match device.get_config(ConfigID::UserCalAutoSampling)? {
+                ConfigPair::UserCalAutoSampling(auto_sampling) => auto_sampling,
+                _ => false,
+            };
+            device.set_config(ConfigPair::UserCalAutoSampling(true))?;
+            match device.start_cal(calibration_type) {
+                Ok(samples) => Ok((auto_sampling, samples)),
+                Err(e) => {
+                    let _ = device.set_config(ConfigPair::UserCalAutoSampling(auto_sampling));
+                    Err(e)
+                }
+            }
+        })?;
+        Ok(AutoCal {
+            device: self,
+            samples,
+            idle_timeout: AUTO_CAL_IDLE_TIMEOUT,
+            idle: Duration::ZERO,
+            finished: false,
+            auto_sampling: Some(auto_sampling),
+        })
     }
 
-    /// This frame clears the magnetometer calibration coefficients and loads the original factory-generated coefficients. The frame has no payload. This frame must be followed by the kSave frame to save the change in non-volatile memory.
-    pub fn factory_mag_coeff(&mut self) -> Result<(), RWError> {
-        self.write_frame(Command::StartCal, None)?;
+    /// Like [Device::start_auto_cal], but first selects coefficient set `set` (0 to 7) for the
+    /// kinds of coefficients `calibration_type` calibrates, so the result is stored there. The
+    /// selection is volatile until [Device::save]; check it with [Device::verify_coeff_set] once
+    /// the calibration finishes
+    pub fn start_auto_cal_in_set(
+        &mut self,
+        calibration_type: CalOption,
+        set: u8,
+    ) -> Result<AutoCal<'_>, RWError> {
+        for &kind in calibration_type.coeff_kinds() {
+            self.set_active_coeff_set(kind, set)?;
+        }
+        self.start_auto_cal(calibration_type)
+    }
+
+    /// Checks that the coefficient sets `calibration_type` calibrates are still set `set`, e.g.
+    /// after a calibration started with [Device::start_auto_cal_in_set]. Returns
+    /// [RWError::DeviceError] naming the set that is active otherwise
+    pub fn verify_coeff_set(&mut self, calibration_type: CalOption, set: u8) -> Result<(), RWError> {
+        for &kind in calibration_type.coeff_kinds() {
+            let active = self.get_active_coeff_set(kind)?;
+            if active != set {
+                return Err(RWError::DeviceError(
+                    Message::new(MessageId::CoeffSetMismatch)
+                        .with("kind", kind)
+                        .with("expected", set)
+                        .with("actual", active),
+                ));
+            }
+        }
+        Ok(())
+    }
 
-        let expected_size = Get::<u16>::get(self)?;
-        let resp_command = Get::<u8>::get(self)?;
+    /// The coefficient set of `kind` currently in use, 0 to 7. See [ConfigPair::MagCoeffSet]
+    pub fn get_active_coeff_set(&mut self, kind: CoeffSetKind) -> Result<u8, RWError> {
+        let set = match self.get_config(kind.config_id())? {
+            ConfigPair::MagCoeffSet(set) | ConfigPair::AccelCoeffSet(set) => set,
+            _ => 0,
+        };
+        Ok(set as u8)
+    }
 
-        if resp_command == Command::FactoryMagCoeffDone.discriminant() {
-            self.end_frame(expected_size)?;
-            Ok(())
-        } else {
-            let _ = self.end_frame(expected_size);
-            Err(RWError::ReadError(ReadError::ParseError(format!(
-                "Unexpected response type. Got {}",
-                resp_command
-            ))))
+    /// Switches to coefficient set `set` (0 to 7) of `kind`, for headings and for the next
+    /// calibration to be stored in. Volatile until [Device::save]
+    pub fn set_active_coeff_set(&mut self, kind: CoeffSetKind, set: u8) -> Result<(), RWError> {
+        if set > MAX_COEFF_SET {
+            return Err(RWError::InvalidArgument(
+                Message::new(MessageId::OutOfRange)
+                    .with("name", "set")
+                    .with("value", set)
+                    .with("range", "[0, 7]"),
+            ));
         }
+        self.require(Feature::MultipleCoeffSets)?;
+        self.set_config(match kind {
+            CoeffSetKind::Mag => ConfigPair::MagCoeffSet(set as u32),
+            CoeffSetKind::Accel => ConfigPair::AccelCoeffSet(set as u32),
+        })
     }
 
-    /// This frame clears the accelerometer calibration coefficients and loads the original factory-generated coefficients. The frame has no payload. This frame must be followed by the kSave frame to save the change in non-volatile memory.
-    pub fn factory_accel_coeff(&mut self) -> Result<(), RWError> {
-        self.write_frame(Command::FactorylAccelCoeff, None)?;
+    /// The magnetometer coefficient set currently in use. See [Device::get_active_coeff_set]
+    pub fn get_active_mag_coeff_set(&mut self) -> Result<u8, RWError> {
+        self.get_active_coeff_set(CoeffSetKind::Mag)
+    }
 
-        let expected_size = Get::<u16>::get(self)?;
-        let resp_command = Get::<u8>::get(self)?;
+    /// Switches magnetometer coefficient set. See [Device::set_active_coeff_set]
+    pub fn set_active_mag_coeff_set(&mut self, set: u8) -> Result<(), RWError> {
+        self.set_active_coeff_set(CoeffSetKind::Mag, set)
+    }
 
-        if resp_command == Command::FactoryAccelCoeffDone.discriminant() {
-            self.end_frame(expected_size)?;
-            Ok(())
-        } else {
-            let _ = self.end_frame(expected_size);
-            Err(RWError::ReadError(ReadError::ParseError(format!(
-                "Unexpected response type. Got {}",
-                resp_command
-            ))))
-        }
+    /// This command aborts the calibration process. The prior calibration results are retained.
+    pub fn stop_cal(&mut self) -> Result<(), WriteError> {
+        self.write_frame(Command::StopCal, None)?;
+        Ok(())
     }
 
     /// This frame copies one set of calibration coefficients to another. TargetPoint3 supports 8 sets of magnetic calibration coefficients, and 8 sets of accel calibration coefficients. The set index is from 0 to 7. This frame must be followed by the kSave frame to save the change in non-volatile memory.
     ///
     /// # Arguments
-    /// * `set_type` - Value 0 to copy magnetic calibration coefficient set (default), 1 to copy accel coefficient set
-    /// * `set_indexes` - bit 7 - 4: source coefficient set index from 0 to 7, default 0, bit 0 - 3: destination coefficient set index from 0 to 7, default 0
-    pub fn copy_coeff_set(&mut self, set_type: u8, set_indexes: u8) -> Result<(), RWError> {
-        self.write_frame(Command::CopyCoeffSet, Some(&[set_type, set_indexes]))?;
-
-        let expected_size = Get::<u16>::get(self)?;
-        let resp_command = Get::<u8>::get(self)?;
-
-        if resp_command == Command::CopyCoeffSetDone.discriminant() {
-            self.end_frame(expected_size)?;
-            Ok(())
-        } else {
-            let _ = self.end_frame(expected_size);
-            Err(RWError::ReadError(ReadError::ParseError(format!(
-                "Unexpected response type. Got {}",
-                resp_command
-            ))))
+    /// * `kind` - Whether to copy a magnetometer or accelerometer coefficient set
+    /// * `source` - Set to copy from, 0 to 7
+    /// * `destination` - Set to overwrite, 0 to 7
+    ///
+    /// Indexes outside 0 to 7 return [RWError::InvalidArgument] without sending anything, even
+    /// with [Device::set_strict] off, since they can't be packed without overwriting another set
+    ///
+    /// The coefficients themselves can only be copied within a unit, as the protocol has no
+    /// command to read them back (see [Feature::CoefficientExport]). Which sets are selected,
+    /// along with every other setting that can be read back, is saved to a file and restored
+    /// onto a replacement with `Device::export_archive`, `DeviceArchive::save` and
+    /// `Device::restore_archive` (with the `unstable` feature); the replacement still needs its
+    /// own user calibration
+    pub fn copy_coeff_set(
+        &mut self,
+        kind: CoeffSetKind,
+        source: u8,
+        destination: u8,
+    ) -> Result<(), RWError> {
+        for (name, index) in [("source", source), ("destination", destination)] {
+            if index > MAX_COEFF_SET {
+                return Err(RWError::InvalidArgument(
+                    Message::new(MessageId::OutOfRange)
+                        .with("name", name)
+                        .with("value", index)
+                        .with("range", "[0, 7]"),
+                ));
+            }
         }
+        self.require(Feature::MultipleCoeffSets)?;
+        // source in the high nibble, destination in the low one
+        let set_indexes = (source << 4) | destination;
+        self.request(Command::CopyCoeffSet, Some(&[u8::from(kind), set_indexes]))?
+            .finish()?;
+        Ok(())
     }
 
     /// The TargetPoint3 incorporates a finite impulse response (FIR) filter to provide a more stable heading reading. The number of taps (or samples) represents the amount of filtering to be performed. The number of taps directly affects the time for the initial sample reading, as all the taps must be populated before data is output.  The TargetPoint3 can be configured to clear, or flush, the filters after each measurement, as discussed in Section 7.5.1. Flushing the filter clears all tap values, thus purging old data.  This can be useful if a significant change in heading has occurred since the last reading, as the old heading data would be in the filter. Once the taps are cleared, it is necessary to fully repopulate the filter before data is output. For example, if 32 FIR-tap is set, 32 new samples must be taken before a reading will be output. The length of the delay before outputting data is directly correlated to the number of FIR taps.
     ///
-    /// For recommended taps, see User Manual Table 7-6
+    /// For recommended taps, see User Manual Table 7-6. More taps than the device accepts (see
+    /// [Device::capabilities]) return [RWError::InvalidArgument] without sending anything
     pub fn set_fir_filters(&mut self, taps: Vec<f64>) -> Result<(), RWError> {
+        self.set_fir_filters_for(AxisSelector::Mag, taps)
+    }
+
+    /// Like [Device::set_fir_filters], for the filter chosen by `axis` rather than the
+    /// magnetometer's
+    pub fn set_fir_filters_for(&mut self, axis: AxisSelector, taps: Vec<f64>) -> Result<(), RWError> {
+        self.require(Feature::FirFilters)?;
+        if let Some(max) = self.capabilities().max_fir_taps.filter(|max| taps.len() > *max) {
+            return Err(RWError::InvalidArgument(
+                Message::new(MessageId::OutOfRange)
+                    .with("name", "FIR taps")
+                    .with("value", taps.len())
+                    .with("range", format!("[0, {}]", max)),
+            ));
+        }
         let mut payload =
-            taps.into_iter()
+            taps.iter()
                 .map(|tap| tap.to_be_bytes())
                 .fold(Vec::new(), |mut vec, tap| {
                     vec.extend(tap);
                     vec
                 });
 
-        // From manual: Byte 1 should be set to 3 and Byte 2 should be set to 1. Payload is
-        // 1-indexed in docs
-        payload.insert(0, 3);
-        payload.insert(1, 1);
-        self.write_frame(Command::SetFIRFilters, Some(&payload))?;
-
-        let expected_size = Get::<u16>::get(self)?;
-        let resp_command = Get::<u8>::get(self)?;
-
-        if resp_command == Command::SetFIRFiltersDone.discriminant() {
-            self.end_frame(expected_size)?;
-            Ok(())
-        } else {
-            let _ = self.end_frame(expected_size);
-            Err(RWError::ReadError(ReadError::ParseError(format!(
-                "Unexpected response type. Got {}",
-                resp_command
-            ))))
+        // From manual: Byte 1 and Byte 2 pick the filter, then Byte 3 is the tap count.
+        // Payload is 1-indexed in docs
+        let [byte_1, byte_2] = axis.bytes();
+        payload.splice(0..0, [byte_1, byte_2, taps.len() as u8]);
+        self.request(Command::SetFIRFilters, Some(&payload))?.finish()?;
+        match self.fir_filters.iter_mut().find(|(set, _)| *set == axis) {
+            Some((_, previous)) => *previous = taps,
+            None => self.fir_filters.push((axis, taps)),
         }
+        self.settings.mark_other_unsaved();
+        Ok(())
     }
 
     /// This frame queries the FIR filter settings for the sensors.
     /// For recommended taps, see User Manual Table 7-6
     pub fn get_fir_filters(&mut self) -> Result<Vec<f64>, RWError> {
-        // From manual: Byte 1 should be set to 3 and Byte 2 should be set to 1.
-        self.write_frame(Command::GetFIRFilters, Some(&[3, 1]))?;
+        self.get_fir_filters_for(AxisSelector::Mag)
+    }
 
-        let expected_size = Get::<u16>::get(self)?;
-        let resp_command = Get::<u8>::get(self)?;
+    /// Like [Device::get_fir_filters], for the filter chosen by `axis`. A response for a
+    /// different filter than the one asked for returns [RWError::DeviceError]
+    pub fn get_fir_filters_for(&mut self, axis: AxisSelector) -> Result<Vec<f64>, RWError> {
+        self.require(Feature::FirFilters)?;
+        let mut frame = self.request(Command::GetFIRFilters, Some(&axis.bytes()))?;
+        let echoed = [frame.get::<u8>()?, frame.get::<u8>()?];
+        if echoed != axis.bytes() {
+            return Err(RWError::DeviceError(
+                Message::new(MessageId::FirAxisMismatch)
+                    .with("requested", axis)
+                    .with("received", AxisSelector::Raw(echoed[0], echoed[1])),
+            ));
+        }
 
-        if resp_command == Command::SetFIRFiltersDone.discriminant() {
-            let _byte_1 = Get::<u8>::get(self)?;
-            let _byte_2 = Get::<u8>::get(self)?;
+        let count = frame.get::<u8>()?;
+        let mut taps = Vec::<f64>::new();
+        for _ in 0..count {
+            taps.push(frame.get::<f64>()?);
+        }
 
-            let count = Get::<u8>::get(self)?;
-            let mut taps = Vec::<f64>::new();
-            for _ in 0..count {
-                taps.push(Get::<f64>::get(self)?);
-            }
+        frame.finish()?;
+        Ok(taps)
+    }
 
-            self.end_frame(expected_size)?;
-            Ok(taps)
-        } else {
-            let _ = self.end_frame(expected_size);
-            Err(RWError::ReadError(ReadError::ParseError(format!(
-                "Unexpected response type. Got {}",
-                resp_command
-            ))))
+    /// Checks a calibration's score against the manual's thresholds for `option` (see
+    /// [CalReport::new]), then polls `samples` samples with GetData to see how the output
+    /// settles. Keep the device still while it samples. The device must be in polled mode with
+    /// data components set; pass 0 samples to skip polling
+    pub fn analyze_calibration(
+        &mut self,
+        option: CalOption,
+        score: CalScore,
+        samples: usize,
+    ) -> Result<CalReport, RWError> {
+        let report = CalReport::new(option, score);
+        if samples == 0 {
+            return Ok(report);
         }
+        let data = (0..samples)
+            .map(|_| self.get_data())
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(report.with_samples(&data))
+    }
+
+    /// Commissioning step for fixed installations: with the device held still and pointed along a
+    /// known true bearing (e.g. a surveyed transit), averages `samples` polled headings and
+    /// returns the offset between them and `known_bearing`, in degrees.
+    ///
+    /// Changes the data components to heading only, in volatile memory. Headings are read as
+    /// configured, so if [ConfigPair::TrueNorth] is off the offset includes declination
+    pub fn heading_offset_from_bearing(
+        &mut self,
+        known_bearing: f32,
+        samples: usize,
+    ) -> Result<HeadingOffset, RWError> {
+        self.set_data_components(vec![DataID::Heading])?;
+        let mut headings = Vec::with_capacity(samples);
+        for _ in 0..samples.max(1) {
+            headings.push(self.get_data()?.heading.ok_or_else(|| {
+                ReadError::ParseError(Message::new(MessageId::MissingHeading))
+            })?);
+        }
+        let measured = mean_heading(&headings).ok_or_else(|| {
+            RWError::DeviceError(Message::new(MessageId::HeadingsUnsteady))
+        })?;
+        Ok(HeadingOffset::from_bearing(measured, known_bearing))
+    }
+
+    /// Applies a [HeadingOffset] on the device by folding it into [ConfigPair::Declination] and
+    /// turning on [ConfigPair::TrueNorth], so every consumer of the device's heading sees the
+    /// correction. Returns the new declination. Call [Device::save] to keep it across power
+    /// cycles.
+    ///
+    /// The stored declination is no longer the true magnetic declination afterwards, so don't
+    /// apply this on top of a value later recomputed from a magnetic model
+    pub fn apply_heading_offset(&mut self, offset: &HeadingOffset) -> Result<f32, RWError> {
+        // while TrueNorth is off the device ignores the stored declination, so the offset was
+        // measured against magnetic headings and replaces it
+        let current = match self.get_config(ConfigID::TrueNorth)? {
+            ConfigPair::TrueNorth(true) => match self.get_config(ConfigID::Declination)? {
+                ConfigPair::Declination(declination) => declination,
+                _ => 0.0,
+            },
+            _ => 0.0,
+        };
+        let declination = (current + offset.degrees + 180.0).rem_euclid(360.0) - 180.0;
+        self.set_config(ConfigPair::Declination(declination))?;
+        self.set_config(ConfigPair::TrueNorth(true))?;
+        Ok(declination)
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub enum UserCalResponse {
     /// The calibration score is automatically sent upon taking the final calibration point.
     UserCalScore {
@@ -233,6 +389,28 @@ pub enum UserCalResponse {
     SampleCount(u32),
 }
 
+impl UserCalResponse {
+    /// The score, if this is one
+    pub fn score(&self) -> Option<CalScore> {
+        match *self {
+            UserCalResponse::UserCalScore {
+                mag_cal_score,
+                accel_cal_score,
+                distribution_error,
+                tilt_error,
+                tilt_range,
+            } => Some(CalScore {
+                mag_cal_score,
+                accel_cal_score,
+                distribution_error,
+                tilt_error,
+                tilt_range,
+            }),
+            UserCalResponse::SampleCount(_) => None,
+        }
+    }
+}
+
 impl From<UserCalResponseReserved> for UserCalResponse {
     fn from(value: UserCalResponseReserved) -> Self {
         match value {
@@ -242,6 +420,304 @@ impl From<UserCalResponseReserved> for UserCalResponse {
     }
 }
 
+/// Calibration score, as carried by [UserCalResponse::UserCalScore], in a standalone struct. See
+/// there for what each field means
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CalScore {
+    pub mag_cal_score: f32,
+    pub accel_cal_score: f32,
+    pub distribution_error: f32,
+    pub tilt_error: f32,
+    pub tilt_range: f32,
+}
+
+/// One acceptance check in a [CalReport]
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+pub enum CalCheck {
+    MagCalScore,
+    AccelCalScore,
+    DistributionError,
+    TiltError,
+    TiltRange,
+}
+
+/// Outcome of one [CalCheck]
+#[derive(Debug, Clone, PartialEq)]
+pub struct CheckResult {
+    pub check: CalCheck,
+    pub value: f32,
+
+    /// The acceptance criterion from the user manual, e.g. `"≤ 1"`
+    pub criterion: &'static str,
+    pub passed: bool,
+
+    /// What a failure means and what to do about it. [None] if the check passed
+    pub advice: Option<&'static str>,
+}
+
+/// Output settling after calibration, from a burst of samples. See
+/// [Device::analyze_calibration]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PostCalSamples {
+    pub samples: usize,
+
+    /// Circular standard deviation of heading across the samples, in degrees. High values with a
+    /// stationary device mean a noisy environment or too few FIR taps
+    pub heading_std_dev: Option<f32>,
+
+    /// Mean of [Data::mag_accuracy], if it was among the data components
+    pub mean_mag_accuracy: Option<f32>,
+}
+
+/// A calibration score checked against the user manual's acceptance thresholds for the
+/// calibration method used, so results don't depend on reading the raw floats correctly
+#[derive(Debug, Clone, PartialEq)]
+pub struct CalReport {
+    pub option: CalOption,
+    pub score: CalScore,
+
+    /// Only the checks that apply to `option`, e.g. no accel score for a mag-only calibration
+    pub checks: Vec<CheckResult>,
+
+    pub post_cal: Option<PostCalSamples>,
+}
+
+impl CalReport {
+    /// Checks `score` for a calibration done with `option`
+    pub fn new(option: CalOption, score: CalScore) -> Self {
+        use CalOption::*;
+
+        let mag = option != AccelOnly;
+        let accel = matches!(option, AccelOnly | MagAndAccel);
+        let mut checks = Vec::new();
+        let mut check = |check, value: f32, criterion, passed: bool, advice| {
+            checks.push(CheckResult {
+                check,
+                value,
+                criterion,
+                passed,
+                advice: (!passed).then_some(advice),
+            });
+        };
+
+        if mag {
+            // ≤1 for full range calibration, ≤2 for the other methods
+            let (criterion, limit) = match option {
+                FullRange | MagAndAccel => ("≤ 1", 1.0),
+                _ => ("≤ 2", 2.0),
+            };
+            check(
+                CalCheck::MagCalScore,
+                score.mag_cal_score,
+                criterion,
+                score.mag_cal_score <= limit,
+                "Magnetometer calibration is poor. If the distribution and tilt checks passed, a \
+                 source of magnetic distortion is probably moving relative to the device, or the \
+                 device was calibrated away from where it's installed",
+            );
+        }
+        if accel {
+            check(
+                CalCheck::AccelCalScore,
+                score.accel_cal_score,
+                "≤ 1",
+                score.accel_cal_score <= 1.0,
+                "Accelerometer calibration is poor. Hold the device still at each point and cover \
+                 the full range of orientations",
+            );
+        }
+        if mag {
+            check(
+                CalCheck::DistributionError,
+                score.distribution_error,
+                "= 0",
+                score.distribution_error == 0.0,
+                "Sample points were clumped or missed part of the heading circle. Spread them \
+                 evenly around 360˚ of heading",
+            );
+        }
+        check(
+            CalCheck::TiltError,
+            score.tilt_error,
+            "= 0",
+            score.tilt_error == 0.0,
+            "The device wasn't tilted enough for this calibration method. Tilt further, or use a \
+             method suited to the tilt available",
+        );
+        // half the pitch range: ≥30˚ for full range and hard-iron-only, about 2˚ for 2D. Limited
+        // tilt should use as much as is available, and is only worth choosing above 5˚
+        let tilt_range = match option {
+            FullRange | HardIronOnly => Some(("≥ 30˚", score.tilt_range >= 30.0)),
+            TwoDimensional => Some(("≤ 5˚", score.tilt_range <= 5.0)),
+            LimitedTilt => Some(("> 5˚", score.tilt_range > 5.0)),
+            AccelOnly | MagAndAccel => None,
+        };
+        if let Some((criterion, passed)) = tilt_range {
+            check(
+                CalCheck::TiltRange,
+                score.tilt_range,
+                criterion,
+                passed,
+                "Tilt range doesn't suit this calibration method: below 5˚ use TwoDimensional, \
+                 5˚ to 30˚ use LimitedTilt, and beyond that FullRange",
+            );
+        }
+
+        Self {
+            option,
+            score,
+            checks,
+            post_cal: None,
+        }
+    }
+
+    /// Adds statistics over samples taken with the device stationary after calibrating
+    pub fn with_samples(mut self, samples: &[Data]) -> Self {
+        let headings = samples.iter().filter_map(|data| data.heading).collect::<Vec<_>>();
+        let accuracies = samples
+            .iter()
+            .filter_map(|data| data.mag_accuracy)
+            .collect::<Vec<_>>();
+        self.post_cal = Some(PostCalSamples {
+            samples: samples.len(),
+            heading_std_dev: (!headings.is_empty()).then(|| heading_std_dev(&headings)),
+            mean_mag_accuracy: (!accuracies.is_empty())
+                .then(|| accuracies.iter().sum::<f32>() / accuracies.len() as f32),
+        });
+        self
+    }
+
+    /// Whether every check passed
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|check| check.passed)
+    }
+
+    /// The checks that failed
+    pub fn failures(&self) -> impl Iterator<Item = &CheckResult> {
+        self.checks.iter().filter(|check| !check.passed)
+    }
+}
+
+/// Progress of an auto-sampling calibration. See [Device::start_auto_cal]
+#[derive(Debug)]
+pub enum CalProgress {
+    /// A sample point was taken; the count of points so far
+    Sample(u32),
+
+    /// Heading, pitch and roll output while calibrating, if [ConfigPair::HPRDuringCal] is on and
+    /// the device is in continuous mode
+    Data(Data),
+
+    /// Calibration finished. This is the last item
+    Score(CalScore),
+}
+
+/// Iterator over auto-sampling calibration progress. See [Device::start_auto_cal]
+pub struct AutoCal<'a> {
+    device: &'a mut Device,
+    samples: u32,
+    idle_timeout: Duration,
+
+    /// Time spent in reads that timed out since the last sample point
+    idle: Duration,
+
+    finished: bool,
+
+    /// UserCalAutoSampling from before the calibration, until it's been set back
+    auto_sampling: Option<bool>,
+}
+
+impl AutoCal<'_> {
+    /// How long to wait for the next sample point before stopping the calibration and failing.
+    /// Defaults to a minute, to allow for moving the device between points. Counted in the
+    /// port's read timeouts, so it's measured by the same reads that wait for the device
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    /// Sample points taken so far
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    /// Calls `on_progress` for each step until the calibration finishes, and returns the score
+    pub fn run(mut self, mut on_progress: impl FnMut(&CalProgress)) -> Result<CalScore, ReadError> {
+        for progress in self.by_ref() {
+            let progress = progress?;
+            on_progress(&progress);
+            if let CalProgress::Score(score) = progress {
+                return Ok(score);
+            }
+        }
+        Err(ReadError::ParseError(
+            Message::new(MessageId::CalEndedWithoutScore).with("samples", self.samples),
+        ))
+    }
+
+    /// Ends the calibration, stopping it on the device first if it's still running, and sets
+    /// UserCalAutoSampling back
+    fn finish(&mut self, stop: bool) {
+        self.finished = true;
+        if stop {
+            let _ = self.device.stop_cal();
+        }
+        if let Some(auto_sampling) = self.auto_sampling.take() {
+            let restore = ConfigPair::UserCalAutoSampling(auto_sampling);
+            if let Err(e) = self.device.while_streaming(|device| device.set_config(restore)) {
+                log::warn!("Couldn't set UserCalAutoSampling back after calibrating: {}", e);
+            }
+        }
+    }
+}
+
+impl Iterator for AutoCal<'_> {
+    type Item = Result<CalProgress, ReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.finished {
+            let response = match self.device.read_response() {
+                Ok(response) => response,
+                Err(ReadError::PipeError(e)) if e.kind() == std::io::ErrorKind::TimedOut => {
+                    self.idle += self.device.transport.timeout();
+                    if self.idle < self.idle_timeout {
+                        continue;
+                    }
+                    self.finish(true);
+                    return Some(Err(ReadError::ParseError(
+                        Message::new(MessageId::CalIdleTimeout)
+                            .with("idle", format!("{:?}", self.idle))
+                            .with("samples", self.samples),
+                    )));
+                }
+                Err(e) => return Some(Err(e)),
+            };
+            match response {
+                Response::UserCalSampleCount(count) => {
+                    self.samples = count;
+                    self.idle = Duration::ZERO;
+                    return Some(Ok(CalProgress::Sample(count)));
+                }
+                Response::UserCalScore(score) => {
+                    self.finish(false);
+                    return score.score().map(|score| Ok(CalProgress::Score(score)));
+                }
+                Response::Data(data) => return Some(Ok(CalProgress::Data(data))),
+                response => self.device.queue_unsolicited(response),
+            }
+        }
+        None
+    }
+}
+
+impl Drop for AutoCal<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.finish(true);
+        }
+    }
+}
+
 pub enum UserCalResponseReserved {
     /// The calibration score is automatically sent upon taking the final calibration point.
     UserCalScore {
@@ -268,10 +744,60 @@ pub enum UserCalResponseReserved {
     SampleCount(u32),
 }
 
+/// Which FIR filter SetFIRFilters and GetFIRFilters address, through the first two payload
+/// bytes. See [Device::set_fir_filters_for]
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+pub enum AxisSelector {
+    /// Byte 1 = 3, Byte 2 = 1: the magnetometer filter the manual documents, which smooths
+    /// heading. What [Device::set_fir_filters] uses
+    Mag,
+
+    /// Other byte values, for firmware with more filters. The manual documents no others
+    #[display(fmt = "Raw({}, {})", _0, _1)]
+    Raw(u8, u8),
+}
+
+impl AxisSelector {
+    /// Byte 1 and Byte 2 of the payload
+    pub fn bytes(&self) -> [u8; 2] {
+        match *self {
+            AxisSelector::Mag => [3, 1],
+            AxisSelector::Raw(byte_1, byte_2) => [byte_1, byte_2],
+        }
+    }
+}
+
+/// Which kind of calibration coefficients a set holds. See [Device::copy_coeff_set]
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+pub enum CoeffSetKind {
+    /// Magnetometer coefficients, selected by [ConfigPair::MagCoeffSet]
+    Mag = 0,
+
+    /// Accelerometer coefficients, selected by [ConfigPair::AccelCoeffSet]
+    Accel = 1,
+}
+
+impl CoeffSetKind {
+    /// The setting that selects the active set of this kind
+    pub fn config_id(&self) -> ConfigID {
+        match self {
+            CoeffSetKind::Mag => ConfigID::MagCoeffSet,
+            CoeffSetKind::Accel => ConfigID::AccelCoeffSet,
+        }
+    }
+}
+
+impl From<CoeffSetKind> for u8 {
+    fn from(kind: CoeffSetKind) -> Self {
+        kind as u8
+    }
+}
+
 /// Type of calibration to use when calibrating device
-#[derive(Debug, Display)]
+#[derive(Debug, Display, Default, Clone, Copy, PartialEq, Eq)]
 pub enum CalOption {
     /// Default. Recommended calibration method when >30° of pitch is possible. Can be used for between 20° and 30° of pitch, but accuracy will not be as good
+    #[default]
     FullRange = 10,
 
     /// Recommended when the available tilt range is limited to ≤5° . Can be used for 5° to 10° of tilt, but accuracy will not be as good.
@@ -290,8 +816,37 @@ pub enum CalOption {
     MagAndAccel = 110,
 }
 
-impl Default for CalOption {
-    fn default() -> Self {
-        CalOption::FullRange
+impl CalOption {
+    /// The kinds of coefficients this calibration replaces
+    pub fn coeff_kinds(&self) -> &'static [CoeffSetKind] {
+        match self {
+            CalOption::AccelOnly => &[CoeffSetKind::Accel],
+            CalOption::MagAndAccel => &[CoeffSetKind::Mag, CoeffSetKind::Accel],
+            _ => &[CoeffSetKind::Mag],
+        }
+    }
+}
+
+impl From<CalOption> for u8 {
+    fn from(option: CalOption) -> Self {
+        option as u8
+    }
+}
+
+impl TryFrom<u8> for CalOption {
+    type Error = ReadError;
+    fn try_from(value: u8) -> Result<Self, ReadError> {
+        use CalOption::*;
+        match value {
+            10 => Ok(FullRange),
+            20 => Ok(TwoDimensional),
+            30 => Ok(HardIronOnly),
+            40 => Ok(LimitedTilt),
+            100 => Ok(AccelOnly),
+            110 => Ok(MagAndAccel),
+            _ => Err(ReadError::ParseError(
+                Message::new(MessageId::UnknownCalOption).with("value", value),
+            )),
+        }
     }
 }
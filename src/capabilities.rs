@@ -0,0 +1,210 @@
+use crate::constants::MAX_FIR_TAPS;
+use crate::responses::{FirmwareVersion, Model};
+use crate::{Device, RWError};
+
+/// An operation or data component that may or may not be available on a given device/firmware
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    /// Polled acquisition via GetData
+    PolledData,
+
+    /// Continuous acquisition via StartContinuousMode/StopContinuousMode
+    ContinuousMode,
+
+    /// Magnetometer user calibration (StartCal, TakeUserCalSample, StopCal)
+    MagCalibration,
+
+    /// Accelerometer user calibration ([crate::calibration::CalOption::AccelOnly] and
+    /// [crate::calibration::CalOption::MagAndAccel])
+    AccelCalibration,
+
+    /// Restoring factory magnetometer/accelerometer coefficients
+    FactoryCoefficients,
+
+    /// Multiple calibration coefficient sets (MagCoeffSet, AccelCoeffSet, CopyCoeffSet)
+    MultipleCoeffSets,
+
+    /// FIR filter configuration (SetFIRFilters, GetFIRFilters)
+    FirFilters,
+
+    /// The MagAccuracy data component
+    MagAccuracy,
+
+    /// Powering the module down via PowerDown
+    PowerDown,
+
+    /// Serial number query via SerialNumber
+    SerialNumber,
+
+    /// The GyroX, GyroY and GyroZ data components
+    Gyro,
+
+    /// The Quaternion data component
+    Quaternion,
+
+    /// The HeadingStatus data component
+    HeadingStatus,
+
+    /// Reading calibration coefficients back from the device, so they can be archived and
+    /// written to another unit
+    CoefficientExport,
+
+    /// Board temperature and supply voltage. The PNI protocol documentation has no command that
+    /// reports them, so every device is treated as lacking this until one is documented
+    Diagnostics,
+}
+
+/// How confident we are that a device supports a [Feature]
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+pub enum SupportLevel {
+    /// The capability table lists this feature as supported by the connected device
+    KnownYes,
+
+    /// The capability table lists this feature as unsupported by the connected device
+    KnownNo,
+
+    /// The device hasn't been identified yet (see [Device::get_mod_info]), or it isn't in the
+    /// capability table
+    Unknown,
+}
+
+/// One row of the firmware capability table
+struct CapabilityEntry {
+    /// Device families the entry covers
+    models: &'static [Model],
+
+    /// Features this device family is known to support
+    supported: &'static [Feature],
+
+    /// Features this device family is known to lack, whatever the firmware
+    unsupported: &'static [Feature],
+
+    /// Most FIR filter taps the device accepts, if known
+    max_fir_taps: Option<usize>,
+}
+
+// Features not listed in either column of an entry are reported as Unknown. Entries cover every
+// firmware revision of a family: no feature is known to need a minimum revision, so none are
+// gated on one. Device::firmware_at_least is there for callers that learn otherwise
+const CAPABILITY_TABLE: &[CapabilityEntry] = &[
+    CapabilityEntry {
+        models: &[Model::TargetPoint3],
+        supported: &[
+            Feature::PolledData,
+            Feature::ContinuousMode,
+            Feature::MagCalibration,
+            Feature::AccelCalibration,
+            Feature::FactoryCoefficients,
+            Feature::MultipleCoeffSets,
+            Feature::FirFilters,
+            Feature::MagAccuracy,
+            Feature::PowerDown,
+            Feature::SerialNumber,
+        ],
+        unsupported: &[
+            Feature::Gyro,
+            Feature::Quaternion,
+            Feature::HeadingStatus,
+            Feature::CoefficientExport,
+            Feature::Diagnostics,
+        ],
+        max_fir_taps: Some(MAX_FIR_TAPS),
+    },
+    // TRAX and TRAX2 AHRS units speak the same protocol, with extra data components
+    CapabilityEntry {
+        models: &[Model::Trax, Model::Trax2],
+        supported: &[
+            Feature::PolledData,
+            Feature::ContinuousMode,
+            Feature::MagCalibration,
+            Feature::SerialNumber,
+            Feature::Gyro,
+            Feature::Quaternion,
+            Feature::HeadingStatus,
+        ],
+        unsupported: &[Feature::Diagnostics],
+        max_fir_taps: Some(MAX_FIR_TAPS),
+    },
+];
+
+/// What the connected device can do, as far as its identity tells us. See [Device::capabilities]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    /// [None] until the device has been identified with [Device::get_mod_info], unless it was
+    /// opened with a [crate::connection::Profile]
+    pub model: Option<Model>,
+    pub firmware: Option<FirmwareVersion>,
+
+    /// The GyroX, GyroY and GyroZ data components
+    pub gyro: SupportLevel,
+
+    /// Coefficient sets other than 0, and copying between sets
+    pub multiple_coeff_sets: SupportLevel,
+
+    pub fir_filters: SupportLevel,
+
+    /// Most FIR filter taps the device accepts, if known
+    pub max_fir_taps: Option<usize>,
+}
+
+impl Device {
+    fn capability_entry(&self) -> Option<&'static CapabilityEntry> {
+        let model = match &self.mod_info {
+            Some(mod_info) => mod_info.model,
+            None => self.profile.model()?,
+        };
+        CAPABILITY_TABLE
+            .iter()
+            .find(|entry| entry.models.contains(&model))
+    }
+
+    /// Looks up whether the connected device supports `feature`, based on the identity cached by
+    /// the last successful [Device::get_mod_info], or failing that the
+    /// [crate::connection::Profile] it was opened with. This doesn't talk to the device, so it's cheap
+    /// enough to call when deciding what to grey-out in a UI
+    pub fn supports(&self, feature: Feature) -> SupportLevel {
+        let Some(entry) = self.capability_entry() else {
+            return SupportLevel::Unknown;
+        };
+
+        if entry.unsupported.contains(&feature) {
+            SupportLevel::KnownNo
+        } else if entry.supported.contains(&feature) {
+            SupportLevel::KnownYes
+        } else {
+            SupportLevel::Unknown
+        }
+    }
+
+    /// Capabilities of the connected device, from the identity and firmware version cached by
+    /// the last successful [Device::get_mod_info]. Doesn't talk to the device
+    pub fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            model: self.model().or(self.profile.model()),
+            firmware: self.firmware_version(),
+            gyro: self.supports(Feature::Gyro),
+            multiple_coeff_sets: self.supports(Feature::MultipleCoeffSets),
+            fir_filters: self.supports(Feature::FirFilters),
+            max_fir_taps: self.capability_entry().and_then(|entry| entry.max_fir_taps),
+        }
+    }
+
+    /// Fails with [RWError::UnsupportedByDevice] if the device is known to lack `feature`, so
+    /// callers get a clear error instead of a timeout or garbled response. Devices that haven't
+    /// been identified are given the benefit of the doubt
+    pub(crate) fn require(&self, feature: Feature) -> Result<(), RWError> {
+        if self.supports(feature) != SupportLevel::KnownNo {
+            return Ok(());
+        }
+        Err(self.unsupported(feature))
+    }
+
+    /// [RWError::UnsupportedByDevice] for `feature` on the connected device
+    pub(crate) fn unsupported(&self, feature: Feature) -> RWError {
+        let device = match &self.mod_info {
+            Some(info) => format!("{} {}", info.device_type.trim_end(), info.revision.trim_end()),
+            None => String::new(),
+        };
+        RWError::UnsupportedByDevice { feature, device }
+    }
+}
@@ -0,0 +1,175 @@
+use std::fmt;
+
+/// Identifies a user-facing error message independently of its wording. Match on these rather
+/// than on message text, which may be reworded or translated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum MessageId {
+    UnexpectedResponse,
+    ResponseNotReceived,
+    FrameTooShort,
+    InvalidUtf8,
+    InvalidBool,
+    UnknownDataId,
+    DataComponentsNotSet,
+    UnsupportedDataId,
+    UnknownHeadingStatus,
+    InvalidBaud,
+    InvalidMountingRef,
+    SaveFailed,
+    MissingHeading,
+    HeadingRequired,
+    HeadingsUnsteady,
+    OutOfRange,
+    UnknownCommand,
+    UnknownConfigId,
+    UnknownCalOption,
+    MissingAccel,
+    MalformedSentence,
+    FrameTooLong,
+    ComponentsMismatch,
+    CoeffSetMismatch,
+    FirAxisMismatch,
+    CalIdleTimeout,
+    CalEndedWithoutScore,
+}
+
+impl MessageId {
+    /// Stable machine-readable key, e.g. for looking up a translation
+    pub fn key(&self) -> &'static str {
+        match self {
+            MessageId::UnexpectedResponse => "unexpected_response",
+            MessageId::ResponseNotReceived => "response_not_received",
+            MessageId::FrameTooShort => "frame_too_short",
+            MessageId::InvalidUtf8 => "invalid_utf8",
+            MessageId::InvalidBool => "invalid_bool",
+            MessageId::UnknownDataId => "unknown_data_id",
+            MessageId::DataComponentsNotSet => "data_components_not_set",
+            MessageId::UnsupportedDataId => "unsupported_data_id",
+            MessageId::UnknownHeadingStatus => "unknown_heading_status",
+            MessageId::InvalidBaud => "invalid_baud",
+            MessageId::InvalidMountingRef => "invalid_mounting_ref",
+            MessageId::SaveFailed => "save_failed",
+            MessageId::MissingHeading => "missing_heading",
+            MessageId::HeadingRequired => "heading_required",
+            MessageId::HeadingsUnsteady => "headings_unsteady",
+            MessageId::OutOfRange => "out_of_range",
+            MessageId::UnknownCommand => "unknown_command",
+            MessageId::UnknownConfigId => "unknown_config_id",
+            MessageId::UnknownCalOption => "unknown_cal_option",
+            MessageId::MissingAccel => "missing_accel",
+            MessageId::MalformedSentence => "malformed_sentence",
+            MessageId::FrameTooLong => "frame_too_long",
+            MessageId::ComponentsMismatch => "components_mismatch",
+            MessageId::CoeffSetMismatch => "coeff_set_mismatch",
+            MessageId::FirAxisMismatch => "fir_axis_mismatch",
+            MessageId::CalIdleTimeout => "cal_idle_timeout",
+            MessageId::CalEndedWithoutScore => "cal_ended_without_score",
+        }
+    }
+
+    /// English text, with `{name}` placeholders for the message's parameters
+    pub fn template(&self) -> &'static str {
+        match self {
+            MessageId::UnexpectedResponse => "Unexpected response type. Got {command}",
+            MessageId::ResponseNotReceived => "No {response} among the next {frames} frames",
+            MessageId::FrameTooShort => {
+                "Frame length {length} is too short to hold a command and checksum"
+            }
+            MessageId::InvalidUtf8 => "UTF8 String couldn't be parsed: {error}",
+            MessageId::InvalidBool => {
+                "Boolean must be 0 for false, 1 for true and nothing else. Got {value}. If this \
+                 firmware is known to send other values, see Device::set_lenient_bools"
+            }
+            MessageId::UnknownDataId => "Unknown DataID from device: {id}",
+            MessageId::DataComponentsNotSet => {
+                "Unknown DataID from device: {id}. This ID is usually detected when \
+                 set_data_components is not called before calling get_data. You must specify what \
+                 data you want from the device before parsing data back from the device."
+            }
+            MessageId::UnsupportedDataId => "DataID {id} isn't supported by this device",
+            MessageId::UnknownHeadingStatus => "Unknown HeadingStatus from device: {value}",
+            MessageId::InvalidBaud => {
+                "Baud descriptor from device must be one of [4,14], the only supported bauds. Got {value}"
+            }
+            MessageId::InvalidMountingRef => "MountingRef must be within [1, 16]. Got {value}",
+            MessageId::SaveFailed => {
+                "Recieved error code {code} from device, settings not saved succesfully"
+            }
+            MessageId::MissingHeading => "Device didn't return a heading",
+            MessageId::HeadingRequired => {
+                "Data components must include heading to judge stability"
+            }
+            MessageId::HeadingsUnsteady => "Headings were too unsteady to average",
+            MessageId::OutOfRange => "{name} must be within {range}. Got {value}",
+            MessageId::UnknownCommand => "Unknown command from device: {value}",
+            MessageId::UnknownConfigId => "Unknown ConfigID: {value}",
+            MessageId::UnknownCalOption => "Unknown CalOption: {value}",
+            MessageId::MissingAccel => "Device didn't return AccelX, AccelY and AccelZ",
+            MessageId::MalformedSentence => "Malformed ASCII sentence: {sentence}",
+            MessageId::FrameTooLong => "Frame length {length} is longer than the maximum of {max}",
+            MessageId::ComponentsMismatch => {
+                "Data components were set to {requested}, but the device sent {received}"
+            }
+            MessageId::CoeffSetMismatch => {
+                "Calibrated into {kind} coefficient set {expected}, but set {actual} is active"
+            }
+            MessageId::FirAxisMismatch => {
+                "Asked for the {requested} FIR filter, but the device answered for {received}"
+            }
+            MessageId::CalIdleTimeout => {
+                "No calibration sample point taken for {idle} after {samples} points, calibration \
+                 stopped"
+            }
+            MessageId::CalEndedWithoutScore => {
+                "Calibration ended after {samples} sample points without a score"
+            }
+        }
+    }
+}
+
+/// A user-facing message: a [MessageId] plus the values for its placeholders. Displays using the
+/// English template; applications can format it with their own with [Message::format_with]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub id: MessageId,
+    pub params: Vec<(&'static str, String)>,
+}
+
+impl Message {
+    pub fn new(id: MessageId) -> Self {
+        Self {
+            id,
+            params: Vec::new(),
+        }
+    }
+
+    /// Adds a value for the `{name}` placeholder
+    pub fn with(mut self, name: &'static str, value: impl ToString) -> Self {
+        self.params.push((name, value.to_string()));
+        self
+    }
+
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(param, _)| *param == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Fills `template`, e.g. a translation of [MessageId::template], with this message's
+    /// parameters. Placeholders without a value are left as they are
+    pub fn format_with(&self, template: &str) -> String {
+        let mut text = template.to_string();
+        for (name, value) in &self.params {
+            text = text.replace(&format!("{{{}}}", name), value);
+        }
+        text
+    }
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.format_with(self.id.template()))
+    }
+}
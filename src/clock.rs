@@ -0,0 +1,140 @@
+use crate::PowerUpPolicy;
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Source of time for retries, timeouts and scheduling. [Device](crate::Device) uses
+/// [SystemClock] unless given another with [crate::Device::set_clock]; tests can substitute a
+/// [SimulatedClock] to fast-forward through long waits
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+
+    /// Blocks for `duration`, as measured by this clock
+    fn sleep(&self, duration: Duration);
+}
+
+/// The real monotonic clock
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration)
+    }
+}
+
+/// A clock that only moves when told to. Sleeping advances it immediately rather than blocking,
+/// so hours of retries and timeouts run in microseconds. Clones share the same time
+#[derive(Debug, Clone)]
+pub struct SimulatedClock {
+    now: Arc<Mutex<Instant>>,
+}
+
+impl Default for SimulatedClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SimulatedClock {
+    /// Starts at the real current time, so [Instant]s from it can be compared with others
+    pub fn new() -> Self {
+        Self {
+            now: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    /// Moves time forward by `duration`
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap() += duration;
+    }
+}
+
+impl Clock for SimulatedClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().unwrap()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration)
+    }
+}
+
+/// Exponential backoff between attempts, bounded by a number of attempts and an overall time
+/// budget. Time is measured against a [Clock]
+pub struct Backoff {
+    clock: Arc<dyn Clock>,
+    start: Instant,
+    delay: Duration,
+    max_delay: Duration,
+    budget: Duration,
+    attempts: u32,
+    max_attempts: u32,
+}
+
+impl Backoff {
+    pub fn new(clock: Arc<dyn Clock>, policy: &PowerUpPolicy) -> Self {
+        Self::with_limits(
+            clock,
+            policy.initial_backoff,
+            policy.max_backoff,
+            policy.attempts,
+            policy.max_wake_duration,
+        )
+    }
+
+    /// Backoff starting at `initial` and doubling up to `max`, for at most `attempts` attempts
+    /// within `budget`
+    pub fn with_limits(
+        clock: Arc<dyn Clock>,
+        initial: Duration,
+        max: Duration,
+        attempts: u32,
+        budget: Duration,
+    ) -> Self {
+        Self {
+            start: clock.now(),
+            clock,
+            delay: initial,
+            max_delay: max,
+            budget,
+            attempts: 0,
+            max_attempts: attempts,
+        }
+    }
+
+    /// Records that an attempt is being made
+    pub fn attempt(&mut self) {
+        self.attempts += 1;
+    }
+
+    /// Sleeps before the next attempt and returns true, or returns false straight away if the
+    /// attempts are used up or waiting would overrun the time budget
+    pub fn wait(&mut self) -> bool {
+        if self.attempts >= self.max_attempts || self.elapsed() + self.delay > self.budget {
+            return false;
+        }
+        self.clock.sleep(self.delay);
+        self.delay = (self.delay * 2).min(self.max_delay);
+        true
+    }
+
+    /// How long [Backoff::wait] will sleep next time
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+
+    /// Attempts recorded so far
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// Time since the backoff was created
+    pub fn elapsed(&self) -> Duration {
+        self.clock.now().saturating_duration_since(self.start)
+    }
+}
@@ -0,0 +1,436 @@
+// A frame is laid out as: 2 byte big-endian length (of the whole frame, including the length and
+// checksum), 1 command byte, the payload, and a 2 byte big-endian CRC-16/XMODEM of everything
+// before it.
+
+use core::hash::Hasher;
+
+pub use crate::constants::{FRAME_OVERHEAD, MAX_FRAME_LEN};
+use crate::constants::{FLAG_VALUE_LEN, FLOAT_VALUE_LEN, QUATERNION_VALUE_LEN};
+
+/// Problem encoding or decoding a frame
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+pub enum CodecError {
+    /// More bytes are needed before the frame can be decoded
+    Incomplete,
+
+    /// Length field is smaller than [FRAME_OVERHEAD]
+    #[display(fmt = "TooShort {{ len: {} }}", len)]
+    TooShort { len: usize },
+
+    /// Length field (or payload being encoded) is larger than [MAX_FRAME_LEN] or the buffer
+    /// provided to hold it
+    #[display(fmt = "TooLong {{ len: {} }}", len)]
+    TooLong { len: usize },
+
+    /// Checksum for frame didn't match
+    #[display(
+        fmt = "ChecksumMismatch {{ expected: {}, actual: {} }}",
+        expected,
+        actual
+    )]
+    ChecksumMismatch { expected: u16, actual: u16 },
+
+    /// Payload ended before a field could be read
+    UnexpectedEnd,
+
+    /// Boolean field was neither 0 nor 1
+    #[display(fmt = "InvalidBool({})", _0)]
+    InvalidBool(u8),
+}
+
+/// CRC-16/XMODEM of `bytes`. If you are porting this to another language, note the CRC algorithm
+/// XMODEM may also be called CCITT or ITU, but is different from CCITT-FALSE and AUG-CCITT
+pub fn checksum(bytes: &[u8]) -> u16 {
+    let mut crc = crc16::State::<crc16::XMODEM>::new();
+    crc.update(bytes);
+    crc.finish() as u16
+}
+
+/// Total length of a frame carrying `payload_len` bytes of payload
+pub fn frame_len(payload_len: usize) -> Result<u16, CodecError> {
+    let len = payload_len + FRAME_OVERHEAD;
+    if len > MAX_FRAME_LEN {
+        return Err(CodecError::TooLong { len });
+    }
+    Ok(len as u16)
+}
+
+/// Writes a complete frame into `out`, returning the number of bytes used
+pub fn encode_frame(command: u8, payload: &[u8], out: &mut [u8]) -> Result<usize, CodecError> {
+    let len = frame_len(payload.len())? as usize;
+    if out.len() < len {
+        return Err(CodecError::TooLong { len });
+    }
+
+    out[..2].copy_from_slice(&(len as u16).to_be_bytes());
+    out[2] = command;
+    out[3..3 + payload.len()].copy_from_slice(payload);
+    let crc = checksum(&out[..len - 2]);
+    out[len - 2..len].copy_from_slice(&crc.to_be_bytes());
+    Ok(len)
+}
+
+/// A decoded frame, borrowing its payload from the buffer it was decoded from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame<'a> {
+    /// Raw command byte. See [crate::command::Command] for known values
+    pub command: u8,
+
+    /// Payload bytes, excluding the length, command and checksum
+    pub payload: &'a [u8],
+}
+
+/// Decodes the frame at the start of `bytes`, verifying its length and checksum. Returns the
+/// frame and the number of bytes it occupied, or [CodecError::Incomplete] if `bytes` doesn't yet
+/// hold the whole frame
+pub fn decode_frame(bytes: &[u8]) -> Result<(Frame<'_>, usize), CodecError> {
+    if bytes.len() < 2 {
+        return Err(CodecError::Incomplete);
+    }
+
+    let len = u16::from_be_bytes([bytes[0], bytes[1]]) as usize;
+    if len < FRAME_OVERHEAD {
+        return Err(CodecError::TooShort { len });
+    }
+    if len > MAX_FRAME_LEN {
+        return Err(CodecError::TooLong { len });
+    }
+    if bytes.len() < len {
+        return Err(CodecError::Incomplete);
+    }
+
+    let expected = checksum(&bytes[..len - 2]);
+    let actual = u16::from_be_bytes([bytes[len - 2], bytes[len - 1]]);
+    if expected != actual {
+        return Err(CodecError::ChecksumMismatch { expected, actual });
+    }
+
+    Ok((
+        Frame {
+            command: bytes[2],
+            payload: &bytes[3..len - 2],
+        },
+        len,
+    ))
+}
+
+/// Reassembles frames from a byte-at-a-time stream (e.g. a UART interrupt) into a fixed buffer of
+/// `N` bytes. Frames longer than `N` are rejected
+pub struct FrameDecoder<const N: usize> {
+    buffer: [u8; N],
+
+    /// Bytes of the frame currently being received
+    len: usize,
+
+    /// Length of the frame just completed, which is still at the start of `buffer`
+    complete: usize,
+}
+
+impl<const N: usize> Default for FrameDecoder<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> FrameDecoder<N> {
+    pub const fn new() -> Self {
+        Self {
+            buffer: [0u8; N],
+            len: 0,
+            complete: 0,
+        }
+    }
+
+    /// Adds one received byte. Returns the frame once its final byte arrives, and discards the
+    /// buffered bytes on any error so decoding restarts with the next byte
+    pub fn push(&mut self, byte: u8) -> Result<Option<Frame<'_>>, CodecError> {
+        self.complete = 0;
+        if self.len == N {
+            self.len = 0;
+            return Err(CodecError::TooLong { len: N + 1 });
+        }
+        self.buffer[self.len] = byte;
+        self.len += 1;
+
+        if self.len == 2 {
+            let len = u16::from_be_bytes([self.buffer[0], self.buffer[1]]) as usize;
+            if len > N {
+                self.len = 0;
+                return Err(CodecError::TooLong { len });
+            }
+        }
+
+        match decode_frame(&self.buffer[..self.len]) {
+            Ok(_) => {
+                self.complete = self.len;
+                self.len = 0;
+                Ok(self.last_frame())
+            }
+            Err(CodecError::Incomplete) => Ok(None),
+            Err(e) => {
+                self.len = 0;
+                Err(e)
+            }
+        }
+    }
+
+    /// The frame completed by the most recent [FrameDecoder::push], if it completed one
+    pub fn last_frame(&self) -> Option<Frame<'_>> {
+        decode_frame(&self.buffer[..self.complete])
+            .ok()
+            .map(|(frame, _)| frame)
+    }
+
+    /// Discards any partially received frame
+    pub fn reset(&mut self) {
+        self.len = 0;
+        self.complete = 0;
+    }
+}
+
+/// Cursor for reading big-endian fields out of a payload
+#[derive(Debug, Clone)]
+pub struct PayloadReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> PayloadReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    /// Bytes not yet read
+    pub fn remaining(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    pub fn read_bytes<const N: usize>(&mut self) -> Result<[u8; N], CodecError> {
+        if self.bytes.len() < N {
+            return Err(CodecError::UnexpectedEnd);
+        }
+        let (head, tail) = self.bytes.split_at(N);
+        self.bytes = tail;
+        let mut out = [0u8; N];
+        out.copy_from_slice(head);
+        Ok(out)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, CodecError> {
+        Ok(self.read_bytes::<1>()?[0])
+    }
+
+    pub fn read_i8(&mut self) -> Result<i8, CodecError> {
+        Ok(i8::from_be_bytes(self.read_bytes()?))
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, CodecError> {
+        Ok(u16::from_be_bytes(self.read_bytes()?))
+    }
+
+    pub fn read_i16(&mut self) -> Result<i16, CodecError> {
+        Ok(i16::from_be_bytes(self.read_bytes()?))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, CodecError> {
+        Ok(u32::from_be_bytes(self.read_bytes()?))
+    }
+
+    pub fn read_i32(&mut self) -> Result<i32, CodecError> {
+        Ok(i32::from_be_bytes(self.read_bytes()?))
+    }
+
+    pub fn read_f32(&mut self) -> Result<f32, CodecError> {
+        Ok(f32::from_be_bytes(self.read_bytes()?))
+    }
+
+    pub fn read_f64(&mut self) -> Result<f64, CodecError> {
+        Ok(f64::from_be_bytes(self.read_bytes()?))
+    }
+
+    /// Booleans must be exactly 0 or 1
+    pub fn read_bool(&mut self) -> Result<bool, CodecError> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            other => Err(CodecError::InvalidBool(other)),
+        }
+    }
+}
+
+/// Cursor for writing big-endian fields into a caller-provided payload buffer
+#[derive(Debug)]
+pub struct PayloadWriter<'a> {
+    buffer: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> PayloadWriter<'a> {
+    pub fn new(buffer: &'a mut [u8]) -> Self {
+        Self { buffer, len: 0 }
+    }
+
+    /// Payload written so far
+    pub fn payload(&self) -> &[u8] {
+        &self.buffer[..self.len]
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), CodecError> {
+        let end = self.len + bytes.len();
+        if end > self.buffer.len() {
+            return Err(CodecError::TooLong { len: end });
+        }
+        self.buffer[self.len..end].copy_from_slice(bytes);
+        self.len = end;
+        Ok(())
+    }
+
+    pub fn write_u8(&mut self, value: u8) -> Result<(), CodecError> {
+        self.write_bytes(&[value])
+    }
+
+    pub fn write_u16(&mut self, value: u16) -> Result<(), CodecError> {
+        self.write_bytes(&value.to_be_bytes())
+    }
+
+    pub fn write_u32(&mut self, value: u32) -> Result<(), CodecError> {
+        self.write_bytes(&value.to_be_bytes())
+    }
+
+    pub fn write_f32(&mut self, value: f32) -> Result<(), CodecError> {
+        self.write_bytes(&value.to_be_bytes())
+    }
+
+    pub fn write_f64(&mut self, value: f64) -> Result<(), CodecError> {
+        self.write_bytes(&value.to_be_bytes())
+    }
+
+    /// Writes exactly 0 or 1
+    pub fn write_bool(&mut self, value: bool) -> Result<(), CodecError> {
+        self.write_u8(if value { 1 } else { 0 })
+    }
+}
+
+/// Size of the value of the data component with raw ID `id` in a GetDataResp, or [None] for IDs
+/// this crate doesn't know. See [crate::acquisition::DataID]
+pub const fn component_value_len(id: u8) -> Option<usize> {
+    match id {
+        // Distortion, CalStatus, HeadingStatus
+        8 | 9 | 79 => Some(FLAG_VALUE_LEN),
+        // Quaternion
+        77 => Some(QUATERNION_VALUE_LEN),
+        5 | 7 | 21..=25 | 27..=29 | 74..=76 | 88 => Some(FLOAT_VALUE_LEN),
+        _ => None,
+    }
+}
+
+/// Value of one data component in a [FixedData]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ComponentValue {
+    Float(f32),
+
+    /// Distortion and CalStatus (0 or 1), or HeadingStatus
+    Flag(u8),
+
+    Quaternion([f32; 4]),
+}
+
+/// Problem parsing a GetDataResp payload into a [FixedData]
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+pub enum DataPayloadError {
+    Codec(CodecError),
+
+    /// A component ID with no known size, so nothing after it can be read
+    #[display(fmt = "UnknownComponent({})", _0)]
+    UnknownComponent(u8),
+
+    /// The payload has more components than the [FixedData] holds
+    #[display(fmt = "TooManyComponents {{ count: {}, capacity: {} }}", count, capacity)]
+    TooManyComponents { count: usize, capacity: usize },
+}
+
+impl From<CodecError> for DataPayloadError {
+    fn from(value: CodecError) -> Self {
+        Self::Codec(value)
+    }
+}
+
+/// A GetDataResp payload parsed into room for at most `N` components, without allocating. For
+/// heapless targets, and for hot loops that reuse one with [FixedData::parse_into]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FixedData<const N: usize> {
+    components: [(u8, ComponentValue); N],
+    len: usize,
+}
+
+impl<const N: usize> Default for FixedData<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> FixedData<N> {
+    pub const fn new() -> Self {
+        Self {
+            components: [(0, ComponentValue::Flag(0)); N],
+            len: 0,
+        }
+    }
+
+    /// Parses a GetDataResp payload: a component count, then each component's ID and value
+    pub fn parse(payload: &[u8]) -> Result<Self, DataPayloadError> {
+        let mut data = Self::new();
+        data.parse_into(payload)?;
+        Ok(data)
+    }
+
+    /// Like [FixedData::parse], overwriting this one. On error its contents are unspecified
+    pub fn parse_into(&mut self, payload: &[u8]) -> Result<(), DataPayloadError> {
+        let mut reader = PayloadReader::new(payload);
+        let count = reader.read_u8()? as usize;
+        if count > N {
+            return Err(DataPayloadError::TooManyComponents { count, capacity: N });
+        }
+        self.len = 0;
+        for _ in 0..count {
+            let id = reader.read_u8()?;
+            let value = match component_value_len(id) {
+                Some(FLAG_VALUE_LEN) => ComponentValue::Flag(reader.read_u8()?),
+                Some(QUATERNION_VALUE_LEN) => ComponentValue::Quaternion([
+                    reader.read_f32()?,
+                    reader.read_f32()?,
+                    reader.read_f32()?,
+                    reader.read_f32()?,
+                ]),
+                Some(_) => ComponentValue::Float(reader.read_f32()?),
+                None => return Err(DataPayloadError::UnknownComponent(id)),
+            };
+            self.components[self.len] = (id, value);
+            self.len += 1;
+        }
+        if !reader.remaining().is_empty() {
+            return Err(CodecError::TooLong { len: payload.len() }.into());
+        }
+        Ok(())
+    }
+
+    /// Components as (raw ID, value), in the order the device sent them
+    pub fn components(&self) -> &[(u8, ComponentValue)] {
+        &self.components[..self.len]
+    }
+
+    /// Value of the component with raw ID `id`, if the payload had it
+    pub fn get(&self, id: u8) -> Option<ComponentValue> {
+        self.components()
+            .iter()
+            .find(|(component, _)| *component == id)
+            .map(|(_, value)| *value)
+    }
+
+    /// Value of a float component such as heading (raw ID 5)
+    pub fn float(&self, id: u8) -> Option<f32> {
+        match self.get(id)? {
+            ComponentValue::Float(value) => Some(value),
+            _ => None,
+        }
+    }
+}
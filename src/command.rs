@@ -1,5 +1,10 @@
 /// The type of command being sent/recieved from the device. All frames have a command.
+///
+/// Newer firmware may add commands, and so may later versions of this enum. Frames with command
+/// bytes it doesn't know are surfaced as [crate::dispatch::Response::Unknown]
 #[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum Command {
     /// Queries the device’s type and firmware revision.
     GetModInfo = 0x01,
@@ -113,13 +118,93 @@ pub enum Command {
     SerialNumberResp = 0x35,
 }
 
+/// A command byte that doesn't match any [Command]
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+#[display(fmt = "Unknown command {:#04x}", _0)]
+pub struct UnknownCommand(pub u8);
+
 impl Command {
-    // [unsafe]: This code pulls the integer representation of the enum, since the enum is repr(u8)
-    // and the u8 is the first element in the enum, the pointer cast will work. Additionally, this
-    // pattern has been directly copied from the rust documentation for error codes, with modification
-    // only to its parameters and return values
-    // src: https://github.com/rust-lang/rust/blob/master/compiler/rustc_error_codes/src/error_codes/E0732.md
-    pub(crate) fn discriminant(&self) -> u8 {
-        unsafe { *(self as *const Self as *const u8) }
+    /// Whether this can be sent while the device streams in continuous mode. Anything else
+    /// waits for a response that arrives among data frames, or not at all
+    pub fn allowed_while_streaming(self) -> bool {
+        use Command::*;
+        matches!(self, StartContinuousMode | StopContinuousMode | PowerDown)
+    }
+
+    /// The frame the device answers this command with, for commands that always get the same
+    /// one. [None] for responses themselves, for commands the device doesn't answer (such as
+    /// StopCal), and for TakeUserCalSample, which gets a sample count or a score
+    pub fn response(self) -> Option<Command> {
+        use Command::*;
+        match self {
+            GetModInfo => Some(GetModInfoResp),
+            GetData => Some(GetDataResp),
+            SetConfig => Some(SetConfigDone),
+            GetConfig => Some(GetConfigResp),
+            Save => Some(SaveDone),
+            StartCal => Some(UserCalSampleCount),
+            SetFIRFilters => Some(SetFIRFiltersDone),
+            GetFIRFilters => Some(GetFIRFiltersResp),
+            PowerDown => Some(PowerDownDone),
+            SetAcqParams => Some(SetAcqParamsDone),
+            GetAcqParams => Some(GetAcqParamsResp),
+            FactoryMagCoeff => Some(FactoryMagCoeffDone),
+            FactorylAccelCoeff => Some(FactoryAccelCoeffDone),
+            CopyCoeffSet => Some(CopyCoeffSetDone),
+            SerialNumber => Some(SerialNumberResp),
+            _ => None,
+        }
+    }
+}
+
+impl From<Command> for u8 {
+    fn from(command: Command) -> Self {
+        command as u8
+    }
+}
+
+impl TryFrom<u8> for Command {
+    type Error = UnknownCommand;
+    fn try_from(value: u8) -> Result<Self, UnknownCommand> {
+        match value {
+            0x01 => Ok(Command::GetModInfo),
+            0x02 => Ok(Command::GetModInfoResp),
+            0x03 => Ok(Command::SetDataComponents),
+            0x04 => Ok(Command::GetData),
+            0x05 => Ok(Command::GetDataResp),
+            0x06 => Ok(Command::SetConfig),
+            0x07 => Ok(Command::GetConfig),
+            0x08 => Ok(Command::GetConfigResp),
+            0x09 => Ok(Command::Save),
+            0x0A => Ok(Command::StartCal),
+            0x0B => Ok(Command::StopCal),
+            0x0C => Ok(Command::SetFIRFilters),
+            0x0D => Ok(Command::GetFIRFilters),
+            0x0E => Ok(Command::GetFIRFiltersResp),
+            0x0F => Ok(Command::PowerDown),
+            0x10 => Ok(Command::SaveDone),
+            0x11 => Ok(Command::UserCalSampleCount),
+            0x12 => Ok(Command::UserCalScore),
+            0x13 => Ok(Command::SetConfigDone),
+            0x14 => Ok(Command::SetFIRFiltersDone),
+            0x15 => Ok(Command::StartContinuousMode),
+            0x16 => Ok(Command::StopContinuousMode),
+            0x17 => Ok(Command::PowerUpDone),
+            0x18 => Ok(Command::SetAcqParams),
+            0x19 => Ok(Command::GetAcqParams),
+            0x1A => Ok(Command::SetAcqParamsDone),
+            0x1B => Ok(Command::GetAcqParamsResp),
+            0x1C => Ok(Command::PowerDownDone),
+            0x1D => Ok(Command::FactoryMagCoeff),
+            0x1E => Ok(Command::FactoryMagCoeffDone),
+            0x1F => Ok(Command::TakeUserCalSample),
+            0x24 => Ok(Command::FactorylAccelCoeff),
+            0x25 => Ok(Command::FactoryAccelCoeffDone),
+            0x2B => Ok(Command::CopyCoeffSet),
+            0x2C => Ok(Command::CopyCoeffSetDone),
+            0x34 => Ok(Command::SerialNumber),
+            0x35 => Ok(Command::SerialNumberResp),
+            _ => Err(UnknownCommand(value)),
+        }
     }
 }
@@ -1,10 +1,17 @@
+use crate::acquisition::{AngleFormat, AngleUnits, NorthReference};
+use crate::capabilities::Feature;
+use crate::catalog::{Message, MessageId};
 use crate::command::Command;
+use crate::constants::{MAX_CAL_POINTS, MAX_COEFF_SET, MIN_CAL_POINTS};
 use crate::responses::Get;
 use crate::{RWError, ReadError, Device};
 
+use std::collections::HashMap;
+use std::fmt;
+
 /// Represents a configuration parameter ID only. See also: ConfigParam, which represents ID +
 /// value
-#[derive(Debug, Display, Clone)]
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ConfigID {
     /// This sets the declination angle to determine True North heading.
     /// Positive declination is easterly declination and negative is westerly declination.  This is not applied unless TrueNorth is set to TRUE.
@@ -55,6 +62,7 @@ pub enum ConfigID {
 
 /// Represents a configuration parameter and setting. See also: [ConfigID] for the name of a
 /// configuration parameter only
+#[derive(Debug, Clone, PartialEq)]
 #[repr(u8)]
 pub enum ConfigPair {
     /// This sets the declination angle to determine True North heading.
@@ -83,7 +91,7 @@ pub enum ConfigPair {
     /// Sensor Default: true
     UserCalAutoSampling(bool) = 13,
 
-    /// Baud rate index value. A power-down, power-up cycle is required when changing the baud rate. Additionally, you will need to re-construct the tp3 object and provide a [SerialPort] with the chosen baud.
+    /// Baud rate index value. A power-down, power-up cycle is required when changing the baud rate. Additionally, you will need to re-construct the tp3 object and provide a [SerialPort] with the chosen baud. [Device::change_baud] does all of this.
     /// Library & Sensor Default = 38400. Range: One of { 2400, 3600, 4800, 7200, 9600, 14400, 19200, 28800, 38400, 57600, 115200 }
     BaudRate(Baud) = 14,
 
@@ -105,13 +113,135 @@ pub enum ConfigPair {
 }
 
 impl ConfigPair {
-    // [unsafe]: This code pulls the integer representation of the enum, since the enum is repr(u8)
-    // and the u8 is the first element in the enum, the pointer cast will work. Additionally, this
-    // pattern has been directly copied from the rust documentation for error codes, with modification
-    // only to its parameters and return values
-    // src: https://github.com/rust-lang/rust/blob/master/compiler/rustc_error_codes/src/error_codes/E0732.md
-    fn discriminant(&self) -> u8 {
-        unsafe { *(self as *const Self as *const u8) }
+    /// Checks the value is within the range the device documents for it. The device doesn't
+    /// reject out-of-range values itself; it silently accepts or misbehaves on them
+    pub fn validate(&self) -> Result<(), Message> {
+        fn out_of_range(name: &str, value: impl ToString, range: &str) -> Result<(), Message> {
+            Err(Message::new(MessageId::OutOfRange)
+                .with("name", name)
+                .with("value", value)
+                .with("range", range))
+        }
+
+        match *self {
+            ConfigPair::Declination(value) if !(-180.0..=180.0).contains(&value) => {
+                out_of_range("Declination", value, "[-180, 180]")
+            }
+            ConfigPair::UserCalNumPoints(value)
+                if !(MIN_CAL_POINTS..=MAX_CAL_POINTS).contains(&value) =>
+            {
+                out_of_range("UserCalNumPoints", value, "[4, 18]")
+            }
+            ConfigPair::MagCoeffSet(value) if value > MAX_COEFF_SET as u32 => {
+                out_of_range("MagCoeffSet", value, "[0, 7]")
+            }
+            ConfigPair::AccelCoeffSet(value) if value > MAX_COEFF_SET as u32 => {
+                out_of_range("AccelCoeffSet", value, "[0, 7]")
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The parameter this sets
+    pub fn id(&self) -> ConfigID {
+        match self {
+            ConfigPair::Declination(_) => ConfigID::Declination,
+            ConfigPair::TrueNorth(_) => ConfigID::TrueNorth,
+            ConfigPair::BigEndian(_) => ConfigID::BigEndian,
+            ConfigPair::MountingRef(_) => ConfigID::MountingRef,
+            ConfigPair::UserCalNumPoints(_) => ConfigID::UserCalNumPoints,
+            ConfigPair::UserCalAutoSampling(_) => ConfigID::UserCalAutoSampling,
+            ConfigPair::BaudRate(_) => ConfigID::BaudRate,
+            ConfigPair::MilOut(_) => ConfigID::MilOut,
+            ConfigPair::HPRDuringCal(_) => ConfigID::HPRDuringCal,
+            ConfigPair::MagCoeffSet(_) => ConfigID::MagCoeffSet,
+            ConfigPair::AccelCoeffSet(_) => ConfigID::AccelCoeffSet,
+        }
+    }
+}
+
+/// Reads the value of setting `id`, which is laid out the same in SetConfig and GetConfigResp
+pub(crate) fn read_setting<S>(source: &mut S, id: ConfigID) -> Result<ConfigPair, ReadError>
+where
+    S: Get<f32> + Get<bool> + Get<u32> + Get<MountingRef> + Get<Baud> + ?Sized,
+{
+    Ok(match id {
+        ConfigID::Declination => ConfigPair::Declination(Get::<f32>::get(source)?),
+        ConfigID::TrueNorth => ConfigPair::TrueNorth(Get::<bool>::get(source)?),
+        ConfigID::BigEndian => ConfigPair::BigEndian(Get::<bool>::get(source)?),
+        ConfigID::MountingRef => ConfigPair::MountingRef(Get::<MountingRef>::get(source)?),
+        ConfigID::UserCalNumPoints => ConfigPair::UserCalNumPoints(Get::<u32>::get(source)?),
+        ConfigID::UserCalAutoSampling => {
+            ConfigPair::UserCalAutoSampling(Get::<bool>::get(source)?)
+        }
+        ConfigID::BaudRate => ConfigPair::BaudRate(Get::<Baud>::get(source)?),
+        ConfigID::MilOut => ConfigPair::MilOut(Get::<bool>::get(source)?),
+        ConfigID::HPRDuringCal => ConfigPair::HPRDuringCal(Get::<bool>::get(source)?),
+        ConfigID::MagCoeffSet => ConfigPair::MagCoeffSet(Get::<u32>::get(source)?),
+        ConfigID::AccelCoeffSet => ConfigPair::AccelCoeffSet(Get::<u32>::get(source)?),
+    })
+}
+
+impl ConfigPair {
+    /// Parses a SetConfig payload, the ID byte followed by the value: the inverse of
+    /// `Vec::<u8>::from(pair)`. Every byte must be used
+    pub fn parse(payload: &[u8]) -> Result<Self, ReadError> {
+        let mut cursor = payload;
+        let id = ConfigID::try_from(Get::<u8>::get(&mut cursor)?)?;
+        let pair = read_setting(&mut cursor, id)?;
+        if !cursor.is_empty() {
+            return Err(ReadError::SizeMismatch {
+                expected: (payload.len() - cursor.len() + crate::codec::FRAME_OVERHEAD) as u16,
+                actual: (payload.len() + crate::codec::FRAME_OVERHEAD).min(u16::MAX as usize)
+                    as u16,
+            });
+        }
+        Ok(pair)
+    }
+}
+
+impl ConfigID {
+    /// Every parameter, in ID order
+    pub const ALL: [ConfigID; 11] = [
+        ConfigID::Declination,
+        ConfigID::TrueNorth,
+        ConfigID::BigEndian,
+        ConfigID::MountingRef,
+        ConfigID::UserCalNumPoints,
+        ConfigID::UserCalAutoSampling,
+        ConfigID::BaudRate,
+        ConfigID::MilOut,
+        ConfigID::HPRDuringCal,
+        ConfigID::MagCoeffSet,
+        ConfigID::AccelCoeffSet,
+    ];
+}
+
+impl From<ConfigID> for u8 {
+    fn from(id: ConfigID) -> Self {
+        id as u8
+    }
+}
+
+impl TryFrom<u8> for ConfigID {
+    type Error = ReadError;
+    fn try_from(value: u8) -> Result<Self, ReadError> {
+        match value {
+            1 => Ok(ConfigID::Declination),
+            2 => Ok(ConfigID::TrueNorth),
+            6 => Ok(ConfigID::BigEndian),
+            10 => Ok(ConfigID::MountingRef),
+            12 => Ok(ConfigID::UserCalNumPoints),
+            13 => Ok(ConfigID::UserCalAutoSampling),
+            14 => Ok(ConfigID::BaudRate),
+            15 => Ok(ConfigID::MilOut),
+            16 => Ok(ConfigID::HPRDuringCal),
+            18 => Ok(ConfigID::MagCoeffSet),
+            19 => Ok(ConfigID::AccelCoeffSet),
+            _ => Err(ReadError::ParseError(
+                Message::new(MessageId::UnknownConfigId).with("value", value),
+            )),
+        }
     }
 }
 
@@ -119,7 +249,7 @@ impl From<ConfigPair> for Vec<u8> {
     fn from(param: ConfigPair) -> Self {
         use ConfigPair::*;
         let mut vec = Vec::<u8>::new();
-        vec.push(param.discriminant());
+        vec.push(u8::from(param.id()));
 
         match param {
             Declination(val) => {
@@ -142,7 +272,7 @@ impl From<ConfigPair> for Vec<u8> {
                 }
             }
             MountingRef(mr) => {
-                vec.push(mr as u8);
+                vec.push(u8::from(mr));
             }
             UserCalNumPoints(val) => vec.extend_from_slice(&val.to_be_bytes()),
             UserCalAutoSampling(val) => {
@@ -152,7 +282,7 @@ impl From<ConfigPair> for Vec<u8> {
                     vec.push(0);
                 }
             }
-            BaudRate(val) => vec.push(val as u8),
+            BaudRate(val) => vec.push(u8::from(val)),
             MilOut(val) => {
                 if val {
                     vec.push(1);
@@ -176,7 +306,7 @@ impl From<ConfigPair> for Vec<u8> {
 }
 
 /// Baud rates supported by tp3
-#[derive(Debug, Display)]
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
 pub enum Baud {
     B2400 = 4,
     B3600,
@@ -191,14 +321,51 @@ pub enum Baud {
     B115200,
 }
 
-impl Get<Baud> for Device {
-    fn get(&mut self) -> Result<Baud, ReadError> {
+impl Baud {
+    /// Every supported rate, slowest first
+    pub const ALL: [Baud; 11] = [
+        Baud::B2400,
+        Baud::B3600,
+        Baud::B4800,
+        Baud::B7200,
+        Baud::B9600,
+        Baud::B14400,
+        Baud::B19200,
+        Baud::B28800,
+        Baud::B38400,
+        Baud::B57600,
+        Baud::B115200,
+    ];
+
+    /// Rate in bits per second, as passed to [serialport::new]
+    pub fn bits_per_second(&self) -> u32 {
+        match self {
+            Baud::B2400 => 2400,
+            Baud::B3600 => 3600,
+            Baud::B4800 => 4800,
+            Baud::B7200 => 7200,
+            Baud::B9600 => 9600,
+            Baud::B14400 => 14400,
+            Baud::B19200 => 19200,
+            Baud::B28800 => 28800,
+            Baud::B38400 => 38400,
+            Baud::B57600 => 57600,
+            Baud::B115200 => 115200,
+        }
+    }
+}
+
+impl From<Baud> for u8 {
+    fn from(baud: Baud) -> Self {
+        baud as u8
+    }
+}
+
+impl TryFrom<u8> for Baud {
+    type Error = ReadError;
+    fn try_from(value: u8) -> Result<Self, ReadError> {
         use Baud::*;
-        let mut rbuff = [0u8; 1];
-        self.serialport.read_exact(&mut rbuff)?;
-        self.read_bytes += 1;
-        self.read_checksum.update(&rbuff);
-        match rbuff[0] {
+        match value {
             4 => Ok(B2400),
             5 => Ok(B3600),
             6 => Ok(B4800),
@@ -211,11 +378,28 @@ impl Get<Baud> for Device {
             13 => Ok(B57600),
             14 => Ok(B115200),
             _ => Err(ReadError::ParseError(
-                "Baud descriptor from device must be one of [4,14], the only supported bauds"
-                    .to_string(),
+                Message::new(MessageId::InvalidBaud).with("value", value),
             )),
         }
     }
+}
+
+impl Get<Baud> for &[u8] {
+    fn get(&mut self) -> Result<Baud, ReadError> {
+        Baud::try_from(Get::<u8>::get(self)?)
+    }
+
+    fn get_string(&mut self) -> Result<String, ReadError> {
+        Ok(Get::<Baud>::get(self)?.to_string())
+    }
+}
+
+impl Get<Baud> for Device {
+    fn get(&mut self) -> Result<Baud, ReadError> {
+        let mut rbuff = [0u8; 1];
+        self.read_exact_tracked(&mut rbuff)?;
+        Baud::try_from(rbuff[0])
+    }
 
     fn get_string(&mut self) -> Result<String, ReadError> {
         Ok(Get::<Baud>::get(self)?.to_string())
@@ -223,7 +407,7 @@ impl Get<Baud> for Device {
 }
 
 /// Represents the device mounting orientation
-#[derive(Debug, Display)]
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
 pub enum MountingRef {
     Std0 = 1,
     XUp0,
@@ -243,14 +427,17 @@ pub enum MountingRef {
     ZDown270,
 }
 
-impl Get<MountingRef> for Device {
-    fn get(&mut self) -> Result<MountingRef, ReadError> {
+impl From<MountingRef> for u8 {
+    fn from(mounting_ref: MountingRef) -> Self {
+        mounting_ref as u8
+    }
+}
+
+impl TryFrom<u8> for MountingRef {
+    type Error = ReadError;
+    fn try_from(value: u8) -> Result<Self, ReadError> {
         use MountingRef::*;
-        let mut rbuff = [0u8; 1];
-        self.serialport.read_exact(&mut rbuff)?;
-        self.read_bytes += 1;
-        self.read_checksum.update(&rbuff);
-        match rbuff[0] {
+        match value {
             1 => Ok(Std0),
             2 => Ok(XUp0),
             3 => Ok(YUp0),
@@ -268,17 +455,298 @@ impl Get<MountingRef> for Device {
             15 => Ok(ZDown180),
             16 => Ok(ZDown270),
             _ => Err(ReadError::ParseError(
-                "MountingRef must be within [1, 16]".to_string(),
+                Message::new(MessageId::InvalidMountingRef).with("value", value),
             )),
         }
     }
+}
+
+impl Get<MountingRef> for &[u8] {
+    fn get(&mut self) -> Result<MountingRef, ReadError> {
+        MountingRef::try_from(Get::<u8>::get(self)?)
+    }
+
+    fn get_string(&mut self) -> Result<String, ReadError> {
+        Ok(Get::<MountingRef>::get(self)?.to_string())
+    }
+}
+
+impl Get<MountingRef> for Device {
+    fn get(&mut self) -> Result<MountingRef, ReadError> {
+        let mut rbuff = [0u8; 1];
+        self.read_exact_tracked(&mut rbuff)?;
+        MountingRef::try_from(rbuff[0])
+    }
 
     fn get_string(&mut self) -> Result<String, ReadError> {
         Ok(Get::<MountingRef>::get(self)?.to_string())
     }
 }
 
+/// The settings that decide how angles are output, as last set or read. Forgotten on power-down,
+/// since unsaved settings don't survive it
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct AngleConfig {
+    mil_out: Option<bool>,
+    true_north: Option<bool>,
+    declination: Option<f32>,
+}
+
+impl AngleConfig {
+    fn observe(&mut self, pair: &ConfigPair) {
+        match *pair {
+            ConfigPair::MilOut(value) => self.mil_out = Some(value),
+            ConfigPair::TrueNorth(value) => self.true_north = Some(value),
+            ConfigPair::Declination(value) => self.declination = Some(value),
+            _ => {}
+        }
+    }
+
+    pub(crate) fn format(&self) -> Option<AngleFormat> {
+        Some(AngleFormat {
+            units: if self.mil_out? {
+                AngleUnits::Mils
+            } else {
+                AngleUnits::Degrees
+            },
+            north: if self.true_north? {
+                NorthReference::True {
+                    declination: self.declination?,
+                }
+            } else {
+                NorthReference::Magnetic
+            },
+        })
+    }
+}
+
+/// A setting whose value in the device's volatile memory differs from the saved one. See
+/// [Device::diff]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigChange {
+    pub id: ConfigID,
+
+    /// Value in non-volatile memory, if it's known: read before being changed, or saved since
+    pub saved: Option<ConfigPair>,
+
+    pub current: ConfigPair,
+}
+
+impl fmt::Display for ConfigChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.saved {
+            Some(saved) => write!(f, "{}: {:?} -> {:?}", self.id, saved, self.current),
+            None => write!(f, "{}: unknown -> {:?}", self.id, self.current),
+        }
+    }
+}
+
+/// A setting that read back differently after a save than it was set. See [Device::save_verified]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigMismatch {
+    /// Value set or read this session, which the save should have kept
+    pub expected: ConfigPair,
+
+    /// Value read back from the device
+    pub actual: ConfigPair,
+}
+
+impl fmt::Display for ConfigMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: expected {:?}, read back {:?}",
+            self.expected.id(),
+            self.expected,
+            self.actual
+        )
+    }
+}
+
+/// Host-side copy of the device's settings, as last set, read or saved. See [Device::diff]
+#[derive(Debug, Default, Clone)]
+pub(crate) struct SettingsShadow {
+    /// Saved and current value of every setting seen this session
+    config: HashMap<ConfigID, (Option<ConfigPair>, ConfigPair)>,
+
+    /// Set by changes other than SetConfig that Save persists, e.g. acquisition parameters
+    other_unsaved: bool,
+
+    /// Settings that read back differently after a save, to be sent again before the next one
+    unverified: Vec<ConfigID>,
+}
+
+impl SettingsShadow {
+    /// A value read from the device. Unless it was changed this session, that's also what's saved
+    pub(crate) fn observe_read(&mut self, pair: &ConfigPair) {
+        self.config
+            .entry(pair.id())
+            .and_modify(|(_, current)| *current = pair.clone())
+            .or_insert_with(|| (Some(pair.clone()), pair.clone()));
+    }
+
+    pub(crate) fn observe_set(&mut self, pair: &ConfigPair) {
+        self.unverified.retain(|id| *id != pair.id());
+        self.config
+            .entry(pair.id())
+            .and_modify(|(_, current)| *current = pair.clone())
+            .or_insert_with(|| (None, pair.clone()));
+    }
+
+    /// A setting that read back as `actual` after a save. That's what the device has saved, but
+    /// `expected` is still what the host wants, so it stays an unsaved change
+    pub(crate) fn observe_mismatch(&mut self, expected: &ConfigPair, actual: &ConfigPair) {
+        self.config
+            .insert(expected.id(), (Some(actual.clone()), expected.clone()));
+        if !self.unverified.contains(&expected.id()) {
+            self.unverified.push(expected.id());
+        }
+    }
+
+    /// Wanted values of the settings [SettingsShadow::observe_mismatch] recorded, forgetting them
+    pub(crate) fn take_unverified(&mut self) -> Vec<ConfigPair> {
+        let unverified = std::mem::take(&mut self.unverified);
+        unverified
+            .into_iter()
+            .filter_map(|id| self.config.get(&id).map(|(_, current)| current.clone()))
+            .collect()
+    }
+
+    pub(crate) fn mark_other_unsaved(&mut self) {
+        self.other_unsaved = true;
+    }
+
+    pub(crate) fn mark_other_saved(&mut self) {
+        self.other_unsaved = false;
+    }
+
+    pub(crate) fn mark_saved(&mut self) {
+        for (saved, current) in self.config.values_mut() {
+            *saved = Some(current.clone());
+        }
+        self.other_unsaved = false;
+    }
+
+    /// After a power cycle, settings are back to their saved values. Other changes are left
+    /// marked unsaved, since [Device::power_up] sends them again
+    pub(crate) fn revert_unsaved(&mut self) {
+        self.config.retain(|_, (saved, _)| saved.is_some());
+        for (saved, current) in self.config.values_mut() {
+            if let Some(saved) = saved {
+                *current = saved.clone();
+            }
+        }
+    }
+
+    pub(crate) fn other_unsaved(&self) -> bool {
+        self.other_unsaved
+    }
+
+    /// Current value of every setting seen this session, in ConfigID order
+    pub(crate) fn current(&self) -> Vec<ConfigPair> {
+        let mut current = self
+            .config
+            .values()
+            .map(|(_, current)| current.clone())
+            .collect::<Vec<_>>();
+        current.sort_by_key(|pair| u8::from(pair.id()));
+        current
+    }
+
+    pub(crate) fn changes(&self) -> Vec<ConfigChange> {
+        let mut changes = self
+            .config
+            .iter()
+            .filter(|(_, (saved, current))| saved.as_ref() != Some(current))
+            .map(|(id, (saved, current))| ConfigChange {
+                id: *id,
+                saved: saved.clone(),
+                current: current.clone(),
+            })
+            .collect::<Vec<_>>();
+        changes.sort_by_key(|change| u8::from(change.id));
+        changes
+    }
+}
+
 impl Device {
+    /// Settings changed with [Device::set_config] this session that haven't been saved, in
+    /// ConfigID order. Compared against values read with [Device::get_config] or saved with
+    /// [Device::save]; a setting changed without being read first shows an unknown saved value,
+    /// even if the change happens to match it
+    pub fn diff(&self) -> Vec<ConfigChange> {
+        self.settings.changes()
+    }
+
+    /// Whether anything has been changed since the last [Device::save]: settings (see
+    /// [Device::diff]), acquisition parameters, FIR filters or data components. Calibration and
+    /// coefficient set commands aren't tracked
+    pub fn has_unsaved_changes(&self) -> bool {
+        self.settings.other_unsaved || !self.settings.changes().is_empty()
+    }
+
+    /// Saves only if [Device::has_unsaved_changes], sparing the flash a write. Returns whether it
+    /// saved
+    pub fn save_if_dirty(&mut self) -> Result<bool, RWError> {
+        if !self.has_unsaved_changes() {
+            return Ok(false);
+        }
+        self.save()?;
+        Ok(true)
+    }
+
+    /// Like [Device::save], then reads back every setting set or read this session and checks
+    /// it against what the host expects, to catch saves that were cut short, e.g. by a brownout.
+    /// Fails with [RWError::VerificationFailed] listing the settings that differ.
+    ///
+    /// Safe to call again after a failure: settings that didn't verify stay in [Device::diff]
+    /// as unsaved changes, with the value read back as their saved value, and the next call sends
+    /// them again before saving. Until the device keeps them, every call fails
+    pub fn save_verified(&mut self) -> Result<(), RWError> {
+        for pair in self.settings.take_unverified() {
+            self.set_config(pair)?;
+        }
+        let expected = self.settings.current();
+        self.save()?;
+
+        let mut mismatches = Vec::new();
+        for expected in expected {
+            let actual = self.get_config(expected.id())?;
+            if actual != expected {
+                // what was read back is what's in non-volatile memory now
+                self.settings.observe_mismatch(&expected, &actual);
+                mismatches.push(ConfigMismatch { expected, actual });
+            }
+        }
+        if mismatches.is_empty() {
+            return Ok(());
+        }
+        Err(RWError::VerificationFailed { mismatches })
+    }
+
+    /// Units and north reference the device outputs angles in, reading whichever of MilOut,
+    /// TrueNorth and Declination haven't been set or read this session. Once known, every [Data]
+    /// read carries it in [crate::acquisition::Data::angle_format]
+    pub fn angle_format(&mut self) -> Result<AngleFormat, RWError> {
+        if self.angle_config.mil_out.is_none() {
+            self.get_config(ConfigID::MilOut)?;
+        }
+        if self.angle_config.true_north.is_none() {
+            self.get_config(ConfigID::TrueNorth)?;
+        }
+        if self.angle_config.true_north == Some(true) && self.angle_config.declination.is_none() {
+            self.get_config(ConfigID::Declination)?;
+        }
+        Ok(self
+            .angle_config
+            .format()
+            .expect("angle settings were just read"))
+    }
+
+    pub(crate) fn forget_angle_config(&mut self) {
+        self.angle_config = AngleConfig::default();
+    }
+
     /// Sets configuration on device, without saving to volatile memory. These configurations can only be set one at time.
     /// To save these in non-volatile memory, call [TargetPoint3::save].
     /// See also: [TargetPoint3::get_config]
@@ -286,19 +754,17 @@ impl Device {
     /// # Arguments
     /// * `config_option` - Configuration parameter and value to set
     pub fn set_config(&mut self, config_option: ConfigPair) -> Result<(), RWError> {
-        let payload = Vec::<u8>::from(config_option);
-        self.write_frame(Command::SetConfig, Some(&payload))?;
-
-        let expected_size = Get::<u16>::get(self)?;
-        if Get::<u8>::get(self)? == Command::SetConfigDone.discriminant() {
-            self.end_frame(expected_size)?;
-            Ok(())
-        } else {
-            let _ = self.end_frame(expected_size);
-            Err(RWError::ReadError(ReadError::ParseError(
-                "Unexpected response type".to_string(),
-            )))
+        if self.strict {
+            config_option.validate().map_err(RWError::InvalidArgument)?;
+        }
+        if let ConfigPair::MagCoeffSet(1..) | ConfigPair::AccelCoeffSet(1..) = config_option {
+            self.require(Feature::MultipleCoeffSets)?;
         }
+        let payload = Vec::<u8>::from(config_option.clone());
+        self.request(Command::SetConfig, Some(&payload))?.finish()?;
+        self.angle_config.observe(&config_option);
+        self.settings.observe_set(&config_option);
+        Ok(())
     }
 
     /// This frame queries the TargetPoint3 for the current internal configuration value.
@@ -306,72 +772,11 @@ impl Device {
     /// # Arguments
     /// * `id` - The configuration parameter to query
     pub fn get_config(&mut self, id: ConfigID) -> Result<ConfigPair, RWError> {
-        self.write_frame(Command::GetConfig, Some(&[id.clone() as u8]))?;
-
-        let expected_size = Get::<u16>::get(self)?;
-        if Get::<u8>::get(self)? == Command::GetConfigResp.discriminant() {
-            match id {
-                ConfigID::Declination => {
-                    let setting = ConfigPair::Declination(Get::<f32>::get(self)?);
-                    self.end_frame(expected_size)?;
-                    Ok(setting)
-                }
-                ConfigID::TrueNorth => {
-                    let setting = ConfigPair::TrueNorth(Get::<bool>::get(self)?);
-                    self.end_frame(expected_size)?;
-                    Ok(setting)
-                }
-                ConfigID::BigEndian => {
-                    let setting = ConfigPair::BigEndian(Get::<bool>::get(self)?);
-                    self.end_frame(expected_size)?;
-                    Ok(setting)
-                }
-                ConfigID::MountingRef => {
-                    let setting = ConfigPair::MountingRef(Get::<MountingRef>::get(self)?);
-                    self.end_frame(expected_size)?;
-                    Ok(setting)
-                }
-                ConfigID::UserCalNumPoints => {
-                    let setting = ConfigPair::UserCalNumPoints(Get::<u32>::get(self)?);
-                    self.end_frame(expected_size)?;
-                    Ok(setting)
-                }
-                ConfigID::UserCalAutoSampling => {
-                    let setting = ConfigPair::UserCalAutoSampling(Get::<bool>::get(self)?);
-                    self.end_frame(expected_size)?;
-                    Ok(setting)
-                }
-                ConfigID::BaudRate => {
-                    let setting = ConfigPair::BaudRate(Get::<Baud>::get(self)?);
-                    self.end_frame(expected_size)?;
-                    Ok(setting)
-                }
-                ConfigID::MilOut => {
-                    let setting = ConfigPair::MilOut(Get::<bool>::get(self)?);
-                    self.end_frame(expected_size)?;
-                    Ok(setting)
-                }
-                ConfigID::HPRDuringCal => {
-                    let setting = ConfigPair::HPRDuringCal(Get::<bool>::get(self)?);
-                    self.end_frame(expected_size)?;
-                    Ok(setting)
-                }
-                ConfigID::MagCoeffSet => {
-                    let setting = ConfigPair::MagCoeffSet(Get::<u32>::get(self)?);
-                    self.end_frame(expected_size)?;
-                    Ok(setting)
-                }
-                ConfigID::AccelCoeffSet => {
-                    let setting = ConfigPair::AccelCoeffSet(Get::<u32>::get(self)?);
-                    self.end_frame(expected_size)?;
-                    Ok(setting)
-                }
-            }
-        } else {
-            let _ = self.end_frame(expected_size);
-            Err(RWError::ReadError(ReadError::ParseError(
-                "Unexpcted response type".to_string(),
-            )))
-        }
+        let mut frame = self.request(Command::GetConfig, Some(&[u8::from(id)]))?;
+        let setting = read_setting(&mut frame, id)?;
+        frame.finish()?;
+        self.angle_config.observe(&setting);
+        self.settings.observe_read(&setting);
+        Ok(setting)
     }
 }
@@ -0,0 +1,848 @@
+use crate::clock::{Backoff, Clock, SystemClock};
+use crate::config::Baud;
+use crate::instrument::AttemptSpan;
+use crate::responses::{ModInfoResp, Model};
+use crate::transport::{Closed, Transport};
+use crate::{Device, WriteError};
+
+use serialport::{SerialPort, SerialPortInfo, SerialPortType};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, TrySendError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Events held for each [Device::connection_events] subscriber before newer ones are dropped
+const MAX_PENDING_EVENTS: usize = 64;
+
+/// How often a read waiting on the port checks for cancellation, once a [CancelToken] is handed
+/// out
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long [Device::connect_probe] waits for an answer at each baud rate
+const PROBE_TIMEOUT: Duration = Duration::from_millis(300);
+
+/// A change in the state of the link to a device. Subscribe with [Device::connection_events]
+#[derive(Debug, Display, Clone, PartialEq)]
+pub enum ConnectionEvent {
+    /// Serial port is open but the device hasn't answered yet (also sent while waking the device)
+    Connecting,
+
+    /// Device answered a command, and can be polled
+    Identified,
+
+    /// Device is in continuous mode
+    Streaming,
+
+    /// Device is still connected but a frame was lost or malformed
+    #[display(fmt = "Degraded {{ reason: {} }}", reason)]
+    Degraded { reason: String },
+
+    /// Device is being power cycled and re-opened; subscribers carry over to the new [Device]
+    Reconnecting,
+
+    /// Device was powered down, with the serial port kept open to wake it. See
+    /// [crate::power::PoweredDown]
+    Asleep,
+
+    /// Device was powered down and the serial port released
+    Closed,
+}
+
+/// Aborts a read blocked on a [Device] from another thread, e.g. a [Device::get_data] waiting
+/// out FIR repopulation. Get one with [Device::cancel_token]; clones share the same flag
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    /// Cancels the read in progress, or the next one if none is. The cancelled read returns a
+    /// [crate::ReadError::PipeError] of kind [io::ErrorKind::Interrupted] within about 50 ms.
+    /// Each cancel aborts one read
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether a cancel is pending, i.e. no read has picked it up yet
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    fn take(&self) -> bool {
+        self.cancelled.swap(false, Ordering::SeqCst)
+    }
+}
+
+/// Whether `error` is a read aborted through a [CancelToken]
+pub fn is_cancelled(error: &io::Error) -> bool {
+    error.kind() == io::ErrorKind::Interrupted
+}
+
+impl Device {
+    /// A token for cancelling this device's reads from another thread. Until the first call,
+    /// reads go straight to the port; afterwards they wait in 50 ms slices so the token can be
+    /// checked in between, with the port timeout still applying to the whole wait.
+    ///
+    /// A cancelled read may leave the rest of its frame on the port; call
+    /// [Device::sync_to_frame_boundary] before sending another command
+    pub fn cancel_token(&mut self) -> CancelToken {
+        self.cancel_token.get_or_insert_with(CancelToken::default).clone()
+    }
+
+    /// Fills `buf` from the port like [io::Read::read_exact], returning early if the cancel
+    /// token fires. The port timeout is how long to wait without a byte arriving
+    pub(crate) fn port_read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        let Some(token) = self.cancel_token.clone() else {
+            return self.transport.read_exact(buf);
+        };
+        let timeout = self.transport.timeout();
+        self.transport.set_timeout(timeout.min(CANCEL_POLL_INTERVAL))?;
+
+        let mut filled = 0;
+        let mut last_byte = Instant::now();
+        let result = loop {
+            if filled == buf.len() {
+                break Ok(());
+            }
+            if token.take() {
+                break Err(io::Error::new(io::ErrorKind::Interrupted, "read cancelled"));
+            }
+            match self.transport.read(&mut buf[filled..]) {
+                Ok(0) => break Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(read) => {
+                    filled += read;
+                    last_byte = Instant::now();
+                }
+                Err(e) if e.kind() == io::ErrorKind::TimedOut && last_byte.elapsed() < timeout => {}
+                Err(e) => break Err(e),
+            }
+        };
+        let _ = self.transport.set_timeout(timeout);
+        result
+    }
+}
+
+/// Where [Device::reconnect] looks for the device once its port has gone away
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReconnectTarget {
+    /// Re-open the port by the name it had, e.g. `/dev/ttyUSB0`. Fine when the device always
+    /// comes back under the same name, e.g. with a udev rule or a `/dev/serial/by-id` path
+    SamePort,
+
+    /// Try every USB serial port until one answers with this serial number. Finds the device
+    /// when re-enumeration gives it a new name, at the cost of sending a few frames to other
+    /// USB serial devices that happen to be attached
+    SerialNumber(u32),
+}
+
+/// How a [Device] recovers when its serial port disappears, e.g. when a USB hub drops and
+/// re-enumerates. Set with [Device::set_reconnect_policy]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReconnectPolicy {
+    pub target: ReconnectTarget,
+
+    /// Maximum number of times to try re-opening the port
+    pub attempts: u32,
+
+    /// Delay after the first failed attempt. Doubles after every subsequent failure
+    pub initial_backoff: Duration,
+
+    /// Upper bound on the delay between attempts
+    pub max_backoff: Duration,
+
+    /// Give up once this much time has passed since the port was lost, even if attempts remain
+    pub max_duration: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    /// Same port, retrying for up to two minutes, which covers a hub re-enumerating
+    fn default() -> Self {
+        Self {
+            target: ReconnectTarget::SamePort,
+            attempts: 30,
+            initial_backoff: Duration::from_millis(250),
+            max_backoff: Duration::from_secs(5),
+            max_duration: Duration::from_secs(120),
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// The default policy, but finding the device by serial number. See
+    /// [ReconnectTarget::SerialNumber]
+    pub fn by_serial_number(serial_number: u32) -> Self {
+        Self {
+            target: ReconnectTarget::SerialNumber(serial_number),
+            ..Default::default()
+        }
+    }
+}
+
+/// The kind of device expected on a port. See [DeviceBuilder::profile]
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Profile {
+    /// Nothing is assumed until the device is identified with [Device::get_mod_info]
+    #[default]
+    Auto,
+    TargetPoint3,
+    Trax,
+    Trax2,
+}
+
+impl Profile {
+    /// Device family the profile assumes, [None] for [Profile::Auto]
+    pub fn model(&self) -> Option<Model> {
+        match self {
+            Profile::Auto => None,
+            Profile::TargetPoint3 => Some(Model::TargetPoint3),
+            Profile::Trax => Some(Model::Trax),
+            Profile::Trax2 => Some(Model::Trax2),
+        }
+    }
+}
+
+/// Output format a device streams in. See [DeviceBuilder::protocol]
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Protocol {
+    /// PNI Serial Binary Protocol frames
+    #[default]
+    Binary,
+
+    /// TCM-style ASCII sentences, one per line. See [crate::ascii]
+    Ascii,
+}
+
+/// When frames written to a [Device] are pushed out of the OS and adapter buffers. See
+/// [Device::set_write_strategy]
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WriteStrategy {
+    /// Flush after every frame, so it's on the wire before the response timeout starts. Some
+    /// USB-serial adapters otherwise hold small writes back long enough for commands to time out
+    #[default]
+    Flush,
+
+    /// Only flush before waiting for a response, so frames that get none (e.g.
+    /// SetDataComponents, StartContinuousMode) can go out together. Call [Device::flush] to push
+    /// them out without reading anything
+    Batched,
+}
+
+impl Device {
+    /// How frames are flushed to the port. [WriteStrategy::Flush] by default
+    pub fn set_write_strategy(&mut self, strategy: WriteStrategy) {
+        self.write_strategy = strategy;
+    }
+
+    /// Waits until everything written so far has left the port
+    pub fn flush(&mut self) -> Result<(), WriteError> {
+        Ok(self.flush_port()?)
+    }
+
+    pub(crate) fn flush_port(&mut self) -> io::Result<()> {
+        self.unflushed = false;
+        let result = self.transport.flush();
+        if let Err(e) = &result {
+            self.note_port_error(e);
+        }
+        result
+    }
+
+    /// Writes all of `frame` with [write_frame_to], noting a lost port
+    pub(crate) fn write_frame_bytes(&mut self, frame: &[u8]) -> Result<(), WriteError> {
+        let result = write_frame_to(&mut *self.transport, frame, self.write_strategy);
+        match &result {
+            Ok(()) => self.unflushed = self.write_strategy == WriteStrategy::Batched,
+            Err(WriteError::PipeError(e) | WriteError::PartialWrite { error: e, .. }) => {
+                self.unflushed = false;
+                self.note_port_error(e)
+            }
+            Err(WriteError::EncodeError(_)) => {}
+        }
+        result
+    }
+}
+
+/// Writes all of `frame` to `port`, reporting how much got out if the port fails part way
+/// through, then flushes it unless `strategy` is [WriteStrategy::Batched]. Used by [Device] and
+/// [crate::split::Writer], which each note a lost port from the error
+pub(crate) fn write_frame_to(
+    port: &mut dyn Transport,
+    frame: &[u8],
+    strategy: WriteStrategy,
+) -> Result<(), WriteError> {
+    let mut written = 0;
+    while written < frame.len() {
+        let error = match port.write(&frame[written..]) {
+            Ok(0) => io::ErrorKind::WriteZero.into(),
+            Ok(count) => {
+                written += count;
+                continue;
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => e,
+        };
+        return Err(match written {
+            0 => WriteError::PipeError(error),
+            _ => WriteError::PartialWrite {
+                written,
+                len: frame.len(),
+                error,
+            },
+        });
+    }
+    match strategy {
+        WriteStrategy::Flush => Ok(port.flush()?),
+        WriteStrategy::Batched => Ok(()),
+    }
+}
+
+/// Serial port settings for opening a [Device]. See [Device::builder]
+#[derive(Clone)]
+pub struct DeviceBuilder {
+    port: Option<String>,
+    baud: Baud,
+    timeout: Duration,
+    profile: Profile,
+    protocol: Protocol,
+    retries: u32,
+    clock: Arc<dyn Clock>,
+}
+
+impl fmt::Debug for DeviceBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DeviceBuilder")
+            .field("port", &self.port)
+            .field("baud", &self.baud)
+            .field("timeout", &self.timeout)
+            .field("profile", &self.profile)
+            .field("protocol", &self.protocol)
+            .field("retries", &self.retries)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for DeviceBuilder {
+    /// Auto-detected port, 38400 baud (the factory setting), 1 second timeout, no retries
+    fn default() -> Self {
+        Self {
+            port: None,
+            baud: Baud::B38400,
+            timeout: Duration::from_secs(1),
+            profile: Profile::Auto,
+            protocol: Protocol::Binary,
+            retries: 0,
+            clock: Arc::new(SystemClock),
+        }
+    }
+}
+
+impl DeviceBuilder {
+    /// Serial port to open, e.g. `/dev/ttyUSB0` or `COM3`. Without one, the first USB serial port
+    /// found is used
+    pub fn port(mut self, port: impl Into<String>) -> Self {
+        self.port = Some(port.into());
+        self
+    }
+
+    /// Baud rate the device is configured for. The port is opened at this rate; it doesn't change
+    /// the device's setting
+    pub fn baud(mut self, baud: Baud) -> Self {
+        self.baud = baud;
+        self
+    }
+
+    /// How long a read waits for the device before giving up
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Kind of device expected, so [Device::supports] and [Device::capabilities] have answers
+    /// before [Device::get_mod_info] is called. Once the device is identified, its reported
+    /// model takes precedence
+    pub fn profile(mut self, profile: Profile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    /// Format the device streams in. With [Protocol::Ascii], [Device::iter] and the other
+    /// continuous-mode iterators parse sentences into [crate::acquisition::Data], so units of
+    /// either kind can be read by the same code. Commands still use binary frames, which
+    /// ASCII-only units don't answer
+    pub fn protocol(mut self, protocol: Protocol) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
+    /// Tries opening the port up to `retries` more times if it fails, e.g. while a USB adapter is
+    /// still enumerating after being plugged in. Waits 100 ms before the first retry, doubling up
+    /// to 2 seconds between later ones. Finding no port to auto-detect
+    /// ([serialport::ErrorKind::NoDevice]) isn't retried
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Time source for the waits between retries, also given to the opened device. See
+    /// [Device::set_clock]
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Opens the port and returns the device. Doesn't talk to the device
+    pub fn open(self) -> Result<Device, serialport::Error> {
+        let mut backoff = Backoff::with_limits(
+            self.clock.clone(),
+            Duration::from_millis(100),
+            Duration::from_secs(2),
+            self.retries.saturating_add(1),
+            Duration::MAX,
+        );
+        loop {
+            backoff.attempt();
+            let span = AttemptSpan::enter("open", backoff.attempts());
+            let error = match self.open_once() {
+                Ok(device) => return Ok(device),
+                Err(e) => e,
+            };
+            span.cause(&error);
+            log::debug!("Opening attempt {} failed: {}", backoff.attempts(), error);
+            if error.kind() == serialport::ErrorKind::NoDevice {
+                // nothing to wait for
+                return Err(error);
+            }
+            span.backoff(backoff.delay());
+            if !backoff.wait() {
+                return Err(error);
+            }
+        }
+    }
+
+    fn open_once(&self) -> Result<Device, serialport::Error> {
+        let port = match &self.port {
+            Some(port) => port.clone(),
+            None => auto_detect_port()?,
+        };
+        log::info!("Using port {}", port);
+
+        let builder = Device::port_builder(port)
+            .baud_rate(self.baud.bits_per_second())
+            .timeout(self.timeout);
+        let mut device = Device::connect_with(builder)?;
+        device.profile = self.profile;
+        device.protocol = self.protocol;
+        device.clock = self.clock.clone();
+        Ok(device)
+    }
+}
+
+/// A device found by [Device::discover]
+#[derive(Debug, Display, Clone, PartialEq)]
+#[display(
+    fmt = "{} {} on {} (serial number {})",
+    "mod_info.device_type",
+    "mod_info.revision",
+    port_name,
+    serial_number
+)]
+pub struct DiscoveredDevice {
+    /// Serial port it answered on, to pass to [DeviceBuilder::port] or [Device::connect]
+    pub port_name: String,
+
+    /// Its answer to GetModInfo: device type and firmware revision
+    pub mod_info: ModInfoResp,
+
+    /// Factory serial number, as returned by [Device::serial_number]. Stays the same across
+    /// ports and reboots, so it's the way to find the same unit again, e.g. with
+    /// [ReconnectTarget::SerialNumber]
+    pub serial_number: u32,
+}
+
+/// The ports in `ports` that open at `baud_rate` with `timeout`, in order, each with its name.
+/// Ones that can't be opened, e.g. because another program has them, are skipped
+fn open_each(
+    ports: Vec<SerialPortInfo>,
+    baud_rate: u32,
+    timeout: Duration,
+) -> impl Iterator<Item = (String, Box<dyn SerialPort>)> {
+    ports.into_iter().filter_map(move |port| {
+        let builder = Device::port_builder(&port.port_name).baud_rate(baud_rate).timeout(timeout);
+        match builder.open() {
+            Ok(serialport) => Some((port.port_name, serialport)),
+            Err(e) => {
+                log::debug!("Skipped {}: {}", port.port_name, e);
+                None
+            }
+        }
+    })
+}
+
+/// Last port with "usb" in its name, as [Device::connect] has always picked
+fn auto_detect_port() -> Result<String, serialport::Error> {
+    serialport::available_ports()?
+        .into_iter()
+        .rfind(|port| port.port_name.contains("usb"))
+        .map(|port| port.port_name)
+        .ok_or_else(|| {
+            serialport::Error::new(
+                serialport::ErrorKind::NoDevice,
+                "Could not auto-detect serial port",
+            )
+        })
+}
+
+/// Whether an IO error means the port itself is gone (unplugged, or its USB hub reset) rather
+/// than a timeout or bad data. Such a port has to be re-opened before it works again
+pub fn is_port_gone(e: &io::Error) -> bool {
+    // EIO, ENXIO and ENODEV on Linux and macOS; on Windows ERROR_BAD_COMMAND,
+    // ERROR_GEN_FAILURE, ERROR_OPERATION_ABORTED and ERROR_DEVICE_NOT_CONNECTED
+    #[cfg(unix)]
+    const GONE: &[i32] = &[5, 6, 19];
+    #[cfg(windows)]
+    const GONE: &[i32] = &[22, 31, 995, 1167];
+    #[cfg(not(any(unix, windows)))]
+    const GONE: &[i32] = &[];
+
+    matches!(
+        e.kind(),
+        io::ErrorKind::BrokenPipe | io::ErrorKind::NotConnected | io::ErrorKind::NotFound
+    ) || e.raw_os_error().is_some_and(|code| GONE.contains(&code))
+}
+
+impl Device {
+    /// Starts configuring a connection: port, baud rate, timeout, retries and expected device, for
+    /// units [Device::connect]'s defaults can't reach, such as ones set to another baud rate.
+    /// Finish with [DeviceBuilder::open]
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use pni_sdk::{config::Baud, Device};
+    /// use std::time::Duration;
+    ///
+    /// let tp3 = Device::builder()
+    ///     .port("/dev/ttyUSB0")
+    ///     .baud(Baud::B115200)
+    ///     .timeout(Duration::from_millis(500))
+    ///     .retries(3)
+    ///     .open()?;
+    /// # Ok::<(), serialport::Error>(())
+    /// ```
+    pub fn builder() -> DeviceBuilder {
+        DeviceBuilder::default()
+    }
+
+    /// Like [Device::connect], but finds the baud rate the device is set to rather than assuming
+    /// 38400, for units whose rate was changed and forgotten. Tries each of [Baud::ALL], 38400
+    /// first, until valid frames arrive unprompted (the device is streaming) or the device answers
+    /// GetModInfo, and returns the device at that rate with the usual 1 second timeout. The rate
+    /// found is logged, and can be read back with [Device::baud_rate].
+    ///
+    /// Each rate that gets no answer costs up to 900 ms, so a silent port takes about ten seconds.
+    /// A device found streaming is left streaming
+    pub fn connect_probe(port: Option<String>) -> Result<Self, Box<dyn Error>> {
+        let port = match port {
+            Some(port) => port,
+            None => auto_detect_port()?,
+        };
+        let default = Baud::B38400;
+        let order = std::iter::once(default).chain(Baud::ALL.into_iter().filter(|b| *b != default));
+        for baud in order {
+            let mut device = Self::builder()
+                .port(port.as_str())
+                .baud(baud)
+                .timeout(PROBE_TIMEOUT)
+                .open()?;
+            // valid frames arriving unprompted mean the device is streaming at this rate;
+            // otherwise ask it to identify itself. A powered-down device wakes on the first byte
+            // and ignores it, so ask twice
+            let streaming = device.drain_frames(PROBE_TIMEOUT).unwrap_or(0) > 0;
+            if !streaming && device.get_mod_info().or_else(|_| device.get_mod_info()).is_err() {
+                log::debug!("No answer on {} at {} baud", port, baud.bits_per_second());
+                continue;
+            }
+            log::info!("Device on {} answers at {} baud", port, baud.bits_per_second());
+            device.transport.set_timeout(Duration::from_secs(1))?;
+            return Ok(device);
+        }
+        Err(Box::new(serialport::Error::new(
+            serialport::ErrorKind::NoDevice,
+            format!("No answer on {} at any baud rate", port),
+        )))
+    }
+
+    /// Opens every serial port in turn and asks whatever is on it for GetModInfo and its serial
+    /// number, at 38400 baud with a short timeout. Returns the devices that answered, so a setup
+    /// with several can pick one by serial number rather than by [Device::connect]'s guess.
+    ///
+    /// Ports that can't be opened, e.g. because another program has them, are skipped. Each
+    /// silent port adds 600 ms: GetModInfo is asked twice, in case the device was asleep, with a
+    /// 300 ms timeout each
+    pub fn discover() -> Result<Vec<DiscoveredDevice>, serialport::Error> {
+        let mut found = Vec::new();
+        let ports = serialport::available_ports()?;
+        for (port_name, serialport) in open_each(ports, 38400, PROBE_TIMEOUT) {
+            let mut device = Device::new(serialport);
+            // a powered-down device wakes on the first byte and ignores it, so ask twice
+            let identity = device
+                .get_mod_info()
+                .or_else(|_| device.get_mod_info())
+                .and_then(|mod_info| Ok((mod_info, device.serial_number()?)));
+            match identity {
+                Ok((mod_info, serial_number)) => found.push(DiscoveredDevice {
+                    port_name,
+                    mod_info,
+                    serial_number,
+                }),
+                Err(e) => log::debug!("No device answering on {}: {}", port_name, e),
+            }
+        }
+        Ok(found)
+    }
+
+    /// Re-opens the port automatically, following `policy`, when reads or writes find it gone:
+    /// the next command re-opens it before sending, and [Device::iter] and the other
+    /// continuous-mode iterators re-open it and restart streaming. [None], the default, leaves
+    /// errors to the caller
+    pub fn set_reconnect_policy(&mut self, policy: Option<ReconnectPolicy>) {
+        self.reconnect_policy = policy;
+    }
+
+    /// Whether a read or write found the port gone since it was last opened
+    pub fn is_port_lost(&self) -> bool {
+        self.port_lost
+    }
+
+    /// Re-opens the port as described by the reconnect policy (or [ReconnectPolicy::default]
+    /// if none is set), wakes the device, and restarts continuous mode if it was streaming.
+    /// [ReconnectTarget::SamePort] re-opens the link with [Transport::reopen], or by its port
+    /// name. Volatile settings are lost if the device lost power; re-apply them afterwards
+    pub fn reconnect(&mut self) -> Result<(), Box<dyn Error>> {
+        let policy = self.reconnect_policy.clone().unwrap_or_default();
+        self.set_connection_state(ConnectionEvent::Reconnecting);
+        let mut backoff = Backoff::with_limits(
+            self.clock.clone(),
+            policy.initial_backoff,
+            policy.max_backoff,
+            policy.attempts,
+            policy.max_duration,
+        );
+
+        loop {
+            backoff.attempt();
+            let span = AttemptSpan::enter("reconnect", backoff.attempts());
+            match self.reopen(&policy.target) {
+                Ok(()) => break,
+                Err(e) => {
+                    span.cause(&e);
+                    log::debug!("Reconnect attempt {} failed: {}", backoff.attempts(), e);
+                    span.backoff(backoff.delay());
+                    if !backoff.wait() {
+                        return Err(e);
+                    }
+                }
+            }
+        }
+
+        log::info!("Reconnected on {:?}", self.port_name());
+        if self.streaming {
+            self.start_continuous_mode()?;
+        }
+        Ok(())
+    }
+
+    /// One attempt at finding and waking the device
+    fn reopen(&mut self, target: &ReconnectTarget) -> Result<(), Box<dyn Error>> {
+        let baud_rate = self.transport.baud_rate().unwrap_or(38400);
+        let timeout = self.transport.timeout();
+
+        match target {
+            ReconnectTarget::SamePort => {
+                match self.transport.reopen() {
+                    Err(e) if e.kind() == io::ErrorKind::Unsupported => {
+                        // a link without a name, such as a TCP serial server, can only be woken
+                        if self.port_name().is_some() {
+                            self.reopen_named(baud_rate)?;
+                        }
+                    }
+                    result => result?,
+                }
+                self.after_reopen()?;
+                Ok(())
+            }
+            ReconnectTarget::SerialNumber(serial_number) => {
+                // ports are opened for exclusive use, so the lost one has to go first
+                self.transport = Box::new(Closed {
+                    name: self.port_name(),
+                    timeout,
+                });
+                self.port_lost = true;
+                let mut ports = serialport::available_ports()?;
+                ports.retain(|port| matches!(port.port_type, SerialPortType::UsbPort(_)));
+                let candidates = open_each(ports, baud_rate, timeout)
+                    .map(|(name, port)| (name, Box::new(port) as Box<dyn Transport>));
+                self.reopen_serial_number(*serial_number, candidates)
+            }
+        }
+    }
+
+    /// Takes over the first of `candidates` that answers as `serial_number`. Others are only
+    /// woken and asked for their serial number, so units that aren't ours never get our
+    /// settings. If none answers, the port stays lost
+    pub(crate) fn reopen_serial_number(
+        &mut self,
+        serial_number: u32,
+        candidates: impl Iterator<Item = (String, Box<dyn Transport>)>,
+    ) -> Result<(), Box<dyn Error>> {
+        for (port_name, transport) in candidates {
+            let mut probe = Device::from_transport(Closed {
+                name: None,
+                timeout: transport.timeout(),
+            });
+            probe.transport = transport;
+            probe.set_clock(self.clock.clone());
+            // a sleeping device answers the first frame with PowerUpDone, if at all
+            let _ = probe.power_up_once();
+            match probe.serial_number() {
+                Ok(found) if found == serial_number => {
+                    self.transport = probe.transport;
+                    self.after_reopen()?;
+                    return Ok(());
+                }
+                Ok(found) => log::debug!(
+                    "{} is serial number {}, not {}",
+                    port_name,
+                    found,
+                    serial_number
+                ),
+                Err(e) => log::debug!("No device answering on {}: {}", port_name, e),
+            }
+        }
+        self.port_lost = true;
+        Err(Box::new(serialport::Error::new(
+            serialport::ErrorKind::NoDevice,
+            format!("No USB serial port answered as serial number {}", serial_number),
+        )))
+    }
+
+    /// Closes the serial port and opens the same path again at `baud`, keeping the timeout. The
+    /// device isn't woken. Fails without closing anything if the link has no port name
+    pub(crate) fn reopen_port_at(&mut self, baud: Baud) -> Result<(), WriteError> {
+        self.reopen_named(baud.bits_per_second())?;
+        self.port_lost = false;
+        self.reset_frame();
+        Ok(())
+    }
+
+    /// Closes the serial port and opens the same path again at `baud_rate`, keeping the timeout
+    fn reopen_named(&mut self, baud_rate: u32) -> io::Result<()> {
+        let Some(name) = self.port_name() else {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "link has no serial port to re-open",
+            ));
+        };
+        let timeout = self.transport.timeout();
+        // ports are opened for exclusive use, so the old handle has to go first
+        self.transport = Box::new(Closed {
+            name: Some(name.clone()),
+            timeout,
+        });
+        let port = Self::port_builder(name)
+            .baud_rate(baud_rate)
+            .timeout(timeout)
+            .open()
+            .map_err(io::Error::from)?;
+        self.transport = Box::new(port);
+        Ok(())
+    }
+
+    /// Serial number of a device just woken on a re-opened port, which may be streaming already
+    fn after_reopen(&mut self) -> Result<(), Box<dyn Error>> {
+        self.port_lost = false;
+        self.reset_frame();
+        self.power_up()?;
+        Ok(())
+    }
+
+    /// Records an IO error from the port, noting if it means the port is gone
+    pub(crate) fn note_port_error(&mut self, e: &io::Error) {
+        if is_port_gone(e) && !self.port_lost {
+            log::warn!("Serial port lost: {}", e);
+            self.port_lost = true;
+            self.set_connection_state(ConnectionEvent::Degraded {
+                reason: format!("port lost: {}", e),
+            });
+        }
+    }
+
+    /// Reconnects if the port was lost and a reconnect policy is set. Returns whether the port
+    /// is usable (as far as we know)
+    pub(crate) fn recover_port(&mut self) -> bool {
+        if !self.port_lost || self.reconnect_policy.is_none() {
+            return !self.port_lost;
+        }
+        match self.reconnect() {
+            Ok(()) => true,
+            Err(e) => {
+                log::error!("Couldn't reconnect: {}", e);
+                false
+            }
+        }
+    }
+
+    /// Subscribes to connection lifecycle events. The current state is sent immediately, followed
+    /// by every subsequent transition. A disconnected channel means the [Device] was dropped.
+    ///
+    /// Up to 64 events are held for a subscriber that isn't keeping up; later ones are dropped
+    /// until it catches up, so a forgotten receiver can't grow without bound on a long run
+    pub fn connection_events(&mut self) -> Receiver<ConnectionEvent> {
+        let (tx, rx) = sync_channel(MAX_PENDING_EVENTS);
+        let _ = tx.send(self.connection_state.clone());
+        self.connection_subscribers.push(tx);
+        rx
+    }
+
+    /// Most recent connection state
+    pub fn connection_state(&self) -> &ConnectionEvent {
+        &self.connection_state
+    }
+
+    /// Records a state transition and notifies subscribers, forgetting any that hung up.
+    /// Repeated identical states aren't re-sent
+    pub(crate) fn set_connection_state(&mut self, state: ConnectionEvent) {
+        if self.connection_state == state {
+            return;
+        }
+        self.connection_subscribers.retain(|subscriber| {
+            match subscriber.try_send(state.clone()) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    log::debug!("Connection event subscriber is behind, dropped {:?}", state);
+                    true
+                }
+                Err(TrySendError::Disconnected(_)) => false,
+            }
+        });
+        self.connection_state = state;
+    }
+
+    /// Called after a frame was read successfully, clearing [ConnectionEvent::Connecting] and
+    /// [ConnectionEvent::Degraded]
+    pub(crate) fn mark_healthy(&mut self) {
+        if matches!(
+            self.connection_state,
+            ConnectionEvent::Connecting | ConnectionEvent::Degraded { .. }
+        ) {
+            self.set_connection_state(if self.streaming {
+                ConnectionEvent::Streaming
+            } else {
+                ConnectionEvent::Identified
+            });
+        }
+    }
+}
@@ -0,0 +1,33 @@
+// Sizes and limits of the binary protocol, from the TargetPoint3 user manual. The codec, the
+// parsers and the range checks all use these, so tooling that builds or checks frames itself can
+// too.
+
+/// Bytes in a frame besides the payload: 2 length bytes, 1 command byte, 2 crc bytes
+pub const FRAME_OVERHEAD: usize = 5;
+
+/// Longest frame we'll accept. No TargetPoint3 frame comes close to this
+pub const MAX_FRAME_LEN: usize = 4096;
+
+/// Longest payload that fits in a [MAX_FRAME_LEN] frame
+pub const MAX_PAYLOAD_LEN: usize = MAX_FRAME_LEN - FRAME_OVERHEAD;
+
+/// Most FIR filter taps a TargetPoint3 or TRAX takes. Tap counts are 0, 4, 8, 16 or 32
+pub const MAX_FIR_TAPS: usize = 32;
+
+/// Fewest user calibration points `UserCalNumPoints` may be set to
+pub const MIN_CAL_POINTS: u32 = 4;
+
+/// Most user calibration points `UserCalNumPoints` may be set to
+pub const MAX_CAL_POINTS: u32 = 18;
+
+/// Highest calibration coefficient set index
+pub const MAX_COEFF_SET: u8 = 7;
+
+/// Size of a float data component's value in a GetDataResp, e.g. heading
+pub const FLOAT_VALUE_LEN: usize = 4;
+
+/// Size of a flag or status data component's value in a GetDataResp, e.g. distortion
+pub const FLAG_VALUE_LEN: usize = 1;
+
+/// Size of the quaternion data component's value in a GetDataResp
+pub const QUATERNION_VALUE_LEN: usize = 16;
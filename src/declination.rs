@@ -0,0 +1,203 @@
+// Declination changes slowly but not negligibly (up to a few tenths of a degree a year), so where
+// it comes from is a trade-off between accuracy and what's available at install time: a surveyed
+// value, a model file shipped with the software, or a lookup against the current official model.
+
+use crate::config::ConfigPair;
+use crate::Device;
+
+use std::error::Error;
+use std::time::SystemTime;
+
+/// WGS-84 position, as used to look up declination
+#[derive(Debug, Display, Clone, Copy, PartialEq)]
+#[display(fmt = "{}˚, {}˚ at {} km", latitude, longitude, altitude_km)]
+pub struct Location {
+    /// Degrees, positive north
+    pub latitude: f64,
+
+    /// Degrees, positive east
+    pub longitude: f64,
+
+    /// Height above the WGS-84 ellipsoid in km
+    pub altitude_km: f64,
+}
+
+impl Location {
+    /// Location at the ellipsoid surface, close enough for declination anywhere near the ground
+    pub fn new(latitude: f64, longitude: f64) -> Self {
+        Self {
+            latitude,
+            longitude,
+            altitude_km: 0.0,
+        }
+    }
+}
+
+/// Error from a [DeclinationSource]
+#[derive(Debug, Display)]
+pub enum DeclinationError {
+    /// The source doesn't cover this date, e.g. a WMM release past its five years
+    #[display(fmt = "{} doesn't cover {:.2}", source, year)]
+    OutOfRange { source: String, year: f64 },
+
+    /// An online lookup failed or returned something unexpected
+    Lookup(String),
+}
+
+impl Error for DeclinationError {}
+
+/// Somewhere to get the declination at a location from. Implemented by [Manual],
+/// [crate::wmm::MagneticModel] and [crate::wmm::Igrf] (with the `wmm` feature), and
+/// [OnlineLookup] (with the `online-declination` feature). Use [FirstAvailable] to fall back
+/// from one to another
+pub trait DeclinationSource {
+    /// Declination in degrees, positive east, at `location` and `time`
+    fn declination(&self, location: &Location, time: SystemTime) -> Result<f64, DeclinationError>;
+
+    /// Short description for logs and prompts, e.g. `WMM-2025`
+    fn name(&self) -> String;
+}
+
+/// A fixed declination, e.g. from a survey or a chart. The location and time are ignored
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Manual(pub f64);
+
+impl DeclinationSource for Manual {
+    fn declination(&self, _: &Location, _: SystemTime) -> Result<f64, DeclinationError> {
+        Ok(self.0)
+    }
+
+    fn name(&self) -> String {
+        "manual".to_string()
+    }
+}
+
+/// Tries each source in order and returns the first answer, e.g. an online lookup falling back
+/// to a bundled model when offline
+pub struct FirstAvailable(pub Vec<Box<dyn DeclinationSource>>);
+
+impl DeclinationSource for FirstAvailable {
+    fn declination(&self, location: &Location, time: SystemTime) -> Result<f64, DeclinationError> {
+        let mut last_error = DeclinationError::Lookup("no declination sources given".to_string());
+        for source in &self.0 {
+            match source.declination(location, time) {
+                Ok(declination) => return Ok(declination),
+                Err(e) => {
+                    log::warn!("Declination from {} unavailable: {}", source.name(), e);
+                    last_error = e;
+                }
+            }
+        }
+        Err(last_error)
+    }
+
+    fn name(&self) -> String {
+        let names: Vec<String> = self.0.iter().map(|source| source.name()).collect();
+        names.join(", then ")
+    }
+}
+
+/// NOAA's geomagnetic calculator web service, which always uses the current official model.
+/// Needs a (free) API key from https://www.ngdc.noaa.gov/geomag/CalcSurvey.shtml
+#[cfg(feature = "online-declination")]
+#[derive(Debug, Clone)]
+pub struct OnlineLookup {
+    pub api_key: String,
+    pub timeout: std::time::Duration,
+}
+
+#[cfg(feature = "online-declination")]
+impl OnlineLookup {
+    const URL: &'static str = "https://www.ngdc.noaa.gov/geomag-web/calculators/calculateDeclination";
+
+    pub fn new(api_key: impl Into<String>) -> Self {
+        Self {
+            api_key: api_key.into(),
+            timeout: std::time::Duration::from_secs(10),
+        }
+    }
+}
+
+#[cfg(feature = "online-declination")]
+impl DeclinationSource for OnlineLookup {
+    fn declination(&self, location: &Location, time: SystemTime) -> Result<f64, DeclinationError> {
+        let (year, month, day) = civil_date(time);
+        let body = ureq::get(Self::URL)
+            .timeout(self.timeout)
+            .query("lat1", &location.latitude.to_string())
+            .query("lon1", &location.longitude.to_string())
+            .query("elevation", &location.altitude_km.to_string())
+            .query("elevationUnits", "K")
+            .query("startYear", &year.to_string())
+            .query("startMonth", &month.to_string())
+            .query("startDay", &day.to_string())
+            .query("resultFormat", "json")
+            .query("key", &self.api_key)
+            .call()
+            .map_err(|e| DeclinationError::Lookup(e.to_string()))?
+            .into_string()
+            .map_err(|e| DeclinationError::Lookup(e.to_string()))?;
+        json_number(&body, "declination").ok_or_else(|| {
+            DeclinationError::Lookup(format!("no declination in response: {}", body))
+        })
+    }
+
+    fn name(&self) -> String {
+        "NOAA online calculator".to_string()
+    }
+}
+
+/// UTC year, month and day of a time
+#[cfg(feature = "online-declination")]
+fn civil_date(time: SystemTime) -> (i64, u32, u32) {
+    let seconds = match time.duration_since(std::time::UNIX_EPOCH) {
+        Ok(since) => since.as_secs() as i64,
+        Err(before) => -(before.duration().as_secs() as i64),
+    };
+    // Howard Hinnant's civil_from_days
+    let z = seconds.div_euclid(86400) + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// First number following `"key":` in a JSON document. The calculator's response is small and
+/// flat enough that this is all the parsing needed
+#[cfg(feature = "online-declination")]
+fn json_number(json: &str, key: &str) -> Option<f64> {
+    let pattern = format!("\"{}\"", key);
+    let rest = &json[json.find(&pattern)? + pattern.len()..];
+    let rest = rest.trim_start().strip_prefix(':')?.trim_start();
+    let end = rest
+        .find(|c: char| !(c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E')))
+        .unwrap_or(rest.len());
+    rest[..end].parse().ok()
+}
+
+impl Device {
+    /// Looks up the declination at `location` for the current date and sets
+    /// [ConfigPair::Declination] to it, returning the value set. As with setting it directly,
+    /// declination only affects heading once [ConfigPair::TrueNorth] is enabled, and needs
+    /// [Device::save] to persist across power cycles
+    pub fn set_declination_from(
+        &mut self,
+        source: &dyn DeclinationSource,
+        location: &Location,
+    ) -> Result<f32, Box<dyn Error>> {
+        let declination = source.declination(location, SystemTime::now())? as f32;
+        log::info!(
+            "Declination at {} from {}: {}˚",
+            location,
+            source.name(),
+            declination
+        );
+        self.set_config(ConfigPair::Declination(declination))?;
+        Ok(declination)
+    }
+}
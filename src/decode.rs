@@ -0,0 +1,272 @@
+// Offline decoding of raw captures, e.g. from a customer's serial sniffer or logic analyser, using
+// the same frame layout and response parsers as a live Device. Nothing here needs a port.
+
+use crate::acquisition::{Data, DataID};
+use crate::codec::{self, CodecError, FRAME_OVERHEAD};
+use crate::command::Command;
+use crate::dispatch::Response;
+
+use std::fmt::{self, Write};
+
+/// State of a decoded frame's CRC
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+pub enum CrcStatus {
+    Valid,
+
+    #[display(fmt = "Invalid (expected {:#06x}, got {:#06x})", expected, actual)]
+    Invalid { expected: u16, actual: u16 },
+
+    /// The capture ended before the frame did
+    Truncated,
+
+    /// Bytes that aren't part of any frame
+    NotAFrame,
+}
+
+/// One stretch of a capture: a frame, or bytes between frames. See [explain]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedFrame {
+    /// Position of the first byte in the capture
+    pub offset: usize,
+
+    /// The bytes, including length and checksum
+    pub bytes: Vec<u8>,
+
+    /// [None] for bytes between frames
+    pub command: Option<u8>,
+
+    /// Command name, e.g. `GetDataResp`, or a description of what the bytes are
+    pub name: String,
+
+    pub crc: CrcStatus,
+
+    /// Payload fields by name, as far as they could be parsed
+    pub fields: Vec<(&'static str, String)>,
+
+    /// Why the payload couldn't be parsed, if it couldn't
+    pub error: Option<String>,
+}
+
+impl fmt::Display for DecodedFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#06x} {} [{}]", self.offset, self.name, self.crc)?;
+        for (name, value) in &self.fields {
+            write!(f, " {}={}", name, value)?;
+        }
+        if let Some(error) = &self.error {
+            write!(f, " error: {}", error)?;
+        }
+        write!(f, "\n       {}", hex(&self.bytes))
+    }
+}
+
+/// Bytes as space-separated hex pairs, e.g. `00 05 01 ef d4`
+pub fn hex(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() * 3);
+    for (i, byte) in bytes.iter().enumerate() {
+        if i > 0 {
+            out.push(' ');
+        }
+        let _ = write!(out, "{:02x}", byte);
+    }
+    out
+}
+
+/// Classic 16 bytes per line hex dump with offsets and printable ASCII, for pasting into tickets
+pub fn hex_dump(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for (line, chunk) in bytes.chunks(16).enumerate() {
+        let ascii = chunk
+            .iter()
+            .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' })
+            .collect::<String>();
+        let _ = writeln!(out, "{:08x}  {:<47}  |{}|", line * 16, hex(chunk), ascii);
+    }
+    out
+}
+
+fn command_name(command: u8) -> String {
+    match Command::try_from(command) {
+        Ok(command) => format!("{:?}", command),
+        Err(unknown) => unknown.to_string(),
+    }
+}
+
+fn data_fields(data: &Data) -> Vec<(&'static str, String)> {
+    let scalars = [
+        ("heading", data.heading),
+        ("pitch", data.pitch),
+        ("roll", data.roll),
+        ("temperature", data.temperature),
+        ("accel_x", data.accel_x),
+        ("accel_y", data.accel_y),
+        ("accel_z", data.accel_z),
+        ("mag_x", data.mag_x),
+        ("mag_y", data.mag_y),
+        ("mag_z", data.mag_z),
+        ("mag_accuracy", data.mag_accuracy),
+        ("gyro_x", data.gyro_x),
+        ("gyro_y", data.gyro_y),
+        ("gyro_z", data.gyro_z),
+    ];
+    let mut fields = scalars
+        .into_iter()
+        .filter_map(|(name, value)| Some((name, value?.to_string())))
+        .collect::<Vec<_>>();
+    let flags = [("distortion", data.distortion), ("cal_status", data.cal_status)];
+    fields.extend(flags.into_iter().filter_map(|(name, value)| Some((name, value?.to_string()))));
+    if let Some(quaternion) = data.quaternion {
+        fields.push(("quaternion", format!("{:?}", quaternion)));
+    }
+    if let Some(status) = data.heading_status {
+        fields.push(("heading_status", status.to_string()));
+    }
+    fields
+}
+
+fn response_fields(response: &Response) -> Vec<(&'static str, String)> {
+    match response {
+        Response::ModInfo(info) => vec![
+            ("device_type", format!("{:?}", info.device_type)),
+            ("revision", format!("{:?}", info.revision)),
+        ],
+        Response::SerialNumber(serial_number) => vec![("serial_number", serial_number.to_string())],
+        Response::Data(data) => data_fields(data),
+        Response::AcqParams(params) => vec![
+            ("acquisition_mode", params.acquisition_mode.to_string()),
+            ("flush_filter", params.flush_filter.to_string()),
+            ("sample_delay", params.sample_delay.to_string()),
+        ],
+        Response::FirFilters(taps) => vec![("taps", format!("{:?}", taps))],
+        Response::UserCalSampleCount(count) => vec![("sample_count", count.to_string())],
+        Response::UserCalScore(score) => vec![("score", format!("{:?}", score))],
+        Response::SaveDone { error_code } => vec![("error_code", error_code.to_string())],
+        Response::Done(_) => Vec::new(),
+        Response::Unparsed { payload, .. } | Response::Unknown { payload, .. } => {
+            if payload.is_empty() {
+                Vec::new()
+            } else {
+                vec![("payload", hex(payload))]
+            }
+        }
+    }
+}
+
+/// Fields of frames the host sends, which the response parsers don't cover
+fn request_fields(command: Command, payload: &[u8]) -> Option<Vec<(&'static str, String)>> {
+    match command {
+        Command::SetDataComponents => {
+            let (&count, ids) = payload.split_first()?;
+            let names = ids
+                .iter()
+                .map(|&id| match DataID::try_from(id) {
+                    Ok(id) => format!("{:?}", id),
+                    Err(_) => format!("{:#04x}", id),
+                })
+                .collect::<Vec<_>>();
+            Some(vec![("count", count.to_string()), ("components", names.join(","))])
+        }
+        _ => None,
+    }
+}
+
+fn explain_frame(offset: usize, bytes: &[u8], crc: CrcStatus) -> DecodedFrame {
+    let command = bytes[2];
+    let payload = match crc {
+        CrcStatus::Truncated => &bytes[3..],
+        _ => &bytes[3..bytes.len() - 2],
+    };
+    let mut decoded = DecodedFrame {
+        offset,
+        bytes: bytes.to_vec(),
+        command: Some(command),
+        name: command_name(command),
+        crc,
+        fields: Vec::new(),
+        error: None,
+    };
+    if crc != CrcStatus::Valid {
+        if !payload.is_empty() {
+            decoded.fields.push(("payload", hex(payload)));
+        }
+        return decoded;
+    }
+
+    let request = Command::try_from(command)
+        .ok()
+        .and_then(|command| request_fields(command, payload));
+    match request {
+        Some(fields) => decoded.fields = fields,
+        None => match (codec::Frame { command, payload }).parse() {
+            Ok(response) => decoded.fields = response_fields(&response),
+            Err(e) => {
+                decoded.fields.push(("payload", hex(payload)));
+                decoded.error = Some(e.to_string());
+            }
+        },
+    }
+    decoded
+}
+
+/// Splits a raw capture into frames and describes each: command name, payload fields and
+/// whether the CRC checks out. Bytes that don't start a plausible frame, e.g. line noise or the
+/// tail of a frame the capture started in the middle of, are grouped into
+/// [CrcStatus::NotAFrame] entries so nothing in the capture is hidden.
+///
+/// A frame with a bad CRC is only recognised as such if its command byte is known; otherwise its
+/// first byte is treated as noise and decoding resyncs from the next one
+pub fn explain(bytes: &[u8]) -> Vec<DecodedFrame> {
+    let mut frames = Vec::new();
+    let mut noise_start = None;
+    let mut offset = 0;
+
+    while offset < bytes.len() {
+        let rest = &bytes[offset..];
+        let declared_len = match rest {
+            [high, low, ..] => u16::from_be_bytes([*high, *low]) as usize,
+            _ => 0,
+        };
+        let known_command = rest.get(2).is_some_and(|&c| Command::try_from(c).is_ok());
+
+        let found = match codec::decode_frame(rest) {
+            Ok((_, len)) => Some((len, CrcStatus::Valid)),
+            Err(CodecError::ChecksumMismatch { expected, actual }) if known_command => {
+                Some((declared_len, CrcStatus::Invalid { expected, actual }))
+            }
+            Err(CodecError::Incomplete) if known_command && declared_len >= FRAME_OVERHEAD => {
+                Some((rest.len(), CrcStatus::Truncated))
+            }
+            Err(_) => None,
+        };
+
+        match found {
+            Some((len, crc)) => {
+                if let Some(start) = noise_start.take() {
+                    frames.push(noise(start, &bytes[start..offset]));
+                }
+                frames.push(explain_frame(offset, &rest[..len], crc));
+                offset += len;
+            }
+            None => {
+                noise_start.get_or_insert(offset);
+                offset += 1;
+            }
+        }
+    }
+    if let Some(start) = noise_start {
+        frames.push(noise(start, &bytes[start..]));
+    }
+    frames
+}
+
+fn noise(offset: usize, bytes: &[u8]) -> DecodedFrame {
+    DecodedFrame {
+        offset,
+        bytes: bytes.to_vec(),
+        command: None,
+        name: format!("{} unframed byte(s)", bytes.len()),
+        crc: CrcStatus::NotAFrame,
+        fields: Vec::new(),
+        error: None,
+    }
+}
@@ -0,0 +1,293 @@
+// Timing checks for a particular setup. Baud rate, FIR taps and SampleDelay all trade latency for
+// something else, and the manual's figures don't include the host's serial stack or USB adapter,
+// so the only reliable way to know whether a combination fits a timing budget is to measure it.
+
+use crate::capabilities::Feature;
+use crate::catalog::{Message, MessageId};
+use crate::config::ConfigID;
+use crate::{Device, RWError, ReadError};
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Spread of a set of measured durations
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+#[display(
+    fmt = "mean {:?}, min {:?}, max {:?}, jitter {:?}, p95 {:?} over {}",
+    mean,
+    min,
+    max,
+    jitter,
+    p95,
+    count
+)]
+pub struct TimingStats {
+    pub count: usize,
+    pub mean: Duration,
+    pub min: Duration,
+    pub max: Duration,
+
+    /// Standard deviation
+    pub jitter: Duration,
+
+    /// 95th percentile, nearest rank
+    pub p95: Duration,
+}
+
+impl TimingStats {
+    /// Statistics over `durations`, or [None] if it's empty
+    pub fn from_durations(durations: &[Duration]) -> Option<Self> {
+        if durations.is_empty() {
+            return None;
+        }
+        let mut sorted = durations.to_vec();
+        sorted.sort();
+
+        let count = sorted.len();
+        let seconds = sorted.iter().map(Duration::as_secs_f64);
+        let mean = seconds.clone().sum::<f64>() / count as f64;
+        let variance = seconds.map(|s| (s - mean).powi(2)).sum::<f64>() / count as f64;
+        let rank = ((count as f64 * 0.95).ceil() as usize).clamp(1, count);
+
+        Some(Self {
+            count,
+            mean: Duration::from_secs_f64(mean),
+            min: sorted[0],
+            max: sorted[count - 1],
+            jitter: Duration::from_secs_f64(variance.sqrt()),
+            p95: sorted[rank - 1],
+        })
+    }
+}
+
+/// Board health. See [Device::diagnostics]
+#[derive(Debug, Display, Clone, Copy, PartialEq)]
+#[display(fmt = "board {:.1} °C, supply {:.2} V", temperature, supply_voltage)]
+pub struct BoardDiagnostics {
+    /// Board temperature in °C. Unlike the Temperature data component, this doesn't need data
+    /// components set or polled mode
+    pub temperature: f32,
+
+    /// Supply voltage at the board, in volts
+    pub supply_voltage: f32,
+}
+
+/// Result of [Device::measure_round_trip_latency]
+#[derive(Debug, Clone, PartialEq)]
+pub struct LatencyReport {
+    /// Time from sending GetData to having parsed the response, for requests that succeeded
+    pub round_trip: TimingStats,
+
+    /// Requests that failed, e.g. by timing out
+    pub failures: usize,
+}
+
+/// Result of [Device::measure_continuous_rate]
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateReport {
+    /// Samples received
+    pub samples: usize,
+
+    /// From the first sample's arrival to the last's
+    pub elapsed: Duration,
+
+    /// Samples per second over [RateReport::elapsed]
+    pub rate_hz: f64,
+
+    /// Gaps between consecutive samples. [None] with fewer than two samples
+    pub intervals: Option<TimingStats>,
+
+    /// Frames that arrived but couldn't be parsed
+    pub errors: usize,
+}
+
+/// One exchange in a [SelfTestReport]
+#[derive(Debug)]
+pub struct SelfTestStep {
+    /// What was sent, e.g. `GetConfig(Declination)`
+    pub name: String,
+
+    /// From sending the request to having parsed the response, or to the failure
+    pub elapsed: Duration,
+
+    /// [None] if the step passed
+    pub error: Option<RWError>,
+}
+
+impl SelfTestStep {
+    pub fn passed(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Result of [Device::self_test]. Displays as one line per step
+#[derive(Debug)]
+pub struct SelfTestReport {
+    pub steps: Vec<SelfTestStep>,
+}
+
+impl SelfTestReport {
+    /// Whether every step passed: the go/no-go answer
+    pub fn passed(&self) -> bool {
+        self.steps.iter().all(SelfTestStep::passed)
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &SelfTestStep> {
+        self.steps.iter().filter(|step| !step.passed())
+    }
+
+    /// Sum of every step's time
+    pub fn elapsed(&self) -> Duration {
+        self.steps.iter().map(|step| step.elapsed).sum()
+    }
+}
+
+impl fmt::Display for SelfTestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for step in &self.steps {
+            match &step.error {
+                None => writeln!(f, "ok   {} ({:?})", step.name, step.elapsed)?,
+                Some(e) => writeln!(f, "FAIL {} ({:?}): {}", step.name, step.elapsed, e)?,
+            }
+        }
+        let verdict = if self.passed() { "PASS" } else { "FAIL" };
+        write!(f, "{} in {:?}", verdict, self.elapsed())
+    }
+}
+
+impl Device {
+    /// Reads board temperature and supply voltage, for fleet health monitoring.
+    ///
+    /// No documented command reports these yet (see [Feature::Diagnostics]), so this fails with
+    /// [RWError::UnsupportedByDevice] on every device, without sending anything
+    pub fn diagnostics(&mut self) -> Result<BoardDiagnostics, RWError> {
+        Err(self.unsupported(Feature::Diagnostics))
+    }
+
+    /// Runs through the read-only commands (GetModInfo, SerialNumber, GetConfig for every
+    /// [ConfigID], GetAcqParams and one GetData) and reports each one's result and timing, as a
+    /// one-call go/no-go check for production lines. Nothing is changed on the device.
+    ///
+    /// A failed step doesn't stop the test: the input is cleared and the next step runs. The
+    /// device must be in polled mode; GetData also needs data components set, or it fails
+    pub fn self_test(&mut self) -> SelfTestReport {
+        let mut steps = Vec::new();
+        steps.push(self.self_test_step("GetModInfo".into(), |device| {
+            device.get_mod_info().map(drop)
+        }));
+        steps.push(self.self_test_step("SerialNumber".into(), |device| {
+            device.serial_number().map(drop)
+        }));
+        for id in ConfigID::ALL {
+            steps.push(self.self_test_step(format!("GetConfig({:?})", id), |device| {
+                device.get_config(id).map(drop)
+            }));
+        }
+        steps.push(self.self_test_step("GetAcqParams".into(), |device| {
+            device.get_acq_params().map(drop)
+        }));
+        steps.push(self.self_test_step("GetData".into(), |device| device.get_data().map(drop)));
+        SelfTestReport { steps }
+    }
+
+    fn self_test_step(
+        &mut self,
+        name: String,
+        run: impl FnOnce(&mut Device) -> Result<(), RWError>,
+    ) -> SelfTestStep {
+        let start = Instant::now();
+        let error = run(self).err();
+        if error.is_some() {
+            // drop the rest of whatever failed so the next step starts on a frame boundary
+            self.reset_frame();
+            let _ = self.transport.clear_input();
+        }
+        SelfTestStep {
+            name,
+            elapsed: start.elapsed(),
+            error,
+        }
+    }
+
+    /// Sends `requests` GetData requests one after another and times each round trip, as seen
+    /// by a polling loop. The device must be in polled mode, with data components set.
+    ///
+    /// Failed requests are counted rather than ending the measurement; if every request fails,
+    /// the last error is returned
+    pub fn measure_round_trip_latency(
+        &mut self,
+        requests: usize,
+    ) -> Result<LatencyReport, RWError> {
+        let mut round_trips = Vec::with_capacity(requests);
+        let mut last_error = None;
+        for _ in 0..requests {
+            let start = Instant::now();
+            match self.get_data() {
+                Ok(_) => round_trips.push(start.elapsed()),
+                Err(e) => {
+                    log::debug!("Latency probe failed: {}", e);
+                    if self.sync_to_frame_boundary().is_err() {
+                        self.reset_frame();
+                    }
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        match (TimingStats::from_durations(&round_trips), last_error) {
+            (Some(round_trip), _) => Ok(LatencyReport {
+                round_trip,
+                failures: requests - round_trips.len(),
+            }),
+            (None, Some(e)) => Err(e),
+            (None, None) => Err(RWError::InvalidArgument(
+                Message::new(MessageId::OutOfRange)
+                    .with("name", "requests")
+                    .with("value", 0)
+                    .with("range", "[1, ∞)"),
+            )),
+        }
+    }
+
+    /// Times `samples` samples from a device already in continuous mode, to check the rate and
+    /// jitter the host actually sees. Stops early if the stream goes quiet for longer than the
+    /// port timeout. Frames that fail to parse are counted in [RateReport::errors]; IO errors
+    /// end the measurement
+    pub fn measure_continuous_rate(&mut self, samples: usize) -> Result<RateReport, ReadError> {
+        let mut arrivals = Vec::with_capacity(samples);
+        let mut errors = 0;
+        let mut stream = self.iter_timestamped(None);
+        while arrivals.len() < samples {
+            match stream.next() {
+                None => break,
+                Some(Ok(sample)) => arrivals.push(sample.received_at),
+                Some(Err(e @ ReadError::PipeError(_))) => return Err(e),
+                Some(Err(e)) => {
+                    log::debug!("Rate measurement skipped a frame: {}", e);
+                    errors += 1;
+                }
+            }
+        }
+
+        let intervals = arrivals
+            .windows(2)
+            .map(|pair| pair[1] - pair[0])
+            .collect::<Vec<_>>();
+        let elapsed = match (arrivals.first(), arrivals.last()) {
+            (Some(first), Some(last)) => *last - *first,
+            _ => Duration::ZERO,
+        };
+        let rate_hz = if elapsed.is_zero() {
+            0.0
+        } else {
+            intervals.len() as f64 / elapsed.as_secs_f64()
+        };
+        Ok(RateReport {
+            samples: arrivals.len(),
+            elapsed,
+            rate_hz,
+            intervals: TimingStats::from_durations(&intervals),
+            errors,
+        })
+    }
+}
@@ -0,0 +1,425 @@
+use crate::acquisition::{AcqParams, AcqParamsReserved, Data};
+use crate::calibration::UserCalResponse;
+use crate::codec::{self, FRAME_OVERHEAD};
+use crate::command::Command;
+use crate::frame::{FrameReader, RawFrame};
+use crate::responses::{Get, ModInfoResp};
+use crate::{Device, ReadError};
+
+use std::time::Duration;
+
+use std::collections::VecDeque;
+
+/// Unsolicited responses kept for [Device::take_unsolicited]. Older ones are dropped beyond this,
+/// so a device left streaming can't grow the queue without bound
+const MAX_UNSOLICITED: usize = 256;
+
+/// A response frame parsed according to its command byte, without knowing which request (if any)
+/// it answers
+#[derive(Debug, PartialEq)]
+#[non_exhaustive]
+pub enum Response {
+    ModInfo(ModInfoResp),
+    SerialNumber(u32),
+    Data(Data),
+    AcqParams(AcqParams),
+    FirFilters(Vec<f64>),
+    UserCalSampleCount(u32),
+    UserCalScore(UserCalResponse),
+    SaveDone { error_code: u16 },
+
+    /// Acknowledgement without a payload, e.g. SetConfigDone
+    Done(Command),
+
+    /// Frame with no parser in the table, e.g. GetConfigResp, whose payload can only be read
+    /// knowing which setting was queried
+    Unparsed { command: u8, payload: Vec<u8> },
+
+    /// Frame whose command byte isn't a known [Command], e.g. one added by newer firmware. Its
+    /// length and checksum are still verified, so the stream stays in sync
+    Unknown { id: u8, payload: Vec<u8> },
+}
+
+/// Where response parsers read payload fields from: a [FrameReader] on a live device, or a
+/// `&mut &[u8]` cursor over a payload already in memory
+pub trait Fields:
+    Get<u8> + Get<u16> + Get<u32> + Get<f32> + Get<f64> + Get<bool> + Get<Data>
+{
+}
+
+impl<T> Fields for T where
+    T: Get<u8> + Get<u16> + Get<u32> + Get<f32> + Get<f64> + Get<bool> + Get<Data> + ?Sized
+{
+}
+
+impl dyn Fields + '_ {
+    /// Reads the next field. Named apart from [Get::get] so a parser can pick the field type
+    /// with a turbofish
+    fn read<T>(&mut self) -> Result<T, ReadError>
+    where
+        Self: Get<T>,
+    {
+        Get::<T>::get(self)
+    }
+
+    fn read_string<T>(&mut self) -> Result<String, ReadError>
+    where
+        Self: Get<T>,
+    {
+        Get::<T>::get_string(self)
+    }
+}
+
+type Parser = fn(&mut dyn Fields) -> Result<Response, ReadError>;
+
+/// Payload parser for each response command
+const PARSERS: &[(Command, Parser)] = &[
+    (Command::GetModInfoResp, |frame| {
+        let device_type = frame.read_string::<u32>()?;
+        let revision = frame.read_string::<u32>()?;
+        Ok(Response::ModInfo(ModInfoResp::new(device_type, revision)))
+    }),
+    (Command::SerialNumberResp, |frame| {
+        Ok(Response::SerialNumber(frame.read::<u32>()?))
+    }),
+    (Command::GetDataResp, |frame| Ok(Response::Data(frame.read::<Data>()?))),
+    (Command::GetAcqParamsResp, |frame| {
+        Ok(Response::AcqParams(
+            AcqParamsReserved {
+                acquisition_mode: frame.read::<bool>()?,
+                flush_filter: frame.read::<bool>()?,
+                reserved: frame.read::<f32>()?,
+                sample_delay: frame.read::<f32>()?,
+            }
+            .into(),
+        ))
+    }),
+    (Command::GetFIRFiltersResp, |frame| {
+        let _byte_1 = frame.read::<u8>()?;
+        let _byte_2 = frame.read::<u8>()?;
+        let count = frame.read::<u8>()?;
+        let mut taps = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            taps.push(frame.read::<f64>()?);
+        }
+        Ok(Response::FirFilters(taps))
+    }),
+    (Command::UserCalSampleCount, |frame| {
+        Ok(Response::UserCalSampleCount(frame.read::<u32>()?))
+    }),
+    (Command::UserCalScore, |frame| {
+        let mag_cal_score = frame.read::<f32>()?;
+        let _reserved = frame.read::<f32>()?;
+        Ok(Response::UserCalScore(UserCalResponse::UserCalScore {
+            mag_cal_score,
+            accel_cal_score: frame.read::<f32>()?,
+            distribution_error: frame.read::<f32>()?,
+            tilt_error: frame.read::<f32>()?,
+            tilt_range: frame.read::<f32>()?,
+        }))
+    }),
+    (Command::SaveDone, |frame| {
+        Ok(Response::SaveDone {
+            error_code: frame.read::<u16>()?,
+        })
+    }),
+    (Command::SetConfigDone, |_| Ok(Response::Done(Command::SetConfigDone))),
+    (Command::SetFIRFiltersDone, |_| {
+        Ok(Response::Done(Command::SetFIRFiltersDone))
+    }),
+    (Command::SetAcqParamsDone, |_| {
+        Ok(Response::Done(Command::SetAcqParamsDone))
+    }),
+    (Command::PowerUpDone, |_| Ok(Response::Done(Command::PowerUpDone))),
+    (Command::PowerDownDone, |_| Ok(Response::Done(Command::PowerDownDone))),
+    (Command::FactoryMagCoeffDone, |_| {
+        Ok(Response::Done(Command::FactoryMagCoeffDone))
+    }),
+    (Command::FactoryAccelCoeffDone, |_| {
+        Ok(Response::Done(Command::FactoryAccelCoeffDone))
+    }),
+    (Command::CopyCoeffSetDone, |_| {
+        Ok(Response::Done(Command::CopyCoeffSetDone))
+    }),
+];
+
+fn parser_for(command: u8) -> Option<Parser> {
+    let command = Command::try_from(command).ok()?;
+    PARSERS
+        .iter()
+        .find(|(response, _)| *response == command)
+        .map(|(_, parser)| *parser)
+}
+
+impl FrameReader<'_> {
+    /// Parses the whole frame with the parser registered for its command, checking the checksum
+    pub fn dispatch(mut self) -> Result<Response, ReadError> {
+        let response = match parser_for(self.command()) {
+            Some(parser) => parser(&mut self as &mut dyn Fields)?,
+            None if Command::try_from(self.command()).is_err() => Response::Unknown {
+                id: self.command(),
+                payload: self.remaining_payload()?,
+            },
+            None => Response::Unparsed {
+                command: self.command(),
+                payload: self.remaining_payload()?,
+            },
+        };
+        self.finish()?;
+        Ok(response)
+    }
+}
+
+impl codec::Frame<'_> {
+    /// Parses a frame decoded from bytes captured elsewhere, such as a packet capture or a
+    /// datalogger's dump, the same way [Device::read_response] parses a live one. The whole
+    /// payload must be used by the parser.
+    ///
+    /// Parsing is as strict as it can be without a device: [Device::set_lenient_bools] doesn't
+    /// apply, and data components aren't checked against what the device supports
+    pub fn parse(&self) -> Result<Response, ReadError> {
+        let mut payload = self.payload;
+        let response = match parser_for(self.command) {
+            Some(parser) => parser(&mut payload as &mut dyn Fields)?,
+            None if Command::try_from(self.command).is_err() => Response::Unknown {
+                id: self.command,
+                payload: std::mem::take(&mut payload).to_vec(),
+            },
+            None => Response::Unparsed {
+                command: self.command,
+                payload: std::mem::take(&mut payload).to_vec(),
+            },
+        };
+        if !payload.is_empty() {
+            let frame_len =
+                |payload_len: usize| (payload_len + FRAME_OVERHEAD).min(u16::MAX as usize) as u16;
+            return Err(ReadError::SizeMismatch {
+                expected: frame_len(self.payload.len()),
+                actual: frame_len(self.payload.len() - payload.len()),
+            });
+        }
+        Ok(response)
+    }
+}
+
+/// 4 bytes of a ModInfo string, padded with spaces as the device pads its own
+fn mod_info_field(field: &str) -> [u8; 4] {
+    let mut bytes = [b' '; 4];
+    for (byte, value) in bytes.iter_mut().zip(field.bytes()) {
+        *byte = value;
+    }
+    bytes
+}
+
+impl Response {
+    /// Command byte of the frame this response arrives in
+    pub fn command(&self) -> u8 {
+        let command = match self {
+            Response::ModInfo(_) => Command::GetModInfoResp,
+            Response::SerialNumber(_) => Command::SerialNumberResp,
+            Response::Data(_) => Command::GetDataResp,
+            Response::AcqParams(_) => Command::GetAcqParamsResp,
+            Response::FirFilters(_) => Command::GetFIRFiltersResp,
+            Response::UserCalSampleCount(_) => Command::UserCalSampleCount,
+            Response::UserCalScore(UserCalResponse::SampleCount(_)) => Command::UserCalSampleCount,
+            Response::UserCalScore(_) => Command::UserCalScore,
+            Response::SaveDone { .. } => Command::SaveDone,
+            Response::Done(command) => *command,
+            Response::Unparsed { command, .. } => return *command,
+            Response::Unknown { id, .. } => return *id,
+        };
+        u8::from(command)
+    }
+
+    /// Encodes the payload the way the device sends it, so that parsing it gives back the same
+    /// response. Reserved fields are zeroed. Mostly for building frames in tests and simulators
+    pub fn to_payload(&self) -> Vec<u8> {
+        match self {
+            Response::ModInfo(info) => {
+                let mut payload = mod_info_field(&info.device_type).to_vec();
+                payload.extend_from_slice(&mod_info_field(&info.revision));
+                payload
+            }
+            Response::SerialNumber(value) | Response::UserCalSampleCount(value) => {
+                value.to_be_bytes().to_vec()
+            }
+            Response::Data(data) => data.to_payload(),
+            Response::AcqParams(params) => AcqParamsReserved::from(params.clone()).to_payload(),
+            Response::FirFilters(taps) => {
+                let mut payload = vec![3, 1, taps.len() as u8];
+                for tap in taps {
+                    payload.extend_from_slice(&tap.to_be_bytes());
+                }
+                payload
+            }
+            Response::UserCalScore(UserCalResponse::SampleCount(count)) => {
+                count.to_be_bytes().to_vec()
+            }
+            Response::UserCalScore(UserCalResponse::UserCalScore {
+                mag_cal_score,
+                accel_cal_score,
+                distribution_error,
+                tilt_error,
+                tilt_range,
+            }) => [
+                *mag_cal_score,
+                0.0,
+                *accel_cal_score,
+                *distribution_error,
+                *tilt_error,
+                *tilt_range,
+            ]
+            .iter()
+            .flat_map(|value| value.to_be_bytes())
+            .collect(),
+            Response::SaveDone { error_code } => error_code.to_be_bytes().to_vec(),
+            Response::Done(_) => Vec::new(),
+            Response::Unparsed { payload, .. } | Response::Unknown { payload, .. } => {
+                payload.clone()
+            }
+        }
+    }
+
+    /// The whole frame, with length and checksum, e.g. to feed to [parse_frame]
+    pub fn to_frame(&self) -> Vec<u8> {
+        let payload = self.to_payload();
+        let mut frame = vec![0u8; payload.len() + FRAME_OVERHEAD];
+        let len = codec::encode_frame(self.command(), &payload, &mut frame)
+            .expect("frame buffer sized for the payload");
+        frame.truncate(len);
+        frame
+    }
+}
+
+/// Decodes and parses the frame at the start of `bytes`, returning the response and how many
+/// bytes the frame took up. Needs no device or port, so it suits fuzzing and parsing captures.
+///
+/// Safe on arbitrary input: lengths from the frame are checked against `bytes` and
+/// [codec::MAX_FRAME_LEN] before use, and nothing is read past the frame. A `bytes` that ends
+/// mid-frame gives a [ReadError::PipeError] of kind `UnexpectedEof`
+pub fn parse_frame(bytes: &[u8]) -> Result<(Response, usize), ReadError> {
+    let (frame, len) = codec::decode_frame(bytes)?;
+    Ok((frame.parse()?, len))
+}
+
+impl RawFrame<'_> {
+    /// Parses the frame by its command byte. See [codec::Frame::parse]
+    pub fn parse(&self) -> Result<Response, ReadError> {
+        codec::Frame {
+            command: self.command(),
+            payload: self.payload(),
+        }
+        .parse()
+    }
+}
+
+impl Device {
+    /// Reads the next frame and parses it by its command byte, whatever it is
+    pub fn read_response(&mut self) -> Result<Response, ReadError> {
+        self.begin_frame()?.dispatch()
+    }
+
+    /// Takes the responses that arrived while a command was waiting for a different one, oldest
+    /// first. For example, a UserCalSampleCount that arrives late during a [Device::get_data]
+    /// ends up here instead of failing the poll
+    pub fn take_unsolicited(&mut self) -> Vec<Response> {
+        self.unsolicited.drain(..).collect()
+    }
+
+    pub(crate) fn queue_unsolicited(&mut self, response: Response) {
+        if self.unsolicited.len() == MAX_UNSOLICITED {
+            self.unsolicited.pop_front();
+        }
+        self.unsolicited.push_back(response);
+    }
+}
+
+/// Something the device sent on its own initiative rather than in answer to a command in flight.
+/// See [Device::events]
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum DeviceEvent {
+    /// Sample from continuous mode
+    Data(Data),
+
+    /// A calibration sample point was taken; the count of points so far
+    CalSample(u32),
+
+    /// Calibration finished, with its score. Always a [UserCalResponse::UserCalScore]
+    CalScore(UserCalResponse),
+
+    /// Device woke up
+    PowerUp,
+
+    /// Reply to a command that arrived after the command stopped waiting for it
+    Response(Response),
+
+    /// Frame without a parser, including ones with command bytes this version doesn't know
+    Unknown { cmd: u8, payload: Vec<u8> },
+}
+
+impl From<Response> for DeviceEvent {
+    fn from(response: Response) -> Self {
+        match response {
+            Response::Data(data) => DeviceEvent::Data(data),
+            Response::UserCalSampleCount(count) => DeviceEvent::CalSample(count),
+            Response::UserCalScore(score @ UserCalResponse::UserCalScore { .. }) => {
+                DeviceEvent::CalScore(score)
+            }
+            Response::Done(crate::command::Command::PowerUpDone) => DeviceEvent::PowerUp,
+            Response::Unparsed { command, payload } => DeviceEvent::Unknown {
+                cmd: command,
+                payload,
+            },
+            Response::Unknown { id, payload } => DeviceEvent::Unknown { cmd: id, payload },
+            response => DeviceEvent::Response(response),
+        }
+    }
+}
+
+impl Device {
+    /// Iterates over frames the device sends on its own: continuous-mode data, calibration
+    /// progress when samples are taken automatically, wake-ups, and so on. Events already queued
+    /// by commands that read past them (see [Device::take_unsolicited]) come first.
+    ///
+    /// Ends when nothing arrives within the port timeout, like [Device::iter]
+    pub fn events(&mut self) -> Events<'_> {
+        Events { device: self }
+    }
+}
+
+/// Iterator returned by [Device::events]
+pub struct Events<'a> {
+    device: &'a mut Device,
+}
+
+impl Events<'_> {
+    /// Waits up to `timeout` for the next event, instead of the port timeout
+    pub fn next_timeout(&mut self, timeout: Duration) -> Option<Result<DeviceEvent, ReadError>> {
+        let previous_timeout = self.device.transport.timeout();
+        if let Err(e) = self.device.transport.set_timeout(timeout) {
+            return Some(Err(e.into()));
+        }
+        let event = self.next();
+        let _ = self.device.transport.set_timeout(previous_timeout);
+        event
+    }
+}
+
+impl Iterator for Events<'_> {
+    type Item = Result<DeviceEvent, ReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(response) = self.device.unsolicited.pop_front() {
+            return Some(Ok(response.into()));
+        }
+        match self.device.read_response() {
+            Ok(response) => Some(Ok(response.into())),
+            Err(ReadError::PipeError(e)) if e.kind() == std::io::ErrorKind::TimedOut => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// Queue type held by [Device]
+pub(crate) type UnsolicitedQueue = VecDeque<Response>;
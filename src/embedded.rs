@@ -0,0 +1,120 @@
+use crate::codec::{self, CodecError, Frame, FrameDecoder, PayloadReader, PayloadWriter};
+use crate::command::Command;
+
+use embedded_hal_nb::nb::block;
+use embedded_hal_nb::serial::{Read, Write};
+
+/// Error talking to a device over an embedded-hal serial port
+#[derive(Debug, Display)]
+pub enum EmbeddedError<E> {
+    /// Error from the underlying serial port
+    #[display(fmt = "Serial({:?})", _0)]
+    Serial(E),
+
+    /// Frame couldn't be encoded or decoded
+    Codec(CodecError),
+
+    /// Device answered with a different command than expected
+    #[display(fmt = "UnexpectedResponse({})", _0)]
+    UnexpectedResponse(u8),
+}
+
+impl<E> From<CodecError> for EmbeddedError<E> {
+    fn from(value: CodecError) -> Self {
+        Self::Codec(value)
+    }
+}
+
+/// A device attached to an embedded-hal serial port, for `no_std` targets. Frames are assembled
+/// in a fixed buffer of `N` bytes, which must fit the largest frame exchanged (64 is enough for
+/// everything but long FIR filter lists)
+pub struct EmbeddedDevice<S, const N: usize = 64> {
+    serial: S,
+    decoder: FrameDecoder<N>,
+    write_buffer: [u8; N],
+}
+
+impl<S, E, const N: usize> EmbeddedDevice<S, N>
+where
+    S: Read<u8, Error = E> + Write<u8, Error = E>,
+{
+    pub fn new(serial: S) -> Self {
+        Self {
+            serial,
+            decoder: FrameDecoder::new(),
+            write_buffer: [0u8; N],
+        }
+    }
+
+    /// Gives back the serial port
+    pub fn release(self) -> S {
+        self.serial
+    }
+
+    /// Sends the given command and payload to the device, with appropriate CRC and sizing
+    pub fn write_frame(&mut self, command: Command, payload: &[u8]) -> Result<(), EmbeddedError<E>> {
+        let len = codec::encode_frame(u8::from(command), payload, &mut self.write_buffer)?;
+        for byte in &self.write_buffer[..len] {
+            block!(self.serial.write(*byte)).map_err(EmbeddedError::Serial)?;
+        }
+        block!(self.serial.flush()).map_err(EmbeddedError::Serial)
+    }
+
+    /// Blocks until a whole frame has been received and verified
+    pub fn read_frame(&mut self) -> Result<Frame<'_>, EmbeddedError<E>> {
+        loop {
+            let byte = block!(self.serial.read()).map_err(EmbeddedError::Serial)?;
+            if self.decoder.push(byte)?.is_some() {
+                break;
+            }
+        }
+        // returning the frame from inside the loop would hold the decoder borrowed across
+        // iterations, so fetch it again now the loop is done
+        Ok(self.decoder.last_frame().ok_or(CodecError::Incomplete)?)
+    }
+
+    /// Sends `command` and reads the response, checking it has the `expected` command byte
+    fn transact(
+        &mut self,
+        command: Command,
+        payload: &[u8],
+        expected: Command,
+    ) -> Result<Frame<'_>, EmbeddedError<E>> {
+        self.write_frame(command, payload)?;
+        let frame = self.read_frame()?;
+        if frame.command != u8::from(expected) {
+            return Err(EmbeddedError::UnexpectedResponse(frame.command));
+        }
+        Ok(frame)
+    }
+
+    /// Returns device type and revision, as the raw 4 byte ASCII fields
+    pub fn get_mod_info(&mut self) -> Result<([u8; 4], [u8; 4]), EmbeddedError<E>> {
+        let frame = self.transact(Command::GetModInfo, &[], Command::GetModInfoResp)?;
+        let mut payload = PayloadReader::new(frame.payload);
+        Ok((payload.read_bytes()?, payload.read_bytes()?))
+    }
+
+    /// Returns device serial number, which can also be found on the front sticker
+    pub fn serial_number(&mut self) -> Result<u32, EmbeddedError<E>> {
+        let frame = self.transact(Command::SerialNumber, &[], Command::SerialNumberResp)?;
+        Ok(PayloadReader::new(frame.payload).read_u32()?)
+    }
+
+    /// Selects the data components returned by GetData, as raw
+    /// [crate::acquisition::DataID] values
+    pub fn set_data_components(&mut self, components: &[u8]) -> Result<(), EmbeddedError<E>> {
+        let mut buffer = [0u8; N];
+        let mut payload = PayloadWriter::new(&mut buffer);
+        payload.write_u8(components.len() as u8)?;
+        payload.write_bytes(components)?;
+        let len = payload.payload().len();
+        self.write_frame(Command::SetDataComponents, &buffer[..len])
+    }
+
+    /// Requests a single measurement data set, returning the raw GetDataResp payload. Parse it
+    /// with [codec::FixedData::parse_into], or field by field with [PayloadReader]
+    pub fn get_data(&mut self) -> Result<Frame<'_>, EmbeddedError<E>> {
+        self.transact(Command::GetData, &[], Command::GetDataResp)
+    }
+}
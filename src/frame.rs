@@ -0,0 +1,533 @@
+use crate::catalog::{Message, MessageId};
+use crate::codec::{self, FRAME_OVERHEAD, MAX_FRAME_LEN};
+use crate::command::Command;
+use crate::responses::Get;
+use crate::{Device, RWError, ReadError};
+
+use std::ops::{Deref, DerefMut};
+use std::time::{Duration, Instant};
+
+/// Frames of other types [Device::request] will read past before giving up on the response
+const MAX_INTERLEAVED_FRAMES: usize = 8;
+
+/// Counts of frames read since the device was opened or [Device::reset_stats] was called. See
+/// [Device::stats]
+#[derive(Debug, Display, Clone, Copy, Default, PartialEq, Eq)]
+#[display(
+    fmt = "LinkStats {{ frames_ok: {}, checksum_errors: {}, size_errors: {}, resyncs: {}, dropped_frames: {} }}",
+    frames_ok,
+    checksum_errors,
+    size_errors,
+    resyncs,
+    dropped_frames
+)]
+pub struct LinkStats {
+    /// Frames whose length and checksum checked out
+    pub frames_ok: u64,
+
+    /// Frames that failed their checksum
+    pub checksum_errors: u64,
+
+    /// Frames that were a different length from what their header said
+    pub size_errors: u64,
+
+    /// Times [Device::sync_to_frame_boundary] had to find the start of a frame again
+    pub resyncs: u64,
+
+    /// Corrupt frames skipped in continuous mode within [Device::set_checksum_tolerance]
+    pub dropped_frames: u64,
+}
+
+impl LinkStats {
+    /// Fraction of frames that failed their checksum, or 0 before any frames
+    pub fn checksum_error_rate(&self) -> f64 {
+        let total = self.frames_ok + self.checksum_errors + self.size_errors;
+        if total == 0 {
+            0.0
+        } else {
+            self.checksum_errors as f64 / total as f64
+        }
+    }
+}
+
+/// Payload bytes found after the last known field of a frame. See [Device::trailing_bytes]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrailingBytes {
+    /// Command byte of the frame they were in
+    pub command: u8,
+
+    pub bytes: Vec<u8>,
+
+    /// Frames with trailing bytes skipped so far, including this one
+    pub frames: u64,
+}
+
+/// A frame read by [Device::read_frame]. Borrows the device's internal frame buffer, so it must
+/// be dropped (or its contents copied out) before the device is used again
+#[derive(Debug)]
+pub struct RawFrame<'a> {
+    /// Command byte followed by the payload
+    bytes: &'a [u8],
+}
+
+impl<'a> RawFrame<'a> {
+    /// The raw command byte. See [crate::command::Command] for known values
+    pub fn command(&self) -> u8 {
+        self.bytes[0]
+    }
+
+    /// Payload bytes, excluding the length, command and checksum
+    pub fn payload(&self) -> &'a [u8] {
+        &self.bytes[1..]
+    }
+}
+
+/// Holds the device while a frame is read, and throws away the partly-read frame state (byte
+/// count, running checksum and buffered payload) when dropped unless [FrameGuard::complete] is
+/// called first. Early returns and `?` in the middle of a frame then can't leave the next read
+/// counting from the wrong place
+pub(crate) struct FrameGuard<'a> {
+    device: &'a mut Device,
+    complete: bool,
+}
+
+impl FrameGuard<'_> {
+    /// Marks the frame as read to the end, so dropping the guard leaves the state alone
+    pub(crate) fn complete(&mut self) {
+        self.complete = true;
+    }
+}
+
+impl Deref for FrameGuard<'_> {
+    type Target = Device;
+
+    fn deref(&self) -> &Device {
+        self.device
+    }
+}
+
+impl DerefMut for FrameGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Device {
+        self.device
+    }
+}
+
+impl Drop for FrameGuard<'_> {
+    fn drop(&mut self) {
+        if !self.complete {
+            self.device.reset_frame();
+        }
+    }
+}
+
+/// Reads the fields of one incoming frame in order, keeping the length, running checksum and byte
+/// count for it. Created by [Device::begin_frame] once the length and command bytes are read.
+///
+/// Call [FrameReader::finish] after the last field to check the checksum. A reader dropped
+/// before then, e.g. on an unexpected command or an early `?` return, discards the rest of the
+/// frame so the next read starts on a frame boundary
+pub struct FrameReader<'a> {
+    device: FrameGuard<'a>,
+    expected_size: u16,
+    command: u8,
+
+    /// Set once the checksum has been consumed, or the stream can't be trusted to be on a frame
+    /// boundary any more
+    done: bool,
+}
+
+impl FrameReader<'_> {
+    /// The raw command byte
+    pub fn command(&self) -> u8 {
+        self.command
+    }
+
+    /// When the frame's length field arrived, on the device's [crate::clock::Clock], before the
+    /// rest of the frame was read
+    pub(crate) fn started(&self) -> Instant {
+        self.device
+            .frame_started
+            .expect("set when the header was read")
+    }
+
+    /// Checks the frame is a `response`, otherwise discards it and returns a parse error
+    pub fn expect(self, response: Command) -> Result<Self, ReadError> {
+        if self.command == u8::from(response) {
+            Ok(self)
+        } else {
+            Err(ReadError::ParseError(
+                Message::new(MessageId::UnexpectedResponse).with("command", self.command),
+            ))
+        }
+    }
+
+    /// Reads the next field of the payload
+    pub fn get<T>(&mut self) -> Result<T, ReadError>
+    where
+        Device: Get<T>,
+    {
+        let result = Get::<T>::get(&mut *self.device);
+        if let Err(ReadError::PipeError(_)) = result {
+            // part of the field may have been consumed, so there's no telling where the frame
+            // ends; leave it to the caller to resync
+            self.abandon();
+        }
+        result
+    }
+
+    /// Reads the next field of the payload as a string. See [Get::get_string]
+    pub fn get_string<T>(&mut self) -> Result<String, ReadError>
+    where
+        Device: Get<T>,
+    {
+        let result = Get::<T>::get_string(&mut *self.device);
+        if let Err(ReadError::PipeError(_)) = result {
+            self.abandon();
+        }
+        result
+    }
+
+    /// Reads the checksum, which must come right after the last field unless
+    /// [Device::set_lenient_trailing_bytes] is on
+    pub fn finish(mut self) -> Result<(), ReadError> {
+        if self.device.lenient_trailing_bytes {
+            self.skip_trailing_bytes()?;
+        }
+        self.done = true;
+        self.device.end_frame(self.expected_size)?;
+        self.device.complete();
+        Ok(())
+    }
+
+    fn skip_trailing_bytes(&mut self) -> Result<(), ReadError> {
+        let payload = self.remaining_payload()?;
+        if payload.is_empty() {
+            return Ok(());
+        }
+        log::debug!(
+            "Skipped {} unknown trailing byte(s) in frame {:#04x}",
+            payload.len(),
+            self.command
+        );
+        let frames = self.device.trailing_bytes.as_ref().map_or(0, |last| last.frames);
+        self.device.trailing_bytes = Some(TrailingBytes {
+            command: self.command,
+            bytes: payload,
+            frames: frames + 1,
+        });
+        Ok(())
+    }
+
+    /// Reads the rest of the payload, up to the checksum
+    pub(crate) fn remaining_payload(&mut self) -> Result<Vec<u8>, ReadError> {
+        let remaining =
+            (self.expected_size as usize).saturating_sub(self.device.read_bytes as usize + 2);
+        let mut payload = vec![0u8; remaining];
+        if let Err(e) = self.device.read_exact_tracked(&mut payload) {
+            self.abandon();
+            return Err(e);
+        }
+        Ok(payload)
+    }
+
+    fn abandon(&mut self) {
+        self.done = true;
+        self.device.reset_frame();
+    }
+}
+
+impl<T> Get<T> for FrameReader<'_>
+where
+    Device: Get<T>,
+{
+    fn get(&mut self) -> Result<T, ReadError> {
+        FrameReader::get(self)
+    }
+
+    fn get_string(&mut self) -> Result<String, ReadError> {
+        FrameReader::get_string::<T>(self)
+    }
+}
+
+impl Drop for FrameReader<'_> {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+        if self.remaining_payload().is_err() {
+            return;
+        }
+        if self.device.end_frame(self.expected_size).is_ok() {
+            self.device.complete();
+        }
+    }
+}
+
+impl Device {
+    /// Starts reading a frame; see [FrameGuard]
+    pub(crate) fn frame_guard(&mut self) -> FrameGuard<'_> {
+        FrameGuard {
+            device: self,
+            complete: false,
+        }
+    }
+
+    /// Reads the length and command of the next frame, returning a [FrameReader] for the rest
+    pub fn begin_frame(&mut self) -> Result<FrameReader<'_>, ReadError> {
+        let (expected_size, command) = self.read_frame_header()?;
+        Ok(FrameReader {
+            device: self.frame_guard(),
+            expected_size,
+            command,
+            done: false,
+        })
+    }
+
+    /// Rejects a length field that can't belong to a real frame, before anything is allocated
+    /// or read for it
+    fn check_frame_len(&mut self, expected_size: u16) -> Result<(), ReadError> {
+        let message = if (expected_size as usize) < FRAME_OVERHEAD {
+            Message::new(MessageId::FrameTooShort).with("length", expected_size)
+        } else if expected_size as usize > MAX_FRAME_LEN {
+            Message::new(MessageId::FrameTooLong)
+                .with("length", expected_size)
+                .with("max", MAX_FRAME_LEN)
+        } else {
+            return Ok(());
+        };
+        self.reset_frame();
+        self.stats.size_errors += 1;
+        Err(ReadError::ParseError(message))
+    }
+
+    fn read_frame_header(&mut self) -> Result<(u16, u8), ReadError> {
+        if self.unflushed {
+            // a batched request has to be on the wire before its response can arrive
+            self.flush_port()?;
+        }
+        let expected_size = Get::<u16>::get(self)?;
+        self.frame_started = Some(self.clock.now());
+        self.check_frame_len(expected_size)?;
+        let command = match Get::<u8>::get(self) {
+            Ok(command) => command,
+            Err(e) => {
+                self.reset_frame();
+                return Err(e);
+            }
+        };
+
+        // pull in the payload and checksum with one read, to be parsed from memory
+        self.rx_buffer.resize(expected_size as usize - 3, 0);
+        let mut payload = std::mem::take(&mut self.rx_buffer);
+        let read = self.port_read_exact(&mut payload);
+        self.rx_buffer = payload;
+        if let Err(e) = read {
+            self.note_port_error(&e);
+            self.reset_frame();
+            return Err(e.into());
+        }
+        self.frame_len = expected_size;
+        Ok((expected_size, command))
+    }
+
+    /// Writes `command` unless the device is streaming and it isn't
+    /// [Command::allowed_while_streaming], in which case nothing is written and
+    /// [RWError::Streaming] is returned
+    pub(crate) fn send(&mut self, command: Command, payload: Option<&[u8]>) -> Result<(), RWError> {
+        if self.streaming && !command.allowed_while_streaming() {
+            return Err(RWError::Streaming { command });
+        }
+        Ok(self.write_frame(command, payload)?)
+    }
+
+    /// Sends `command` and returns a reader for the response, which must be the frame
+    /// [Command::response] says it's answered with. Other valid frames arriving first, such as
+    /// a late UserCalSampleCount, are parsed and queued for [Device::take_unsolicited] rather
+    /// than failing the command.
+    ///
+    /// Sent with [Device::send], so it's subject to the same continuous mode check
+    pub(crate) fn request(
+        &mut self,
+        command: Command,
+        payload: Option<&[u8]>,
+    ) -> Result<FrameReader<'_>, RWError> {
+        let response = command
+            .response()
+            .expect("request() is only used for commands with a single response");
+        self.send(command, payload)?;
+        for _ in 0..=MAX_INTERLEAVED_FRAMES {
+            let (expected_size, command) = self.read_frame_header()?;
+            if command == u8::from(response) {
+                return Ok(FrameReader {
+                    device: self.frame_guard(),
+                    expected_size,
+                    command,
+                    done: false,
+                });
+            }
+
+            let unsolicited = FrameReader {
+                device: self.frame_guard(),
+                expected_size,
+                command,
+                done: false,
+            }
+            .dispatch()?;
+            log::debug!("Queued unsolicited {:?} while waiting for {:?}", unsolicited, response);
+            self.queue_unsolicited(unsolicited);
+        }
+        Err(RWError::ReadError(ReadError::ParseError(
+            Message::new(MessageId::ResponseNotReceived)
+                .with("response", format!("{:?}", response))
+                .with("frames", MAX_INTERLEAVED_FRAMES + 1),
+        )))
+    }
+
+    /// Runs `f` with the [RWError::Streaming] guard off, for commands whose responses are
+    /// expected among data frames. request() reads past up to 8 of them
+    pub(crate) fn while_streaming<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+        let streaming = std::mem::replace(&mut self.streaming, false);
+        let result = f(self);
+        self.streaming = streaming;
+        result
+    }
+
+    /// Reads the next whole frame from the device without interpreting it, verifying its length
+    /// and checksum. The frame is read into a buffer owned by the device and reused between
+    /// calls, so this doesn't allocate once the buffer has grown to fit the largest frame seen
+    pub fn read_frame(&mut self) -> Result<RawFrame<'_>, ReadError> {
+        {
+            let mut frame = self.frame_guard();
+            let expected_size = Get::<u16>::get(&mut *frame)?;
+            frame.check_frame_len(expected_size)?;
+
+            let mut buffer = std::mem::take(&mut frame.frame_buffer);
+            buffer.resize(expected_size as usize - 4, 0);
+            let read = frame.read_exact_tracked(&mut buffer);
+            frame.frame_buffer = buffer;
+            read?;
+
+            frame.end_frame(expected_size)?;
+            frame.complete();
+        }
+        Ok(RawFrame {
+            bytes: &self.frame_buffer,
+        })
+    }
+
+    /// Discards bytes until the stream is positioned on a frame boundary, by searching for a run
+    /// of bytes that forms a frame with a valid length and checksum and dropping everything up to
+    /// and including it. Useful after attaching to a device that is already streaming, or after a
+    /// checksum error in continuous mode.
+    ///
+    /// Returns the number of bytes discarded
+    pub fn sync_to_frame_boundary(&mut self) -> Result<usize, ReadError> {
+        self.reset_frame();
+        self.stats.resyncs += 1;
+        let mut window = Vec::<u8>::new();
+        let mut discarded = 0;
+
+        loop {
+            let mut byte = [0u8; 1];
+            self.port_read_exact(&mut byte)?;
+            window.push(byte[0]);
+
+            // try every candidate start position still in the window
+            let mut start = 0;
+            while start + 2 <= window.len() {
+                let frame_len = u16::from_be_bytes([window[start], window[start + 1]]) as usize;
+                if !(FRAME_OVERHEAD..=MAX_FRAME_LEN).contains(&frame_len) {
+                    start += 1;
+                    continue;
+                }
+                if window.len() - start < frame_len {
+                    break;
+                }
+
+                if codec::decode_frame(&window[start..]).is_ok() {
+                    return Ok(discarded + start + frame_len);
+                }
+                start += 1;
+            }
+
+            discarded += start;
+            window.drain(..start);
+        }
+    }
+
+    /// Frame counters for judging link quality, e.g. on long RS-232 runs
+    pub fn stats(&self) -> LinkStats {
+        self.stats
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats = LinkStats::default();
+    }
+
+    /// In continuous mode, frames that fail their checksum are an error by default. With a
+    /// tolerance, up to `consecutive` corrupt frames in a row are dropped (and counted in
+    /// [LinkStats::dropped_frames]) and the stream carries on with the next one; only a longer
+    /// run of failures is returned as an error. Request/response commands are unaffected, since
+    /// a dropped response can't be skipped over
+    pub fn set_checksum_tolerance(&mut self, consecutive: u32) {
+        self.checksum_tolerance = consecutive;
+    }
+
+    /// Whether a continuous-mode read error should be dropped rather than returned, recording it
+    /// if so
+    pub(crate) fn tolerate(&mut self, error: &ReadError) -> bool {
+        let tolerated = matches!(error, ReadError::ChecksumMismatch { .. })
+            && self.consecutive_checksum_errors <= self.checksum_tolerance;
+        if tolerated {
+            log::debug!("Dropped a corrupt frame: {}", error);
+            self.stats.dropped_frames += 1;
+        }
+        tolerated
+    }
+
+    /// Reads and discards frames until the device stops sending for `quiet_period`, then
+    /// restores the previous timeout. Corrupt frames trigger a [Device::sync_to_frame_boundary].
+    ///
+    /// Returns the number of frames discarded
+    pub fn drain_frames(&mut self, quiet_period: Duration) -> Result<usize, ReadError> {
+        self.drain_frames_counting(quiet_period, |_| true)
+    }
+
+    /// Like [Device::drain_frames], but only counts frames whose command byte satisfies
+    /// `counted`. Corrupt frames are counted regardless, since their command can't be trusted
+    pub(crate) fn drain_frames_counting(
+        &mut self,
+        quiet_period: Duration,
+        counted: impl Fn(u8) -> bool,
+    ) -> Result<usize, ReadError> {
+        let previous_timeout = self.transport.timeout();
+        self.transport.set_timeout(quiet_period)?;
+
+        let mut drained = 0;
+        let result = loop {
+            match self.read_frame() {
+                Ok(frame) => {
+                    if counted(frame.command()) {
+                        drained += 1
+                    }
+                }
+                Err(ReadError::PipeError(e)) if e.kind() == std::io::ErrorKind::TimedOut => {
+                    self.reset_frame();
+                    break Ok(drained);
+                }
+                Err(ReadError::PipeError(e)) => break Err(ReadError::PipeError(e)),
+                Err(_) => match self.sync_to_frame_boundary() {
+                    Ok(_) => drained += 1,
+                    Err(ReadError::PipeError(e)) if e.kind() == std::io::ErrorKind::TimedOut => {
+                        self.reset_frame();
+                        break Ok(drained);
+                    }
+                    Err(e) => break Err(e),
+                },
+            }
+        };
+
+        self.transport.set_timeout(previous_timeout)?;
+        result
+    }
+}
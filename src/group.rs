@@ -0,0 +1,407 @@
+// Several devices driven together, e.g. an array of compasses along a boom. Commands go out to
+// every device in parallel on scoped threads, so a slow or unplugged unit holds up the others by
+// at most its own timeout rather than the sum of everyone's.
+
+use crate::acquisition::TimestampedData;
+use crate::config::ConfigPair;
+use crate::{Device, RWError, ReadError};
+
+use derive_more::Display;
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Barrier, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+
+/// Devices managed together. Each has a label, used to tag its samples and errors
+#[derive(Default)]
+pub struct DeviceGroup {
+    members: Vec<(Arc<str>, Device)>,
+}
+
+/// Operations that failed on some members of a [DeviceGroup]. The others succeeded
+#[derive(Debug)]
+pub struct GroupError {
+    /// Label and error for each device that failed
+    pub failures: Vec<(Arc<str>, RWError)>,
+}
+
+impl fmt::Display for GroupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} device(s) failed:", self.failures.len())?;
+        for (label, error) in &self.failures {
+            write!(f, " {}: {};", label, error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for GroupError {}
+
+/// A sample, or a read error, from one member of a [GroupStream]
+#[derive(Debug)]
+pub struct TaggedSample {
+    /// Position of the device in the group, in the order it was added
+    pub index: usize,
+    pub label: Arc<str>,
+    pub sample: Result<TimestampedData, ReadError>,
+}
+
+/// What a [GroupStream] does with new samples when its consumer falls behind. Read errors are
+/// never dropped, so a device going away is always reported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropPolicy {
+    /// Reader threads wait for room once this many samples are queued. Nothing is lost here, but
+    /// the devices' own buffers overflow if the consumer stays behind for long. Suits loggers
+    Block(usize),
+
+    /// Once this many samples are queued, the oldest is dropped to make room
+    DropOldest(usize),
+
+    /// Once this many samples are queued, new ones are dropped until there's room
+    DropNewest(usize),
+
+    /// Only the newest sample from each device is kept; a new one replaces any still queued.
+    /// Suits displays, which only ever show the latest
+    Latest,
+}
+
+impl Default for DropPolicy {
+    /// Lossless and unbounded
+    fn default() -> Self {
+        DropPolicy::Block(usize::MAX)
+    }
+}
+
+/// Counters for a [GroupStream]'s queue
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Display)]
+#[display(
+    fmt = "{} delivered, {} dropped, {} replaced by newer, {} queued",
+    delivered,
+    dropped,
+    coalesced,
+    queued
+)]
+pub struct StreamStats {
+    /// Samples taken from the stream
+    pub delivered: u64,
+
+    /// Samples dropped under [DropPolicy::DropOldest] or [DropPolicy::DropNewest]
+    pub dropped: u64,
+
+    /// Samples replaced by a newer one from the same device under [DropPolicy::Latest]
+    pub coalesced: u64,
+
+    /// Samples waiting to be taken
+    pub queued: usize,
+}
+
+struct QueueState<T> {
+    items: VecDeque<(Option<usize>, T)>,
+    producers: usize,
+    closed: bool,
+    stats: StreamStats,
+}
+
+/// Many-producer queue applying a [DropPolicy]. Items pushed with a key may be dropped or, under
+/// [DropPolicy::Latest], replaced by a newer item with the same key; items without one are kept
+pub(crate) struct SampleQueue<T> {
+    policy: DropPolicy,
+    state: Mutex<QueueState<T>>,
+    changed: Condvar,
+}
+
+impl<T> SampleQueue<T> {
+    pub(crate) fn new(policy: DropPolicy, producers: usize) -> Self {
+        Self {
+            policy,
+            state: Mutex::new(QueueState {
+                items: VecDeque::new(),
+                producers,
+                closed: false,
+                stats: StreamStats::default(),
+            }),
+            changed: Condvar::new(),
+        }
+    }
+
+    /// Queues `item`, waiting for room under [DropPolicy::Block]. False once the queue is closed
+    pub(crate) fn push(&self, key: Option<usize>, item: T) -> bool {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if state.closed {
+                return false;
+            }
+            let capacity = match self.policy {
+                DropPolicy::Block(capacity)
+                | DropPolicy::DropOldest(capacity)
+                | DropPolicy::DropNewest(capacity) => capacity,
+                DropPolicy::Latest => usize::MAX,
+            };
+            if key.is_none() || state.items.len() < capacity {
+                break;
+            }
+            match self.policy {
+                DropPolicy::DropOldest(_) => {
+                    // errors stay queued, so drop the oldest item that may be dropped
+                    if let Some(oldest) = state.items.iter().position(|(key, _)| key.is_some()) {
+                        state.items.remove(oldest);
+                        state.stats.dropped += 1;
+                    }
+                    break;
+                }
+                DropPolicy::DropNewest(_) => {
+                    state.stats.dropped += 1;
+                    return true;
+                }
+                _ => state = self.changed.wait(state).unwrap(),
+            }
+        }
+
+        if self.policy == DropPolicy::Latest && key.is_some() {
+            if let Some(queued) = state.items.iter_mut().find(|(queued, _)| *queued == key) {
+                queued.1 = item;
+                state.stats.coalesced += 1;
+                return true;
+            }
+        }
+        state.items.push_back((key, item));
+        self.changed.notify_all();
+        true
+    }
+
+    /// Takes the next item, waiting for one while any producer is left
+    pub(crate) fn pop(&self) -> Option<T> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some((_, item)) = state.items.pop_front() {
+                state.stats.delivered += 1;
+                // a reader may be waiting for room
+                self.changed.notify_all();
+                return Some(item);
+            }
+            if state.producers == 0 || state.closed {
+                return None;
+            }
+            state = self.changed.wait(state).unwrap();
+        }
+    }
+
+    /// Called by each producer as it stops
+    pub(crate) fn finish(&self) {
+        self.state.lock().unwrap().producers -= 1;
+        self.changed.notify_all();
+    }
+
+    /// Drops anything queued and turns away further items
+    pub(crate) fn close(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.closed = true;
+        state.items.clear();
+        self.changed.notify_all();
+    }
+
+    pub(crate) fn stats(&self) -> StreamStats {
+        let state = self.state.lock().unwrap();
+        StreamStats {
+            queued: state.items.len(),
+            ..state.stats
+        }
+    }
+}
+
+impl DeviceGroup {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a device under `label`, e.g. its position on the boom or its serial number
+    pub fn add(&mut self, label: impl Into<Arc<str>>, device: Device) -> &mut Self {
+        self.members.push((label.into(), device));
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    pub fn labels(&self) -> impl Iterator<Item = &str> {
+        self.members.iter().map(|(label, _)| &**label)
+    }
+
+    /// The device added under `label`, for talking to it alone
+    pub fn device_mut(&mut self, label: &str) -> Option<&mut Device> {
+        self.members
+            .iter_mut()
+            .find(|(member, _)| &**member == label)
+            .map(|(_, device)| device)
+    }
+
+    /// Hands the devices back, in the order they were added
+    pub fn into_devices(self) -> Vec<(Arc<str>, Device)> {
+        self.members
+    }
+
+    /// Runs `job` on every device at once, one thread each, and returns the results in the order
+    /// the devices were added
+    pub fn broadcast<R, F>(&mut self, job: F) -> Vec<(Arc<str>, R)>
+    where
+        R: Send,
+        F: Fn(&mut Device) -> R + Sync,
+    {
+        let job = &job;
+        thread::scope(|scope| {
+            let handles = self
+                .members
+                .iter_mut()
+                .map(|(label, device)| (label.clone(), scope.spawn(move || job(device))))
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|(label, handle)| match handle.join() {
+                    Ok(result) => (label, result),
+                    Err(panic) => std::panic::resume_unwind(panic),
+                })
+                .collect()
+        })
+    }
+
+    /// Like [DeviceGroup::broadcast] for fallible jobs, collecting the failures
+    pub fn try_broadcast<F>(&mut self, job: F) -> Result<(), GroupError>
+    where
+        F: Fn(&mut Device) -> Result<(), RWError> + Sync,
+    {
+        let failures = self
+            .broadcast(job)
+            .into_iter()
+            .filter_map(|(label, result)| result.err().map(|e| (label, e)))
+            .collect::<Vec<_>>();
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(GroupError { failures })
+        }
+    }
+
+    /// Applies one setting to every device
+    pub fn set_config(&mut self, config: ConfigPair) -> Result<(), GroupError> {
+        self.try_broadcast(|device| device.set_config(config.clone()))
+    }
+
+    /// Starts continuous mode on every device as close to simultaneously as the host allows:
+    /// every thread waits until all are ready, then sends StartContinuousMode at once, so the
+    /// streams start within a few milliseconds of each other rather than one port's round trip
+    /// apart. Acquisition parameters and data components must already be set
+    pub fn start_continuous_mode(&mut self) -> Result<(), GroupError> {
+        let ready = Barrier::new(self.members.len());
+        self.try_broadcast(|device| {
+            ready.wait();
+            device.start_continuous_mode()
+        })
+    }
+
+    /// Stops continuous mode on every device, without saving
+    pub fn stop_continuous_mode(&mut self) -> Result<(), GroupError> {
+        self.try_broadcast(Device::stop_continuous_mode)
+    }
+
+    /// Reads every device's continuous-mode stream on its own thread and merges them into one,
+    /// in order of arrival. Start continuous mode first. Stop with [GroupStream::stop] to get the
+    /// group back.
+    ///
+    /// An IO error other than a timeout, e.g. an unplugged device, is passed on and ends that
+    /// device's part of the stream; the others carry on.
+    ///
+    /// Nothing is dropped if the consumer falls behind; see [DeviceGroup::stream_with] to choose
+    pub fn stream(self) -> GroupStream {
+        self.stream_with(DropPolicy::default())
+    }
+
+    /// Like [DeviceGroup::stream], with `policy` deciding what happens to samples the consumer
+    /// hasn't kept up with. See [GroupStream::stats] for how many were dropped
+    pub fn stream_with(self, policy: DropPolicy) -> GroupStream {
+        let queue = Arc::new(SampleQueue::new(policy, self.members.len()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let threads = self
+            .members
+            .into_iter()
+            .enumerate()
+            .map(|(index, (label, mut device))| {
+                let queue = queue.clone();
+                let stop = stop.clone();
+                thread::spawn(move || {
+                    let mut samples = device.iter_timestamped(None);
+                    // the iterator returns None on a read timeout, which is when to check for stop
+                    while !stop.load(Ordering::Relaxed) {
+                        let Some(sample) = samples.next() else {
+                            continue;
+                        };
+                        let port_failed = matches!(sample, Err(ReadError::PipeError(_)));
+                        let key = sample.is_ok().then_some(index);
+                        let tagged = TaggedSample {
+                            index,
+                            label: label.clone(),
+                            sample,
+                        };
+                        if !queue.push(key, tagged) || port_failed {
+                            break;
+                        }
+                    }
+                    drop(samples);
+                    queue.finish();
+                    (label, device)
+                })
+            })
+            .collect();
+
+        GroupStream {
+            samples: queue,
+            stop,
+            threads,
+        }
+    }
+}
+
+/// Merged continuous-mode stream from a [DeviceGroup]. See [DeviceGroup::stream]
+pub struct GroupStream {
+    samples: Arc<SampleQueue<TaggedSample>>,
+    stop: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<(Arc<str>, Device)>>,
+}
+
+impl GroupStream {
+    /// Stops reading, which takes up to one port timeout, and returns the group. Devices are
+    /// left in continuous mode. Samples not yet taken from the stream are dropped
+    pub fn stop(self) -> DeviceGroup {
+        self.stop.store(true, Ordering::Relaxed);
+        self.samples.close();
+        let members = self
+            .threads
+            .into_iter()
+            .map(|thread| match thread.join() {
+                Ok(member) => member,
+                Err(panic) => std::panic::resume_unwind(panic),
+            })
+            .collect();
+        DeviceGroup { members }
+    }
+
+    /// How many samples were delivered, dropped or replaced so far
+    pub fn stats(&self) -> StreamStats {
+        self.samples.stats()
+    }
+}
+
+impl Iterator for GroupStream {
+    type Item = TaggedSample;
+
+    /// Blocks until any device produces a sample or error
+    fn next(&mut self) -> Option<Self::Item> {
+        self.samples.pop()
+    }
+}
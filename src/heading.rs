@@ -0,0 +1,52 @@
+// Heading arithmetic on plain degrees. Headings wrap at 360, so `a + b`, `a - b` and comparisons
+// go wrong near north; these do the wrapping in one place, with the same conventions as the
+// device: headings in [0, 360), declination positive east.
+
+use crate::acquisition::{AngleUnits, MILS_PER_CIRCLE};
+use crate::processing::{angle_difference, wrap_heading};
+
+/// Wraps an angle into [0, 360), e.g. -10 becomes 350 and 725 becomes 5
+pub fn wrap(degrees: f32) -> f32 {
+    wrap_heading(degrees)
+}
+
+/// Wraps an angle into [-180, 180), e.g. 350 becomes -10
+pub fn wrap_signed(degrees: f32) -> f32 {
+    angle_difference(0.0, degrees)
+}
+
+/// Turns `heading` clockwise by `degrees` (anticlockwise if negative)
+pub fn add(heading: f32, degrees: f32) -> f32 {
+    wrap(heading + degrees)
+}
+
+/// Turns `heading` anticlockwise by `degrees`
+pub fn subtract(heading: f32, degrees: f32) -> f32 {
+    wrap(heading - degrees)
+}
+
+/// How far to turn from `from` to reach `to` the short way round, in [-180, 180). Positive is
+/// clockwise, so `difference(350.0, 10.0)` is 20, not -340
+pub fn difference(from: f32, to: f32) -> f32 {
+    angle_difference(from, to)
+}
+
+/// True heading from a magnetic one, given the declination in degrees (positive east)
+pub fn magnetic_to_true(magnetic: f32, declination: f32) -> f32 {
+    add(magnetic, declination)
+}
+
+/// Magnetic heading from a true one, given the declination in degrees (positive east)
+pub fn true_to_magnetic(true_heading: f32, declination: f32) -> f32 {
+    subtract(true_heading, declination)
+}
+
+/// Converts degrees to mils, 6400 per circle
+pub fn degrees_to_mils(degrees: f32) -> f32 {
+    degrees * MILS_PER_CIRCLE / 360.0
+}
+
+/// Converts mils to degrees. See [AngleUnits::to_degrees]
+pub fn mils_to_degrees(mils: f32) -> f32 {
+    AngleUnits::Mils.to_degrees(mils)
+}
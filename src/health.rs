@@ -0,0 +1,434 @@
+use crate::acquisition::Data;
+use crate::clock::Clock;
+use crate::connection::ConnectionEvent;
+use crate::{Device, RWError, ReadError};
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Settings for [Device::watchdog]
+#[derive(Debug, Clone)]
+pub struct WatchdogConfig {
+    /// How long the stream may go without a valid data frame before recovery starts. Should be
+    /// several times the SampleDelay, so one or two dropped frames don't trigger it
+    pub stall_timeout: Duration,
+
+    /// Give up after this many reconnect attempts in a row without a frame arriving. [None]
+    /// keeps trying forever, which suits unattended deployments
+    pub max_reconnects: Option<u32>,
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        Self {
+            stall_timeout: Duration::from_secs(5),
+            max_reconnects: None,
+        }
+    }
+}
+
+/// What a [StallDetector] says to do about a stalled stream
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+pub enum Recovery {
+    /// Discard buffered input and re-send StartContinuousMode on the same connection
+    Resync,
+
+    /// Wake the device and restart continuous mode, re-opening the serial port first if it was
+    /// lost (e.g. a USB adapter re-enumerating)
+    Reconnect,
+}
+
+/// Tracks the gap since the last frame of a continuous stream, escalating from
+/// [Recovery::Resync] to [Recovery::Reconnect] each time another `stall_timeout` passes without
+/// one. Measures time against a [Clock], so the escalation can be tested in simulated time
+pub struct StallDetector {
+    clock: Arc<dyn Clock>,
+    stall_timeout: Duration,
+
+    /// Last frame, or last recovery attempt if more recent
+    window_start: Instant,
+
+    /// Recovery attempts since the last frame
+    attempts: u32,
+}
+
+impl StallDetector {
+    pub fn new(clock: Arc<dyn Clock>, stall_timeout: Duration) -> Self {
+        Self {
+            window_start: clock.now(),
+            clock,
+            stall_timeout,
+            attempts: 0,
+        }
+    }
+
+    /// Records that a frame arrived
+    pub fn feed(&mut self) {
+        self.window_start = self.clock.now();
+        self.attempts = 0;
+    }
+
+    /// Time since the last frame or recovery attempt
+    pub fn gap(&self) -> Duration {
+        self.clock.now().saturating_duration_since(self.window_start)
+    }
+
+    /// Recovery attempts since the last frame
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// Returns the recovery to attempt if the stream has stalled, and starts a new window so the
+    /// next one isn't suggested until another `stall_timeout` has passed
+    pub fn check(&mut self) -> Option<Recovery> {
+        if self.gap() < self.stall_timeout {
+            return None;
+        }
+        self.window_start = self.clock.now();
+        self.attempts += 1;
+        Some(if self.attempts == 1 {
+            Recovery::Resync
+        } else {
+            Recovery::Reconnect
+        })
+    }
+}
+
+/// When an [AccuracyMonitor] raises an alarm
+#[derive(Debug, Clone)]
+pub struct AccuracyThresholds {
+    /// Alarm when [Data::mag_accuracy] is worse (higher) than this. [None] ignores it
+    pub max_mag_accuracy: Option<f32>,
+
+    /// Alarm while [Data::distortion] is set
+    pub distortion: bool,
+
+    /// How long a problem must last before the alarm is raised, so a passing car or a brief
+    /// manoeuvre doesn't trigger it. The alarm clears as soon as a good sample arrives
+    pub hold: Duration,
+}
+
+impl Default for AccuracyThresholds {
+    fn default() -> Self {
+        Self {
+            max_mag_accuracy: Some(2.0),
+            distortion: true,
+            hold: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Why heading accuracy is considered degraded
+#[derive(Debug, Display, Clone, Copy, PartialEq)]
+pub enum AccuracyProblem {
+    /// The device's own accuracy estimate, worse than [AccuracyThresholds::max_mag_accuracy]
+    #[display(fmt = "MagAccuracy({})", _0)]
+    MagAccuracy(f32),
+
+    /// The device is reporting magnetic distortion
+    Distortion,
+}
+
+/// Change in heading accuracy reported by an [AccuracyMonitor]
+#[derive(Debug, Display, Clone, Copy, PartialEq)]
+pub enum AccuracyEvent {
+    /// Accuracy has been degraded for at least [AccuracyThresholds::hold]. If it persists after
+    /// the device has been moved away from local disturbances, it needs a new user calibration
+    #[display(fmt = "Degraded {{ problem: {}, duration: {:?} }}", problem, duration)]
+    Degraded {
+        /// The problem seen in the latest sample
+        problem: AccuracyProblem,
+        duration: Duration,
+    },
+
+    /// Accuracy is back within the thresholds after an alarm
+    #[display(fmt = "Recovered {{ after: {:?} }}", after)]
+    Recovered { after: Duration },
+}
+
+type AccuracyCallback = Box<dyn FnMut(&AccuracyEvent) + Send>;
+
+/// Watches the accuracy fields of a data stream and raises an alarm when accuracy stays degraded
+/// for a while. Feed it every sample with [AccuracyMonitor::update]; events are returned and
+/// also passed to the callback set with [AccuracyMonitor::on_event].
+///
+/// The data components must include MagAccuracy or Distortion for it to see anything. Measures
+/// time against a [Clock], so it can be tested in simulated time
+pub struct AccuracyMonitor {
+    clock: Arc<dyn Clock>,
+    thresholds: AccuracyThresholds,
+    callback: Option<AccuracyCallback>,
+
+    /// When the current run of degraded samples began
+    degraded_since: Option<Instant>,
+    alarmed: bool,
+}
+
+impl AccuracyMonitor {
+    pub fn new(clock: Arc<dyn Clock>, thresholds: AccuracyThresholds) -> Self {
+        Self {
+            clock,
+            thresholds,
+            callback: None,
+            degraded_since: None,
+            alarmed: false,
+        }
+    }
+
+    /// Calls `callback` with every event, in addition to returning it from
+    /// [AccuracyMonitor::update]
+    pub fn on_event(mut self, callback: impl FnMut(&AccuracyEvent) + Send + 'static) -> Self {
+        self.callback = Some(Box::new(callback));
+        self
+    }
+
+    /// Checks a sample, returning an event if the alarm was raised or cleared
+    pub fn update(&mut self, data: &Data) -> Option<AccuracyEvent> {
+        let now = self.clock.now();
+        let event = match (self.problem(data), self.degraded_since) {
+            (Some(_), None) => {
+                self.degraded_since = Some(now);
+                None
+            }
+            (Some(problem), Some(since)) => {
+                let duration = now.saturating_duration_since(since);
+                (!self.alarmed && duration >= self.thresholds.hold).then(|| {
+                    self.alarmed = true;
+                    AccuracyEvent::Degraded { problem, duration }
+                })
+            }
+            (None, since) => {
+                self.degraded_since = None;
+                let was_alarmed = std::mem::take(&mut self.alarmed);
+                since.filter(|_| was_alarmed).map(|since| AccuracyEvent::Recovered {
+                    after: now.saturating_duration_since(since),
+                })
+            }
+        };
+
+        if let (Some(event), Some(callback)) = (&event, &mut self.callback) {
+            callback(event);
+        }
+        event
+    }
+
+    /// Whether the alarm is currently raised
+    pub fn is_alarmed(&self) -> bool {
+        self.alarmed
+    }
+
+    fn problem(&self, data: &Data) -> Option<AccuracyProblem> {
+        if self.thresholds.distortion && data.distortion == Some(true) {
+            return Some(AccuracyProblem::Distortion);
+        }
+        match (data.mag_accuracy, self.thresholds.max_mag_accuracy) {
+            (Some(accuracy), Some(max)) if accuracy > max => {
+                Some(AccuracyProblem::MagAccuracy(accuracy))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Device {
+    /// An [AccuracyMonitor] timed by this device's clock
+    pub fn accuracy_monitor(&self, thresholds: AccuracyThresholds) -> AccuracyMonitor {
+        AccuracyMonitor::new(self.clock.clone(), thresholds)
+    }
+
+    /// Cheap health check: asks for the serial number and returns the round-trip time. Uses
+    /// `timeout` for the response instead of the port's usual timeout, which is restored after.
+    /// Doesn't work in continuous mode, since the response is interleaved with data; use
+    /// [Device::watchdog] there instead
+    pub fn ping(&mut self, timeout: Duration) -> Result<Duration, RWError> {
+        let previous_timeout = self.transport.timeout();
+        self.transport.set_timeout(timeout).map_err(ReadError::from)?;
+
+        let start = self.clock.now();
+        let result = self.serial_number();
+        let round_trip = self.clock.now().saturating_duration_since(start);
+
+        if result.is_err() {
+            // a late response would otherwise be read as the answer to the next command
+            self.reset_frame();
+            let _ = self.transport.clear_input();
+        }
+        self.transport.set_timeout(previous_timeout).map_err(ReadError::from)?;
+        result.map(|_| round_trip)
+    }
+
+    /// Reads a continuous-mode stream like [Device::iter], but recovers on its own when the stream
+    /// stalls: first by resyncing on the same connection, then by repeatedly calling
+    /// [Device::reconnect], which re-opens the port and restarts continuous mode following the
+    /// reconnect policy. Recovery is reported through [Device::connection_events] and the log.
+    ///
+    /// Frames that fail to parse are returned as errors and the stream is resynced to the next
+    /// frame boundary. Iteration only ends if [WatchdogConfig::max_reconnects] runs out.
+    ///
+    /// Restarting uses the live (unsaved) continuous mode, so the acquisition parameters and data
+    /// components must still be set on the device, either saved or from
+    /// [Device::start_continuous_mode_live] earlier in this session
+    pub fn watchdog(&mut self, config: WatchdogConfig) -> Watchdog<'_> {
+        Watchdog {
+            detector: StallDetector::new(self.clock.clone(), config.stall_timeout),
+            device: self,
+            max_reconnects: config.max_reconnects,
+            port_lost: false,
+            stats: WatchdogStats::default(),
+            on_recovery: None,
+        }
+    }
+
+    fn resync_stream(&mut self) -> Result<(), RWError> {
+        self.reset_frame();
+        let _ = self.transport.clear_input();
+        self.start_continuous_mode()
+    }
+}
+
+/// Running totals for a [Watchdog], for judging a long unattended run
+#[derive(Debug, Display, Default, Clone, Copy, PartialEq, Eq)]
+#[display(
+    fmt = "{} samples, {} errors, {} resyncs, {} reconnects ({} failed)",
+    samples,
+    errors,
+    resyncs,
+    reconnects,
+    failed_recoveries
+)]
+pub struct WatchdogStats {
+    /// Samples returned
+    pub samples: u64,
+
+    /// Errors returned, after each of which the stream was resynced
+    pub errors: u64,
+
+    /// [Recovery::Resync] attempts
+    pub resyncs: u64,
+
+    /// [Recovery::Reconnect] attempts
+    pub reconnects: u64,
+
+    /// Recovery attempts that failed outright, rather than just not bringing data back
+    pub failed_recoveries: u64,
+}
+
+/// See [Watchdog::on_recovery]
+type RecoveryHook<'a> = Box<dyn FnMut(Recovery, &WatchdogStats) + 'a>;
+
+/// Self-healing continuous-mode iterator. See [Device::watchdog]
+pub struct Watchdog<'a> {
+    device: &'a mut Device,
+    detector: StallDetector,
+    max_reconnects: Option<u32>,
+
+    /// Whether the port reported an error other than a timeout since the last frame, which
+    /// [Device::reconnect] re-opens it for
+    port_lost: bool,
+
+    stats: WatchdogStats,
+    on_recovery: Option<RecoveryHook<'a>>,
+}
+
+impl<'a> Watchdog<'a> {
+    /// Time since the last frame or recovery attempt
+    pub fn gap(&self) -> Duration {
+        self.detector.gap()
+    }
+
+    /// Totals since the watchdog was created
+    pub fn stats(&self) -> WatchdogStats {
+        self.stats
+    }
+
+    /// Calls `hook` before each recovery attempt, with the totals so far, e.g. to raise an alarm
+    /// or power cycle the sensor's supply when reconnects keep failing
+    pub fn on_recovery(mut self, hook: impl FnMut(Recovery, &WatchdogStats) + 'a) -> Self {
+        self.on_recovery = Some(Box::new(hook));
+        self
+    }
+}
+
+impl Iterator for Watchdog<'_> {
+    type Item = Result<Data, ReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.device.next_data_frame() {
+                Some(Ok((data, _))) => {
+                    self.detector.feed();
+                    self.port_lost = false;
+                    self.stats.samples += 1;
+                    return Some(Ok(data));
+                }
+                Some(Err(ReadError::PipeError(e))) => {
+                    // the port is gone, so there's nothing to read until it's re-opened; don't
+                    // spin on the error in the meantime
+                    if !self.port_lost {
+                        log::warn!("Serial port error in continuous mode: {}", e);
+                    }
+                    self.port_lost = true;
+                    self.device.clock.sleep(Duration::from_millis(100));
+                }
+                Some(Err(e)) => {
+                    let _ = self.device.sync_to_frame_boundary();
+                    self.stats.errors += 1;
+                    return Some(Err(e));
+                }
+                None => {}
+            }
+
+            let Some(recovery) = self.detector.check() else {
+                continue;
+            };
+            let span = crate::instrument::AttemptSpan::enter(
+                match recovery {
+                    Recovery::Resync => "stream_resync",
+                    Recovery::Reconnect => "stream_reconnect",
+                },
+                self.detector.attempts(),
+            );
+            span.cause(&format_args!(
+                "no data for {:?}{}",
+                self.detector.stall_timeout,
+                if self.port_lost { ", port lost" } else { "" }
+            ));
+            span.backoff(self.detector.stall_timeout);
+            match recovery {
+                Recovery::Resync => self.stats.resyncs += 1,
+                Recovery::Reconnect => self.stats.reconnects += 1,
+            }
+            if let Some(hook) = self.on_recovery.as_mut() {
+                hook(recovery, &self.stats);
+            }
+            match recovery {
+                Recovery::Resync => {
+                    log::warn!("No data for {:?}, resyncing stream", self.detector.stall_timeout);
+                    self.device.set_connection_state(ConnectionEvent::Degraded {
+                        reason: "stream stalled".to_string(),
+                    });
+                    if let Err(e) = self.device.resync_stream() {
+                        log::warn!("Resync failed: {}", e);
+                        self.stats.failed_recoveries += 1;
+                    }
+                }
+                Recovery::Reconnect => {
+                    let reconnects = self.detector.attempts() - 1;
+                    if self.max_reconnects.is_some_and(|max| reconnects > max) {
+                        log::error!("Stream still stalled after {} reconnects, giving up", reconnects - 1);
+                        return None;
+                    }
+                    log::warn!("Stream still stalled, reconnecting (attempt {})", reconnects);
+                    self.device.set_connection_state(ConnectionEvent::Reconnecting);
+                    match self.device.reconnect() {
+                        Ok(()) => self.port_lost = false,
+                        Err(e) => {
+                            log::warn!("Reconnect failed: {}", e);
+                            self.stats.failed_recoveries += 1;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
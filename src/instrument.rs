@@ -0,0 +1,41 @@
+use std::fmt::Display;
+use std::time::Duration;
+
+/// One attempt of a retried operation. With the `tracing` feature this is a span, entered until
+/// it's dropped, recording the attempt number, why the attempt failed and how long the backoff
+/// before the next one is. Without it, it does nothing
+pub(crate) struct AttemptSpan {
+    #[cfg(feature = "tracing")]
+    span: tracing::span::EnteredSpan,
+}
+
+impl AttemptSpan {
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    pub(crate) fn enter(operation: &'static str, attempt: u32) -> Self {
+        Self {
+            #[cfg(feature = "tracing")]
+            span: tracing::info_span!(
+                "attempt",
+                operation,
+                attempt,
+                cause = tracing::field::Empty,
+                backoff_ms = tracing::field::Empty,
+            )
+            .entered(),
+        }
+    }
+
+    /// Records why the attempt failed
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    pub(crate) fn cause(&self, cause: &dyn Display) {
+        #[cfg(feature = "tracing")]
+        self.span.record("cause", tracing::field::display(cause));
+    }
+
+    /// Records the wait before the next attempt
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    pub(crate) fn backoff(&self, delay: Duration) {
+        #[cfg(feature = "tracing")]
+        self.span.record("backoff_ms", delay.as_millis() as u64);
+    }
+}
@@ -0,0 +1,131 @@
+// Frames as untyped JSON, for tooling that shows or logs whatever the device sends (dashboards,
+// protocol loggers) without matching on every Response variant. Field names follow the Rust
+// field names, so the JSON and the typed API describe a frame the same way.
+
+use crate::acquisition::Data;
+use crate::calibration::UserCalResponse;
+use crate::command::Command;
+use crate::decode::hex;
+use crate::dispatch::Response;
+use crate::{Device, ReadError};
+
+use serde_json::{json, Map, Value};
+
+/// A response as `{"command": "GetDataResp", "id": 5, "fields": {...}}`. Frames with an
+/// unknown command byte have `"command": null`; frames without a parser carry their payload as
+/// a `"payload"` hex string. Floats that aren't finite become `null`
+pub fn to_value(response: &Response) -> Value {
+    let id = response.command();
+    let command = Command::try_from(id).ok().map(|command| format!("{:?}", command));
+    json!({
+        "command": command,
+        "id": id,
+        "fields": fields(response),
+    })
+}
+
+fn fields(response: &Response) -> Map<String, Value> {
+    let mut fields = Map::new();
+    let mut insert = |name: &str, value: Value| {
+        fields.insert(name.to_owned(), value);
+    };
+    match response {
+        Response::ModInfo(info) => {
+            insert("device_type", json!(info.device_type));
+            insert("revision", json!(info.revision));
+            insert("model", json!(info.model.to_string()));
+            insert("firmware", json!(info.firmware.map(|version| version.to_string())));
+        }
+        Response::SerialNumber(serial_number) => insert("serial_number", json!(serial_number)),
+        Response::Data(data) => return data_fields(data),
+        Response::AcqParams(params) => {
+            insert("acquisition_mode", json!(params.acquisition_mode));
+            insert("flush_filter", json!(params.flush_filter));
+            insert("sample_delay", json!(params.sample_delay));
+        }
+        Response::FirFilters(taps) => insert("taps", json!(taps)),
+        Response::UserCalSampleCount(count)
+        | Response::UserCalScore(UserCalResponse::SampleCount(count)) => {
+            insert("sample_count", json!(count))
+        }
+        Response::UserCalScore(UserCalResponse::UserCalScore {
+            mag_cal_score,
+            accel_cal_score,
+            distribution_error,
+            tilt_error,
+            tilt_range,
+        }) => {
+            insert("mag_cal_score", json!(mag_cal_score));
+            insert("accel_cal_score", json!(accel_cal_score));
+            insert("distribution_error", json!(distribution_error));
+            insert("tilt_error", json!(tilt_error));
+            insert("tilt_range", json!(tilt_range));
+        }
+        Response::SaveDone { error_code } => insert("error_code", json!(error_code)),
+        Response::Done(_) => {}
+        Response::Unparsed { payload, .. } | Response::Unknown { payload, .. } => {
+            insert("payload", json!(hex(payload)))
+        }
+    }
+    fields
+}
+
+fn data_fields(data: &Data) -> Map<String, Value> {
+    let scalars = [
+        ("heading", data.heading),
+        ("pitch", data.pitch),
+        ("roll", data.roll),
+        ("temperature", data.temperature),
+        ("accel_x", data.accel_x),
+        ("accel_y", data.accel_y),
+        ("accel_z", data.accel_z),
+        ("mag_x", data.mag_x),
+        ("mag_y", data.mag_y),
+        ("mag_z", data.mag_z),
+        ("mag_accuracy", data.mag_accuracy),
+        ("gyro_x", data.gyro_x),
+        ("gyro_y", data.gyro_y),
+        ("gyro_z", data.gyro_z),
+    ];
+    let flags = [("distortion", data.distortion), ("cal_status", data.cal_status)];
+
+    let mut fields = Map::new();
+    for (name, value) in scalars {
+        if let Some(value) = value {
+            fields.insert(name.to_owned(), json!(value));
+        }
+    }
+    for (name, value) in flags {
+        if let Some(value) = value {
+            fields.insert(name.to_owned(), json!(value));
+        }
+    }
+    if let Some(quaternion) = data.quaternion {
+        fields.insert("quaternion".to_owned(), json!(quaternion));
+    }
+    if let Some(status) = data.heading_status {
+        fields.insert("heading_status".to_owned(), json!(status.to_string()));
+    }
+    fields
+}
+
+impl Device {
+    /// Reads the next frame, whatever it is, as JSON. See [to_value]
+    pub fn read_value(&mut self) -> Result<Value, ReadError> {
+        Ok(to_value(&self.read_response()?))
+    }
+
+    /// Every frame the device sends, as JSON, starting with any that arrived while a command was
+    /// waiting for another. Iteration ends when the port times out
+    pub fn values(&mut self) -> impl Iterator<Item = Result<Value, ReadError>> + '_ {
+        std::iter::from_fn(move || {
+            if let Some(response) = self.unsolicited.pop_front() {
+                return Some(Ok(to_value(&response)));
+            }
+            match self.read_value() {
+                Err(ReadError::PipeError(e)) if e.kind() == std::io::ErrorKind::TimedOut => None,
+                result => Some(result),
+            }
+        })
+    }
+}
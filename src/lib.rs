@@ -1,35 +1,256 @@
+//! Bindings to PNI sensors that speak the PNI Serial Binary Protocol.
+//!
+//! # API stability
+//! The protocol core follows semver: [Device] and the [responses], [command], [config],
+//! [acquisition], [calibration], [capabilities], [processing] and `embedded` modules only break
+//! in a new major version. Releases are checked against the previous one with
+//! `cargo semver-checks` on default features.
+//!
+//! Newer subsystems sit behind the `unstable` feature (which `wmm`, `spectral`, `mint`, `ros2`,
+//! `parquet`, `json`, `prometheus`, `tokio`, `monitor` and `online-declination` also enable) and
+//! may change in any release while their design settles: `archive`, `ascii`, `async_device`,
+//! `bridge`, `catalog`, `clock`, `codec`, `connection`, `constants`, `declination`, `decode`,
+//! `diagnostics`, `dispatch`, `frame`, `group`, `health`, `heading`, `json`, `logger`, `merge`,
+//! `mock`, `mounting`, `power`, `prometheus`, `replay`, `ros2`, `rotation`, `simulator`,
+//! `spectral`, `split`, `transport`, `wmm` and `worker`. Without the feature, [Device] methods
+//! that take or return their types still work, but the types can't be named. Pin an exact
+//! version if you use them
+#![cfg_attr(not(feature = "std"), no_std)]
+
+/// `requests!`, which generates the simple request/response command methods
+#[cfg(feature = "std")]
+#[macro_use]
+mod macros;
+
 /// Centered around the [Get] trait
+#[cfg(feature = "std")]
 pub mod responses;
 
 /// The second byte of a frame
 pub mod command;
 
 /// Configuration + config options
+#[cfg(feature = "std")]
 pub mod config;
 
 /// Acquisition of data
+#[cfg(feature = "std")]
 pub mod acquisition;
 
 /// User + factory device calibration
+#[cfg(feature = "std")]
 pub mod calibration;
 
+/// Archiving device settings to clone onto replacement units
+#[cfg(feature = "unstable")]
+pub mod archive;
+
+/// Runtime feature support queries
+#[cfg(feature = "std")]
+pub mod capabilities;
+
+/// Parsing TCM-style ASCII sentence output
+#[cfg(feature = "unstable")]
+pub mod ascii;
+#[cfg(all(feature = "std", not(feature = "unstable")))]
+#[allow(dead_code)]
+mod ascii;
+
+/// Decoding raw byte captures into readable frames, for support and debugging
+#[cfg(feature = "unstable")]
+pub mod decode;
+
+/// Raw, uninterpreted frame access
+#[cfg(feature = "unstable")]
+pub mod frame;
+#[cfg(all(feature = "std", not(feature = "unstable")))]
+#[allow(dead_code)]
+mod frame;
+
+/// Parsing responses by command byte, and events for frames the device sends unprompted
+#[cfg(feature = "unstable")]
+pub mod dispatch;
+#[cfg(all(feature = "std", not(feature = "unstable")))]
+#[allow(dead_code)]
+mod dispatch;
+
+/// Swappable time source, for testing long-running timing behaviour
+#[cfg(feature = "unstable")]
+pub mod clock;
+#[cfg(all(feature = "std", not(feature = "unstable")))]
+#[allow(dead_code)]
+mod clock;
+
+/// Connection lifecycle events
+#[cfg(feature = "unstable")]
+pub mod connection;
+#[cfg(all(feature = "std", not(feature = "unstable")))]
+#[allow(dead_code)]
+mod connection;
+
+/// The byte link [Device] talks over: a serial port, a TCP serial server or a test double
+#[cfg(feature = "unstable")]
+pub mod transport;
+#[cfg(all(feature = "std", not(feature = "unstable")))]
+#[allow(dead_code)]
+mod transport;
+
+/// A [transport::Transport] that answers like a device, for testing without hardware
+#[cfg(feature = "unstable")]
+pub mod mock;
+
+/// Powering the device down and waking it on the same connection
+#[cfg(feature = "unstable")]
+pub mod power;
+#[cfg(all(feature = "std", not(feature = "unstable")))]
+#[allow(dead_code)]
+mod power;
+
+/// Error messages as codes plus parameters, for matching and translation
+#[cfg(feature = "unstable")]
+pub mod catalog;
+#[cfg(all(feature = "std", not(feature = "unstable")))]
+#[allow(dead_code)]
+mod catalog;
+
+/// Spans around retried operations, with the `tracing` feature
+#[cfg(feature = "std")]
+mod instrument;
+
+/// Health checks, a watchdog that recovers stalled streams, and heading accuracy alarms
+#[cfg(feature = "unstable")]
+pub mod health;
+
+/// Crash-safe logging of acquired data, and CSV or Parquet captures with file rotation
+#[cfg(feature = "unstable")]
+pub mod logger;
+
+/// Host-side smoothing of heading, pitch and roll, and correction of mag readings
+#[cfg(feature = "std")]
+pub mod processing;
+
+/// Quaternion and rotation matrix forms of orientations and mounting references
+#[cfg(feature = "unstable")]
+pub mod rotation;
+
+/// Working out the MountingRef for an installed unit from accelerometer readings
+#[cfg(feature = "unstable")]
+pub mod mounting;
+
+/// Driving several devices together, with a merged sample stream
+#[cfg(feature = "unstable")]
+pub mod group;
+
+/// Separate read and write halves of a [Device], for use from different threads
+#[cfg(feature = "unstable")]
+pub mod split;
+
+/// Paced playback of recorded logs, with pause, stepping, seeking and speed control
+#[cfg(feature = "unstable")]
+pub mod replay;
+
+/// Background thread owning a [Device], with a prioritized command queue
+#[cfg(feature = "unstable")]
+pub mod worker;
+
+/// Time alignment with other sensors' streams
+#[cfg(feature = "unstable")]
+pub mod merge;
+
+/// Round-trip latency, streaming rate and jitter measurements
+#[cfg(feature = "unstable")]
+pub mod diagnostics;
+
+/// Declination from a location, by hand, model or online lookup
+#[cfg(feature = "unstable")]
+pub mod declination;
+
+/// Serving a [Device] to network clients over TCP or UDP
+#[cfg(feature = "unstable")]
+pub mod bridge;
+
+/// Wrap-safe heading arithmetic: adding, differencing, declination and mils
+#[cfg(feature = "unstable")]
+pub mod heading;
+
+/// Samples in the layout of ROS 2 `sensor_msgs/Imu` and `sensor_msgs/MagneticField`
+#[cfg(feature = "ros2")]
+pub mod ros2;
+
+/// World Magnetic Model and IGRF, for computing declination from a location
+#[cfg(feature = "wmm")]
+pub mod wmm;
+
+/// Noise spectra of logged data, for choosing FIR filter taps
+#[cfg(feature = "spectral")]
+pub mod spectral;
+
+/// Any frame as a `serde_json::Value`, for generic tooling
+#[cfg(feature = "json")]
+pub mod json;
+
+/// A TargetPoint3 in software, answering over any [transport::Transport]. Behind `tp3-sim`
+#[cfg(feature = "unstable")]
+pub mod simulator;
+
+/// [Device]'s everyday commands as async fns on tokio, over `tokio-serial` or any async stream
+#[cfg(feature = "tokio")]
+pub mod async_device;
+
+/// Sample rate, link errors and magnetic health as Prometheus metrics, for Grafana
+#[cfg(feature = "prometheus")]
+pub mod prometheus;
+
+/// Protocol sizes and limits: frame overhead, payload and FIR tap limits, data component sizes
+#[cfg(feature = "unstable")]
+pub mod constants;
+#[cfg(not(feature = "unstable"))]
+#[allow(dead_code)]
+mod constants;
+
+/// `no_std` frame encoding/decoding, shared by [Device] and embedded targets
+#[cfg(feature = "unstable")]
+pub mod codec;
+#[cfg(not(feature = "unstable"))]
+#[allow(dead_code)]
+mod codec;
+
+/// [codec] driven over embedded-hal serial traits, for microcontrollers
+#[cfg(feature = "embedded-hal")]
+pub mod embedded;
+
+#[cfg(feature = "std")]
 use serialport::SerialPort;
-use std::{error::Error, hash::Hasher, string::FromUtf8Error, time::Duration};
+#[cfg(feature = "std")]
+use transport::Transport;
+#[cfg(feature = "std")]
+use std::{
+    error::Error,
+    hash::Hasher,
+    string::FromUtf8Error,
+    time::Duration,
+};
 #[macro_use]
 extern crate derive_more;
 
+#[cfg(feature = "std")]
+use codec::CodecError;
+#[cfg(feature = "std")]
 use command::Command;
+#[cfg(feature = "std")]
+use connection::ConnectionEvent;
+#[cfg(feature = "std")]
 use responses::{Get, ModInfoResp};
 
-
 /// Error that ocurred while reading data back from the device
+#[cfg(feature = "std")]
 #[derive(Debug, Display)]
 pub enum ReadError {
     /// IO Error when communicating with device on serial port.
     PipeError(std::io::Error),
 
     /// Error parsing response/data from device
-    ParseError(String),
+    ParseError(catalog::Message),
 
     /// Checksum for frame didn't match
     #[display(
@@ -40,41 +261,100 @@ pub enum ReadError {
     ChecksumMismatch { expected: u16, actual: u16 }, // in case of misaligned read, return the
     // actual checksum for easy debugging
     /// Frame length was different from expected length, check device compatibility or library
-    /// version. Size mismatches result in a PipeError if the frame was shorter than expected
-    /// and a read timed out
+    /// version. Also returned when the payload's fields need more bytes than the frame's length
+    /// field allows
     #[display(fmt = "SizeMismatch {{ expected: {}, actual: {} }}", expected, actual)]
     SizeMismatch { expected: u16, actual: u16 },
 }
 
+#[cfg(feature = "std")]
 impl Error for ReadError {}
 
+#[cfg(feature = "std")]
 impl From<std::io::Error> for ReadError {
     fn from(value: std::io::Error) -> Self {
         Self::PipeError(value)
     }
 }
 
+#[cfg(feature = "std")]
+impl From<command::UnknownCommand> for ReadError {
+    fn from(e: command::UnknownCommand) -> Self {
+        Self::ParseError(catalog::Message::new(catalog::MessageId::UnknownCommand).with("value", e.0))
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<CodecError> for ReadError {
+    fn from(e: CodecError) -> Self {
+        use catalog::{Message, MessageId};
+        match e {
+            CodecError::Incomplete | CodecError::UnexpectedEnd => {
+                Self::PipeError(std::io::ErrorKind::UnexpectedEof.into())
+            }
+            CodecError::TooShort { len } => {
+                Self::ParseError(Message::new(MessageId::FrameTooShort).with("length", len))
+            }
+            CodecError::TooLong { len } => Self::ParseError(
+                Message::new(MessageId::FrameTooLong)
+                    .with("length", len)
+                    .with("max", codec::MAX_FRAME_LEN),
+            ),
+            CodecError::ChecksumMismatch { expected, actual } => {
+                Self::ChecksumMismatch { expected, actual }
+            }
+            CodecError::InvalidBool(value) => {
+                Self::ParseError(Message::new(MessageId::InvalidBool).with("value", value))
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
 impl From<FromUtf8Error> for ReadError {
     fn from(e: FromUtf8Error) -> Self {
-        Self::ParseError(format!("UTF8 String couldn't be parsed: {}", e))
+        Self::ParseError(catalog::Message::new(catalog::MessageId::InvalidUtf8).with("error", e))
     }
 }
 
 /// Error that ocurred while writing data to the device
+#[cfg(feature = "std")]
 #[derive(Debug, Display)]
 pub enum WriteError {
     /// IO Error when writing to device
     PipeError(std::io::Error),
+
+    /// Frame couldn't be encoded, e.g. the payload was too long
+    EncodeError(CodecError),
+
+    /// The port failed after taking only `written` of the frame's `len` bytes. The device has
+    /// a truncated frame, which it discards after its own timeout
+    #[display(fmt = "PartialWrite {{ written: {}, len: {}, error: {} }}", written, len, error)]
+    PartialWrite {
+        written: usize,
+        len: usize,
+        error: std::io::Error,
+    },
 }
 
+#[cfg(feature = "std")]
 impl Error for WriteError {}
 
+#[cfg(feature = "std")]
 impl From<std::io::Error> for WriteError {
     fn from(value: std::io::Error) -> Self {
         Self::PipeError(value)
     }
 }
 
+#[cfg(feature = "std")]
+impl From<CodecError> for WriteError {
+    fn from(value: CodecError) -> Self {
+        Self::EncodeError(value)
+    }
+}
+
+#[cfg(feature = "std")]
 #[derive(Debug, Display)]
 pub enum RWError {
     /// Error occurred when reading/parsing data from serial
@@ -84,23 +364,88 @@ pub enum RWError {
     WriteError(WriteError),
 
     /// Device indicated error status
-    DeviceError(String),
+    DeviceError(catalog::Message),
+
+    /// Device never answered any wake-up attempt in [Device::power_up_with]. If it answered but
+    /// the response couldn't be parsed, the [RWError::ReadError] is returned instead
+    #[display(fmt = "NeverWoke {{ attempts: {}, elapsed: {:?} }}", attempts, elapsed)]
+    NeverWoke { attempts: u32, elapsed: Duration },
+
+    /// An argument was rejected before anything was sent to the device. See
+    /// [Device::set_strict]
+    InvalidArgument(catalog::Message),
+
+    /// Output was still settling when [Device::wait_until_stable] gave up
+    #[display(fmt = "NeverStabilized {{ elapsed: {:?} }}", elapsed)]
+    NeverStabilized { elapsed: Duration },
+
+    /// The connected device is known not to support this, according to its identity and
+    /// firmware version (see [Device::capabilities]). Nothing was sent
+    #[display(fmt = "UnsupportedByDevice {{ feature: {}, device: {} }}", feature, device)]
+    UnsupportedByDevice {
+        feature: capabilities::Feature,
+
+        /// Device type and revision as reported by GetModInfo
+        device: String,
+    },
+
+    /// The command needs a response, which can't be read reliably while the device streams in
+    /// continuous mode. Call [Device::stop_continuous_mode_live] first. Nothing was sent
+    #[display(fmt = "Streaming {{ command: {:?} }}", command)]
+    Streaming { command: command::Command },
+
+    /// Settings read back after [Device::save_verified] didn't match what was set
+    #[display(fmt = "VerificationFailed {{ mismatches: {:?} }}", mismatches)]
+    VerificationFailed { mismatches: Vec<config::ConfigMismatch> },
 }
 
+#[cfg(feature = "std")]
 impl Error for RWError {}
 
+#[cfg(feature = "std")]
 impl From<WriteError> for RWError {
     fn from(value: WriteError) -> Self {
         Self::WriteError(value)
     }
 }
 
+#[cfg(feature = "std")]
 impl From<ReadError> for RWError {
     fn from(value: ReadError) -> Self {
         Self::ReadError(value)
     }
 }
 
+/// Controls how persistently [Device::power_up_with] tries to wake a device. Cold units
+/// frequently ignore the first wake-up attempt
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub struct PowerUpPolicy {
+    /// Maximum number of wake-up frames to send
+    pub attempts: u32,
+
+    /// Delay after the first failed attempt. Doubles after every subsequent failure
+    pub initial_backoff: Duration,
+
+    /// Upper bound on the delay between attempts
+    pub max_backoff: Duration,
+
+    /// Give up once this much time has passed since the first attempt, even if attempts remain
+    pub max_wake_duration: Duration,
+}
+
+#[cfg(feature = "std")]
+impl Default for PowerUpPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 5,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(2),
+            max_wake_duration: Duration::from_secs(10),
+        }
+    }
+}
+
 /// Represents a connected device
 ///
 /// # Examples
@@ -113,32 +458,198 @@ impl From<ReadError> for RWError {
 /// println!("Accel X: {}", tp3.get_data().unwrap().accel_x.unwrap());
 /// # }
 /// ```
+#[cfg(feature = "std")]
 pub struct Device {
-    serialport: Box<dyn SerialPort>,
+    transport: Box<dyn Transport>,
 
     /// Checksum of the current frame so far
     read_checksum: crc16::State<crc16::XMODEM>,
 
     /// # of bytes read since the frame started
     read_bytes: u16,
+
+    /// Identity reported by the last successful GetModInfo, if any
+    mod_info: Option<ModInfoResp>,
+
+    /// Reusable buffer backing [frame::RawFrame]
+    frame_buffer: Vec<u8>,
+
+    /// Rest of the current frame after the header, read in one go so fields are parsed from
+    /// memory instead of a read call each. Empty between frames
+    rx_buffer: Vec<u8>,
+
+    /// Bytes of `rx_buffer` already handed out
+    rx_pos: usize,
+
+    /// Length field of the frame being parsed, which reads may not go past. 0 between frames
+    frame_len: u16,
+
+    /// When the length field of the last frame arrived, on `clock`, before its payload was read
+    frame_started: Option<std::time::Instant>,
+
+    /// Whether we've put the device in continuous mode
+    streaming: bool,
+
+    /// Last state sent to [Device::connection_events] subscribers
+    connection_state: ConnectionEvent,
+
+    connection_subscribers: Vec<std::sync::mpsc::SyncSender<ConnectionEvent>>,
+
+    /// Time source for retries and timeouts
+    clock: std::sync::Arc<dyn clock::Clock>,
+
+    /// Responses that arrived while waiting for another. See [Device::take_unsolicited]
+    unsolicited: dispatch::UnsolicitedQueue,
+
+    /// Whether arguments are range-checked before sending. See [Device::set_strict]
+    strict: bool,
+
+    /// Whether any nonzero boolean byte reads as true. See [Device::set_lenient_bools]
+    lenient_bools: bool,
+
+    /// Whether unknown fields at the end of a frame are skipped. See
+    /// [Device::set_lenient_trailing_bytes]
+    lenient_trailing_bytes: bool,
+
+    /// See [Device::trailing_bytes]
+    trailing_bytes: Option<frame::TrailingBytes>,
+
+    /// See [Device::set_write_strategy]
+    write_strategy: connection::WriteStrategy,
+
+    /// Whether frames have been written since the last flush, with [connection::WriteStrategy::Batched]
+    unflushed: bool,
+
+    /// Settings affecting angle output, as last seen. See [Device::angle_format]
+    angle_config: config::AngleConfig,
+
+    /// See [Device::set_reconnect_policy]
+    reconnect_policy: Option<connection::ReconnectPolicy>,
+
+    /// Set when a read or write finds the port gone, until it's re-opened
+    port_lost: bool,
+
+    /// Device family assumed until it's identified. See [connection::DeviceBuilder::profile]
+    profile: connection::Profile,
+
+    /// Format of streamed samples. See [connection::DeviceBuilder::protocol]
+    protocol: connection::Protocol,
+
+    /// Components last sent with SetDataComponents. See [Device::get_data_components]
+    data_components: Option<Vec<acquisition::DataID>>,
+
+    /// Acquisition parameters last set, re-sent by [Device::power_up]
+    acq_params: Option<acquisition::AcqParamsReserved>,
+
+    /// FIR filter taps last set for each filter, re-sent by [Device::power_up]
+    fir_filters: Vec<(calibration::AxisSelector, Vec<f64>)>,
+
+    /// Settings as last set, read or saved. See [Device::diff]
+    settings: config::SettingsShadow,
+
+    /// Handed out by [Device::cancel_token]; reads check it while one exists
+    cancel_token: Option<connection::CancelToken>,
+
+    /// Frame counters. See [Device::stats]
+    stats: frame::LinkStats,
+
+    /// See [Device::set_checksum_tolerance]
+    checksum_tolerance: u32,
+
+    /// Checksum failures since the last good frame
+    consecutive_checksum_errors: u32,
 }
 
+#[cfg(feature = "std")]
 impl Device {
     /// Creates a new Device with provided serialport
     pub fn new(serialport: impl Into<Box<dyn SerialPort>>) -> Self {
+        Self::from_transport(serialport.into())
+    }
+
+    /// Creates a new Device on any [Transport], e.g. a [transport::TcpTransport] to a serial
+    /// server
+    pub fn from_transport(transport: impl Transport + 'static) -> Self {
         Self {
-            serialport: serialport.into(),
+            transport: Box::new(transport),
             read_checksum: crc16::State::<crc16::XMODEM>::new(),
             read_bytes: 0,
+            mod_info: None,
+            frame_buffer: Vec::new(),
+            rx_buffer: Vec::new(),
+            rx_pos: 0,
+            frame_len: 0,
+            frame_started: None,
+            streaming: false,
+            connection_state: ConnectionEvent::Connecting,
+            connection_subscribers: Vec::new(),
+            clock: std::sync::Arc::new(clock::SystemClock),
+            unsolicited: Default::default(),
+            strict: true,
+            lenient_bools: false,
+            lenient_trailing_bytes: false,
+            trailing_bytes: None,
+            write_strategy: Default::default(),
+            unflushed: false,
+            angle_config: Default::default(),
+            reconnect_policy: None,
+            port_lost: false,
+            profile: Default::default(),
+            protocol: Default::default(),
+            data_components: None,
+            acq_params: None,
+            fir_filters: Vec::new(),
+            settings: Default::default(),
+            cancel_token: None,
+            stats: Default::default(),
+            checksum_tolerance: 0,
+            consecutive_checksum_errors: 0,
         }
     }
 
+    /// Strict mode, on by default, range-checks arguments such as [config::ConfigPair] values
+    /// before sending them, returning [RWError::InvalidArgument] instead of letting the device
+    /// silently misbehave. Turn it off to send values as given, e.g. for firmware that accepts a
+    /// wider range than documented
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// Booleans from the device must be exactly 0 or 1 by default, and anything else is a parse
+    /// error. Some firmware sends other values (e.g. 0xFF) for true in config reads; with lenient
+    /// booleans on, any nonzero byte reads as true so those units can still be configured
+    pub fn set_lenient_bools(&mut self, lenient: bool) {
+        self.lenient_bools = lenient;
+    }
+
+    /// A frame with bytes left over after the last field is a [ReadError::SizeMismatch] by
+    /// default. Newer firmware may append fields to existing responses; with lenient trailing
+    /// bytes on, the extra bytes are skipped (and kept in [Device::trailing_bytes]) so the fields
+    /// this library knows about can still be used. Frames that are too short are still errors
+    pub fn set_lenient_trailing_bytes(&mut self, lenient: bool) {
+        self.lenient_trailing_bytes = lenient;
+    }
+
+    /// Extra bytes skipped at the end of the most recent frame that had any, with
+    /// [Device::set_lenient_trailing_bytes] on
+    pub fn trailing_bytes(&self) -> Option<&frame::TrailingBytes> {
+        self.trailing_bytes.as_ref()
+    }
+
+    /// Replaces the time source used for retries and timeouts, e.g. with a
+    /// [clock::SimulatedClock] in tests
+    pub fn set_clock(&mut self, clock: std::sync::Arc<dyn clock::Clock>) {
+        self.clock = clock;
+    }
+
     /// Creates and connects to a device, auto-detecting the serial port, and choosing the
-    /// default baud rate of 38400
+    /// default baud rate of 38400. Shorthand for [Device::builder] with only the port set
     ///
     /// # Arguments
     ///
-    /// * `port` - If [Some], uses the given serial port string. If [None], tries to auto-detect
+    /// * `port` - If [Some], uses the given serial port string. If [None], tries to auto-detect.
+    ///   The port used is logged and available from [Device::port_name]. With several devices
+    ///   attached, use [Device::discover] to choose one
     ///
     /// # Examples
     ///
@@ -148,38 +659,51 @@ impl Device {
     /// # }
     /// ```
     pub fn connect(port: Option<String>) -> Result<Self, Box<dyn Error>> {
-        let ports = serialport::available_ports()?;
+        let mut builder = Self::builder();
+        if let Some(port) = port {
+            builder = builder.port(port);
+        }
+        Ok(builder.open()?)
+    }
 
-        let port = if let Some(provided_port) = port {
-            provided_port
-        } else {
-            match ports.into_iter().fold(None, |chosen, port| {
-                if port.port_name.contains("usb") {
-                    Some(port)
-                } else {
-                    chosen
-                }
-            }) {
-                Some(port) => port.port_name,
-                None => {
-                    return Err(Box::new(serialport::Error::new(
-                        serialport::ErrorKind::NoDevice,
-                        "Could not auto-detect serial port",
-                    )))
-                }
-            }
-        };
+    /// Like [Device::connect], but also stops the device streaming if it was left in continuous
+    /// mode, so the first command gets a clean response. Takes about a second longer than
+    /// [Device::connect]; see [Device::drain_continuous]
+    pub fn connect_recovering(port: Option<String>) -> Result<Self, Box<dyn Error>> {
+        let mut device = Self::connect(port)?;
+        device.drain_continuous()?;
+        Ok(device)
+    }
+
+    /// Serial port settings [Device::connect] uses: 38400 baud, 8N1, 1 second timeout. Adjust
+    /// and pass to [Device::connect_with] for control over flow control, timeouts etc.
+    pub fn port_builder<'a>(
+        port: impl Into<std::borrow::Cow<'a, str>>,
+    ) -> serialport::SerialPortBuilder {
+        serialport::new(port, 38400)
+            .data_bits(serialport::DataBits::Eight)
+            .stop_bits(serialport::StopBits::One)
+            .parity(serialport::Parity::None)
+            .timeout(Duration::new(1, 0))
+    }
+
+    /// Opens a prepared serial port builder, e.g. from [Device::port_builder]
+    pub fn connect_with(builder: serialport::SerialPortBuilder) -> Result<Self, serialport::Error> {
+        let device = Device::new(builder.open()?);
+        log::debug!("Opened port {:?}", device.port_name());
+        Ok(device)
+    }
 
-        println!("Using port {}", port);
+    /// Name of the serial port the device is attached to, if the port has one. See
+    /// [Transport::name]
+    pub fn port_name(&self) -> Option<String> {
+        self.transport.name()
+    }
 
-        Ok(Device::new(
-            serialport::new(port, 38400)
-                .data_bits(serialport::DataBits::Eight)
-                .stop_bits(serialport::StopBits::One)
-                .parity(serialport::Parity::None)
-                .timeout(Duration::new(1, 0))
-                .open()?,
-        ))
+    /// Baud rate the port is open at, e.g. the one [Device::connect_probe] found. [None] for
+    /// links without one
+    pub fn baud_rate(&self) -> Option<u32> {
+        self.transport.baud_rate().ok()
     }
 
     /// Sends the given command and payload to the device, with appropriate CRC and sizing
@@ -188,43 +712,27 @@ impl Device {
         command: Command,
         payload: Option<&[u8]>,
     ) -> Result<(), WriteError> {
-        let payload_length = if let Some(payload) = payload {
-            payload.len() as u16
-        } else {
-            0
-        };
-
-        // offset of 5 comes from 2 length bytes, 1 command byte, 2 crc bytes
-        let size = (payload_length + 5u16).to_be_bytes();
-        let command = command.discriminant().to_be_bytes();
-
-        // if you are porting this to another language, note the CRC algorithm XMODEM may also be
-        // called CCITT or ITU, but is different from CCITT-FALSE and AUG-CCITT
-        let mut crc = crc16::State::<crc16::XMODEM>::new();
-
-        // write packet size
-        self.serialport.write(&size)?;
-        crc.update(&size);
-
-        // write command
-        self.serialport.write(&command)?;
-        crc.update(&command);
-
-        if let Some(payload_bytes) = payload {
-            // write payload
-            self.serialport.write(payload_bytes)?;
-            crc.update(payload_bytes);
+        if self.port_lost && !self.recover_port() {
+            return Err(WriteError::PipeError(std::io::ErrorKind::NotConnected.into()));
         }
+        let payload = payload.unwrap_or(&[]);
+        let mut frame = vec![0u8; payload.len() + codec::FRAME_OVERHEAD];
+        codec::encode_frame(u8::from(command), payload, &mut frame)?;
+        self.write_frame_bytes(&frame)
+    }
 
-        // finish and write CRC
-        let crc = &(crc.finish() as u16).to_be_bytes();
-        self.serialport.write(crc)?;
-
-        Ok(())
+    /// Discards the partially-read frame state, used when a frame is abandoned part way through
+    pub(crate) fn reset_frame(&mut self) {
+        self.read_checksum = crc16::State::<crc16::XMODEM>::new();
+        self.read_bytes = 0;
+        self.rx_buffer.clear();
+        self.rx_pos = 0;
+        self.frame_len = 0;
     }
 
-    /// Reads, checks then resets checksum when reading a frame.
-    /// Must be called at the end of every frame to reset counters and crc
+    /// Reads, checks then resets checksum when reading a frame. Called by
+    /// [frame::FrameReader::finish]; commands should read responses through that rather than
+    /// calling this directly
     fn end_frame(&mut self, expected_frame_len: u16) -> Result<(), ReadError> {
         // must compute expected sum before reading the checksum, since reading the checksum
         // updates the hasher
@@ -233,8 +741,9 @@ impl Device {
 
         // reset checksum (though it should auto-reset to zero...).
         self.read_checksum = crc16::State::<crc16::XMODEM>::new();
+        self.frame_len = 0;
 
-        if expected_sum == checksum && self.read_bytes == expected_frame_len {
+        let result = if expected_sum == checksum && self.read_bytes == expected_frame_len {
             self.read_bytes = 0;
             Ok(())
         } else if self.read_bytes != expected_frame_len {
@@ -250,89 +759,159 @@ impl Device {
                 expected: expected_sum,
                 actual: checksum,
             })
+        };
+
+        match &result {
+            Ok(()) => {
+                self.stats.frames_ok += 1;
+                self.consecutive_checksum_errors = 0;
+                self.mark_healthy()
+            }
+            Err(e) => {
+                match e {
+                    ReadError::ChecksumMismatch { .. } => {
+                        self.stats.checksum_errors += 1;
+                        self.consecutive_checksum_errors += 1;
+                    }
+                    _ => self.stats.size_errors += 1,
+                }
+                self.set_connection_state(ConnectionEvent::Degraded {
+                    reason: e.to_string(),
+                })
+            }
         }
+        result
     }
 
-    /// Returns device type and revision
+    /// Returns device type and revision. The result is cached for [Device::supports]
     pub fn get_mod_info(&mut self) -> Result<ModInfoResp, RWError> {
-        self.write_frame(Command::GetModInfo, None)?;
-        let expected_size = Get::<u16>::get(self)?;
-        if Get::<u8>::get(self)? == Command::GetModInfoResp.discriminant() {
-            let device_type = Get::<u32>::get_string(self)?;
-            let revision = Get::<u32>::get_string(self)?;
-            self.end_frame(expected_size)?;
-            Ok(ModInfoResp {
-                device_type,
-                revision,
-            })
-        } else {
-            let _ = self.end_frame(expected_size);
-            Err(RWError::ReadError(ReadError::ParseError(
-                "Unexpected response type".to_string(),
-            )))
-        }
+        let mut frame = self.request(Command::GetModInfo, None)?;
+        let device_type = frame.get_string::<u32>()?;
+        let revision = frame.get_string::<u32>()?;
+        frame.finish()?;
+        let mod_info = ModInfoResp::new(device_type, revision);
+        self.mod_info = Some(mod_info.clone());
+        Ok(mod_info)
     }
 
-    /// Returns device serial number, which can also be found on the front sticker
-    pub fn serial_number(&mut self) -> Result<u32, RWError> {
-        self.write_frame(Command::SerialNumber, None)?;
-        let expected_size = Get::<u16>::get(self)?;
-        if Get::<u8>::get(self)? == Command::SerialNumberResp.discriminant() {
-            let serial_number = Get::<u32>::get(self)?;
-            self.end_frame(expected_size)?;
-            Ok(serial_number)
-        } else {
-            let _ = self.end_frame(expected_size);
-            Err(RWError::ReadError(ReadError::ParseError(
-                "Unexpected response type".to_string(),
-            )))
-        }
+    /// Identity cached by the last successful [Device::get_mod_info], without talking to the
+    /// device
+    pub fn identity(&self) -> Option<&ModInfoResp> {
+        self.mod_info.as_ref()
     }
 
-    /// This frame commands the device to save internal configurations and user calibration to non-volatile memory. Internal configurations and user calibration are restored on power up. The frame has no payload. This is the ONLY command that causes the device to save information to non-volatile memory.
-    /// See also: [Device::get_config], [Device::set_config]
-    pub fn save(&mut self) -> Result<(), RWError> {
-        self.write_frame(Command::Save, None)?;
+    /// Device family, if the device has been identified. See [Device::identity]
+    pub fn model(&self) -> Option<responses::Model> {
+        self.mod_info.as_ref().map(|info| info.model)
+    }
+
+    /// Firmware version, if the device has been identified and reported a parsable revision. See
+    /// [Device::identity]
+    pub fn firmware_version(&self) -> Option<responses::FirmwareVersion> {
+        self.mod_info.as_ref().and_then(|info| info.firmware)
+    }
 
-        let expected_size = Get::<u16>::get(self)?;
-        if Get::<u8>::get(self)? == Command::SaveDone.discriminant() {
-            let error_code = Get::<u16>::get(self)?;
-            self.end_frame(expected_size)?;
+    /// Whether the identified firmware is at least `version`; [None] if it isn't known. For
+    /// gating workarounds that only apply to some firmware
+    pub fn firmware_at_least(&self, version: responses::FirmwareVersion) -> Option<bool> {
+        self.firmware_version().map(|firmware| firmware >= version)
+    }
+
+    requests! {
+        /// Returns device serial number, which can also be found on the front sticker
+        pub fn serial_number() -> u32 = SerialNumber => |serial_number: u32| Ok(serial_number);
+
+        fn save_impl() -> () = Save => |error_code: u16| {
             if error_code != 0 {
                 return Err(RWError::DeviceError(
-                    "Recieved error code from device, settings not saved succesfully".to_string(),
+                    catalog::Message::new(catalog::MessageId::SaveFailed).with("code", error_code),
                 ));
             }
             Ok(())
-        } else {
-            let _ = self.end_frame(expected_size);
-            Err(RWError::ReadError(ReadError::ParseError(
-                "Unexpected response type".to_string(),
-            )))
-        }
+        };
+    }
+
+    /// This frame commands the device to save internal configurations and user calibration to non-volatile memory. Internal configurations and user calibration are restored on power up. The frame has no payload. This is the ONLY command that causes the device to save information to non-volatile memory.
+    /// See also: [Device::get_config], [Device::set_config], [Device::save_if_dirty]
+    pub fn save(&mut self) -> Result<(), RWError> {
+        self.save_impl()?;
+        self.settings.mark_saved();
+        Ok(())
     }
 
     /// "Powers up" the device by sending data over serial (asks for SerialPort) Consumes the power up packet emitted by the device, useful to call after you call
-    /// power_down and reconnect the device
+    /// power_down and reconnect the device.
+    ///
+    /// Data components, acquisition parameters and FIR filters set on this connection are sent
+    /// again once the device is awake, since powering down reverts them to their saved values.
+    /// Settings changed with [Device::set_config] aren't; [Device::save] those to keep them.
+    ///
+    /// Retries according to [PowerUpPolicy::default]; see [Device::power_up_with]
     pub fn power_up(&mut self) -> Result<(), RWError> {
-        self.write_frame(Command::SerialNumber, None)?;
+        self.power_up_with(&PowerUpPolicy::default())
+    }
+
+    /// Like [Device::power_up], but retries with exponential backoff as described by `policy`.
+    ///
+    /// Returns [RWError::NeverWoke] if every attempt timed out. If the device did respond but the
+    /// response was malformed or unexpected, the last such error is returned instead
+    pub fn power_up_with(&mut self, policy: &PowerUpPolicy) -> Result<(), RWError> {
+        self.set_connection_state(ConnectionEvent::Connecting);
+        let mut backoff = clock::Backoff::new(self.clock.clone(), policy);
+        let mut last_response_error = None;
+
+        loop {
+            backoff.attempt();
+            let span = instrument::AttemptSpan::enter("power_up", backoff.attempts());
+            match self.power_up_once() {
+                Ok(()) => return self.reassert_volatile_settings(),
+                Err(RWError::ReadError(ReadError::PipeError(e)))
+                    if e.kind() == std::io::ErrorKind::TimedOut =>
+                {
+                    span.cause(&e)
+                }
+                Err(e @ RWError::ReadError(_)) => {
+                    span.cause(&e);
+                    last_response_error = Some(e);
+                }
+                Err(e) => return Err(e),
+            }
+
+            // drop whatever is left of a garbled response so the next attempt starts on a
+            // frame boundary
+            self.reset_frame();
+            let _ = self.transport.clear_input();
 
-        let expected_size = Get::<u16>::get(self)?;
-        let resp_command = Get::<u8>::get(self)?;
+            span.backoff(backoff.delay());
+            if !backoff.wait() {
+                break;
+            }
+        }
+
+        Err(last_response_error.unwrap_or(RWError::NeverWoke {
+            attempts: backoff.attempts(),
+            elapsed: backoff.elapsed(),
+        }))
+    }
 
-        if resp_command == Command::PowerUpDone.discriminant() {
-            self.end_frame(expected_size)?;
+    /// A single wake-up attempt
+    fn power_up_once(&mut self) -> Result<(), RWError> {
+        self.write_frame(Command::SerialNumber, None)?;
+
+        let mut frame = self.begin_frame()?;
+        if frame.command() == u8::from(Command::PowerUpDone) {
+            frame.finish()?;
             Ok(())
-        } else if resp_command == Command::SerialNumberResp.discriminant() {
+        } else if frame.command() == u8::from(Command::SerialNumberResp) {
             // if the device is already powered up or if it did buffering of the wake-up command,
             // we might actually get the serial number back!
-            Get::<u32>::get(self)?;
-            self.end_frame(expected_size)?;
+            frame.get::<u32>()?;
+            frame.finish()?;
             Ok(())
         } else {
-            let _ = self.end_frame(expected_size);
             Err(RWError::ReadError(ReadError::ParseError(
-                "Unexpected response type".to_string(),
+                catalog::Message::new(catalog::MessageId::UnexpectedResponse)
+                    .with("command", frame.command()),
             )))
         }
     }
@@ -341,18 +920,10 @@ impl Device {
     /// This frame frequently does not recieve a response even when it works, it's suggested that
     /// you ignore ParseErrors
     fn power_down_impl(&mut self) -> Result<(), RWError> {
-        self.write_frame(Command::PowerDown, None)?;
-
-        let expected_size = Get::<u16>::get(self)?;
-        if Get::<u8>::get(self)? == Command::PowerDownDone.discriminant() {
-            self.end_frame(expected_size)?;
-            Ok(())
-        } else {
-            let _ = self.end_frame(expected_size);
-            Err(RWError::ReadError(ReadError::ParseError(
-                "Unexpected response type".to_string(),
-            )))
-        }
+        self.forget_angle_config();
+        self.settings.revert_unsaved();
+        self.request(Command::PowerDown, None)?.finish()?;
+        Ok(())
     }
     
     /// You should consider using [Self::power_down] instead of [Self::power_down_raw] to avoid
@@ -365,41 +936,2228 @@ impl Device {
     pub fn power_down_raw(&mut self) -> Result<(), RWError> {
         self.power_down_impl()
     }
-
-    //NOTE: when powering up, we want to connect to the same device in case multiple devices were
-    //provided? Otherwise we basically force the end user to deliberately re-choose the new device
-    //anyhow by re-constructing tp3. Consuming self in power down also drops the serial port which
-    //is desireable
-    /// This frame is used to power-down the module. The frame has no payload. The command will power down all peripherals including the sensors, microprocessor, and RS-232 driver. However, the driver chip has a feature to keep the Rx line enabled. The device will power up when it receives any signal on the native UART Rx line.
-    /// Similar to power_down_raw, but ignores common errors due to power down, and takes ownership to hang up the socket and force developer to create a new tp3 object
-    /// The very action of reconnecting the device will cause it to power back up.
-    pub fn power_down(mut self) -> Result<(), RWError> {
-        let ret = match self.power_down_impl() {
-            Ok(_) => Ok(()),
-            Err(RWError::ReadError(_)) => Ok(()),
-            Err(e) => Err(e),
-        };
-        ret
-    }
 }
 
 // NOTE: when testing or writing doctests, be sure to put everything in its own scope so that the
 // serialport is dropped afte each test
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
     use crate::acquisition::*;
     use crate::*;
 
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn logger_recovers_from_torn_record() {
+        use crate::logger::*;
+        use std::io::Write;
+        use std::time::{Instant, SystemTime};
+
+        let path = std::env::temp_dir().join(format!("pni-sdk-logger-{}.csv", std::process::id()));
+        let sample = || TimestampedData {
+            data: Data { heading: Some(12.5), ..Default::default() },
+            provenance: Provenance::Simulated,
+            received_at: Instant::now(),
+            received_at_system: SystemTime::now(),
+            estimated_sample_time: None,
+        };
+
+        {
+            let mut logger = DataLogger::create(&path).unwrap();
+            logger.set_checkpoint_interval(2);
+            for _ in 0..3 {
+                logger.write(&sample()).unwrap();
+            }
+        }
+        // simulate a crash part way through a record
+        std::fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(b"3,17").unwrap();
+
+        let (mut logger, report) = DataLogger::open_or_recover(&path).unwrap();
+        assert_eq!(report.checkpoint.samples, 2, "Third sample was never checkpointed");
+        assert!(report.discarded_bytes > 0);
+        logger.write(&sample()).unwrap();
+        logger.finish().unwrap();
+
+        let (logger, report) = DataLogger::open_or_recover(&path).unwrap();
+        assert_eq!(report.discarded_bytes, 0);
+        assert_eq!(logger.samples(), 3);
+        let samples = read_log(&path).unwrap();
+        assert_eq!(samples.len(), 3);
+        assert_eq!(samples[2].1.heading, Some(12.5));
+
+        let mut checkpoint = path.clone().into_os_string();
+        checkpoint.push(".checkpoint");
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(checkpoint);
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn log_to_rotates_and_reads_back() {
+        use crate::logger::*;
+
+        let dir = std::env::temp_dir().join(format!("pni-sdk-log-to-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mock = crate::mock::MockTransport::target_point3().value(DataID::Heading, 12.5);
+        let mut device = Device::from_transport(mock.clone());
+        device.set_data_components(vec![DataID::Heading]).unwrap();
+        device.start_continuous_mode().unwrap();
+
+        let summary = device
+            .log_to(
+                dir.join("survey.csv"),
+                LogFormat::Csv,
+                StopAfter::samples(5),
+                Rotation::every_samples(2),
+            )
+            .unwrap();
+        assert!(!mock.is_streaming(), "Logging should stop the stream when it's done");
+        assert_eq!(summary.samples, 5);
+        let names: Vec<_> = summary.files.iter().map(|f| f.file_name().unwrap()).collect();
+        assert_eq!(names, ["survey-0000.csv", "survey-0001.csv", "survey-0002.csv"]);
+
+        let lens: Vec<_> = summary.files.iter().map(|f| read_log(f).unwrap().len()).collect();
+        assert_eq!(lens, [2, 2, 1]);
+        for (_, data) in read_log(&summary.files[0]).unwrap() {
+            assert_eq!(data, Data { heading: Some(12.5), ..Default::default() });
+        }
+
+        device.start_continuous_mode().unwrap();
+        let summary = device
+            .log_to(dir.join("single.csv"), LogFormat::Csv, StopAfter::samples(3), Rotation::NEVER)
+            .unwrap();
+        assert_eq!(summary.files, [dir.join("single.csv")], "No rotation keeps the name as is");
+        assert_eq!(read_log(&summary.files[0]).unwrap().len(), 3);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn parquet_log_round_trip() {
+        use crate::logger::*;
+        use parquet::file::reader::{FileReader, SerializedFileReader};
+        use parquet::record::Field;
+
+        let path = std::env::temp_dir().join(format!("pni-sdk-log-{}.parquet", std::process::id()));
+        let mock = crate::mock::MockTransport::target_point3()
+            .value(DataID::Heading, 12.5)
+            .value(DataID::Distortion, 1.0)
+            .value(DataID::AccelZ, -1.0);
+        let mut device = Device::from_transport(mock);
+        device
+            .set_data_components(vec![DataID::Heading, DataID::Distortion, DataID::AccelZ])
+            .unwrap();
+        device.start_continuous_mode().unwrap();
+        device.log_to(&path, LogFormat::Parquet, StopAfter::samples(3), Rotation::NEVER).unwrap();
+
+        let reader = SerializedFileReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+        let rows: Vec<_> = reader.get_row_iter(None).unwrap().map(Result::unwrap).collect();
+        assert_eq!(rows.len(), 3);
+        for (index, row) in rows.iter().enumerate() {
+            for (name, field) in row.get_column_iter() {
+                let expected = match name.as_str() {
+                    "sample" => Field::Long(index as i64),
+                    "heading" => Field::Float(12.5),
+                    "distortion" => Field::Bool(true),
+                    "accel_z" => Field::Float(-1.0),
+                    "provenance" => continue,
+                    "received_at" => {
+                        assert!(matches!(field, Field::TimestampMicros(_)));
+                        continue;
+                    }
+                    _ => Field::Null,
+                };
+                assert_eq!(field, &expected, "Column {} of row {}", name, index);
+            }
+        }
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn capture_stops_after_samples_or_time() {
+        use crate::clock::SimulatedClock;
+        use crate::logger::*;
+
+        let clock = SimulatedClock::new();
+        let mock = crate::mock::MockTransport::target_point3().value(DataID::Heading, 12.5);
+        let mut device = Device::from_transport(mock.clone());
+        device.set_clock(std::sync::Arc::new(clock.clone()));
+        device.set_data_components(vec![DataID::Heading]).unwrap();
+
+        device.start_continuous_mode().unwrap();
+        let mut capture = device.capture(StopAfter::samples(4), None);
+        assert_eq!(capture.by_ref().filter(Result::is_ok).count(), 4);
+        assert_eq!(Capture::count(&capture), 4);
+        assert!(!mock.is_streaming(), "Reaching the limit should stop the stream");
+
+        device.start_continuous_mode().unwrap();
+        let mut capture = device.capture(StopAfter::duration(Duration::from_secs(60)), None);
+        for _ in 0..3 {
+            assert!(capture.next().unwrap().is_ok());
+            clock.advance(Duration::from_secs(15));
+        }
+        assert!(capture.next().unwrap().is_ok(), "45s in, the capture should still be running");
+        clock.advance(Duration::from_secs(15));
+        assert!(capture.next().is_none());
+        assert_eq!(Capture::count(&capture), 4);
+        assert!(!mock.is_streaming());
+
+        let path = std::env::temp_dir().join(format!("pni-sdk-capture-{}.csv", std::process::id()));
+        device.start_continuous_mode().unwrap();
+        let logger = DataLogger::create(&path).unwrap();
+        assert_eq!(device.capture_to_log(logger, StopAfter::samples(3), None).unwrap(), 3);
+        assert!(!mock.is_streaming());
+        let samples = read_log(&path).unwrap();
+        assert_eq!(samples.len(), 3);
+        assert!(samples.iter().all(|(_, data)| data.heading == Some(12.5)));
+
+        let mut checkpoint = path.clone().into_os_string();
+        checkpoint.push(".checkpoint");
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(checkpoint);
+    }
+
+    #[test]
+    fn complementary_filter_wraps_heading() {
+        use crate::processing::*;
+
+        let sample = |heading| Data {
+            heading: Some(heading),
+            pitch: Some(0.0),
+            roll: Some(0.0),
+            ..Default::default()
+        };
+        let mut filter = ComplementaryFilter::new(0.5);
+        filter.update(&sample(350.0), 0.1);
+        let heading = filter.update(&sample(10.0), 0.1).unwrap().heading;
+        assert!(heading.abs() < 1e-3 || (heading - 360.0).abs() < 1e-3, "350˚ and 10˚ should blend to 0˚, got {}", heading);
+    }
+
+    #[test]
+    fn heading_offset_from_bearing_wraps() {
+        use crate::processing::*;
+
+        assert_eq!(mean_heading(&[358.0, 2.0, 0.0]).map(|h| h.round()), Some(0.0));
+        let offset = HeadingOffset::from_bearing(355.0, 5.0);
+        assert!((offset.degrees - 10.0).abs() < 1e-4);
+        assert!((offset.correct(352.0) - 2.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn config_values_validated() {
+        use crate::catalog::MessageId;
+        use crate::config::ConfigPair;
+
+        assert!(ConfigPair::Declination(-180.0).validate().is_ok());
+        assert!(ConfigPair::Declination(f32::NAN).validate().is_err());
+        assert!(ConfigPair::UserCalNumPoints(3).validate().is_err());
+        assert!(ConfigPair::MagCoeffSet(7).validate().is_ok());
+        let error = ConfigPair::AccelCoeffSet(8).validate().unwrap_err();
+        assert_eq!(error.id, MessageId::OutOfRange);
+        assert_eq!(error.param("value"), Some("8"));
+    }
+
+    #[test]
+    fn byte_conversions_round_trip() {
+        use crate::command::{Command, UnknownCommand};
+        use crate::config::{Baud, ConfigID};
+
+        for byte in 0..=u8::MAX {
+            if let Ok(command) = Command::try_from(byte) {
+                assert_eq!(u8::from(command), byte);
+            }
+        }
+        assert_eq!(Command::try_from(0x20), Err(UnknownCommand(0x20)));
+        assert_eq!(Baud::try_from(u8::from(Baud::B115200)).unwrap(), Baud::B115200);
+        assert_eq!(ConfigID::try_from(14).unwrap(), ConfigID::BaudRate);
+        assert!(ConfigID::try_from(3).is_err());
+    }
+
+    #[test]
+    fn captured_frames_parse_without_device() {
+        use crate::codec::{decode_frame, encode_frame};
+        use crate::dispatch::Response;
+
+        let mut payload = vec![2, 5];
+        payload.extend_from_slice(&90.5f32.to_be_bytes());
+        payload.push(8);
+        payload.push(1);
+        let mut bytes = [0u8; 32];
+        let len = encode_frame(0x05, &payload, &mut bytes).unwrap();
+        let (frame, _) = decode_frame(&bytes[..len]).unwrap();
+        match frame.parse().unwrap() {
+            Response::Data(data) => {
+                assert_eq!(data.heading, Some(90.5));
+                assert_eq!(data.distortion, Some(true));
+            }
+            other => panic!("parsed as {:?}", other),
+        }
+
+        let mut cursor = &payload[..3];
+        assert!(crate::responses::Get::<Data>::get(&mut cursor).is_err());
+
+        let len = encode_frame(0x7e, &[1, 2], &mut bytes).unwrap();
+        let (frame, _) = decode_frame(&bytes[..len]).unwrap();
+        assert!(matches!(frame.parse().unwrap(), Response::Unknown { id: 0x7e, .. }));
+
+        // a serial number with a byte left over
+        let len = encode_frame(0x35, &[0, 0, 0, 7, 0], &mut bytes).unwrap();
+        let (frame, _) = decode_frame(&bytes[..len]).unwrap();
+        assert!(matches!(frame.parse(), Err(ReadError::SizeMismatch { .. })));
+    }
+
+    #[test]
+    fn fixed_data_parses_without_allocating() {
+        use crate::codec::{ComponentValue, DataPayloadError, FixedData};
+
+        let mut payload = vec![2, 5];
+        payload.extend_from_slice(&90.5f32.to_be_bytes());
+        payload.extend_from_slice(&[8, 1]);
+        let data = FixedData::<4>::parse(&payload).unwrap();
+        assert_eq!(data.float(DataID::Heading as u8), Some(90.5));
+        assert_eq!(data.get(DataID::Distortion as u8), Some(ComponentValue::Flag(1)));
+        assert_eq!(data.components().len(), 2);
+
+        assert_eq!(
+            FixedData::<1>::parse(&payload),
+            Err(DataPayloadError::TooManyComponents { count: 2, capacity: 1 })
+        );
+        assert_eq!(
+            FixedData::<4>::parse(&[1, 200, 0]),
+            Err(DataPayloadError::UnknownComponent(200))
+        );
+    }
+
+    #[test]
+    fn angles_normalized_to_degrees() {
+        let data = Data {
+            heading: Some(1600.0),
+            pitch: Some(-100.0),
+            angle_format: Some(AngleFormat {
+                units: AngleUnits::Mils,
+                north: NorthReference::True { declination: 100.0 },
+            }),
+            ..Default::default()
+        };
+        assert_eq!(data.heading_degrees(), Some(90.0));
+        assert_eq!(data.pitch_degrees(), Some(-5.625));
+        assert_eq!(data.magnetic_heading_degrees(), Some(350.0));
+        assert_eq!(data.true_heading_degrees(), Some(90.0));
+        assert_eq!(Data { heading: Some(10.0), ..Default::default() }.heading_degrees(), None);
+    }
+
+    #[test]
+    fn stability_detector_waits_for_settling() {
+        use crate::processing::StabilityDetector;
+
+        let sample = |heading| Data { heading: Some(heading), ..Default::default() };
+        let mut detector = StabilityDetector::new(4, 0.5, 0.1);
+        // transient while the filter fills, then steady either side of north
+        for heading in [300.0, 330.0, 350.0, 359.8, 0.1, 359.9] {
+            assert!(!detector.update(&sample(heading)));
+        }
+        assert!(detector.update(&sample(0.2)));
+    }
+
+    #[test]
+    fn heading_unwrap_round_trip() {
+        use crate::processing::*;
+
+        let headings = [350.0, 355.0, 2.0, 10.0, 355.0, 340.0];
+        let unwrapped = unwrap_headings(&headings);
+        assert_eq!(unwrapped, vec![350.0, 355.0, 362.0, 370.0, 355.0, 340.0]);
+        assert_eq!(wrap_headings(&unwrapped), headings.to_vec());
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn replay_steps_and_seeks() {
+        use crate::replay::Replay;
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let at = |secs| UNIX_EPOCH + Duration::from_secs(secs);
+        let sample = |heading| Data { heading: Some(heading), ..Default::default() };
+        let mut replay = Replay::new(vec![(at(0), sample(1.0)), (at(3600), sample(2.0)), (at(7200), sample(3.0))]);
+        let control = replay.control();
+
+        // an hour apart at up to 100x would take too long, so step through while paused
+        control.pause();
+        control.step();
+        assert_eq!(replay.next().unwrap().data.heading, Some(1.0));
+        control.seek(2);
+        control.step();
+        let last = replay.next().unwrap();
+        assert_eq!((last.data.heading, last.provenance), (Some(3.0), Provenance::Replayed));
+        control.resume();
+        assert!(replay.next().is_none());
+        control.set_speed(1000.0);
+        assert_eq!(control.speed(), crate::replay::MAX_SPEED);
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn bridge_json_skips_missing_components() {
+        let data = Data {
+            heading: Some(12.5),
+            pitch: Some(f32::NAN),
+            distortion: Some(false),
+            ..Default::default()
+        };
+        let at = std::time::UNIX_EPOCH + std::time::Duration::from_millis(1500);
+        assert_eq!(
+            crate::bridge::data_json(&data, at),
+            r#"{"time":1.5,"heading":12.5,"distortion":false}"#
+        );
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn archive_round_trip() {
+        use crate::acquisition::AcqParams;
+        use crate::archive::DeviceArchive;
+        use crate::config::{Baud, ConfigPair, MountingRef};
+
+        // every setting write_to knows, including the ones export_archive leaves out
+        let archive = DeviceArchive {
+            device_type: "TP3".to_string(),
+            revision: "1.00".to_string(),
+            serial_number: 1234,
+            config: vec![
+                ConfigPair::Declination(-3.5),
+                ConfigPair::MountingRef(MountingRef::XUp90),
+                ConfigPair::MagCoeffSet(2),
+                ConfigPair::BigEndian(true),
+                ConfigPair::BaudRate(Baud::B115200),
+            ],
+            acq_params: AcqParams {
+                acquisition_mode: false,
+                flush_filter: true,
+                sample_delay: 0.25,
+            },
+            fir_taps: vec![0.25, 0.5, 0.25],
+        };
+        let mut text = Vec::new();
+        archive.write_to(&mut text).unwrap();
+        let restored = DeviceArchive::read_from(&text[..]).unwrap();
+
+        let mut round_tripped = Vec::new();
+        restored.write_to(&mut round_tripped).unwrap();
+        assert_eq!(text, round_tripped);
+        assert!(matches!(
+            restored.config[1],
+            ConfigPair::MountingRef(MountingRef::XUp90)
+        ));
+        assert!(matches!(restored.config[3], ConfigPair::BigEndian(true)));
+        assert!(matches!(restored.config[4], ConfigPair::BaudRate(Baud::B115200)));
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn archive_file_clones_settings_onto_another_unit() {
+        use crate::archive::DeviceArchive;
+        use crate::config::{ConfigID, ConfigPair};
+        use crate::simulator::{Simulator, SimulatorConfig};
+
+        let mut calibrated = Device::from_transport(Simulator::new(SimulatorConfig::default()));
+        calibrated.set_config(ConfigPair::Declination(4.5)).unwrap();
+        calibrated.set_config(ConfigPair::MagCoeffSet(3)).unwrap();
+        calibrated.set_config(ConfigPair::AccelCoeffSet(1)).unwrap();
+        let path = std::env::temp_dir().join(format!("pni-sdk-archive-{}.txt", std::process::id()));
+        calibrated.export_archive().unwrap().save(&path).unwrap();
+
+        let archive = DeviceArchive::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let mut replacement = Device::from_transport(Simulator::new(SimulatorConfig {
+            serial_number: 7654321,
+            ..Default::default()
+        }));
+        replacement.restore_archive(&archive).unwrap();
+        assert_eq!(archive.serial_number, SimulatorConfig::default().serial_number);
+        for (id, value) in [
+            (ConfigID::Declination, ConfigPair::Declination(4.5)),
+            (ConfigID::MagCoeffSet, ConfigPair::MagCoeffSet(3)),
+            (ConfigID::AccelCoeffSet, ConfigPair::AccelCoeffSet(1)),
+        ] {
+            assert_eq!(replacement.get_config(id).unwrap(), value);
+        }
+        assert!(!replacement.has_unsaved_changes());
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn orientation_quaternion_round_trip() {
+        use crate::processing::Orientation;
+
+        let orientation = Orientation { heading: 90.0, pitch: 10.0, roll: -20.0 };
+        let restored = Orientation::from_quaternion(&orientation.to_quaternion());
+        assert!((restored.heading - 90.0).abs() < 1e-3);
+        assert!((restored.pitch - 10.0).abs() < 1e-3);
+        assert!((restored.roll + 20.0).abs() < 1e-3);
+
+        // facing east, forward is east
+        let level = Orientation { heading: 90.0, pitch: 0.0, roll: 0.0 };
+        let east = level.to_quaternion().rotate([1.0, 0.0, 0.0]);
+        assert!(east[0].abs() < 1e-6 && (east[1] - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn power_up_backoff_in_simulated_time() {
+        use crate::clock::{Backoff, Clock, SimulatedClock};
+        use std::sync::Arc;
+
+        // default policy: 5 attempts with 100, 200, 400 and 800ms between them
+        let clock = SimulatedClock::new();
+        let mut backoff = Backoff::new(Arc::new(clock.clone()), &PowerUpPolicy::default());
+        backoff.attempt();
+        while backoff.wait() {
+            backoff.attempt();
+        }
+        assert_eq!(backoff.attempts(), 5);
+        assert_eq!(backoff.elapsed(), Duration::from_millis(1500));
+
+        // a long backoff is capped, and the time budget stops it before the attempts run out
+        let policy = PowerUpPolicy {
+            attempts: 1000,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            max_wake_duration: Duration::from_secs(3600),
+        };
+        let start = clock.now();
+        let mut backoff = Backoff::new(Arc::new(clock.clone()), &policy);
+        backoff.attempt();
+        while backoff.wait() {
+            backoff.attempt();
+        }
+        // 1+2+4+8+16+32 = 63s, then 58 waits of 60s fit in the remaining hour
+        assert_eq!(backoff.attempts(), 65);
+        assert_eq!(clock.now() - start, Duration::from_secs(63 + 58 * 60));
+    }
+
+    #[test]
+    fn mod_info_parses_model_and_firmware() {
+        use crate::responses::{FirmwareVersion, Model};
+
+        let info = ModInfoResp::new("TP3 ".into(), "5.04".into());
+        assert_eq!(info.model, Model::TargetPoint3);
+        assert_eq!(info.firmware, Some(FirmwareVersion::new(5, 4, 0)));
+        assert!(FirmwareVersion::new(5, 4, 0) < FirmwareVersion::new(5, 10, 0));
+        assert_eq!(Model::from_device_type("TRX2"), Model::Trax2);
+        assert_eq!(FirmwareVersion::parse("beta"), None);
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn timing_stats_jitter_and_percentile() {
+        use crate::diagnostics::TimingStats;
+
+        let durations = (1..=20).map(Duration::from_millis).collect::<Vec<_>>();
+        let stats = TimingStats::from_durations(&durations).unwrap();
+        assert_eq!(stats.count, 20);
+        assert_eq!(stats.min, Duration::from_millis(1));
+        assert_eq!(stats.max, Duration::from_millis(20));
+        assert_eq!(stats.p95, Duration::from_millis(19));
+        assert!((stats.mean.as_secs_f64() - 0.0105).abs() < 1e-9);
+        // population standard deviation of 1..=20 is sqrt(33.25)
+        assert!((stats.jitter.as_secs_f64() * 1000.0 - 33.25f64.sqrt()).abs() < 1e-6);
+        assert!(TimingStats::from_durations(&[]).is_none());
+    }
+
+    #[test]
+    fn cal_report_applies_thresholds_per_method() {
+        use crate::calibration::{CalCheck, CalOption, CalReport, CalScore};
+
+        let score = CalScore {
+            mag_cal_score: 1.5,
+            accel_cal_score: 3.0,
+            distribution_error: 0.0,
+            tilt_error: 0.0,
+            tilt_range: 12.0,
+        };
+        // 1.5 is too high for full range, which also wants 30˚ of tilt
+        let full_range = CalReport::new(CalOption::FullRange, score);
+        let failed = full_range.failures().map(|check| check.check).collect::<Vec<_>>();
+        assert_eq!(failed, [CalCheck::MagCalScore, CalCheck::TiltRange]);
+
+        // but fine for limited tilt, and the accel score isn't checked for a mag-only method
+        let limited = CalReport::new(CalOption::LimitedTilt, score);
+        assert!(limited.passed());
+        assert!(limited.checks.iter().all(|check| check.check != CalCheck::AccelCalScore));
+    }
+
+    #[test]
+    fn sample_time_offset_models_fir_and_transmission() {
+        let data = Data {
+            heading: Some(10.0),
+            pitch: Some(0.0),
+            roll: Some(0.0),
+            ..Default::default()
+        };
+        // count byte, three 5-byte components and framing
+        assert_eq!(data.frame_len(), 21);
+
+        let timing = SampleTiming {
+            sample_delay: Duration::from_millis(100),
+            fir_taps: 9,
+            baud_rate: 2100,
+            acquisition_time: Duration::ZERO,
+            host_latency: Duration::from_millis(16),
+        };
+        // 210 bits at 2100 baud is 100ms, so a 200ms period and 4 periods of FIR delay
+        assert_eq!(timing.transmission_time(21), Duration::from_millis(100));
+        assert_eq!(timing.sample_time_offset(21), Duration::from_millis(800 + 100 + 16));
+
+        // sending the frame alone caps the rate at 10 Hz
+        assert_eq!(timing.max_rate_hz(21), 10.0);
+        assert_eq!(timing.sample_delay_for(5.0, 21), Some(Duration::from_millis(100)));
+        assert_eq!(timing.sample_delay_for(20.0, 21), None);
+    }
+
+    #[test]
+    fn port_gone_errors_are_recognized() {
+        use crate::connection::is_port_gone;
+        use std::io::{Error, ErrorKind};
+
+        assert!(is_port_gone(&Error::from(ErrorKind::BrokenPipe)));
+        assert!(!is_port_gone(&Error::from(ErrorKind::TimedOut)));
+        assert!(!is_port_gone(&Error::from(ErrorKind::InvalidData)));
+        #[cfg(unix)]
+        assert!(is_port_gone(&Error::from_raw_os_error(5)));
+    }
+
+    #[test]
+    fn ascii_sentences_parse_into_data() {
+        use crate::ascii::{parse_sentence, Sentences};
+
+        let data = parse_sentence("$C328.3P-2.5R0.3X27.23Y-11.60Z-43.81T22.5E000*0F\r\n").unwrap();
+        assert_eq!(data.heading, Some(328.3));
+        assert_eq!(data.pitch, Some(-2.5));
+        assert_eq!(data.mag_z, Some(-43.81));
+        assert_eq!(data.distortion, Some(false));
+        assert!(matches!(
+            parse_sentence("$C328.3P-2.5R0.3X27.23Y-11.60Z-43.81T22.5E000*10"),
+            Err(ReadError::ChecksumMismatch { .. })
+        ));
+        assert!(parse_sentence("C328.3").is_err());
+
+        let log = "TCM banner\n$C10.0P1.0R2.0E001\n$C11.0Q9\n";
+        let samples = Sentences::new(log.as_bytes()).collect::<Vec<_>>();
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[0].as_ref().unwrap().distortion, Some(true));
+        assert_eq!(samples[1].as_ref().unwrap().heading, Some(11.0));
+    }
+
+    #[test]
+    fn device_over_tcp_transport() {
+        use crate::transport::{TcpTransport, Transport};
+        use std::io::{Read, Write};
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0u8; 5];
+            stream.read_exact(&mut request).unwrap();
+            assert_eq!(request, [0x00, 0x05, 0x34, 0x89, 0x22]);
+            stream.write_all(&[0x00, 0x09, 0x35, 0x00, 0x12, 0xd6, 0x87, 0x14, 0xb6]).unwrap();
+            // hold the connection open so the next read times out rather than ending
+            let _ = stream.read(&mut request);
+        });
+
+        let mut transport = TcpTransport::connect(addr).unwrap();
+        transport.set_timeout(Duration::from_millis(50)).unwrap();
+        let mut device = Device::from_transport(transport);
+        assert_eq!(device.serial_number().unwrap(), 1234567);
+        match device.read_frame() {
+            Err(ReadError::PipeError(e)) => assert_eq!(e.kind(), std::io::ErrorKind::TimedOut),
+            other => panic!("expected a timeout, got {:?}", other.map(|frame| frame.command())),
+        }
+        drop(device);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn torn_frame_leaves_parser_on_a_boundary() {
+        use crate::transport::Transport;
+        use std::collections::VecDeque;
+        use std::io::{self, Read, Write};
+
+        /// Hands out one chunk per read; an empty chunk is a read that times out
+        struct Chunks(VecDeque<Vec<u8>>);
+
+        impl Read for Chunks {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                let mut chunk = self.0.pop_front().unwrap_or_default();
+                if chunk.is_empty() {
+                    return Err(io::ErrorKind::TimedOut.into());
+                }
+                let len = chunk.len().min(buf.len());
+                buf[..len].copy_from_slice(&chunk[..len]);
+                if len < chunk.len() {
+                    self.0.push_front(chunk.split_off(len));
+                }
+                Ok(len)
+            }
+        }
+
+        impl Write for Chunks {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        impl Transport for Chunks {
+            fn timeout(&self) -> Duration {
+                Duration::ZERO
+            }
+
+            fn set_timeout(&mut self, _: Duration) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let frame = [0x00, 0x09, 0x35, 0x00, 0x12, 0xd6, 0x87, 0x14, 0xb6];
+        let mut device = Device::from_transport(Chunks(VecDeque::from([
+            frame[..4].to_vec(),
+            vec![],
+            frame.to_vec(),
+            frame[..6].to_vec(),
+            vec![],
+            frame.to_vec(),
+        ])));
+        assert!(device.read_frame().is_err());
+        assert_eq!(device.read_frame().unwrap().command(), 0x35);
+        assert!(device.serial_number().is_err());
+        assert_eq!(device.read_frame().unwrap().command(), 0x35);
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn cancel_token_ends_a_blocking_read() {
+        use crate::connection::is_cancelled;
+        use crate::transport::Transport;
+        use std::io::{self, Read, Write};
+        use std::sync::{Arc, Mutex};
+        use std::time::Instant;
+
+        /// A line that never sends anything: each read blocks for the whole timeout. Keeps the
+        /// longest wait it was asked for
+        struct Silent {
+            timeout: Duration,
+            longest_wait: Arc<Mutex<Duration>>,
+        }
+
+        impl Read for Silent {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                let mut longest_wait = self.longest_wait.lock().unwrap();
+                *longest_wait = (*longest_wait).max(self.timeout);
+                drop(longest_wait);
+                std::thread::sleep(self.timeout);
+                Err(io::ErrorKind::TimedOut.into())
+            }
+        }
+
+        impl Write for Silent {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        impl Transport for Silent {
+            fn timeout(&self) -> Duration {
+                self.timeout
+            }
+
+            fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+                self.timeout = timeout;
+                Ok(())
+            }
+        }
+
+        let longest_wait = Arc::new(Mutex::new(Duration::ZERO));
+        let mut device = Device::from_transport(Silent {
+            timeout: Duration::from_secs(10),
+            longest_wait: longest_wait.clone(),
+        });
+        let token = device.cancel_token();
+        let started = Instant::now();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            token.cancel();
+        });
+
+        match device.read_frame() {
+            Err(ReadError::PipeError(e)) => assert!(is_cancelled(&e), "{}", e),
+            other => panic!("expected a cancelled read, got {:?}", other),
+        }
+        // the port is only ever waited on for a poll interval at a time
+        assert_eq!(*longest_wait.lock().unwrap(), Duration::from_millis(50));
+        assert!(started.elapsed() < Duration::from_secs(1), "took {:?}", started.elapsed());
+        assert_eq!(device.transport.timeout(), Duration::from_secs(10));
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn capabilities_follow_the_identified_device() {
+        use crate::capabilities::{Feature, SupportLevel};
+        use crate::constants::MAX_FIR_TAPS;
+        use crate::mock::MockTransport;
+        use crate::responses::Model;
+
+        // nothing is known, or refused, before the device is identified
+        let mock = MockTransport::target_point3();
+        let mut device = Device::from_transport(mock.clone());
+        assert_eq!(device.capabilities().model, None);
+        assert_eq!(device.capabilities().gyro, SupportLevel::Unknown);
+        assert_eq!(device.supports(Feature::Gyro), SupportLevel::Unknown);
+
+        device.get_mod_info().unwrap();
+        let capabilities = device.capabilities();
+        assert_eq!(capabilities.model, Some(Model::TargetPoint3));
+        assert_eq!(capabilities.firmware.unwrap().to_string(), "5.4.0");
+        assert_eq!(capabilities.gyro, SupportLevel::KnownNo);
+        assert_eq!(capabilities.multiple_coeff_sets, SupportLevel::KnownYes);
+        assert_eq!(capabilities.fir_filters, SupportLevel::KnownYes);
+        assert_eq!(capabilities.max_fir_taps, Some(MAX_FIR_TAPS));
+        assert_eq!(device.supports(Feature::ContinuousMode), SupportLevel::KnownYes);
+
+        // a known gap fails before anything is sent
+        let sent = mock.commands();
+        match device.set_data_components(vec![DataID::Heading, DataID::GyroX]) {
+            Err(RWError::UnsupportedByDevice { feature, device }) => {
+                assert_eq!(feature, Feature::Gyro);
+                assert_eq!(device, "TP3 5.04");
+            }
+            other => panic!("expected UnsupportedByDevice, got {:?}", other),
+        }
+        assert_eq!(mock.commands(), sent);
+
+        // features neither column lists stay unknown, and aren't refused
+        let mock = MockTransport::new()
+            .respond(Command::GetModInfo, Command::GetModInfoResp, b"TRX22.01")
+            .respond(Command::SetFIRFilters, Command::SetFIRFiltersDone, &[]);
+        let mut device = Device::from_transport(mock);
+        device.get_mod_info().unwrap();
+        assert_eq!(device.capabilities().model, Some(Model::Trax2));
+        assert_eq!(device.supports(Feature::Gyro), SupportLevel::KnownYes);
+        assert_eq!(device.supports(Feature::FirFilters), SupportLevel::Unknown);
+        device.set_fir_filters(vec![1.0]).unwrap();
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn trailing_bytes_only_accepted_when_lenient() {
+        use crate::command::Command;
+
+        // a newer firmware appending two bytes to SerialNumberResp
+        let extended = || {
+            crate::mock::MockTransport::new().respond(
+                Command::SerialNumber,
+                Command::SerialNumberResp,
+                &[0, 0, 0x30, 0x39, 0xaa, 0xbb],
+            )
+        };
+
+        let mut strict = Device::from_transport(extended());
+        assert!(matches!(
+            strict.serial_number(),
+            Err(RWError::ReadError(ReadError::SizeMismatch { .. }))
+        ));
+        assert!(strict.trailing_bytes().is_none());
+
+        let mut lenient = Device::from_transport(extended());
+        lenient.set_lenient_trailing_bytes(true);
+        assert_eq!(lenient.serial_number().unwrap(), 12345);
+        let trailing = lenient.trailing_bytes().unwrap();
+        assert_eq!(trailing.command, u8::from(Command::SerialNumberResp));
+        assert_eq!(trailing.bytes, [0xaa, 0xbb]);
+        assert_eq!(trailing.frames, 1);
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn checksum_errors_skipped_within_tolerance() {
+        let mock = crate::mock::MockTransport::target_point3().value(DataID::Heading, 12.5);
+        let mut device = Device::from_transport(mock.clone());
+        device.set_data_components(vec![DataID::Heading]).unwrap();
+        device.set_checksum_tolerance(2);
+        device.start_continuous_mode().unwrap();
+
+        mock.corrupt_samples(2);
+        let data = device.iter().next().unwrap().unwrap();
+        assert_eq!(data.heading, Some(12.5));
+        let stats = device.stats();
+        assert_eq!((stats.checksum_errors, stats.dropped_frames), (2, 2));
+
+        mock.corrupt_samples(3);
+        assert!(matches!(
+            device.iter().next(),
+            Some(Err(ReadError::ChecksumMismatch { .. }))
+        ));
+        let stats = device.stats();
+        assert_eq!((stats.checksum_errors, stats.dropped_frames), (5, 4));
+        assert!(device.iter().next().unwrap().is_ok(), "The stream carries on after the error");
+
+        device.set_checksum_tolerance(0);
+        mock.corrupt_samples(1);
+        assert!(matches!(
+            device.iter().next(),
+            Some(Err(ReadError::ChecksumMismatch { .. }))
+        ));
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn commands_refused_while_streaming() {
+        use crate::config::ConfigID;
+        use crate::mock::MockTransport;
+
+        let mock = MockTransport::target_point3();
+        let mut device = Device::from_transport(mock.clone());
+        device.start_continuous_mode().unwrap();
+        let sent = mock.commands();
+
+        // nothing is written for commands that expect a response, nor for ones that don't
+        let result = device.get_config(ConfigID::Declination);
+        assert!(matches!(result, Err(RWError::Streaming { command: Command::GetConfig })));
+        let result = device.set_data_components(vec![DataID::Heading]);
+        assert!(matches!(
+            result,
+            Err(RWError::Streaming {
+                command: Command::SetDataComponents
+            })
+        ));
+        assert_eq!(mock.commands(), sent);
+
+        device.stop_continuous_mode().unwrap();
+        assert_eq!(mock.commands().last(), Some(&Command::StopContinuousMode));
+        assert!(!mock.is_streaming());
+    }
+
+    #[cfg(all(unix, feature = "unstable"))]
+    #[test]
+    fn change_baud_reopens_the_port() {
+        use crate::config::{Baud, ConfigID, ConfigPair};
+        use crate::mock::MockTransport;
+        use crate::simulator::{Simulator, SimulatorConfig};
+        use serialport::{SerialPort, TTYPort};
+
+        // a link without a port path is refused before anything is sent
+        let mock = MockTransport::target_point3();
+        assert!(Device::from_transport(mock.clone()).change_baud(Baud::B115200).is_err());
+        assert!(mock.commands().is_empty());
+
+        let (master, terminal) = TTYPort::pair().unwrap();
+        let path = terminal.name().unwrap();
+        std::thread::spawn(move || {
+            let mut port: Box<dyn SerialPort> = Box::new(master);
+            let _ = Simulator::new(SimulatorConfig::default()).serve(&mut port);
+        });
+
+        let device = Device::connect(Some(path.clone())).unwrap();
+        let mut device = device.change_baud(Baud::B115200).unwrap();
+        assert_eq!(device.port_name(), Some(path));
+        assert_eq!(device.transport.baud_rate().unwrap(), 115200);
+        assert_eq!(
+            device.get_config(ConfigID::BaudRate).unwrap(),
+            ConfigPair::BaudRate(Baud::B115200)
+        );
+        assert!(!device.has_unsaved_changes());
+        drop(terminal);
+    }
+
+    #[cfg(all(unix, feature = "unstable"))]
+    #[test]
+    fn connect_probe_finds_a_non_default_baud() {
+        use crate::simulator::{Simulator, SimulatorConfig};
+        use serialport::{SerialPort, TTYPort};
+        use std::io::{Read, Write};
+
+        // a pseudo-terminal doesn't garble mismatched rates, so the simulator side ignores
+        // anything sent while the terminal isn't at 2400 baud
+        let (mut master, terminal) = TTYPort::pair().unwrap();
+        let path = terminal.name().unwrap();
+        std::thread::spawn(move || {
+            let mut simulator = Simulator::new(SimulatorConfig::default());
+            let mut buf = [0u8; 256];
+            master.set_timeout(Duration::from_millis(10)).unwrap();
+            loop {
+                match master.read(&mut buf) {
+                    Ok(0) => return,
+                    Ok(len) if master.baud_rate().ok() == Some(2400) => {
+                        simulator.receive(&buf[..len])
+                    }
+                    Ok(_) => {}
+                    Err(e) if e.kind() == std::io::ErrorKind::TimedOut => {}
+                    Err(_) => return,
+                }
+                let output = simulator.output();
+                if !output.is_empty() && master.write_all(&output).is_err() {
+                    return;
+                }
+            }
+        });
+
+        let mut device = Device::connect_probe(Some(path)).unwrap();
+        assert_eq!(device.baud_rate(), Some(2400));
+        assert_eq!(device.transport.timeout(), Duration::from_secs(1));
+        assert_eq!(device.serial_number().unwrap(), SimulatorConfig::default().serial_number);
+        drop(terminal);
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn simulator_behaves_like_a_target_point3() {
+        use crate::calibration::{CalOption, UserCalResponse};
+        use crate::config::{ConfigID, ConfigPair};
+        use crate::processing::Orientation;
+        use crate::simulator::{Simulator, SimulatorConfig};
+
+        let config = SimulatorConfig {
+            orientation: Orientation {
+                heading: 123.0,
+                pitch: 10.0,
+                roll: -5.0,
+            },
+            noise: 0.5,
+            ..Default::default()
+        };
+        let mut device = Device::from_transport(Simulator::new(config));
+        assert_eq!(device.serial_number().unwrap(), 1234567);
+
+        device.set_data_components(vec![DataID::Heading, DataID::AccelX, DataID::MagY]).unwrap();
+        let data = device.get_data().unwrap();
+        assert!((data.heading.unwrap() - 123.0).abs() < 3.0, "{:?}", data);
+        // nose up reads as negative x
+        assert!(data.accel_x.unwrap() < -0.1, "{:?}", data);
+
+        // settings are volatile until saved, and a power cycle brings back the saved ones
+        device.set_config(ConfigPair::UserCalNumPoints(4)).unwrap();
+        device.save().unwrap();
+        device.set_config(ConfigPair::UserCalNumPoints(8)).unwrap();
+        let mut device = device.power_down().unwrap().wake().unwrap();
+        let points = device.get_config(ConfigID::UserCalNumPoints).unwrap();
+        assert_eq!(points, ConfigPair::UserCalNumPoints(4));
+
+        device.set_config(ConfigPair::UserCalAutoSampling(false)).unwrap();
+        assert_eq!(device.start_cal(CalOption::FullRange).unwrap(), 0);
+        for count in 1..4 {
+            match device.take_user_cal_sample().unwrap() {
+                UserCalResponse::SampleCount(taken) => assert_eq!(taken, count),
+                UserCalResponse::UserCalScore { .. } => panic!("score after {} points", count),
+            }
+        }
+        let score = device.take_user_cal_sample().unwrap().score().unwrap();
+        assert_eq!(score.tilt_range, 35.0);
+
+        // 50 ms SampleDelay plus acquisition and sending comes to about 12 samples a second
+        device.start_continuous_mode_live(0.05, vec![DataID::Heading]).unwrap();
+        let started = std::time::Instant::now();
+        let samples = device.iter().take(6).collect::<Result<Vec<_>, _>>().unwrap();
+        let elapsed = started.elapsed();
+        assert!(samples.iter().all(|data| data.heading.is_some()));
+        assert!(elapsed > Duration::from_millis(300) && elapsed < Duration::from_secs(2));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn async_device_speaks_the_golden_captures() {
+        use crate::async_device::AsyncDevice;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // the device's side of a capture is queued up front; the host's is checked afterwards
+        fn sides(capture: &str) -> (Vec<u8>, Vec<u8>) {
+            let (mut host, mut device) = (Vec::new(), Vec::new());
+            for line in capture.lines() {
+                let side = match line.chars().next() {
+                    Some('>') => &mut host,
+                    Some('<') => &mut device,
+                    _ => continue,
+                };
+                let bytes = line[1..].split_whitespace();
+                side.extend(bytes.map(|b| u8::from_str_radix(b, 16).unwrap()));
+            }
+            (host, device)
+        }
+
+        let (host, device) = sides(concat!(
+            include_str!("../tests/fixtures/mod_info.hex"),
+            include_str!("../tests/fixtures/get_data.hex"),
+        ));
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap();
+        runtime.block_on(async {
+            let (ours, mut theirs) = tokio::io::duplex(1024);
+            theirs.write_all(&device).await.unwrap();
+            let mut async_device = AsyncDevice::new(ours);
+            let info = async_device.get_mod_info().await.unwrap();
+            assert_eq!(info.firmware.unwrap().to_string(), "5.4.0");
+            let components = vec![DataID::Heading, DataID::Pitch, DataID::Roll, DataID::Distortion];
+            async_device.set_data_components(components).await.unwrap();
+            let data = async_device.get_data().await.unwrap();
+            assert_eq!(data.heading, Some(123.5));
+            assert_eq!(data.distortion, Some(false));
+            drop(async_device);
+            let mut written = Vec::new();
+            theirs.read_to_end(&mut written).await.unwrap();
+            assert_eq!(written, host);
+        });
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn async_device_resumes_torn_frames() {
+        use crate::async_device::AsyncDevice;
+        use tokio::io::AsyncWriteExt;
+
+        let frame = [0x00, 0x09, 0x35, 0x00, 0x12, 0xd6, 0x87, 0x14, 0xb6];
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap();
+        runtime.block_on(async {
+            let (ours, mut theirs) = tokio::io::duplex(1024);
+            let mut device = AsyncDevice::new(ours);
+            device.set_timeout(Duration::from_millis(20));
+
+            // timing out part way through keeps the bytes that did arrive
+            theirs.write_all(&frame[..5]).await.unwrap();
+            match device.read_frame().await {
+                Err(ReadError::PipeError(e)) => assert_eq!(e.kind(), std::io::ErrorKind::TimedOut),
+                other => panic!("expected a timeout, got {:?}", other),
+            }
+            theirs.write_all(&frame[5..]).await.unwrap();
+            assert_eq!(device.read_frame().await.unwrap(), frame);
+
+            // a bad length fails once, then the reader finds the next frame
+            theirs.write_all(&[0xff, 0xff, 0x01]).await.unwrap();
+            theirs.write_all(&frame).await.unwrap();
+            assert!(device.read_frame().await.is_err());
+            assert_eq!(device.read_frame().await.unwrap(), frame);
+
+            // as does a bad checksum
+            let mut corrupt = frame;
+            corrupt[4] ^= 0xff;
+            theirs.write_all(&corrupt).await.unwrap();
+            theirs.write_all(&frame).await.unwrap();
+            assert!(matches!(
+                device.read_frame().await,
+                Err(ReadError::ChecksumMismatch { .. })
+            ));
+            assert_eq!(device.read_frame().await.unwrap(), frame);
+        });
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn polled_samples_are_stamped_on_the_device_clock() {
+        use crate::clock::{Clock, SimulatedClock};
+        use crate::mock::MockTransport;
+        use std::sync::Arc;
+
+        let clock = SimulatedClock::new();
+        clock.advance(Duration::from_secs(60));
+        let mock = MockTransport::target_point3().value(DataID::Heading, 90.0);
+        let mut device = Device::from_transport(mock);
+        device.set_clock(Arc::new(clock.clone()));
+        device.set_data_components(vec![DataID::Heading]).unwrap();
+
+        let sample = device.get_data_timestamped().unwrap();
+        assert_eq!(sample.data.heading, Some(90.0));
+        assert_eq!(sample.received_at, clock.now());
+        assert!(sample.estimated_sample_time.is_none());
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn reconnect_reopens_a_lost_port() {
+        use crate::clock::{Clock, SimulatedClock};
+        use crate::connection::ReconnectPolicy;
+        use crate::mock::MockTransport;
+        use std::sync::Arc;
+
+        let clock = SimulatedClock::new();
+        let mock = MockTransport::target_point3().value(DataID::Heading, 90.0);
+        let mut device = Device::from_transport(mock.clone());
+        device.set_clock(Arc::new(clock.clone()));
+        device.set_reconnect_policy(Some(ReconnectPolicy {
+            attempts: 3,
+            ..Default::default()
+        }));
+        device.set_data_components(vec![DataID::Heading]).unwrap();
+        device.start_continuous_mode().unwrap();
+        assert_eq!(device.iter().next().unwrap().unwrap().heading, Some(90.0));
+
+        mock.unplug();
+        assert!(device.iter().next().unwrap().is_err());
+        assert!(device.is_port_lost());
+
+        // still unplugged: each attempt fails, backing off on the device's clock
+        let start = clock.now();
+        assert!(device.reconnect().is_err());
+        assert_eq!(clock.now() - start, Duration::from_millis(250 + 500));
+
+        mock.plug_in();
+        device.reconnect().unwrap();
+        assert!(!device.is_port_lost());
+        assert_eq!(mock.commands().last(), Some(&Command::StartContinuousMode));
+        assert_eq!(device.iter().next().unwrap().unwrap().heading, Some(90.0));
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn reopen_by_serial_number_leaves_other_units_alone() {
+        use crate::mock::{MockTransport, MOCK_SERIAL_NUMBER};
+        use crate::transport::Transport;
+
+        let candidates = |ours: &MockTransport, theirs: &MockTransport| {
+            [("/dev/ttyUSB0", theirs), ("/dev/ttyUSB1", ours)]
+                .map(|(name, mock)| {
+                    (name.to_string(), Box::new(mock.clone()) as Box<dyn Transport>)
+                })
+                .into_iter()
+        };
+        let other_unit = || {
+            MockTransport::new().respond(
+                Command::SerialNumber,
+                Command::SerialNumberResp,
+                &7u32.to_be_bytes(),
+            )
+        };
+
+        let lost = MockTransport::target_point3();
+        let mut device = Device::from_transport(lost.clone());
+        device.set_data_components(vec![DataID::Heading]).unwrap();
+
+        let (ours, theirs) = (MockTransport::target_point3(), other_unit());
+        device.reopen_serial_number(MOCK_SERIAL_NUMBER, candidates(&ours, &theirs)).unwrap();
+        assert!(!device.is_port_lost());
+        assert_eq!(theirs.commands(), [Command::SerialNumber, Command::SerialNumber]);
+        assert_eq!(ours.commands().last(), Some(&Command::SetDataComponents));
+        assert_eq!(device.serial_number().unwrap(), MOCK_SERIAL_NUMBER);
+
+        // nobody answers as ours: the port stays lost rather than reading from another unit
+        let (ours, theirs) = (other_unit(), other_unit());
+        let candidates = candidates(&ours, &theirs);
+        assert!(device.reopen_serial_number(MOCK_SERIAL_NUMBER, candidates).is_err());
+        assert!(device.is_port_lost());
+        assert!(!theirs.commands().contains(&Command::SetDataComponents));
+        assert!(!ours.commands().contains(&Command::SetDataComponents));
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn split_writer_writes_like_the_device() {
+        use crate::connection::WriteStrategy;
+        use crate::mock::MockTransport;
+
+        let mock = MockTransport::target_point3();
+        let (reader, mut writer) = Device::from_transport(mock.clone()).split().unwrap();
+        writer.start_continuous_mode().unwrap();
+        assert_eq!(mock.flushes(), 1);
+        let mut device = Device::unsplit(reader, writer);
+
+        device.set_write_strategy(WriteStrategy::Batched);
+        let (reader, mut writer) = device.split().unwrap();
+        writer.stop_continuous_mode().unwrap();
+        assert_eq!(mock.flushes(), 1);
+        writer.flush().unwrap();
+        assert_eq!(mock.flushes(), 2);
+
+        // a lost port stops the writer, and the device finds out when the halves are rejoined
+        mock.unplug();
+        assert!(matches!(writer.request_data(), Err(WriteError::PipeError(_))));
+        let sent = mock.commands().len();
+        match writer.request_data() {
+            Err(WriteError::PipeError(e)) => assert_eq!(e.kind(), std::io::ErrorKind::NotConnected),
+            other => panic!("expected NotConnected, got {:?}", other),
+        }
+        assert_eq!(mock.commands().len(), sent);
+        let mut device = Device::unsplit(reader, writer);
+        assert!(device.write_frame(Command::GetData, None).is_err());
+        assert!(matches!(device.connection_state(), ConnectionEvent::Degraded { .. }));
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn auto_cal_restores_auto_sampling() {
+        use crate::calibration::{CalOption, CalProgress};
+        use crate::catalog::MessageId;
+        use crate::config::ConfigID;
+        use crate::mock::MockTransport;
+
+        let auto_sampling = [ConfigID::UserCalAutoSampling as u8, 0];
+        let calibrating = || {
+            MockTransport::target_point3()
+                .respond(Command::GetConfig, Command::GetConfigResp, &[0])
+                .respond(Command::StartCal, Command::UserCalSampleCount, &0u32.to_be_bytes())
+        };
+
+        // finishing with a score sets auto sampling back without stopping anything
+        let score: Vec<u8> = [0.5f32, 0.0, 0.25, 0.0, 0.0, 35.0]
+            .iter()
+            .flat_map(|value| value.to_be_bytes())
+            .collect();
+        let mock = calibrating()
+            .respond(Command::StartCal, Command::UserCalSampleCount, &1u32.to_be_bytes())
+            .respond(Command::StartCal, Command::UserCalScore, &score);
+        let mut device = Device::from_transport(mock.clone());
+        let mut cal = device.start_auto_cal(CalOption::FullRange).unwrap();
+        assert!(matches!(cal.next(), Some(Ok(CalProgress::Sample(1)))));
+        match cal.next() {
+            Some(Ok(CalProgress::Score(score))) => assert_eq!(score.tilt_range, 35.0),
+            other => panic!("expected a score, got {:?}", other),
+        }
+        assert!(cal.next().is_none());
+        drop(cal);
+        assert_eq!(mock.last_payload(Command::SetConfig).unwrap(), auto_sampling);
+        assert!(!mock.commands().contains(&Command::StopCal));
+
+        // an idle device is stopped once reads have waited out the idle timeout
+        let mock = calibrating();
+        let mut device = Device::from_transport(mock.clone());
+        let mut cal = device
+            .start_auto_cal(CalOption::FullRange)
+            .unwrap()
+            .idle_timeout(Duration::from_secs(3));
+        match cal.next() {
+            Some(Err(ReadError::ParseError(message))) => {
+                assert_eq!(message.id, MessageId::CalIdleTimeout);
+                assert_eq!(message.param("idle"), Some("3s"));
+            }
+            other => panic!("expected an idle timeout, got {:?}", other),
+        }
+        drop(cal);
+        assert_eq!(mock.commands().iter().filter(|c| **c == Command::StopCal).count(), 1);
+        assert_eq!(mock.commands().last(), Some(&Command::SetConfig));
+        assert_eq!(mock.last_payload(Command::SetConfig).unwrap(), auto_sampling);
+
+        // as is one whose calibration is dropped part way
+        let mock = calibrating();
+        let mut device = Device::from_transport(mock.clone());
+        drop(device.start_auto_cal(CalOption::FullRange).unwrap());
+        let sent = mock.commands();
+        assert_eq!(sent[sent.len() - 2..], [Command::StopCal, Command::SetConfig]);
+        assert_eq!(mock.last_payload(Command::SetConfig).unwrap(), auto_sampling);
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn events_report_unsolicited_frames() {
+        use crate::calibration::UserCalResponse;
+        use crate::command::Command;
+        use crate::dispatch::{DeviceEvent, Response};
+
+        let score: Vec<u8> = [0.5f32, 0.0, 0.25, 0.0, 0.0, 35.0]
+            .iter()
+            .flat_map(|value| value.to_be_bytes())
+            .collect();
+        let mock = crate::mock::MockTransport::new()
+            .respond(Command::StartCal, Command::UserCalSampleCount, &1u32.to_be_bytes())
+            .respond(Command::StartCal, Command::UserCalScore, &score)
+            .respond(Command::StartCal, Command::PowerUpDone, &[]);
+        let mut device = Device::from_transport(mock);
+        device.queue_unsolicited(Response::SerialNumber(42));
+        device.write_frame(Command::StartCal, Some(&[0, 0, 0, 10])).unwrap();
+
+        let events: Vec<_> = device.events().map(Result::unwrap).collect();
+        assert!(
+            matches!(events[0], DeviceEvent::Response(Response::SerialNumber(42))),
+            "Queued responses should come first"
+        );
+        assert!(matches!(events[1], DeviceEvent::CalSample(1)));
+        match &events[2] {
+            DeviceEvent::CalScore(UserCalResponse::UserCalScore {
+                mag_cal_score,
+                tilt_range,
+                ..
+            }) => assert_eq!((*mag_cal_score, *tilt_range), (0.5, 35.0)),
+            other => panic!("expected a score, got {:?}", other),
+        }
+        assert!(matches!(events[3], DeviceEvent::PowerUp));
+        assert_eq!(events.len(), 4);
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn fir_filter_payloads_name_the_filter() {
+        use crate::calibration::AxisSelector;
+        use crate::catalog::MessageId;
+        use crate::mock::MockTransport;
+
+        let mock = MockTransport::target_point3().respond(
+            Command::GetFIRFilters,
+            Command::GetFIRFiltersResp,
+            &[3, 1, 1, 0x3f, 0xf0, 0, 0, 0, 0, 0, 0],
+        );
+        let mut device = Device::from_transport(mock.clone());
+        device.set_fir_filters(vec![0.5]).unwrap();
+        assert_eq!(
+            mock.last_payload(Command::SetFIRFilters).unwrap(),
+            [3, 1, 1, 0x3f, 0xe0, 0, 0, 0, 0, 0, 0]
+        );
+
+        assert_eq!(device.get_fir_filters().unwrap(), [1.0]);
+        assert_eq!(mock.last_payload(Command::GetFIRFilters).unwrap(), [3, 1]);
+
+        // an answer for another filter isn't taken for the one asked for
+        let result = device.get_fir_filters_for(AxisSelector::Raw(3, 2));
+        assert_eq!(mock.last_payload(Command::GetFIRFilters).unwrap(), [3, 2]);
+        match result {
+            Err(RWError::DeviceError(message)) => {
+                assert_eq!(message.id, MessageId::FirAxisMismatch)
+            }
+            other => panic!("expected a mismatch, got {:?}", other),
+        }
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn copy_coeff_set_packs_source_and_destination() {
+        use crate::calibration::CoeffSetKind;
+        use crate::command::Command;
+
+        let mock = crate::mock::MockTransport::target_point3();
+        let mut device = Device::from_transport(mock.clone());
+        device.copy_coeff_set(CoeffSetKind::Accel, 2, 5).unwrap();
+        assert_eq!(mock.last_payload(Command::CopyCoeffSet), Some(vec![1, 0x25]));
+        device.copy_coeff_set(CoeffSetKind::Mag, 7, 0).unwrap();
+        assert_eq!(mock.last_payload(Command::CopyCoeffSet), Some(vec![0, 0x70]));
+
+        assert!(matches!(
+            device.copy_coeff_set(CoeffSetKind::Mag, 0, 8),
+            Err(RWError::InvalidArgument(_))
+        ));
+        assert_eq!(mock.last_payload(Command::CopyCoeffSet), Some(vec![0, 0x70]));
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn golden_captures_replay_through_device() {
+        use crate::calibration::AxisSelector;
+        use crate::config::ConfigPair;
+        use crate::replay::ReplayPort;
+        use crate::responses::Model;
+
+        fn replay(capture: &str, run: impl FnOnce(&mut Device)) {
+            let port = ReplayPort::from_hex(capture).unwrap();
+            let mut device = Device::new(port.clone());
+            run(&mut device);
+            assert!(port.is_finished(), "capture not played to the end:\n{}", capture);
+        }
+
+        replay(include_str!("../tests/fixtures/mod_info.hex"), |device| {
+            let info = device.get_mod_info().unwrap();
+            assert_eq!(info.model, Model::TargetPoint3);
+            assert_eq!(info.firmware.unwrap().to_string(), "5.4.0");
+        });
+        replay(include_str!("../tests/fixtures/serial_number.hex"), |device| {
+            assert_eq!(device.serial_number().unwrap(), 1234567);
+        });
+        replay(include_str!("../tests/fixtures/factory_coeffs.hex"), |device| {
+            device.factory_mag_coeff().unwrap();
+            device.factory_accel_coeff().unwrap();
+        });
+        replay(include_str!("../tests/fixtures/get_data.hex"), |device| {
+            let components = vec![DataID::Heading, DataID::Pitch, DataID::Roll, DataID::Distortion];
+            device.set_data_components(components).unwrap();
+            let data = device.get_data().unwrap();
+            assert_eq!(data.heading, Some(123.5));
+            assert_eq!(data.pitch, Some(1.5));
+            assert_eq!(data.roll, Some(-2.25));
+            assert_eq!(data.distortion, Some(false));
+        });
+        replay(include_str!("../tests/fixtures/config_and_save.hex"), |device| {
+            device.set_config(ConfigPair::Declination(10.5)).unwrap();
+            device.save().unwrap();
+            assert!(!device.has_unsaved_changes());
+        });
+        replay(include_str!("../tests/fixtures/acq_params.hex"), |device| {
+            let params = device.get_acq_params().unwrap();
+            assert!(params.acquisition_mode);
+            assert!(!params.flush_filter);
+            assert_eq!(params.sample_delay, 0.25);
+        });
+        replay(include_str!("../tests/fixtures/fir_filters.hex"), |device| {
+            let taps = device.get_fir_filters_for(AxisSelector::Mag).unwrap();
+            assert_eq!(taps, [0.1, 0.4, 0.4, 0.1]);
+        });
+
+        // a command method sending the wrong frame is caught, not answered
+        let capture = include_str!("../tests/fixtures/serial_number.hex");
+        let mut device = Device::new(ReplayPort::from_hex(capture).unwrap());
+        assert!(device.get_mod_info().is_err());
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn explain_splits_capture_into_frames() {
+        use crate::codec::encode_frame;
+        use crate::decode::{explain, hex, CrcStatus};
+
+        let mut capture = vec![0xAA];
+        let mut frame = [0u8; 16];
+        let len = encode_frame(u8::from(Command::GetModInfo), &[], &mut frame).unwrap();
+        assert_eq!(hex(&frame[..len]), "00 05 01 ef d4");
+        capture.extend_from_slice(&frame[..len]);
+        let len = encode_frame(u8::from(Command::SerialNumberResp), &[0, 0, 0, 42], &mut frame)
+            .unwrap();
+        capture.extend_from_slice(&frame[..len]);
+        frame[len - 1] ^= 0xFF;
+        capture.extend_from_slice(&frame[..len]);
+        capture.extend_from_slice(&frame[..4]);
+
+        let frames = explain(&capture);
+        let crcs = frames.iter().map(|frame| frame.crc).collect::<Vec<_>>();
+        assert_eq!(crcs[0], CrcStatus::NotAFrame);
+        assert_eq!(crcs[1..3], [CrcStatus::Valid, CrcStatus::Valid]);
+        assert!(matches!(crcs[3], CrcStatus::Invalid { .. }));
+        assert_eq!(crcs[4], CrcStatus::Truncated);
+        assert_eq!(frames[1].name, "GetModInfo");
+        assert_eq!(frames[2].fields, vec![("serial_number", "42".to_string())]);
+    }
+
+    #[test]
+    fn parsers_reject_hostile_lengths() {
+        use crate::codec::encode_frame;
+        use crate::dispatch::{parse_frame, Response};
+
+        // length fields below the overhead, beyond the maximum, and beyond the input
+        assert!(parse_frame(&[0x00, 0x02, 0x05]).is_err());
+        assert!(parse_frame(&[0xFF, 0xFF, 0x05, 0x00, 0x00]).is_err());
+        assert!(parse_frame(&[0x00, 0x40, 0x05, 0x01]).is_err());
+
+        // a component count promising more fields than the payload holds
+        assert!(parse_data_payload(&[0xFF, 0x05, 0, 0, 0, 0]).is_err());
+        assert!(parse_data_payload(&[0x01, 0x05, 0, 0, 0, 0, 0xAA]).is_err());
+        let data = parse_data_payload(&[0x01, 0x05, 0x42, 0xC8, 0, 0]).unwrap();
+        assert_eq!(data.heading, Some(100.0));
+
+        let mut frame = [0u8; 16];
+        let len = encode_frame(u8::from(Command::SerialNumberResp), &[0, 0, 0, 7], &mut frame)
+            .unwrap();
+        let mut bytes = frame[..len].to_vec();
+        bytes.push(0xEE);
+        assert!(matches!(parse_frame(&bytes), Ok((Response::SerialNumber(7), 9))));
+    }
+
+    #[test]
+    fn builder_reports_missing_port() {
+        use crate::connection::Profile;
+        use crate::responses::Model;
+
+        assert_eq!(Profile::Auto.model(), None);
+        assert_eq!(Profile::Trax2.model(), Some(Model::Trax2));
+
+        let clock = crate::clock::SimulatedClock::new();
+        let start = crate::clock::Clock::now(&clock);
+        let result = Device::builder()
+            .port("/dev/pni-sdk-no-such-port")
+            .baud(crate::config::Baud::B115200)
+            .profile(Profile::TargetPoint3)
+            .retries(3)
+            .clock(std::sync::Arc::new(clock.clone()))
+            .open();
+        assert!(result.is_err());
+        // three retries, 100ms, 200ms and 400ms apart
+        let waited = crate::clock::Clock::now(&clock) - start;
+        assert_eq!(waited, Duration::from_millis(700));
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn watchdog_escalates_in_simulated_time() {
+        use crate::clock::SimulatedClock;
+        use crate::health::{Recovery, StallDetector};
+        use std::sync::Arc;
+
+        let clock = SimulatedClock::new();
+        let mut detector = StallDetector::new(Arc::new(clock.clone()), Duration::from_secs(5));
+        clock.advance(Duration::from_secs(4));
+        assert_eq!(detector.check(), None);
+
+        // resync first, then reconnect once per further stall_timeout
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(detector.check(), Some(Recovery::Resync));
+        clock.advance(Duration::from_secs(3));
+        assert_eq!(detector.check(), None);
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(detector.check(), Some(Recovery::Reconnect));
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(detector.check(), Some(Recovery::Reconnect));
+        assert_eq!(detector.attempts(), 3);
+
+        // a frame arriving starts over from a resync
+        detector.feed();
+        assert_eq!(detector.gap(), Duration::ZERO);
+        clock.advance(Duration::from_secs(6));
+        assert_eq!(detector.check(), Some(Recovery::Resync));
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn power_cycles_in_simulated_time() {
+        use crate::clock::{Clock, SimulatedClock};
+        use crate::mock::MockTransport;
+        use std::sync::Arc;
+
+        // a silent device is given up on after the default policy's 5 attempts
+        let clock = SimulatedClock::new();
+        let mut device = Device::from_transport(MockTransport::new());
+        device.set_clock(Arc::new(clock.clone()));
+        match device.power_up_with(&PowerUpPolicy::default()) {
+            Err(RWError::NeverWoke { attempts, elapsed }) => {
+                assert_eq!(attempts, 5);
+                assert_eq!(elapsed, Duration::from_millis(1500));
+            }
+            other => panic!("expected NeverWoke, got {:?}", other),
+        }
+
+        // a nap sleeps on the device's clock, then resends the volatile settings
+        let mock = MockTransport::target_point3();
+        let mut device = Device::from_transport(mock.clone());
+        device.set_clock(Arc::new(clock.clone()));
+        device.set_data_components(vec![DataID::Heading]).unwrap();
+        let start = clock.now();
+        device.nap(Duration::from_secs(3600)).unwrap();
+        assert_eq!(clock.now() - start, Duration::from_secs(3600));
+        assert_eq!(
+            mock.commands(),
+            [
+                Command::SetDataComponents,
+                Command::PowerDown,
+                Command::SerialNumber,
+                Command::SetDataComponents
+            ]
+        );
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn powered_down_device_wakes_or_is_released() {
+        use crate::clock::{Clock, SimulatedClock};
+        use crate::connection::ConnectionEvent;
+        use crate::mock::MockTransport;
+        use std::sync::Arc;
+
+        let clock = SimulatedClock::new();
+        let mock = MockTransport::target_point3();
+        let mut device = Device::from_transport(mock.clone());
+        device.set_clock(Arc::new(clock.clone()));
+        device.set_data_components(vec![DataID::Heading]).unwrap();
+        let events = device.connection_events();
+
+        let asleep = device.power_down().unwrap();
+        assert_eq!(asleep.since(), clock.now());
+        assert_eq!(mock.commands().last(), Some(&Command::PowerDown));
+        let mut device = asleep.wake().unwrap();
+        assert_eq!(device.connection_state(), &ConnectionEvent::Identified);
+        assert_eq!(
+            mock.commands()[2..],
+            [Command::SerialNumber, Command::SetDataComponents],
+            "Waking should resend the volatile settings"
+        );
+
+        device.nap(Duration::from_secs(60)).unwrap();
+        let states: Vec<_> = events.try_iter().collect();
+        let asleep_at = |nth| states.iter().filter(|s| **s == ConnectionEvent::Asleep).nth(nth);
+        assert!(asleep_at(1).is_some(), "Both power downs should be reported: {:?}", states);
+        assert_eq!(states.last(), Some(&ConnectionEvent::Identified));
+
+        // a device that doesn't wake comes back still powered down, to try again or release
+        let mut device = Device::from_transport(MockTransport::new());
+        device.set_clock(Arc::new(clock.clone()));
+        let events = device.connection_events();
+        let Err(error) = device.power_down().unwrap().wake() else {
+            panic!("a silent device woke");
+        };
+        assert!(matches!(error.error, RWError::NeverWoke { .. }));
+        error.into_powered_down().release();
+        assert_eq!(events.try_iter().last(), Some(ConnectionEvent::Closed));
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn watchdog_recovers_in_simulated_time() {
+        use crate::clock::{Clock, SimulatedClock};
+        use crate::health::{Recovery, WatchdogConfig};
+        use crate::mock::MockTransport;
+        use std::sync::Arc;
+
+        let clock = SimulatedClock::new();
+        let mock = MockTransport::target_point3().value(DataID::Heading, 45.0);
+        let mut device = Device::from_transport(mock.clone());
+        device.set_clock(Arc::new(clock.clone()));
+        device.set_data_components(vec![DataID::Heading]).unwrap();
+        device.start_continuous_mode().unwrap();
+
+        let replug = mock.clone();
+        let mut recoveries = Vec::new();
+        let mut watchdog = device
+            .watchdog(WatchdogConfig {
+                stall_timeout: Duration::from_secs(5),
+                max_reconnects: Some(3),
+            })
+            .on_recovery(|recovery, _| {
+                // the adapter comes back in time for the first reconnect
+                if recovery == Recovery::Reconnect {
+                    replug.plug_in();
+                }
+                recoveries.push(recovery);
+            });
+        assert_eq!(watchdog.next().unwrap().unwrap().heading, Some(45.0));
+
+        mock.unplug();
+        let start = clock.now();
+        assert_eq!(watchdog.next().unwrap().unwrap().heading, Some(45.0));
+        assert!(clock.now() - start >= Duration::from_secs(10));
+        let stats = watchdog.stats();
+        assert_eq!((stats.samples, stats.resyncs, stats.reconnects), (2, 1, 1));
+        // the resync couldn't write to the pulled port
+        assert_eq!(stats.failed_recoveries, 1);
+        drop(watchdog);
+        assert_eq!(recoveries, [Recovery::Resync, Recovery::Reconnect]);
+        assert!(mock.is_streaming());
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn accuracy_alarm_holds_then_recovers() {
+        use crate::clock::SimulatedClock;
+        use crate::health::{AccuracyEvent, AccuracyMonitor, AccuracyProblem, AccuracyThresholds};
+        use std::sync::Arc;
+
+        let clock = SimulatedClock::new();
+        let mut monitor =
+            AccuracyMonitor::new(Arc::new(clock.clone()), AccuracyThresholds::default());
+        let accuracy = |mag_accuracy| Data {
+            mag_accuracy: Some(mag_accuracy),
+            ..Default::default()
+        };
+
+        // a brief excursion doesn't raise the alarm
+        assert_eq!(monitor.update(&accuracy(5.0)), None);
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(monitor.update(&accuracy(1.0)), None);
+
+        assert_eq!(monitor.update(&accuracy(5.0)), None);
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(
+            monitor.update(&accuracy(4.0)),
+            Some(AccuracyEvent::Degraded {
+                problem: AccuracyProblem::MagAccuracy(4.0),
+                duration: Duration::from_secs(10),
+            })
+        );
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(monitor.update(&accuracy(4.0)), None);
+        assert!(monitor.is_alarmed());
+        assert_eq!(
+            monitor.update(&accuracy(1.0)),
+            Some(AccuracyEvent::Recovered {
+                after: Duration::from_secs(11)
+            })
+        );
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn mounting_recommended_from_gravity() {
+        use crate::config::MountingRef;
+        use crate::mounting::recommend_mounting;
+
+        // level and upright: z down, but the turn can't be told without a nose-up capture
+        let upright = recommend_mounting([0.02, -0.01, 1.0], None).unwrap();
+        assert!(!upright.is_unambiguous());
+        assert_eq!(upright.candidates.len(), 4);
+        assert!(upright.candidates.contains(&MountingRef::Std0));
+
+        // x up; pitching nose-up tips gravity towards -z, so the platform's forward is sensor z
+        let pitch = 20f32.to_radians();
+        let recommendation =
+            recommend_mounting([-1.0, 0.0, 0.0], Some([-pitch.cos(), 0.0, -pitch.sin()]))
+                .unwrap();
+        let [forward, _, down] = recommendation.mounting.to_rotation_matrix();
+        assert!(recommendation.down_error < 1.0);
+        assert!(recommendation.forward_error.unwrap() < 1.0);
+        assert!((down[0] + 1.0).abs() < 1e-3);
+        assert!((forward[2] - 1.0).abs() < 1e-3);
+
+        assert!(recommend_mounting([0.0, 0.0, 1.0], Some([0.0, 0.01, 1.0])).is_none());
+    }
+
+    #[cfg(feature = "ros2")]
+    #[test]
+    fn ros2_imu_uses_enu_and_flu() {
+        use crate::ros2::*;
+
+        let data = Data {
+            heading: Some(0.0),
+            pitch: Some(0.0),
+            roll: Some(0.0),
+            accel_x: Some(0.0),
+            accel_y: Some(0.0),
+            accel_z: Some(-1.0),
+            mag_accuracy: Some(1.0),
+            ..Default::default()
+        };
+        let imu = Imu::from_data(&data);
+
+        // level and facing north is a quarter turn left of east
+        let half = std::f64::consts::FRAC_1_SQRT_2;
+        let q = imu.orientation;
+        assert!((q.w.abs() - half).abs() < 1e-6 && (q.z.abs() - half).abs() < 1e-6);
+        assert!(q.w * q.z > 0.0);
+
+        assert!((imu.linear_acceleration.z - STANDARD_GRAVITY).abs() < 1e-6);
+        assert!((imu.orientation_covariance[8] - 1f64.to_radians().powi(2)).abs() < 1e-12);
+        assert_eq!(imu.angular_velocity_covariance, NOT_REPORTED);
+        assert!(MagneticField::from_data(&data).is_none());
+    }
+
+    #[cfg(feature = "wmm")]
+    #[test]
+    fn wmm_axial_dipole() {
+        use crate::wmm::{decimal_year, MagneticModel};
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let cof = "    2025.0            TEST     01/01/2025\n\
+                   1  0  -30000.0       0.0        0.0        0.0\n\
+                   999999999999999999999999999999999999999999999999\n";
+        let model = MagneticModel::from_cof(cof.as_bytes()).unwrap();
+        let field = model.field(45.0, 10.0, 0.0, 2025.0);
+        assert!(field.declination().abs() < 1e-9);
+        assert!(field.north > 0.0 && field.down > 0.0);
+
+        // 2025-07-02T12:00:00Z is the middle of 2025
+        let midyear = UNIX_EPOCH + Duration::from_secs(1751457600);
+        assert!((decimal_year(midyear) - 2025.5).abs() < 1e-6);
+    }
+
+    #[cfg(feature = "wmm")]
+    #[test]
+    fn igrf_interpolates_between_epochs() {
+        use crate::declination::{DeclinationSource, FirstAvailable, Location, Manual};
+        use crate::wmm::Igrf;
+        use std::time::UNIX_EPOCH;
+
+        let table = "# test table\n\
+                     g/h n m 2015.0 2020.0 2020-25\n\
+                     g  1  0 -30000.0 -29000.0 10.0\n\
+                     g  1  1 0.0 -1000.0 0.0\n\
+                     h  1  1 0.0 1000.0 0.0\n";
+        let igrf = Igrf::from_table(table.as_bytes()).unwrap();
+        assert_eq!(igrf.range(), (2015.0, 2025.0));
+        assert!(igrf.model_at(2014.9).is_none());
+        let field = igrf.model_at(2015.0).unwrap().field(45.0, 10.0, 0.0, 2015.0);
+        assert!(field.declination().abs() < 1e-9);
+        let early = igrf.model_at(2017.5).unwrap().declination(0.0, 0.0, 0.0, 2017.5);
+        let late = igrf.model_at(2020.0).unwrap().declination(0.0, 0.0, 0.0, 2020.0);
+        assert!(early.abs() > 0.0 && early.abs() < late.abs());
+
+        // 2030 is past the table, so the fallback answers
+        let time = UNIX_EPOCH + std::time::Duration::from_secs(1893456000);
+        let source = FirstAvailable(vec![Box::new(igrf), Box::new(Manual(3.0))]);
+        assert_eq!(source.declination(&Location::new(0.0, 0.0), time).unwrap(), 3.0);
+    }
+
+    #[cfg(feature = "spectral")]
+    #[test]
+    fn spectrum_finds_vibration() {
+        use crate::spectral::power_spectral_density;
+
+        let samples: Vec<f32> = (0..512)
+            .map(|i| 90.0 + (2.0 * std::f32::consts::PI * 7.5 * i as f32 / 32.0).sin())
+            .collect();
+        let spectrum = power_spectral_density(&samples, 32.0, 128);
+        assert_eq!(spectrum.dominant_frequency(), Some(7.5));
+    }
+
+    #[test]
+    fn requests_wait_for_their_own_response() {
+        use crate::command::Command;
+
+        assert_eq!(Command::FactoryMagCoeff.response(), Some(Command::FactoryMagCoeffDone));
+        assert_eq!(Command::StartCal.response(), Some(Command::UserCalSampleCount));
+        assert_eq!(Command::TakeUserCalSample.response(), None);
+        for command in (0..=u8::MAX).filter_map(|id| Command::try_from(id).ok()) {
+            if let Some(response) = command.response() {
+                assert_eq!(response.response(), None, "{:?} answers a response", command);
+            }
+        }
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn save_verified_keeps_dropped_settings_unsaved() {
+        use crate::command::Command;
+        use crate::config::ConfigPair;
+        use crate::mock::MockTransport;
+
+        // acknowledges SetConfig but keeps declination at zero
+        let mock = MockTransport::target_point3().respond(
+            Command::GetConfig,
+            Command::GetConfigResp,
+            &0f32.to_be_bytes(),
+        );
+        let mut device = Device::from_transport(mock.clone());
+        device.set_config(ConfigPair::Declination(5.0)).unwrap();
+        for _ in 0..2 {
+            match device.save_verified() {
+                Err(RWError::VerificationFailed { mismatches }) => {
+                    assert_eq!(mismatches.len(), 1);
+                    assert_eq!(mismatches[0].expected, ConfigPair::Declination(5.0));
+                    assert_eq!(mismatches[0].actual, ConfigPair::Declination(0.0));
+                }
+                other => panic!("expected a verification failure, got {:?}", other),
+            }
+            assert!(device.has_unsaved_changes());
+            assert_eq!(device.diff()[0].saved, Some(ConfigPair::Declination(0.0)));
+            assert_eq!(device.diff()[0].current, ConfigPair::Declination(5.0));
+        }
+        let sets = mock.commands().iter().filter(|c| **c == Command::SetConfig).count();
+        assert_eq!(sets, 2);
+    }
+
+    #[test]
+    fn settings_shadow_tracks_unsaved_changes() {
+        use crate::config::{ConfigID, ConfigPair, SettingsShadow};
+
+        let mut shadow = SettingsShadow::default();
+        shadow.observe_read(&ConfigPair::Declination(0.0));
+        shadow.observe_set(&ConfigPair::Declination(5.0));
+        shadow.observe_set(&ConfigPair::MilOut(true));
+        let changes = shadow.changes();
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].id, ConfigID::Declination);
+        assert_eq!(changes[0].saved, Some(ConfigPair::Declination(0.0)));
+        assert_eq!(changes[1].saved, None);
+        assert_eq!(
+            shadow.current(),
+            [ConfigPair::Declination(5.0), ConfigPair::MilOut(true)]
+        );
+
+        shadow.mark_other_unsaved();
+        shadow.revert_unsaved();
+        assert!(shadow.changes().is_empty());
+        // power_up resends those
+        assert!(shadow.other_unsaved());
+        shadow.observe_set(&ConfigPair::Declination(5.0));
+        shadow.mark_saved();
+        assert!(shadow.changes().is_empty());
+    }
+
+    #[test]
+    fn data_quality_takes_the_worst_component() {
+        let mut data = Data::default();
+        assert_eq!(data.quality(), DataQuality::Unknown);
+        data.cal_status = Some(true);
+        data.mag_accuracy = Some(1.5);
+        assert!(data.is_reliable());
+        data.mag_accuracy = Some(4.0);
+        assert_eq!(data.quality(), DataQuality::Degraded);
+        data.distortion = Some(true);
+        assert_eq!(data.quality(), DataQuality::Unreliable);
+        assert!(data.is_distorted());
+    }
+
+    #[test]
+    fn data_records_keep_the_device_order() {
+        use crate::responses::Get;
+
+        // roll before heading, unlike the declaration order
+        let payload = [2, DataID::Roll as u8, 0x3F, 0x80, 0, 0, DataID::Heading as u8, 0, 0, 0, 0];
+        let record = Get::<DataRecord>::get(&mut &payload[..]).unwrap();
+        assert_eq!(
+            record.components,
+            vec![(DataID::Roll, DataValue::Float(1.0)), (DataID::Heading, DataValue::Float(0.0))]
+        );
+        assert_eq!(record.data, Data { heading: Some(0.0), roll: Some(1.0), ..Default::default() });
+        assert_eq!(record.data.values(), vec![
+            (DataID::Heading, DataValue::Float(0.0)),
+            (DataID::Roll, DataValue::Float(1.0)),
+        ]);
+        assert_eq!(Data::from_components(&record.components), record.data);
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn heading_math_wraps_at_north() {
+        use crate::heading;
+
+        assert_eq!(heading::add(350.0, 20.0), 10.0);
+        assert_eq!(heading::subtract(10.0, 20.0), 350.0);
+        assert_eq!(heading::difference(350.0, 10.0), 20.0);
+        assert_eq!(heading::difference(10.0, 350.0), -20.0);
+        assert_eq!(heading::wrap_signed(270.0), -90.0);
+        assert_eq!(heading::magnetic_to_true(355.0, 10.0), 5.0);
+        assert_eq!(heading::true_to_magnetic(5.0, 10.0), 355.0);
+        assert_eq!(heading::degrees_to_mils(90.0), 1600.0);
+        assert_eq!(heading::mils_to_degrees(3200.0), 180.0);
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn group_stream_drop_policies() {
+        use crate::group::{DropPolicy, SampleQueue};
+
+        let drain =
+            |queue: &SampleQueue<u32>| std::iter::from_fn(|| queue.pop()).collect::<Vec<_>>();
+
+        let queue = SampleQueue::new(DropPolicy::DropOldest(2), 0);
+        for item in 1..=3 {
+            queue.push(Some(0), item);
+        }
+        queue.push(None, 99);
+        assert_eq!(queue.stats().dropped, 1);
+        assert_eq!(drain(&queue), [2, 3, 99]);
+
+        let queue = SampleQueue::new(DropPolicy::DropNewest(2), 0);
+        for item in 1..=3 {
+            queue.push(Some(0), item);
+        }
+        assert_eq!(drain(&queue), [1, 2]);
+
+        let queue = SampleQueue::new(DropPolicy::Latest, 0);
+        for (device, item) in [(0, 1), (1, 2), (0, 3), (0, 4)] {
+            queue.push(Some(device), item);
+        }
+        assert_eq!(queue.stats().coalesced, 2);
+        assert_eq!(drain(&queue), [4, 2]);
+        assert_eq!(queue.stats().delivered, 2);
+    }
+
+    #[cfg(feature = "prometheus")]
+    #[test]
+    fn metrics_render_as_prometheus_text() {
+        use crate::prometheus::Metrics;
+
+        let metrics = Metrics::new();
+        let data = Data { mag_accuracy: Some(1.5), distortion: Some(true), ..Default::default() };
+        metrics.record("bow \"A\"", &Ok(data));
+        metrics.record("bow \"A\"", &Err(ReadError::PipeError(std::io::Error::other("gone"))));
+        let text = metrics.render();
+        assert!(text.contains("# TYPE pni_samples_total counter\n"));
+        assert!(text.contains("pni_samples_total{device=\"bow \\\"A\\\"\"} 1\n"));
+        assert!(text.contains("pni_read_errors_total{device=\"bow \\\"A\\\"\"} 1\n"));
+        assert!(text.contains("pni_mag_accuracy_degrees{device=\"bow \\\"A\\\"\"} 1.5\n"));
+        assert!(text.contains("pni_distortion{device=\"bow \\\"A\\\"\"} 1\n"));
+        // no rate from a single sample
+        assert!(!text.contains("pni_sample_rate_hz{"));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn frames_convert_to_json() {
+        use crate::dispatch::Response;
+        use crate::json::to_value;
+
+        let data = Data { heading: Some(90.0), distortion: Some(false), ..Default::default() };
+        let value = to_value(&Response::Data(data));
+        assert_eq!(value["command"], "GetDataResp");
+        assert_eq!(value["fields"]["heading"], 90.0);
+        assert_eq!(value["fields"]["distortion"], false);
+        assert!(value["fields"].get("pitch").is_none());
+
+        let unknown = to_value(&Response::Unknown { id: 0x7F, payload: vec![1, 2] });
+        assert_eq!(unknown["command"], serde_json::Value::Null);
+        assert_eq!(unknown["fields"]["payload"], "01 02");
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn diagnostics_never_sent() {
+        use crate::capabilities::Feature;
+        use crate::command::Command;
+        use crate::mock::MockTransport;
+
+        // an unidentified device isn't asked either
+        let mock = MockTransport::new();
+        let result = Device::from_transport(mock.clone()).diagnostics();
+        assert!(matches!(
+            result,
+            Err(RWError::UnsupportedByDevice { feature: Feature::Diagnostics, .. })
+        ));
+        assert!(mock.commands().is_empty());
+
+        let mock = MockTransport::target_point3();
+        let mut device = Device::from_transport(mock.clone());
+        device.get_mod_info().unwrap();
+        let result = device.diagnostics();
+        assert!(matches!(
+            result,
+            Err(RWError::UnsupportedByDevice { feature: Feature::Diagnostics, .. })
+        ));
+        assert_eq!(mock.commands(), [Command::GetModInfo]);
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn self_test_report_fails_on_any_step() {
+        use crate::diagnostics::{SelfTestReport, SelfTestStep};
+        use std::time::Duration;
+
+        let step = |name: &str, error: Option<RWError>| SelfTestStep {
+            name: name.into(),
+            elapsed: Duration::from_millis(5),
+            error,
+        };
+        let mut report = SelfTestReport { steps: vec![step("GetModInfo", None)] };
+        assert!(report.passed());
+        report.steps.push(step("GetData", Some(RWError::NeverStabilized { elapsed: Duration::ZERO })));
+        assert!(!report.passed());
+        assert_eq!(report.failures().count(), 1);
+        assert!(report.to_string().ends_with("FAIL in 10ms"));
+    }
+
+    mod round_trip {
+        use crate::acquisition::{parse_data_payload, AcqParams, Data, HeadingStatus};
+        use crate::config::{Baud, ConfigPair, MountingRef};
+        use crate::dispatch::{parse_frame, Response};
+        use proptest::prelude::*;
+
+        fn reading() -> impl Strategy<Value = Option<f32>> {
+            proptest::option::of(-1.0e6f32..1.0e6)
+        }
+
+        fn data() -> impl Strategy<Value = Data> {
+            let angles = (reading(), reading(), reading(), reading());
+            let sensors = (
+                [reading(), reading(), reading()],
+                [reading(), reading(), reading()],
+                [reading(), reading(), reading()],
+                reading(),
+            );
+            let status = (
+                proptest::option::of(any::<bool>()),
+                proptest::option::of(any::<bool>()),
+                proptest::option::of([-1.0f32..1.0, -1.0f32..1.0, -1.0f32..1.0, -1.0f32..1.0]),
+                proptest::option::of(prop_oneof![
+                    Just(HeadingStatus::Good),
+                    Just(HeadingStatus::Fair),
+                    Just(HeadingStatus::Poor),
+                ]),
+            );
+            (angles, sensors, status).prop_map(
+                |((heading, pitch, roll, temperature), (accel, mag, gyro, mag_accuracy), flags)| {
+                    let (distortion, cal_status, quaternion, heading_status) = flags;
+                    Data {
+                        heading,
+                        pitch,
+                        roll,
+                        temperature,
+                        distortion,
+                        cal_status,
+                        accel_x: accel[0],
+                        accel_y: accel[1],
+                        accel_z: accel[2],
+                        mag_x: mag[0],
+                        mag_y: mag[1],
+                        mag_z: mag[2],
+                        mag_accuracy,
+                        gyro_x: gyro[0],
+                        gyro_y: gyro[1],
+                        gyro_z: gyro[2],
+                        quaternion,
+                        heading_status,
+                        ..Data::default()
+                    }
+                },
+            )
+        }
+
+        fn config_pair() -> impl Strategy<Value = ConfigPair> {
+            let mounting_ref = (1u8..=24).prop_filter_map("not a mounting ref", |id| {
+                MountingRef::try_from(id).ok()
+            });
+            let baud = (0u8..=16).prop_filter_map("not a baud rate", |id| Baud::try_from(id).ok());
+            prop_oneof![
+                (-180.0f32..180.0).prop_map(ConfigPair::Declination),
+                any::<bool>().prop_map(ConfigPair::TrueNorth),
+                any::<bool>().prop_map(ConfigPair::BigEndian),
+                mounting_ref.prop_map(ConfigPair::MountingRef),
+                any::<u32>().prop_map(ConfigPair::UserCalNumPoints),
+                any::<bool>().prop_map(ConfigPair::UserCalAutoSampling),
+                baud.prop_map(ConfigPair::BaudRate),
+                any::<bool>().prop_map(ConfigPair::MilOut),
+                any::<bool>().prop_map(ConfigPair::HPRDuringCal),
+                any::<u32>().prop_map(ConfigPair::MagCoeffSet),
+                any::<u32>().prop_map(ConfigPair::AccelCoeffSet),
+            ]
+        }
+
+        proptest! {
+            #[test]
+            fn data_round_trips(data in data()) {
+                prop_assert_eq!(&parse_data_payload(&data.to_payload())?, &data);
+                let response = Response::Data(data);
+                let (parsed, len) = parse_frame(&response.to_frame())?;
+                prop_assert_eq!(len, response.to_frame().len());
+                prop_assert_eq!(parsed, response);
+            }
+
+            #[test]
+            fn config_pair_round_trips(pair in config_pair()) {
+                prop_assert_eq!(ConfigPair::parse(&Vec::<u8>::from(pair.clone()))?, pair);
+            }
+
+            #[test]
+            fn acq_params_round_trip(
+                acquisition_mode in any::<bool>(),
+                flush_filter in any::<bool>(),
+                sample_delay in 0.0f32..3600.0,
+            ) {
+                let response = Response::AcqParams(AcqParams {
+                    acquisition_mode,
+                    flush_filter,
+                    sample_delay,
+                });
+                prop_assert_eq!(parse_frame(&response.to_frame())?.0, response);
+            }
+
+            #[test]
+            fn fir_taps_round_trip(taps in proptest::collection::vec(-1.0f64..1.0, 0..=32)) {
+                let response = Response::FirFilters(taps);
+                prop_assert_eq!(parse_frame(&response.to_frame())?.0, response);
+            }
+        }
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn timestamps_come_from_the_device_clock() {
+        use crate::clock::{Clock, SimulatedClock};
+
+        let clock = SimulatedClock::new();
+        let mock = crate::mock::MockTransport::target_point3();
+        let mut device = Device::from_transport(mock);
+        device.set_clock(std::sync::Arc::new(clock.clone()));
+        device.set_data_components(vec![DataID::Heading]).unwrap();
+        device.start_continuous_mode().unwrap();
+        clock.advance(Duration::from_secs(3600));
+
+        let sample = device.iter_timestamped(None).next().unwrap().unwrap();
+        assert_eq!(sample.received_at, clock.now());
+    }
+
+    #[cfg(feature = "unstable")]
     #[test]
+    #[allow(clippy::match_like_matches_macro, clippy::redundant_pattern_matching)]
     fn continuous_mode() {
-        let tp3 = Device::connect(None).expect("connects to device");
+        let mock = crate::mock::MockTransport::target_point3().value(DataID::AccelX, 0.5);
+        let tp3 = Device::from_transport(mock.clone());
         let mut tp3 = tp3
             .continuous_mode_easy(0.25, vec![DataID::AccelX])
             .expect("got into cont mode");
         {
             let mut iter = tp3.iter();
             for _ in 0..16 {
-                assert!(match iter.next() { Some(Ok(Data { accel_x: Some(_accel_measurement), ..})) => true, _ => false }, "Calling next on interator in continuous mode should yield the data we asked for");
+                assert!(match iter.next() { Some(Ok(Data { accel_x: Some(0.5), ..})) => true, _ => false }, "Calling next on interator in continuous mode should yield the data we asked for");
             }
         }
 
@@ -414,5 +3172,6 @@ mod tests {
                 "Stop continious mode should leave continuous mode"
             )
         }
+        assert!(!mock.is_streaming());
     }
 }
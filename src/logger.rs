@@ -0,0 +1,746 @@
+use crate::acquisition::{Data, StopAfter, TimestampedData};
+use crate::{Device, ReadError};
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const CSV_HEADER: &str = "sample,received_at,heading,pitch,roll,temperature,distortion,cal_status,accel_x,accel_y,accel_z,mag_x,mag_y,mag_z,mag_accuracy,provenance\n";
+
+/// Position in a log known to be durably written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint {
+    /// Byte offset of the end of the last complete, flushed record
+    pub offset: u64,
+
+    /// Number of samples written up to `offset`
+    pub samples: u64,
+}
+
+/// Result of reopening a log with [DataLogger::open_or_recover]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecoveryReport {
+    /// Checkpoint the log was rolled back to
+    pub checkpoint: Checkpoint,
+
+    /// Bytes written after the checkpoint (e.g. a torn record from a crash) that were discarded
+    pub discarded_bytes: u64,
+}
+
+/// Writes [TimestampedData] to a CSV file, periodically checkpointing so that a crash or power
+/// loss leaves a log that can be cleanly resumed with [DataLogger::open_or_recover].
+///
+/// The checkpoint lives next to the log, at the same path with `.checkpoint` appended
+pub struct DataLogger {
+    file: BufWriter<File>,
+    checkpoint_path: PathBuf,
+
+    /// Bytes handed to `file`, flushed or not
+    offset: u64,
+
+    /// Samples handed to `file`, flushed or not
+    samples: u64,
+
+    /// Checkpoint after this many samples. 0 disables automatic checkpoints
+    checkpoint_interval: u64,
+}
+
+impl DataLogger {
+    /// Creates a new log, overwriting any existing log (and checkpoint) at `path`
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(CSV_HEADER.as_bytes())?;
+
+        let mut logger = Self {
+            file,
+            checkpoint_path: checkpoint_path(path),
+            offset: CSV_HEADER.len() as u64,
+            samples: 0,
+            checkpoint_interval: 100,
+        };
+        logger.checkpoint()?;
+        Ok(logger)
+    }
+
+    /// Opens an existing log for appending, first rolling it back to its last checkpoint and
+    /// verifying the records up to that point are intact. Creates a new log if none exists
+    pub fn open_or_recover(path: impl AsRef<Path>) -> io::Result<(Self, RecoveryReport)> {
+        let path = path.as_ref();
+        if !path.exists() {
+            let logger = Self::create(path)?;
+            let checkpoint = logger.last_checkpoint();
+            return Ok((
+                logger,
+                RecoveryReport {
+                    checkpoint,
+                    discarded_bytes: 0,
+                },
+            ));
+        }
+
+        let checkpoint_path = checkpoint_path(path);
+        let checkpoint = read_checkpoint(&checkpoint_path)?;
+
+        let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+        let len = file.metadata()?.len();
+        if len < checkpoint.offset {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Log is {} bytes but its checkpoint claims {} bytes were written",
+                    len, checkpoint.offset
+                ),
+            ));
+        }
+
+        verify_records(&mut file, checkpoint)?;
+        file.set_len(checkpoint.offset)?;
+        file.seek(SeekFrom::End(0))?;
+
+        Ok((
+            Self {
+                file: BufWriter::new(file),
+                checkpoint_path,
+                offset: checkpoint.offset,
+                samples: checkpoint.samples,
+                checkpoint_interval: 100,
+            },
+            RecoveryReport {
+                checkpoint,
+                discarded_bytes: len - checkpoint.offset,
+            },
+        ))
+    }
+
+    /// Sets how many samples are written between automatic checkpoints. 0 disables them, in which
+    /// case only explicit [DataLogger::checkpoint] and [DataLogger::finish] calls are durable
+    pub fn set_checkpoint_interval(&mut self, samples: u64) {
+        self.checkpoint_interval = samples;
+    }
+
+    /// Appends one sample, checkpointing if the interval has elapsed
+    pub fn write(&mut self, sample: &TimestampedData) -> io::Result<()> {
+        let record = format_record(self.samples, sample);
+        self.file.write_all(record.as_bytes())?;
+        self.offset += record.len() as u64;
+        self.samples += 1;
+
+        if self.checkpoint_interval != 0 && self.samples.is_multiple_of(self.checkpoint_interval) {
+            self.checkpoint()?;
+        }
+        Ok(())
+    }
+
+    /// Flushes and syncs everything written so far, then records it in the checkpoint file
+    pub fn checkpoint(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        self.file.get_ref().sync_data()?;
+
+        // write-then-rename so the checkpoint itself is never torn
+        let tmp_path = self.checkpoint_path.with_extension("checkpoint.tmp");
+        let mut tmp = File::create(&tmp_path)?;
+        writeln!(tmp, "{} {}", self.offset, self.samples)?;
+        tmp.sync_all()?;
+        fs::rename(tmp_path, &self.checkpoint_path)
+    }
+
+    /// The state that would be recovered if the process crashed right now, assuming the last
+    /// checkpoint succeeded
+    pub fn last_checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            offset: self.offset,
+            samples: self.samples,
+        }
+    }
+
+    /// Number of samples in the log, including ones written before a recovery
+    pub fn samples(&self) -> u64 {
+        self.samples
+    }
+
+    /// Checkpoints and closes the log
+    pub fn finish(mut self) -> io::Result<()> {
+        self.checkpoint()
+    }
+}
+
+impl Device {
+    /// Runs a timed or counted continuous-mode capture into `logger`, then stops the device
+    /// streaming and closes the log. The device must already be streaming, e.g. after
+    /// [Device::start_continuous_mode_live]. See [Device::capture].
+    ///
+    /// Frames that fail to parse are logged and skipped. Returns the number of samples written
+    pub fn capture_to_log(
+        &mut self,
+        mut logger: DataLogger,
+        stop: StopAfter,
+        sample_delay: Option<f32>,
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        let mut written = 0;
+        let mut capture = self.capture(stop, sample_delay);
+        for sample in capture.by_ref() {
+            match sample {
+                Ok(sample) => {
+                    if let Err(e) = logger.write(&sample) {
+                        // keep what's been written, and don't leave the device streaming
+                        let _ = capture.stop();
+                        let _ = logger.finish();
+                        return Err(e.into());
+                    }
+                    written += 1;
+                }
+                Err(ReadError::PipeError(e)) => {
+                    logger.finish()?;
+                    return Err(e.into());
+                }
+                Err(e) => log::warn!("Skipping sample: {}", e),
+            }
+        }
+        logger.finish()?;
+        Ok(written)
+    }
+}
+
+/// File format for [Device::log_to]
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LogFormat {
+    /// The [DataLogger] format: checkpointed, so a crash loses at most the last few samples,
+    /// and readable back with [read_log]
+    Csv,
+
+    /// Columnar, compressed and typed, for loading hours of data into pandas, polars or DuckDB.
+    /// Samples are buffered into row groups and the file is only readable once closed, so a
+    /// crash loses the file being written; rotate often if that matters. Needs the `parquet`
+    /// feature
+    #[cfg(feature = "parquet")]
+    Parquet,
+}
+
+impl LogFormat {
+    /// Conventional file extension
+    pub fn extension(&self) -> &'static str {
+        match self {
+            LogFormat::Csv => "csv",
+            #[cfg(feature = "parquet")]
+            LogFormat::Parquet => "parquet",
+        }
+    }
+}
+
+/// When [Device::log_to] closes the current file and starts the next. Whichever limit is
+/// reached first applies
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Rotation {
+    /// Time since the file's first sample
+    pub duration: Option<Duration>,
+
+    /// Samples in the file
+    pub samples: Option<u64>,
+}
+
+impl Rotation {
+    /// Everything goes in one file
+    pub const NEVER: Rotation = Rotation {
+        duration: None,
+        samples: None,
+    };
+
+    pub fn every(duration: Duration) -> Self {
+        Self {
+            duration: Some(duration),
+            samples: None,
+        }
+    }
+
+    pub fn every_samples(samples: u64) -> Self {
+        Self {
+            duration: None,
+            samples: Some(samples),
+        }
+    }
+
+    fn is_due(&self, first_sample: Instant, now: Instant, samples: u64) -> bool {
+        self.duration
+            .is_some_and(|duration| now.saturating_duration_since(first_sample) >= duration)
+            || self.samples.is_some_and(|limit| samples >= limit)
+    }
+}
+
+/// What [Device::log_to] wrote
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogSummary {
+    /// Every file written, in order
+    pub files: Vec<PathBuf>,
+
+    pub samples: u64,
+}
+
+/// One open log file of either format
+enum LogFile {
+    Csv(DataLogger),
+    #[cfg(feature = "parquet")]
+    Parquet(parquet_log::ParquetLogger),
+}
+
+impl LogFile {
+    fn create(path: &Path, format: LogFormat) -> io::Result<Self> {
+        Ok(match format {
+            LogFormat::Csv => LogFile::Csv(DataLogger::create(path)?),
+            #[cfg(feature = "parquet")]
+            LogFormat::Parquet => LogFile::Parquet(parquet_log::ParquetLogger::create(path)?),
+        })
+    }
+
+    fn write(&mut self, sample: &TimestampedData) -> io::Result<()> {
+        match self {
+            LogFile::Csv(logger) => logger.write(sample),
+            #[cfg(feature = "parquet")]
+            LogFile::Parquet(logger) => logger.write(sample),
+        }
+    }
+
+    fn finish(self) -> io::Result<()> {
+        match self {
+            LogFile::Csv(logger) => logger.finish(),
+            #[cfg(feature = "parquet")]
+            LogFile::Parquet(logger) => logger.finish(),
+        }
+    }
+}
+
+/// `path` with `-NNNN` inserted before the extension, e.g. `survey-0003.csv`
+fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{}-{:04}.{}", stem, index, extension.to_string_lossy()),
+        None => format!("{}-{:04}", stem, index),
+    };
+    path.with_file_name(name)
+}
+
+impl Device {
+    /// Streams continuous-mode data to `path` until `stop`, with host receive times, then stops
+    /// the device streaming. The device must already be streaming, e.g. after
+    /// [Device::start_continuous_mode_live].
+    ///
+    /// With a [Rotation] other than [Rotation::NEVER], files are numbered from 0 as
+    /// `name-0000.ext`, `name-0001.ext` and so on next to `path`, and each is closed (and for
+    /// CSV, checkpointed) before the next is started. Frames that fail to parse are logged and
+    /// skipped
+    pub fn log_to(
+        &mut self,
+        path: impl AsRef<Path>,
+        format: LogFormat,
+        stop: StopAfter,
+        rotation: Rotation,
+    ) -> Result<LogSummary, Box<dyn std::error::Error>> {
+        let path = path.as_ref();
+        let path_for = |index| match rotation {
+            Rotation::NEVER => path.to_path_buf(),
+            _ => rotated_path(path, index),
+        };
+
+        let mut summary = LogSummary {
+            files: vec![path_for(0)],
+            samples: 0,
+        };
+        let mut file = LogFile::create(&summary.files[0], format)?;
+        let mut file_started = None;
+        let mut file_samples = 0;
+
+        let mut capture = self.capture(stop, None);
+        for sample in capture.by_ref() {
+            let sample = match sample {
+                Ok(sample) => sample,
+                Err(ReadError::PipeError(e)) => {
+                    file.finish()?;
+                    return Err(e.into());
+                }
+                Err(e) => {
+                    log::warn!("Skipping sample: {}", e);
+                    continue;
+                }
+            };
+
+            let first = *file_started.get_or_insert(sample.received_at);
+            if rotation.is_due(first, sample.received_at, file_samples) {
+                let next = path_for(summary.files.len());
+                let rotated = file.finish().and_then(|()| LogFile::create(&next, format));
+                file = match rotated {
+                    Ok(file) => file,
+                    Err(e) => {
+                        let _ = capture.stop();
+                        return Err(e.into());
+                    }
+                };
+                log::info!("Logging to {}", next.display());
+                summary.files.push(next);
+                file_started = Some(sample.received_at);
+                file_samples = 0;
+            }
+
+            if let Err(e) = file.write(&sample) {
+                // keep what's been written, and don't leave the device streaming
+                let _ = capture.stop();
+                let _ = file.finish();
+                return Err(e.into());
+            }
+            file_samples += 1;
+            summary.samples += 1;
+        }
+        file.finish()?;
+        Ok(summary)
+    }
+}
+
+#[cfg(feature = "parquet")]
+mod parquet_log {
+    use crate::acquisition::TimestampedData;
+
+    use parquet::basic::Compression;
+    use parquet::data_type::{BoolType, ByteArray, ByteArrayType, FloatType, Int64Type};
+    use parquet::errors::ParquetError;
+    use parquet::file::properties::WriterProperties;
+    use parquet::file::writer::SerializedFileWriter;
+    use parquet::schema::parser::parse_message_type;
+
+    use std::fs::File;
+    use std::io;
+    use std::path::Path;
+    use std::sync::Arc;
+    use std::time::UNIX_EPOCH;
+
+    /// Same columns as the CSV log, with received_at as a UTC timestamp
+    const SCHEMA: &str = "
+        message sample {
+            REQUIRED INT64 sample;
+            REQUIRED INT64 received_at (TIMESTAMP(MICROS, true));
+            OPTIONAL FLOAT heading;
+            OPTIONAL FLOAT pitch;
+            OPTIONAL FLOAT roll;
+            OPTIONAL FLOAT temperature;
+            OPTIONAL BOOLEAN distortion;
+            OPTIONAL BOOLEAN cal_status;
+            OPTIONAL FLOAT accel_x;
+            OPTIONAL FLOAT accel_y;
+            OPTIONAL FLOAT accel_z;
+            OPTIONAL FLOAT mag_x;
+            OPTIONAL FLOAT mag_y;
+            OPTIONAL FLOAT mag_z;
+            OPTIONAL FLOAT mag_accuracy;
+            REQUIRED BYTE_ARRAY provenance (UTF8);
+        }
+    ";
+
+    /// Samples per row group: about five minutes at 30 Hz
+    const ROW_GROUP_SAMPLES: usize = 10_000;
+
+    fn io_error(e: ParquetError) -> io::Error {
+        io::Error::other(e)
+    }
+
+    pub(super) struct ParquetLogger {
+        writer: SerializedFileWriter<File>,
+        samples: i64,
+        rows: Vec<TimestampedData>,
+    }
+
+    impl ParquetLogger {
+        pub(super) fn create(path: &Path) -> io::Result<Self> {
+            let schema = Arc::new(parse_message_type(SCHEMA).map_err(io_error)?);
+            let properties = WriterProperties::builder()
+                .set_compression(Compression::SNAPPY)
+                .build();
+            let writer =
+                SerializedFileWriter::new(File::create(path)?, schema, Arc::new(properties))
+                    .map_err(io_error)?;
+            Ok(Self {
+                writer,
+                samples: 0,
+                rows: Vec::new(),
+            })
+        }
+
+        pub(super) fn write(&mut self, sample: &TimestampedData) -> io::Result<()> {
+            self.rows.push(sample.clone());
+            if self.rows.len() == ROW_GROUP_SAMPLES {
+                self.flush_row_group()?;
+            }
+            Ok(())
+        }
+
+        pub(super) fn finish(mut self) -> io::Result<()> {
+            self.flush_row_group()?;
+            self.writer.close().map_err(io_error)?;
+            Ok(())
+        }
+
+        fn flush_row_group(&mut self) -> io::Result<()> {
+            if self.rows.is_empty() {
+                return Ok(());
+            }
+            let rows = std::mem::take(&mut self.rows);
+            let first = self.samples;
+            self.samples += rows.len() as i64;
+            self.write_row_group(first, &rows).map_err(io_error)
+        }
+
+        fn write_row_group(
+            &mut self,
+            first: i64,
+            rows: &[TimestampedData],
+        ) -> parquet::errors::Result<()> {
+            let names: Vec<String> = self
+                .writer
+                .schema_descr()
+                .columns()
+                .iter()
+                .map(|column| column.name().to_string())
+                .collect();
+
+            let mut row_group = self.writer.next_row_group()?;
+            let mut names = names.iter();
+            while let Some(mut writer) = row_group.next_column()? {
+                let name = names.next().map(String::as_str).unwrap_or_default();
+                match name {
+                    "sample" => {
+                        let samples: Vec<i64> = (first..first + rows.len() as i64).collect();
+                        writer.typed::<Int64Type>().write_batch(&samples, None, None)?;
+                    }
+                    "received_at" => {
+                        let times: Vec<i64> = rows
+                            .iter()
+                            .map(|s| {
+                                s.received_at_system
+                                    .duration_since(UNIX_EPOCH)
+                                    .map(|d| d.as_micros() as i64)
+                                    .unwrap_or_default()
+                            })
+                            .collect();
+                        writer.typed::<Int64Type>().write_batch(&times, None, None)?;
+                    }
+                    "provenance" => {
+                        let provenance: Vec<ByteArray> = rows
+                            .iter()
+                            .map(|s| ByteArray::from(s.provenance.to_string().as_str()))
+                            .collect();
+                        writer.typed::<ByteArrayType>().write_batch(&provenance, None, None)?;
+                    }
+                    _ => {
+                        if let Some(field) = bool_column(name) {
+                            let (values, levels) = optional(rows, field);
+                            writer
+                                .typed::<BoolType>()
+                                .write_batch(&values, Some(&levels), None)?;
+                        } else if let Some(field) = float_column(name) {
+                            let (values, levels) = optional(rows, field);
+                            writer
+                                .typed::<FloatType>()
+                                .write_batch(&values, Some(&levels), None)?;
+                        } else {
+                            let message = format!("no data for column {}", name);
+                            return Err(ParquetError::General(message));
+                        }
+                    }
+                }
+                writer.close()?;
+            }
+            row_group.close()?;
+            Ok(())
+        }
+    }
+
+    /// The FLOAT column called `name` in [SCHEMA]
+    fn float_column(name: &str) -> Option<fn(&TimestampedData) -> Option<f32>> {
+        Some(match name {
+            "heading" => |s| s.data.heading,
+            "pitch" => |s| s.data.pitch,
+            "roll" => |s| s.data.roll,
+            "temperature" => |s| s.data.temperature,
+            "accel_x" => |s| s.data.accel_x,
+            "accel_y" => |s| s.data.accel_y,
+            "accel_z" => |s| s.data.accel_z,
+            "mag_x" => |s| s.data.mag_x,
+            "mag_y" => |s| s.data.mag_y,
+            "mag_z" => |s| s.data.mag_z,
+            "mag_accuracy" => |s| s.data.mag_accuracy,
+            _ => return None,
+        })
+    }
+
+    /// The BOOLEAN column called `name` in [SCHEMA]
+    fn bool_column(name: &str) -> Option<fn(&TimestampedData) -> Option<bool>> {
+        Some(match name {
+            "distortion" => |s| s.data.distortion,
+            "cal_status" => |s| s.data.cal_status,
+            _ => return None,
+        })
+    }
+
+    /// Present values and the definition level of every row, as written for OPTIONAL columns
+    fn optional<T>(
+        rows: &[TimestampedData],
+        field: fn(&TimestampedData) -> Option<T>,
+    ) -> (Vec<T>, Vec<i16>) {
+        let mut values = Vec::with_capacity(rows.len());
+        let mut levels = Vec::with_capacity(rows.len());
+        for row in rows {
+            match field(row) {
+                Some(value) => {
+                    values.push(value);
+                    levels.push(1);
+                }
+                None => levels.push(0),
+            }
+        }
+        (values, levels)
+    }
+}
+
+fn checkpoint_path(path: &Path) -> PathBuf {
+    let mut checkpoint = path.as_os_str().to_owned();
+    checkpoint.push(".checkpoint");
+    checkpoint.into()
+}
+
+fn read_checkpoint(path: &Path) -> io::Result<Checkpoint> {
+    let contents = fs::read_to_string(path)?;
+    let mut fields = contents.split_whitespace().map(str::parse::<u64>);
+    match (fields.next(), fields.next()) {
+        (Some(Ok(offset)), Some(Ok(samples))) => Ok(Checkpoint { offset, samples }),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Malformed checkpoint file: {:?}", contents),
+        )),
+    }
+}
+
+/// Checks that the log up to the checkpoint holds a header plus exactly `checkpoint.samples`
+/// newline-terminated records
+fn verify_records(file: &mut File, checkpoint: Checkpoint) -> io::Result<()> {
+    file.seek(SeekFrom::Start(0))?;
+    let reader = BufReader::new(io::Read::take(&mut *file, checkpoint.offset));
+
+    let mut lines = 0u64;
+    for line in reader.split(b'\n') {
+        let line = line?;
+        lines += 1;
+        if lines == 1 && line != CSV_HEADER.trim_end().as_bytes() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Log doesn't start with the expected CSV header",
+            ));
+        }
+    }
+
+    // every record before the checkpoint must be complete; the last byte must be a newline
+    file.seek(SeekFrom::Start(checkpoint.offset.saturating_sub(1)))?;
+    let mut last = [0u8; 1];
+    io::Read::read_exact(file, &mut last)?;
+
+    if last[0] != b'\n' || lines != checkpoint.samples + 1 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Log has {} records before its checkpoint, expected {}",
+                lines.saturating_sub(1),
+                checkpoint.samples
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Reads back the samples of a log written by [DataLogger], with the wall-clock time each was
+/// received. Components the log doesn't record (gyro, quaternion, heading status) are [None]
+pub fn read_log(path: impl AsRef<Path>) -> io::Result<Vec<(SystemTime, Data)>> {
+    let mut lines = BufReader::new(File::open(path)?).lines();
+    if lines.next().transpose()?.as_deref() != Some(CSV_HEADER.trim_end()) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Log doesn't start with the expected CSV header",
+        ));
+    }
+    let mut samples = Vec::new();
+    for line in lines {
+        let line = line?;
+        // a torn last record from a crash
+        if line.split(',').count() != CSV_HEADER.split(',').count() {
+            break;
+        }
+        samples.push(parse_record(&line)?);
+    }
+    Ok(samples)
+}
+
+fn parse_record(line: &str) -> io::Result<(SystemTime, Data)> {
+    fn cell<T: std::str::FromStr>(field: Option<&str>) -> io::Result<Option<T>> {
+        match field {
+            None | Some("") => Ok(None),
+            Some(value) => value.parse().map(Some).map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Invalid value in log: {:?}", value),
+                )
+            }),
+        }
+    }
+
+    let mut fields = line.split(',');
+    let _index = fields.next();
+    let received_at: f64 = cell(fields.next())?.unwrap_or_default();
+    let data = Data {
+        heading: cell(fields.next())?,
+        pitch: cell(fields.next())?,
+        roll: cell(fields.next())?,
+        temperature: cell(fields.next())?,
+        distortion: cell(fields.next())?,
+        cal_status: cell(fields.next())?,
+        accel_x: cell(fields.next())?,
+        accel_y: cell(fields.next())?,
+        accel_z: cell(fields.next())?,
+        mag_x: cell(fields.next())?,
+        mag_y: cell(fields.next())?,
+        mag_z: cell(fields.next())?,
+        mag_accuracy: cell(fields.next())?,
+        ..Default::default()
+    };
+    Ok((UNIX_EPOCH + Duration::from_secs_f64(received_at.max(0.0)), data))
+}
+
+fn format_record(index: u64, sample: &TimestampedData) -> String {
+    fn cell<T: ToString>(value: Option<T>) -> String {
+        value.map(|v| v.to_string()).unwrap_or_default()
+    }
+
+    let received_at = sample
+        .received_at_system
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or_default();
+
+    let data = &sample.data;
+
+    format!(
+        "{},{:.6},{},{},{},{},{},{},{},{},{},{},{},{},{},{}\n",
+        index,
+        received_at,
+        cell(data.heading),
+        cell(data.pitch),
+        cell(data.roll),
+        cell(data.temperature),
+        cell(data.distortion),
+        cell(data.cal_status),
+        cell(data.accel_x),
+        cell(data.accel_y),
+        cell(data.accel_z),
+        cell(data.mag_x),
+        cell(data.mag_y),
+        cell(data.mag_z),
+        cell(data.mag_accuracy),
+        sample.provenance,
+    )
+}
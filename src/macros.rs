@@ -0,0 +1,62 @@
+// Most commands are the same exchange: send a frame, read a fixed list of fields from the one
+// response it can get, check the frame was used up. Writing each of those out by hand is how
+// factory_mag_coeff ended up sending StartCal, so they're declared in a table instead, and the
+// response to wait for comes from Command::response rather than from each method.
+
+/// Generates [crate::Device] methods for simple request/response commands. Each entry is a
+/// method signature without `&mut self` or the `Result`, the request command and its payload,
+/// then the response fields to read as closure parameters and a body that returns the result:
+///
+/// ```ignore
+/// /// Returns device serial number
+/// pub fn serial_number() -> u32 = SerialNumber => |serial_number: u32| Ok(serial_number);
+///
+/// pub fn start_cal(option: CalOption) -> u32 = StartCal(option.to_payload())
+///     => |sample_count: u32| Ok(sample_count);
+/// ```
+///
+/// The payload is anything a `Vec<u8>` can be made from. The body may `return` early with an
+/// error. Fields are read in order, and the frame must hold nothing after the last one
+macro_rules! requests {
+    (@payload) => {
+        None::<Vec<u8>>
+    };
+    (@payload $payload:expr) => {
+        Some(Vec::<u8>::from($payload))
+    };
+    () => {};
+    // `||` is one token, so entries without fields are respelled with the pipes apart
+    (
+        $(#[$meta:meta])*
+        $vis:vis fn $name:ident($($arg:ident: $arg_ty:ty),* $(,)?) -> $ret:ty
+            = $command:ident $(($payload:expr))?
+            => || $body:expr;
+        $($rest:tt)*
+    ) => {
+        requests! {
+            $(#[$meta])*
+            $vis fn $name($($arg: $arg_ty),*) -> $ret = $command $(($payload))? => | | $body;
+            $($rest)*
+        }
+    };
+    (
+        $(#[$meta:meta])*
+        $vis:vis fn $name:ident($($arg:ident: $arg_ty:ty),* $(,)?) -> $ret:ty
+            = $command:ident $(($payload:expr))?
+            => |$($field:ident: $field_ty:ty),* $(,)?| $body:expr;
+        $($rest:tt)*
+    ) => {
+        $(#[$meta])*
+        $vis fn $name(&mut self, $($arg: $arg_ty),*) -> Result<$ret, crate::RWError> {
+            let payload = requests!(@payload $($payload)?);
+            // fieldless entries don't read from it
+            #[allow(unused_mut)]
+            let mut frame = self.request(crate::command::Command::$command, payload.as_deref())?;
+            $(let $field = frame.get::<$field_ty>()?;)*
+            frame.finish()?;
+            $body
+        }
+
+        requests! { $($rest)* }
+    };
+}
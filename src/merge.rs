@@ -0,0 +1,148 @@
+use crate::acquisition::TimestampedData;
+
+use std::collections::VecDeque;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+/// A sample from some other sensor (IMU, GNSS, ...), timestamped on the same host clock as
+/// [TimestampedData::received_at]
+#[derive(Debug, Clone)]
+pub struct ExternalSample<T> {
+    pub timestamp: Instant,
+    pub value: T,
+}
+
+/// Values that can be linearly interpolated between two samples
+pub trait Interpolate {
+    /// Returns the value a fraction `t` (0 to 1) of the way from `self` to `other`
+    fn interpolate(&self, other: &Self, t: f32) -> Self;
+}
+
+impl Interpolate for f32 {
+    fn interpolate(&self, other: &Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl Interpolate for f64 {
+    fn interpolate(&self, other: &Self, t: f32) -> Self {
+        self + (other - self) * t as f64
+    }
+}
+
+impl<T: Interpolate, const N: usize> Interpolate for [T; N] {
+    fn interpolate(&self, other: &Self, t: f32) -> Self {
+        std::array::from_fn(|i| self[i].interpolate(&other[i], t))
+    }
+}
+
+/// How an external sample is chosen for each compass sample
+#[derive(Debug, Clone, Copy)]
+pub enum Alignment {
+    /// Use the external sample closest in time, if it's within `max_gap`
+    Nearest { max_gap: Duration },
+
+    /// Interpolate between the external samples either side, if both are within `max_gap`
+    Interpolate { max_gap: Duration },
+}
+
+impl Alignment {
+    fn max_gap(&self) -> Duration {
+        match self {
+            Alignment::Nearest { max_gap } | Alignment::Interpolate { max_gap } => *max_gap,
+        }
+    }
+}
+
+/// A compass sample paired with the external measurement aligned to it
+#[derive(Debug)]
+pub struct MergedSample<T> {
+    pub compass: TimestampedData,
+
+    /// [None] if no external sample was close enough in time
+    pub external: Option<T>,
+}
+
+/// Aligns [TimestampedData] against a channel of [ExternalSample]s.
+///
+/// Compass samples are placed in time by [TimestampedData::estimated_sample_time] when
+/// available, otherwise by [TimestampedData::received_at]
+pub struct StreamMerger<T> {
+    external: Receiver<ExternalSample<T>>,
+    alignment: Alignment,
+
+    /// External samples received so far that may still be needed, oldest first
+    buffer: VecDeque<ExternalSample<T>>,
+}
+
+impl<T: Clone + Interpolate> StreamMerger<T> {
+    pub fn new(external: Receiver<ExternalSample<T>>, alignment: Alignment) -> Self {
+        Self {
+            external,
+            alignment,
+            buffer: VecDeque::new(),
+        }
+    }
+
+    /// Pairs `compass` with an external sample. Blocks until an external sample at or after the
+    /// compass sample has arrived, or until that's no longer useful because `max_gap` has passed
+    pub fn merge(&mut self, compass: TimestampedData) -> MergedSample<T> {
+        let at = compass.estimated_sample_time.unwrap_or(compass.received_at);
+        let max_gap = self.alignment.max_gap();
+
+        while self.buffer.back().is_none_or(|latest| latest.timestamp < at) {
+            let wait = (at + max_gap).saturating_duration_since(Instant::now());
+            match self.external.recv_timeout(wait) {
+                Ok(sample) => self.buffer.push_back(sample),
+                Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+
+        // keep only the last sample before `at`; older ones can't be nearer to future samples
+        while self.buffer.len() > 1 && self.buffer[1].timestamp <= at {
+            self.buffer.pop_front();
+        }
+
+        let before = self.buffer.front().filter(|s| s.timestamp <= at);
+        let after = self.buffer.iter().find(|s| s.timestamp >= at);
+        let within = |s: &&ExternalSample<T>| abs_diff(s.timestamp, at) <= max_gap;
+
+        let external = match self.alignment {
+            Alignment::Nearest { .. } => match (before.filter(within), after.filter(within)) {
+                (Some(b), Some(a)) if abs_diff(a.timestamp, at) < abs_diff(b.timestamp, at) => {
+                    Some(a.value.clone())
+                }
+                (Some(b), _) => Some(b.value.clone()),
+                (None, Some(a)) => Some(a.value.clone()),
+                (None, None) => None,
+            },
+            Alignment::Interpolate { .. } => match (before.filter(within), after.filter(within)) {
+                (Some(b), Some(a)) if a.timestamp == b.timestamp => Some(b.value.clone()),
+                (Some(b), Some(a)) => {
+                    let span = (a.timestamp - b.timestamp).as_secs_f32();
+                    let t = (at - b.timestamp).as_secs_f32() / span;
+                    Some(b.value.interpolate(&a.value, t))
+                }
+                _ => None,
+            },
+        };
+
+        MergedSample { compass, external }
+    }
+
+    /// Merges every sample from a timestamped compass stream (e.g. [crate::Device::iter_timestamped])
+    pub fn merge_iter<'a, E: 'a>(
+        &'a mut self,
+        compass: impl Iterator<Item = Result<TimestampedData, E>> + 'a,
+    ) -> impl Iterator<Item = Result<MergedSample<T>, E>> + 'a {
+        compass.map(move |sample| sample.map(|sample| self.merge(sample)))
+    }
+}
+
+fn abs_diff(a: Instant, b: Instant) -> Duration {
+    if a > b {
+        a - b
+    } else {
+        b - a
+    }
+}
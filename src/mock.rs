@@ -0,0 +1,348 @@
+// A stand-in for a device, so code built on Device can be tested without one. It answers each
+// command with canned frames rather than emulating the sensor: enough for request/response
+// commands and a steady continuous-mode stream, not for calibration or timing.
+
+use crate::acquisition::DataID;
+use crate::codec::{self, CodecError, FRAME_OVERHEAD};
+use crate::command::Command;
+use crate::transport::Transport;
+
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+/// Serial number [MockTransport::target_point3] reports
+pub const MOCK_SERIAL_NUMBER: u32 = 1234567;
+
+#[derive(Debug, Default)]
+struct MockState {
+    /// Frames to send each time a command arrives, by command byte
+    responses: HashMap<u8, Vec<Vec<u8>>>,
+
+    /// Values of the data components in generated samples, by component ID
+    values: HashMap<u8, f32>,
+
+    /// Whether GetData and continuous mode get generated samples
+    samples: bool,
+
+    /// Bytes for the host to read
+    pending: VecDeque<u8>,
+
+    /// Bytes of a frame the host is still writing
+    written: Vec<u8>,
+
+    /// Every frame the host sent, in order
+    commands: Vec<u8>,
+
+    /// Payloads of the frames in `commands`
+    payloads: Vec<Vec<u8>>,
+
+    /// Component IDs from the last SetDataComponents
+    components: Vec<u8>,
+
+    /// Payload of the last SetAcqParams, sent back for GetAcqParams
+    acq_params: Vec<u8>,
+
+    streaming: bool,
+
+    /// Times the host flushed the port
+    flushes: usize,
+
+    /// Whether the port has been pulled, failing every read, write and flush until it's
+    /// re-opened
+    unplugged: bool,
+
+    /// Whether the port is back, so re-opening it works
+    plugged_in: bool,
+
+    /// Generated samples still to be sent with a bad checksum
+    corrupt: u32,
+}
+
+impl MockState {
+    fn check_plugged(&self) -> io::Result<()> {
+        match self.unplugged {
+            true => Err(io::Error::new(io::ErrorKind::BrokenPipe, "mock was unplugged")),
+            false => Ok(()),
+        }
+    }
+
+    fn queue(&mut self, command: Command, payload: &[u8]) {
+        let mut frame = vec![0u8; payload.len() + FRAME_OVERHEAD];
+        // payloads here are at most a sample, far below the frame size limit
+        codec::encode_frame(u8::from(command), payload, &mut frame).unwrap();
+        self.pending.extend(frame);
+    }
+
+    /// A GetDataResp of the components last set, with the values set on the mock or zero
+    fn queue_sample(&mut self) {
+        let mut payload = vec![self.components.len() as u8];
+        for &id in &self.components {
+            payload.push(id);
+            let value = self.values.get(&id).copied().unwrap_or_default();
+            match codec::component_value_len(id) {
+                Some(1) => payload.push((value != 0.0) as u8),
+                Some(len) => {
+                    for _ in 0..len / 4 {
+                        payload.extend(value.to_be_bytes());
+                    }
+                }
+                None => {}
+            }
+        }
+        self.queue(Command::GetDataResp, &payload);
+        if self.corrupt > 0 {
+            self.corrupt -= 1;
+            if let Some(checksum) = self.pending.back_mut() {
+                *checksum ^= 0xff;
+            }
+        }
+    }
+
+    fn receive(&mut self, command: u8, payload: &[u8]) {
+        self.commands.push(command);
+        self.payloads.push(payload.to_vec());
+        match Command::try_from(command) {
+            Ok(Command::SetDataComponents) => {
+                self.components = payload.get(1..).unwrap_or_default().to_vec()
+            }
+            Ok(Command::SetAcqParams) => self.acq_params = payload.to_vec(),
+            Ok(Command::StartContinuousMode) => self.streaming = true,
+            Ok(Command::StopContinuousMode) => self.streaming = false,
+            _ => {}
+        }
+
+        if let Some(frames) = self.responses.get(&command) {
+            self.pending.extend(frames.iter().flatten());
+            return;
+        }
+        if !self.samples {
+            return;
+        }
+        match Command::try_from(command) {
+            Ok(Command::GetData) => self.queue_sample(),
+            Ok(Command::GetAcqParams) => {
+                let params = self.acq_params.clone();
+                self.queue(Command::GetAcqParamsResp, &params);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// A [Transport] that answers like a device, for testing without hardware:
+///
+/// ```
+/// # use pni_sdk::Device;
+/// # use pni_sdk::command::Command;
+/// # use pni_sdk::mock::MockTransport;
+/// let mock = MockTransport::new().respond(
+///     Command::SerialNumber,
+///     Command::SerialNumberResp,
+///     &[0, 0, 0, 42],
+/// );
+/// let mut device = Device::from_transport(mock.clone());
+/// assert_eq!(device.serial_number().unwrap(), 42);
+/// assert_eq!(mock.commands(), [Command::SerialNumber]);
+/// ```
+///
+/// Frames set with [MockTransport::respond] are sent each time their command arrives. Reads
+/// with nothing to send time out at once, as a silent device would after the timeout. Clones
+/// share the same state, so keep one to check [MockTransport::commands] after handing the other
+/// to a device
+#[derive(Debug, Clone)]
+pub struct MockTransport {
+    state: Arc<Mutex<MockState>>,
+    timeout: Duration,
+}
+
+impl Default for MockTransport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockTransport {
+    /// A device that accepts every command and answers none
+    pub fn new() -> Self {
+        Self {
+            state: Default::default(),
+            timeout: Duration::from_secs(1),
+        }
+    }
+
+    /// A TargetPoint3 on firmware 5.04 that acknowledges settings, saves and power downs, and
+    /// generates samples of the components last set with SetDataComponents, both for GetData
+    /// and between StartContinuousMode and StopContinuousMode. Components are zero unless set
+    /// with [MockTransport::value]
+    pub fn target_point3() -> Self {
+        let mock = Self::new()
+            .respond(Command::GetModInfo, Command::GetModInfoResp, b"TP3 5.04")
+            .respond(
+                Command::SerialNumber,
+                Command::SerialNumberResp,
+                &MOCK_SERIAL_NUMBER.to_be_bytes(),
+            )
+            .respond(Command::SetConfig, Command::SetConfigDone, &[])
+            .respond(Command::SetAcqParams, Command::SetAcqParamsDone, &[])
+            .respond(Command::SetFIRFilters, Command::SetFIRFiltersDone, &[])
+            .respond(Command::CopyCoeffSet, Command::CopyCoeffSetDone, &[])
+            .respond(Command::Save, Command::SaveDone, &[0, 0])
+            .respond(Command::PowerDown, Command::PowerDownDone, &[]);
+        {
+            let mut state = mock.lock();
+            state.samples = true;
+            // polled mode, no flushing, no delay
+            state.acq_params = vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        }
+        mock
+    }
+
+    /// Sends a `response` frame with `payload` each time `command` arrives, after any frames
+    /// already set for it
+    pub fn respond(self, command: Command, response: Command, payload: &[u8]) -> Self {
+        let mut frame = vec![0u8; payload.len() + FRAME_OVERHEAD];
+        codec::encode_frame(u8::from(response), payload, &mut frame)
+            .expect("payload fits in a frame");
+        self.lock().responses.entry(u8::from(command)).or_default().push(frame);
+        self
+    }
+
+    /// Value of a data component in generated samples. Flags are set by any nonzero value, and
+    /// each element of a quaternion gets the same value
+    pub fn value(self, id: DataID, value: f32) -> Self {
+        self.lock().values.insert(id as u8, value);
+        self
+    }
+
+    /// Commands the host has sent so far, in order. Unknown command bytes are left out
+    pub fn commands(&self) -> Vec<Command> {
+        let state = self.lock();
+        state
+            .commands
+            .iter()
+            .filter_map(|&command| Command::try_from(command).ok())
+            .collect()
+    }
+
+    /// Payload of the last `command` frame the host sent, if it sent one
+    pub fn last_payload(&self, command: Command) -> Option<Vec<u8>> {
+        let state = self.lock();
+        let index = state.commands.iter().rposition(|&sent| sent == u8::from(command))?;
+        Some(state.payloads[index].clone())
+    }
+
+    /// How many times the host flushed the port
+    pub fn flushes(&self) -> usize {
+        self.lock().flushes
+    }
+
+    /// Pulls the port: from now on reads, writes and flushes fail as they do on an unplugged
+    /// serial adapter, and [Transport::reopen] fails until [MockTransport::plug_in]
+    pub fn unplug(&self) {
+        let mut state = self.lock();
+        state.unplugged = true;
+        state.plugged_in = false;
+    }
+
+    /// Puts a pulled port back, so it can be re-opened. The host still has to re-open it
+    pub fn plug_in(&self) {
+        self.lock().plugged_in = true;
+    }
+
+    /// Sends the next `count` generated samples with a bad checksum, as a noisy line would
+    pub fn corrupt_samples(&self, count: u32) {
+        self.lock().corrupt = count;
+    }
+
+    /// Whether the host left the mock in continuous mode
+    pub fn is_streaming(&self) -> bool {
+        self.lock().streaming
+    }
+
+    fn lock(&self) -> MutexGuard<'_, MockState> {
+        self.state.lock().unwrap()
+    }
+}
+
+impl Read for MockTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.lock();
+        state.check_plugged()?;
+        if state.pending.is_empty() && state.streaming && state.samples {
+            state.queue_sample();
+        }
+        if state.pending.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "mock has nothing to send"));
+        }
+        let len = buf.len().min(state.pending.len());
+        for (byte, pending) in buf.iter_mut().zip(state.pending.drain(..len)) {
+            *byte = pending;
+        }
+        Ok(len)
+    }
+}
+
+impl Write for MockTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.lock();
+        state.check_plugged()?;
+        state.written.extend_from_slice(buf);
+        loop {
+            let (command, payload, len) = match codec::decode_frame(&state.written) {
+                Ok((frame, len)) => (frame.command, frame.payload.to_vec(), len),
+                Err(CodecError::Incomplete) => break,
+                Err(e) => {
+                    state.written.clear();
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, e.to_string()));
+                }
+            };
+            state.written.drain(..len);
+            state.receive(command, &payload);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let mut state = self.lock();
+        state.check_plugged()?;
+        state.flushes += 1;
+        Ok(())
+    }
+}
+
+impl Transport for MockTransport {
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.timeout = timeout;
+        Ok(())
+    }
+
+    fn clear_input(&mut self) -> io::Result<()> {
+        self.lock().pending.clear();
+        Ok(())
+    }
+
+    fn baud_rate(&self) -> io::Result<u32> {
+        Ok(38400)
+    }
+
+    fn try_clone(&self) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new(self.clone()))
+    }
+
+    fn reopen(&mut self) -> io::Result<()> {
+        let mut state = self.lock();
+        if state.unplugged && !state.plugged_in {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "mock is unplugged"));
+        }
+        state.unplugged = false;
+        state.pending.clear();
+        state.written.clear();
+        Ok(())
+    }
+}
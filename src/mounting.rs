@@ -0,0 +1,192 @@
+// Choosing a MountingRef from measurements instead of from Figure 4-2. Gravity, as seen by the
+// accelerometer with the platform level, shows which sensor axis points down, which narrows the 16
+// constants to four. The remaining turn about the vertical shows up when the platform is pitched
+// nose-up: gravity shifts towards the sensor direction that points backwards on the platform.
+
+use crate::acquisition::DataID;
+use crate::catalog::{Message, MessageId};
+use crate::config::{ConfigPair, MountingRef};
+use crate::{Device, RWError, ReadError};
+
+/// How far the nose-up capture must differ from the level one to tell which way is forward, in
+/// degrees
+pub const MIN_NOSE_UP_PITCH: f32 = 10.0;
+
+/// A [MountingRef] suggested by [recommend_mounting]
+#[derive(Debug, Clone, PartialEq)]
+pub struct MountRecommendation {
+    pub mounting: MountingRef,
+
+    /// Angle between the measured down direction and the one `mounting` expects, in degrees.
+    /// More than a few degrees means the unit isn't square to the platform, or the platform
+    /// wasn't level
+    pub down_error: f32,
+
+    /// Angle between the measured forward direction and the one `mounting` expects, in degrees.
+    /// [None] without a nose-up capture, in which case the turn about the vertical is a guess:
+    /// any of [MountRecommendation::candidates] fits the level capture equally well
+    pub forward_error: Option<f32>,
+
+    /// Every constant consistent with the down direction alone
+    pub candidates: Vec<MountingRef>,
+}
+
+impl MountRecommendation {
+    /// Whether the turn about the vertical was measured rather than guessed
+    pub fn is_unambiguous(&self) -> bool {
+        self.forward_error.is_some()
+    }
+}
+
+fn normalize(v: [f32; 3]) -> Option<[f32; 3]> {
+    let norm = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+    (norm > 1e-3).then(|| v.map(|c| c / norm))
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn angle_degrees(a: [f32; 3], b: [f32; 3]) -> f32 {
+    dot(a, b).clamp(-1.0, 1.0).acos().to_degrees()
+}
+
+/// Picks the [MountingRef] that best explains the accelerometer readings (in g, in the sensor's
+/// frame, as reported by the AccelX/Y/Z components) taken with the platform level and, optionally,
+/// pitched nose-up by at least [MIN_NOSE_UP_PITCH].
+///
+/// Returns [None] if a reading is zero, or the nose-up reading is too close to the level one to
+/// show which way is forward
+pub fn recommend_mounting(
+    level: [f32; 3],
+    nose_up: Option<[f32; 3]>,
+) -> Option<MountRecommendation> {
+    let down = normalize(level)?;
+    let forward = match nose_up {
+        Some(nose_up) => {
+            let nose_up = normalize(nose_up)?;
+            if angle_degrees(down, nose_up) < MIN_NOSE_UP_PITCH {
+                return None;
+            }
+            // gravity moves away from the forward axis as the nose rises; keep only the part of
+            // that shift perpendicular to down
+            let shift = [0, 1, 2].map(|i| down[i] - nose_up[i]);
+            let along_down = dot(shift, down);
+            Some(normalize([0, 1, 2].map(|i| shift[i] - along_down * down[i]))?)
+        }
+        None => None,
+    };
+
+    // the matrix takes sensor vectors to platform ones, so its rows are the platform's forward,
+    // right and down axes as seen in the sensor frame
+    let scored = (1..=16u8)
+        .filter_map(|value| MountingRef::try_from(value).ok())
+        .map(|mounting| {
+            let [expected_forward, _, expected_down] = mounting.to_rotation_matrix();
+            let down_error = angle_degrees(down, expected_down);
+            let forward_error = forward.map(|forward| angle_degrees(forward, expected_forward));
+            (mounting, down_error, forward_error)
+        })
+        .collect::<Vec<_>>();
+
+    let best_down = scored
+        .iter()
+        .map(|(_, down_error, _)| *down_error)
+        .fold(f32::INFINITY, f32::min);
+    let candidates = scored
+        .iter()
+        .filter(|(_, down_error, _)| *down_error - best_down < 1.0)
+        .map(|(mounting, _, _)| *mounting)
+        .collect::<Vec<_>>();
+    let (mounting, down_error, forward_error) = scored
+        .into_iter()
+        .filter(|(mounting, _, _)| candidates.contains(mounting))
+        .min_by(|a, b| a.2.unwrap_or(0.0).total_cmp(&b.2.unwrap_or(0.0)))?;
+
+    Some(MountRecommendation {
+        mounting,
+        down_error,
+        forward_error,
+        candidates,
+    })
+}
+
+/// Guided selection of the [MountingRef] for an installed unit. See [Device::mount_wizard]
+pub struct MountWizard<'a> {
+    device: &'a mut Device,
+    samples: usize,
+    level: Option<[f32; 3]>,
+    nose_up: Option<[f32; 3]>,
+}
+
+impl Device {
+    /// Starts working out the [MountingRef] for the unit as installed:
+    ///
+    /// 1. With the platform level and still, call [MountWizard::capture_level]
+    /// 2. Pitch the platform nose-up by at least [MIN_NOSE_UP_PITCH] and call
+    ///    [MountWizard::capture_nose_up]. Skippable, but then only which axis points down is
+    ///    known, not which way the unit is turned
+    /// 3. Check [MountWizard::recommendation], and [MountWizard::apply] it
+    ///
+    /// The device must be in polled mode. Its data components are replaced with AccelX, AccelY
+    /// and AccelZ; set them back afterwards
+    pub fn mount_wizard(&mut self) -> Result<MountWizard<'_>, RWError> {
+        self.set_data_components(vec![DataID::AccelX, DataID::AccelY, DataID::AccelZ])?;
+        Ok(MountWizard {
+            device: self,
+            samples: 20,
+            level: None,
+            nose_up: None,
+        })
+    }
+}
+
+impl MountWizard<'_> {
+    /// Samples averaged for each capture. Defaults to 20
+    pub fn samples(mut self, samples: usize) -> Self {
+        self.samples = samples.max(1);
+        self
+    }
+
+    fn average_accel(&mut self) -> Result<[f32; 3], RWError> {
+        let mut sum = [0.0f32; 3];
+        for _ in 0..self.samples {
+            let data = self.device.get_data()?;
+            let (Some(x), Some(y), Some(z)) = (data.accel_x, data.accel_y, data.accel_z) else {
+                return Err(ReadError::ParseError(Message::new(MessageId::MissingAccel)).into());
+            };
+            sum = [sum[0] + x, sum[1] + y, sum[2] + z];
+        }
+        Ok(sum.map(|c| c / self.samples as f32))
+    }
+
+    /// Averages accel with the platform level. Returns the reading, in g
+    pub fn capture_level(&mut self) -> Result<[f32; 3], RWError> {
+        let accel = self.average_accel()?;
+        self.level = Some(accel);
+        Ok(accel)
+    }
+
+    /// Averages accel with the platform pitched nose-up. Returns the reading, in g
+    pub fn capture_nose_up(&mut self) -> Result<[f32; 3], RWError> {
+        let accel = self.average_accel()?;
+        self.nose_up = Some(accel);
+        Ok(accel)
+    }
+
+    /// The best fit for the captures so far. [None] before [MountWizard::capture_level], or if
+    /// the captures don't make sense (see [recommend_mounting])
+    pub fn recommendation(&self) -> Option<MountRecommendation> {
+        recommend_mounting(self.level?, self.nose_up)
+    }
+
+    /// Sets the recommended [MountingRef] on the device, unsaved, and returns the recommendation.
+    /// Returns [None] without changing anything if there's no recommendation
+    pub fn apply(&mut self) -> Result<Option<MountRecommendation>, RWError> {
+        let Some(recommendation) = self.recommendation() else {
+            return Ok(None);
+        };
+        self.device.set_config(ConfigPair::MountingRef(recommendation.mounting))?;
+        Ok(Some(recommendation))
+    }
+}
@@ -0,0 +1,173 @@
+use crate::config::{Baud, ConfigPair};
+use crate::connection::ConnectionEvent;
+use crate::{Device, PowerUpPolicy, RWError, WriteError};
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// A device that has been sent PowerDown, with its serial port still open and configured (baud
+/// rate, timeout, subscribers, reconnect policy and so on). Only waking it is possible; see
+/// [Device::power_down]
+pub struct PoweredDown<D = Device> {
+    device: D,
+    since: Instant,
+}
+
+/// Failure to wake a [PoweredDown] device. The device is still available from
+/// [WakeError::into_powered_down] to try again
+pub struct WakeError<D = Device> {
+    pub error: RWError,
+    powered_down: Box<PoweredDown<D>>,
+}
+
+impl<D> WakeError<D> {
+    /// The device, still powered down
+    pub fn into_powered_down(self) -> PoweredDown<D> {
+        *self.powered_down
+    }
+}
+
+impl<D> fmt::Debug for WakeError<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WakeError")
+            .field("error", &self.error)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<D> fmt::Display for WakeError<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Device didn't wake: {}", self.error)
+    }
+}
+
+impl<D> std::error::Error for WakeError<D> {}
+
+impl<D> PoweredDown<D> {
+    /// When PowerDown was sent
+    pub fn since(&self) -> Instant {
+        self.since
+    }
+}
+
+impl PoweredDown<Device> {
+    /// Name of the serial port the device is attached to, if the port has one
+    pub fn port_name(&self) -> Option<String> {
+        self.device.port_name()
+    }
+
+    /// Wakes the device on the same port, retrying according to [PowerUpPolicy::default]
+    pub fn wake(self) -> Result<Device, WakeError> {
+        self.wake_with(&PowerUpPolicy::default())
+    }
+
+    /// Like [PoweredDown::wake], retrying as described by `policy`
+    pub fn wake_with(mut self, policy: &PowerUpPolicy) -> Result<Device, WakeError> {
+        match self.device.power_up_with(policy) {
+            Ok(()) => Ok(self.device),
+            Err(error) => {
+                self.device.set_connection_state(ConnectionEvent::Asleep);
+                Err(WakeError {
+                    error,
+                    powered_down: Box::new(self),
+                })
+            }
+        }
+    }
+
+    /// Closes the serial port and opens the same path again at `baud`, then wakes the device on
+    /// it. For after a new [ConfigPair::BaudRate] has been saved; see [Device::change_baud]
+    pub fn wake_at(mut self, baud: Baud) -> Result<Device, WakeError> {
+        if let Err(e) = self.device.reopen_port_at(baud) {
+            return Err(WakeError {
+                error: e.into(),
+                powered_down: Box::new(self),
+            });
+        }
+        self.wake()
+    }
+
+    /// Closes the serial port without waking the device, which powers up again next time
+    /// anything is sent to it
+    pub fn release(mut self) {
+        self.device.set_connection_state(ConnectionEvent::Closed);
+    }
+}
+
+impl Device {
+    /// This frame is used to power-down the module. The frame has no payload. The command will power down all peripherals including the sensors, microprocessor, and RS-232 driver. However, the driver chip has a feature to keep the Rx line enabled. The device will power up when it receives any signal on the native UART Rx line.
+    ///
+    /// The serial port stays open, so the device can be woken with [PoweredDown::wake] without
+    /// detecting and configuring the port again. Drop the result, or call
+    /// [PoweredDown::release], to close the port instead. The device frequently doesn't
+    /// acknowledge PowerDown even when it works, so read errors are ignored
+    pub fn power_down(mut self) -> Result<PoweredDown, RWError> {
+        match self.power_down_impl() {
+            Ok(()) | Err(RWError::ReadError(_)) => {}
+            Err(e) => return Err(e),
+        }
+        self.set_connection_state(ConnectionEvent::Asleep);
+        Ok(PoweredDown {
+            since: self.clock.now(),
+            device: self,
+        })
+    }
+
+    /// Sends the data components, acquisition parameters and FIR filters set on this connection
+    /// again, after a power cycle has reverted them to their saved values
+    pub(crate) fn reassert_volatile_settings(&mut self) -> Result<(), RWError> {
+        // a device that streams on power-up interleaves data with the responses
+        self.while_streaming(Device::reassert_volatile_settings_impl)
+    }
+
+    fn reassert_volatile_settings_impl(&mut self) -> Result<(), RWError> {
+        // resending doesn't make anything unsaved that wasn't already
+        let other_unsaved = self.settings.other_unsaved();
+        if let Some(components) = self.data_components.clone() {
+            self.set_data_components(components)?;
+        }
+        if let Some(acq_params) = self.acq_params.clone() {
+            self.set_acq_params_impl(acq_params)?;
+        }
+        for (axis, taps) in self.fir_filters.clone() {
+            self.set_fir_filters_for(axis, taps)?;
+        }
+        if !other_unsaved {
+            self.settings.mark_other_saved();
+        }
+        Ok(())
+    }
+
+    /// Switches the device to `baud`: sets and saves [ConfigPair::BaudRate] (along with any other
+    /// unsaved settings), power cycles the device so the new rate takes effect, and reopens the
+    /// same serial port at it. Returns the device reconnected at the new rate.
+    ///
+    /// Links without a port name to reopen, such as a TCP serial server, fail before anything is
+    /// sent. Once the device is powered down, failures leave it running at `baud`; connect at
+    /// that rate to reach it
+    pub fn change_baud(mut self, baud: Baud) -> Result<Device, RWError> {
+        if self.port_name().is_none() {
+            return Err(WriteError::PipeError(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "link has no serial port to re-open",
+            ))
+            .into());
+        }
+        self.set_config(ConfigPair::BaudRate(baud))?;
+        self.save()?;
+        self.power_down()?.wake_at(baud).map_err(|e| e.error)
+    }
+
+    /// Powers the device down for `duration` then wakes it, keeping the session. For
+    /// duty-cycled, battery-powered deployments that sample occasionally. Settings changed with
+    /// [Device::set_config] are lost unless [Device::save]d beforehand; see [Device::power_up]
+    pub fn nap(&mut self, duration: Duration) -> Result<(), RWError> {
+        match self.power_down_impl() {
+            Ok(()) | Err(RWError::ReadError(_)) => {}
+            Err(e) => return Err(e),
+        }
+        self.set_connection_state(ConnectionEvent::Asleep);
+        self.clock.sleep(duration);
+        self.power_up()
+    }
+}
@@ -0,0 +1,498 @@
+use crate::acquisition::Data;
+
+/// Heading, pitch and roll in degrees, using the same ranges as [Data]
+#[derive(Debug, Display, Clone, Copy, PartialEq)]
+#[display(fmt = "Orientation {{ heading: {}, pitch: {}, roll: {} }}", heading, pitch, roll)]
+pub struct Orientation {
+    /// 0.0˚ to +359.9˚
+    pub heading: f32,
+
+    /// -90.0˚ to +90.0˚
+    pub pitch: f32,
+
+    /// -180.0˚ to +180.0˚
+    pub roll: f32,
+}
+
+impl Orientation {
+    /// Uses the heading, pitch and roll reported by the device if all three are present,
+    /// otherwise computes a tilt-compensated orientation from the raw accel and mag components.
+    /// Returns [None] if neither set of components was requested
+    pub fn from_data(data: &Data) -> Option<Self> {
+        if let (Some(heading), Some(pitch), Some(roll)) = (data.heading, data.pitch, data.roll) {
+            return Some(Self {
+                heading,
+                pitch,
+                roll,
+            });
+        }
+
+        Some(Self::from_accel_mag(
+            [data.accel_x?, data.accel_y?, data.accel_z?],
+            [data.mag_x?, data.mag_y?, data.mag_z?],
+        ))
+    }
+
+    /// Tilt-compensated compass from accelerometer (g) and magnetometer (µT) readings. Assumes
+    /// the device frame is x forward, y right, z down, with the sensor mounted in
+    /// [crate::config::MountingRef::Std0]
+    pub fn from_accel_mag(accel: [f32; 3], mag: [f32; 3]) -> Self {
+        let [ax, ay, az] = accel;
+        let [mx, my, mz] = mag;
+
+        let roll = ay.atan2(az);
+        let pitch = (-ax).atan2((ay * ay + az * az).sqrt());
+
+        let (sin_roll, cos_roll) = roll.sin_cos();
+        let (sin_pitch, cos_pitch) = pitch.sin_cos();
+        let horizontal_x = mx * cos_pitch + my * sin_pitch * sin_roll + mz * sin_pitch * cos_roll;
+        let horizontal_y = my * cos_roll - mz * sin_roll;
+        let heading = (-horizontal_y).atan2(horizontal_x);
+
+        Self {
+            heading: wrap_heading(heading.to_degrees()),
+            pitch: pitch.to_degrees(),
+            roll: roll.to_degrees(),
+        }
+    }
+}
+
+/// Wraps an angle in degrees into [0, 360)
+pub(crate) fn wrap_heading(degrees: f32) -> f32 {
+    let wrapped = degrees.rem_euclid(360.0);
+    // rem_euclid can round up to exactly 360 for tiny negative inputs
+    if wrapped >= 360.0 {
+        0.0
+    } else {
+        wrapped
+    }
+}
+
+/// Signed difference `to - from` in degrees, taking the shortest way around the circle
+pub(crate) fn angle_difference(from: f32, to: f32) -> f32 {
+    (to - from + 180.0).rem_euclid(360.0) - 180.0
+}
+
+/// Something that turns a stream of [Data] into a smoothed [Orientation]
+pub trait OrientationFilter {
+    /// Feeds one sample, `dt` seconds after the previous one. Returns the filtered orientation,
+    /// or [None] if the sample didn't contain enough components to compute one (see
+    /// [Orientation::from_data]), in which case the filter state is unchanged
+    fn update(&mut self, data: &Data, dt: f32) -> Option<Orientation>;
+
+    /// Current estimate, if any sample has been accepted
+    fn estimate(&self) -> Option<Orientation>;
+
+    /// Forgets all history
+    fn reset(&mut self);
+}
+
+/// Blends each new measurement into the running estimate with a fixed weight. Heading and roll
+/// are blended the short way around the circle, so 359˚ and 1˚ average to 0˚, not 180˚
+#[derive(Debug, Clone)]
+pub struct ComplementaryFilter {
+    /// Weight given to each new measurement, in (0, 1]. Smaller is smoother but lags more
+    pub alpha: f32,
+
+    state: Option<Orientation>,
+}
+
+impl ComplementaryFilter {
+    pub fn new(alpha: f32) -> Self {
+        Self { alpha, state: None }
+    }
+}
+
+impl OrientationFilter for ComplementaryFilter {
+    fn update(&mut self, data: &Data, _dt: f32) -> Option<Orientation> {
+        let measured = Orientation::from_data(data)?;
+        let next = match self.state {
+            None => measured,
+            Some(state) => Orientation {
+                heading: wrap_heading(
+                    state.heading + self.alpha * angle_difference(state.heading, measured.heading),
+                ),
+                pitch: state.pitch + self.alpha * (measured.pitch - state.pitch),
+                roll: angle_difference(
+                    0.0,
+                    state.roll + self.alpha * angle_difference(state.roll, measured.roll),
+                ),
+            },
+        };
+        self.state = Some(next);
+        self.state
+    }
+
+    fn estimate(&self) -> Option<Orientation> {
+        self.state
+    }
+
+    fn reset(&mut self) {
+        self.state = None;
+    }
+}
+
+/// Constant-orientation Kalman filter, run independently on each angle. Unlike
+/// [ComplementaryFilter] it adapts its gain: heading measurements are weighted by
+/// [Data::mag_accuracy] when that component is present
+#[derive(Debug, Clone)]
+pub struct KalmanFilter {
+    /// How quickly the true orientation is expected to drift, in degrees² per second
+    pub process_noise: f32,
+
+    /// Variance of each measurement in degrees², used for heading when mag_accuracy is absent
+    pub measurement_noise: f32,
+
+    state: Option<Orientation>,
+
+    /// Estimate variance for heading, pitch and roll
+    variance: [f32; 3],
+}
+
+impl KalmanFilter {
+    pub fn new(process_noise: f32, measurement_noise: f32) -> Self {
+        Self {
+            process_noise,
+            measurement_noise,
+            state: None,
+            variance: [0.0; 3],
+        }
+    }
+
+    /// Runs one predict/update step on a single angle, returning the correction to apply
+    fn step(&mut self, axis: usize, innovation: f32, measurement_noise: f32, dt: f32) -> f32 {
+        self.variance[axis] += self.process_noise * dt;
+        let gain = self.variance[axis] / (self.variance[axis] + measurement_noise);
+        self.variance[axis] *= 1.0 - gain;
+        gain * innovation
+    }
+}
+
+impl OrientationFilter for KalmanFilter {
+    fn update(&mut self, data: &Data, dt: f32) -> Option<Orientation> {
+        let measured = Orientation::from_data(data)?;
+        let Some(state) = self.state else {
+            self.state = Some(measured);
+            self.variance = [self.measurement_noise; 3];
+            return self.state;
+        };
+
+        let heading_noise = data
+            .mag_accuracy
+            .map(|accuracy| accuracy * accuracy)
+            .unwrap_or(self.measurement_noise);
+        let measurement_noise = self.measurement_noise;
+
+        let heading = state.heading
+            + self.step(
+                0,
+                angle_difference(state.heading, measured.heading),
+                heading_noise,
+                dt,
+            );
+        let pitch = state.pitch + self.step(1, measured.pitch - state.pitch, measurement_noise, dt);
+        let roll = state.roll
+            + self.step(
+                2,
+                angle_difference(state.roll, measured.roll),
+                measurement_noise,
+                dt,
+            );
+
+        self.state = Some(Orientation {
+            heading: wrap_heading(heading),
+            pitch,
+            roll: angle_difference(0.0, roll),
+        });
+        self.state
+    }
+
+    fn estimate(&self) -> Option<Orientation> {
+        self.state
+    }
+
+    fn reset(&mut self) {
+        self.state = None;
+        self.variance = [0.0; 3];
+    }
+}
+
+/// Turns headings that wrap around at 360˚ into a continuous angle, so that e.g. 359˚ followed by
+/// 1˚ becomes 359˚, 361˚. Assumes consecutive samples are less than 180˚ apart. Uses f64 so
+/// precision doesn't degrade after many turns
+#[derive(Debug, Clone, Default)]
+pub struct HeadingUnwrapper {
+    previous: Option<f64>,
+}
+
+impl HeadingUnwrapper {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Unwraps the next heading in the sequence
+    pub fn unwrap(&mut self, heading: f32) -> f64 {
+        let unwrapped = match self.previous {
+            None => heading as f64,
+            Some(previous) => {
+                previous + angle_difference(wrap_heading(previous as f32), heading) as f64
+            }
+        };
+        self.previous = Some(unwrapped);
+        unwrapped
+    }
+
+    /// Starts a new sequence
+    pub fn reset(&mut self) {
+        self.previous = None;
+    }
+}
+
+/// Unwraps a whole series of headings. See [HeadingUnwrapper]
+pub fn unwrap_headings(headings: &[f32]) -> Vec<f64> {
+    let mut unwrapper = HeadingUnwrapper::new();
+    headings.iter().map(|h| unwrapper.unwrap(*h)).collect()
+}
+
+/// Wraps continuous angles back into [0, 360)
+pub fn wrap_headings(angles: &[f64]) -> Vec<f32> {
+    angles
+        .iter()
+        .map(|a| wrap_heading(a.rem_euclid(360.0) as f32))
+        .collect()
+}
+
+/// Iterator adaptor returned by [UnwrapHeadings::unwrap_headings]
+pub struct UnwrappedHeadings<I> {
+    inner: I,
+    unwrapper: HeadingUnwrapper,
+}
+
+impl<I: Iterator<Item = f32>> Iterator for UnwrappedHeadings<I> {
+    type Item = f64;
+
+    fn next(&mut self) -> Option<f64> {
+        self.inner.next().map(|h| self.unwrapper.unwrap(h))
+    }
+}
+
+/// Adds `.unwrap_headings()` to iterators of headings, for unwrapping live data
+pub trait UnwrapHeadings: Iterator<Item = f32> + Sized {
+    fn unwrap_headings(self) -> UnwrappedHeadings<Self> {
+        UnwrappedHeadings {
+            inner: self,
+            unwrapper: HeadingUnwrapper::new(),
+        }
+    }
+}
+
+impl<I: Iterator<Item = f32>> UnwrapHeadings for I {}
+
+/// Host-side hard/soft iron correction applied to [Data::mag_x], [Data::mag_y] and
+/// [Data::mag_z], for corrections derived outside the device (e.g. an offline ellipsoid fit).
+/// The on-device calibration coefficients are left alone. Each axis is corrected as
+/// `(raw - bias) * scale`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MagCorrection {
+    /// Hard iron offset per axis, in µT
+    pub bias: [f32; 3],
+
+    /// Soft iron gain per axis, applied after removing the bias
+    pub scale: [f32; 3],
+}
+
+impl Default for MagCorrection {
+    /// Leaves readings unchanged
+    fn default() -> Self {
+        Self {
+            bias: [0.0; 3],
+            scale: [1.0; 3],
+        }
+    }
+}
+
+impl MagCorrection {
+    pub fn new(bias: [f32; 3], scale: [f32; 3]) -> Self {
+        Self { bias, scale }
+    }
+
+    /// Corrects a single reading
+    pub fn correct(&self, mag: [f32; 3]) -> [f32; 3] {
+        std::array::from_fn(|i| (mag[i] - self.bias[i]) * self.scale[i])
+    }
+
+    /// Corrects whichever mag components are present in `data`. Heading, pitch and roll as
+    /// computed by the device are not updated; use [Orientation::from_accel_mag] on the
+    /// corrected components if you need them to reflect the correction
+    pub fn apply(&self, mut data: Data) -> Data {
+        let axes = [&mut data.mag_x, &mut data.mag_y, &mut data.mag_z];
+        for (i, axis) in axes.into_iter().enumerate() {
+            if let Some(value) = axis {
+                *value = (*value - self.bias[i]) * self.scale[i];
+            }
+        }
+        data
+    }
+}
+
+/// Iterator adaptor returned by [CorrectMag::correct_mag]
+pub struct MagCorrected<I> {
+    inner: I,
+    correction: MagCorrection,
+}
+
+impl<I: Iterator<Item = Result<Data, E>>, E> Iterator for MagCorrected<I> {
+    type Item = Result<Data, E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner
+            .next()
+            .map(|data| data.map(|data| self.correction.apply(data)))
+    }
+}
+
+/// Adds `.correct_mag()` to data streams such as [crate::Device::iter]
+pub trait CorrectMag<E>: Iterator<Item = Result<Data, E>> + Sized {
+    fn correct_mag(self, correction: MagCorrection) -> MagCorrected<Self> {
+        MagCorrected {
+            inner: self,
+            correction,
+        }
+    }
+}
+
+impl<I: Iterator<Item = Result<Data, E>>, E> CorrectMag<E> for I {}
+
+/// Circular mean of headings in degrees, so that 359˚ and 1˚ average to 0˚. [None] if there are
+/// no headings, or they're spread so evenly around the circle that there's no mean direction
+pub fn mean_heading(headings: &[f32]) -> Option<f32> {
+    let (sin, cos) = heading_vector_sum(headings);
+    if headings.is_empty() || sin.hypot(cos) < 1e-6 * headings.len() as f64 {
+        return None;
+    }
+    Some(wrap_heading(sin.atan2(cos).to_degrees() as f32))
+}
+
+/// Sum of the headings as unit vectors, (sin, cos)
+fn heading_vector_sum(headings: &[f32]) -> (f64, f64) {
+    headings.iter().fold((0.0, 0.0), |(sin, cos), heading| {
+        let (s, c) = (*heading as f64).to_radians().sin_cos();
+        (sin + s, cos + c)
+    })
+}
+
+/// Constant heading correction found by comparing the device against a known true bearing, e.g.
+/// a surveyed transit, during commissioning. Covers residual mounting misalignment as well as
+/// declination. Apply it host-side with [HeadingOffset::apply], or on the device with
+/// [crate::Device::apply_heading_offset]
+#[derive(Debug, Display, Clone, Copy, PartialEq, Default)]
+#[display(fmt = "HeadingOffset {{ degrees: {} }}", degrees)]
+pub struct HeadingOffset {
+    /// Added to measured headings, in degrees within [-180, 180)
+    pub degrees: f32,
+}
+
+impl HeadingOffset {
+    /// Offset that turns `measured` into `known_bearing`, the short way around the circle
+    pub fn from_bearing(measured: f32, known_bearing: f32) -> Self {
+        Self {
+            degrees: angle_difference(measured, known_bearing),
+        }
+    }
+
+    /// Corrects a single heading
+    pub fn correct(&self, heading: f32) -> f32 {
+        wrap_heading(heading + self.degrees)
+    }
+
+    /// Corrects [Data::heading], if present
+    pub fn apply(&self, mut data: Data) -> Data {
+        data.heading = data.heading.map(|heading| self.correct(heading));
+        data
+    }
+}
+
+/// Watches the output after power-up and reports when it has settled: the FIR filter has filled
+/// and [Data::mag_accuracy] has stopped moving. Stable means the last `window` headings have a
+/// circular standard deviation within `heading_tolerance`, and, if the samples carry mag accuracy,
+/// its range over the window is within `accuracy_tolerance`
+#[derive(Debug, Clone)]
+pub struct StabilityDetector {
+    pub window: usize,
+
+    /// Degrees
+    pub heading_tolerance: f32,
+
+    pub accuracy_tolerance: f32,
+
+    headings: std::collections::VecDeque<f32>,
+    accuracies: std::collections::VecDeque<f32>,
+}
+
+impl Default for StabilityDetector {
+    fn default() -> Self {
+        Self::new(10, 0.5, 0.1)
+    }
+}
+
+impl StabilityDetector {
+    pub fn new(window: usize, heading_tolerance: f32, accuracy_tolerance: f32) -> Self {
+        Self {
+            window: window.max(2),
+            heading_tolerance,
+            accuracy_tolerance,
+            headings: Default::default(),
+            accuracies: Default::default(),
+        }
+    }
+
+    /// Adds a sample and returns whether the output is now stable. Samples without a heading
+    /// are ignored
+    pub fn update(&mut self, data: &Data) -> bool {
+        let Some(heading) = data.heading else {
+            return self.is_stable();
+        };
+        push_window(&mut self.headings, heading, self.window);
+        if let Some(accuracy) = data.mag_accuracy {
+            push_window(&mut self.accuracies, accuracy, self.window);
+        }
+        self.is_stable()
+    }
+
+    pub fn is_stable(&self) -> bool {
+        if self.headings.len() < self.window {
+            return false;
+        }
+        let headings: Vec<f32> = self.headings.iter().copied().collect();
+        let heading_settled = heading_std_dev(&headings) <= self.heading_tolerance;
+
+        let accuracy_settled = self.accuracies.len() < self.window || {
+            let (min, max) = self
+                .accuracies
+                .iter()
+                .fold((f32::MAX, f32::MIN), |(min, max), a| (min.min(*a), max.max(*a)));
+            max - min <= self.accuracy_tolerance
+        };
+        heading_settled && accuracy_settled
+    }
+
+    /// Forgets all samples, e.g. after a power cycle
+    pub fn reset(&mut self) {
+        self.headings.clear();
+        self.accuracies.clear();
+    }
+}
+
+fn push_window(window: &mut std::collections::VecDeque<f32>, value: f32, len: usize) {
+    if window.len() == len {
+        window.pop_front();
+    }
+    window.push_back(value);
+}
+
+/// Circular standard deviation of headings in degrees, which handles spread across 0˚/360˚
+pub(crate) fn heading_std_dev(headings: &[f32]) -> f32 {
+    let (sin, cos) = heading_vector_sum(headings);
+    let resultant = (sin.hypot(cos) / headings.len() as f64).min(1.0);
+    (-2.0 * resultant.ln()).sqrt().to_degrees() as f32
+}
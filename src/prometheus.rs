@@ -0,0 +1,280 @@
+// Compass health in the Prometheus text exposition format, so it can sit in Grafana next to the
+// rest of a vessel's telemetry. The format is simple enough to write by hand, and serving it is a
+// single GET, so this needs neither a Prometheus client library nor an HTTP framework.
+
+use crate::acquisition::Data;
+use crate::frame::LinkStats;
+use crate::group::TaggedSample;
+use crate::ReadError;
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+/// Weight of the newest sample interval in the sample rate gauge. Smooths over serial jitter
+/// while still following a rate change within a few seconds at typical rates
+const RATE_SMOOTHING: f64 = 0.1;
+
+/// What's known about one device
+#[derive(Debug, Default, Clone)]
+struct DeviceMetrics {
+    samples: u64,
+    read_errors: u64,
+    last_sample: Option<Instant>,
+    rate_hz: Option<f64>,
+    mag_accuracy: Option<f32>,
+    distortion: Option<bool>,
+    distorted_samples: u64,
+    link: LinkStats,
+}
+
+impl DeviceMetrics {
+    fn record(&mut self, sample: Result<&Data, &ReadError>, at: Instant) {
+        let data = match sample {
+            Ok(data) => data,
+            Err(_) => {
+                self.read_errors += 1;
+                return;
+            }
+        };
+        self.samples += 1;
+        if let Some(last) = self.last_sample {
+            let interval = at.saturating_duration_since(last).as_secs_f64();
+            if interval > 0.0 {
+                let rate = 1.0 / interval;
+                self.rate_hz = Some(match self.rate_hz {
+                    Some(smoothed) => smoothed + RATE_SMOOTHING * (rate - smoothed),
+                    None => rate,
+                });
+            }
+        }
+        self.last_sample = Some(at);
+        if data.mag_accuracy.is_some() {
+            self.mag_accuracy = data.mag_accuracy;
+        }
+        if let Some(distortion) = data.distortion {
+            self.distortion = Some(distortion);
+            self.distorted_samples += distortion as u64;
+        }
+    }
+}
+
+/// Sample rate, link errors, magnetic accuracy and distortion of one or more devices, published
+/// as Prometheus metrics labelled with each device's name. Clones share the same figures, so one
+/// can be fed from a reader thread while another serves them.
+///
+/// Feed it each sample as it's read ([Metrics::record], or [Metrics::record_tagged] for a
+/// [crate::group::GroupStream]) and now and then the link counters ([Metrics::record_link_stats]),
+/// then publish with [Metrics::serve] or [Metrics::render]
+#[derive(Debug, Default, Clone)]
+pub struct Metrics {
+    devices: Arc<Mutex<BTreeMap<Arc<str>, DeviceMetrics>>>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a sample or read error from `device`, as received now
+    pub fn record(&self, device: &str, sample: &Result<Data, ReadError>) {
+        self.record_at(device, sample.as_ref(), Instant::now());
+    }
+
+    /// Records a sample or read error from a [crate::group::GroupStream], under its label. Uses
+    /// the time the sample arrived, so a consumer that falls behind doesn't skew the rate
+    pub fn record_tagged(&self, sample: &TaggedSample) {
+        match &sample.sample {
+            Ok(timestamped) => {
+                self.record_at(&sample.label, Ok(&timestamped.data), timestamped.received_at)
+            }
+            Err(e) => self.record_at(&sample.label, Err(e), Instant::now()),
+        }
+    }
+
+    /// Replaces `device`'s link counters, e.g. with [crate::Device::stats]
+    pub fn record_link_stats(&self, device: &str, stats: LinkStats) {
+        self.with_device(device, |metrics| metrics.link = stats);
+    }
+
+    fn record_at(&self, device: &str, sample: Result<&Data, &ReadError>, at: Instant) {
+        self.with_device(device, |metrics| metrics.record(sample, at));
+    }
+
+    fn with_device(&self, device: &str, update: impl FnOnce(&mut DeviceMetrics)) {
+        let mut devices = self.devices.lock().unwrap();
+        match devices.get_mut(device) {
+            Some(metrics) => update(metrics),
+            None => {
+                let mut metrics = DeviceMetrics::default();
+                update(&mut metrics);
+                devices.insert(device.into(), metrics);
+            }
+        }
+    }
+
+    /// Every metric in the Prometheus text exposition format. Gauges with nothing to report yet,
+    /// e.g. accuracy before a sample carried MagAccuracy, are left out for that device
+    pub fn render(&self) -> String {
+        let devices = self.devices.lock().unwrap();
+        let mut out = String::new();
+        let mut family = |name: &str,
+                          kind: &str,
+                          help: &str,
+                          value: &dyn Fn(&DeviceMetrics) -> Option<f64>| {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            for (device, metrics) in devices.iter() {
+                if let Some(value) = value(metrics) {
+                    let _ = writeln!(out, "{}{{device=\"{}\"}} {}", name, escape(device), value);
+                }
+            }
+        };
+
+        family(
+            "pni_samples_total",
+            "counter",
+            "Samples received",
+            &|m| Some(m.samples as f64),
+        );
+        family(
+            "pni_read_errors_total",
+            "counter",
+            "Samples that couldn't be read",
+            &|m| Some(m.read_errors as f64),
+        );
+        family(
+            "pni_sample_rate_hz",
+            "gauge",
+            "Smoothed rate samples arrive at",
+            &|m| m.rate_hz,
+        );
+        family(
+            "pni_frames_total",
+            "counter",
+            "Frames whose length and checksum checked out",
+            &|m| Some(m.link.frames_ok as f64),
+        );
+        family(
+            "pni_checksum_errors_total",
+            "counter",
+            "Frames that failed their checksum",
+            &|m| Some(m.link.checksum_errors as f64),
+        );
+        family(
+            "pni_checksum_error_ratio",
+            "gauge",
+            "Fraction of frames that failed their checksum",
+            &|m| Some(m.link.checksum_error_rate()),
+        );
+        family(
+            "pni_mag_accuracy_degrees",
+            "gauge",
+            "Latest estimated RMS heading accuracy",
+            &|m| m.mag_accuracy.map(f64::from),
+        );
+        family(
+            "pni_distortion",
+            "gauge",
+            "1 if the latest sample had a magnetometer axis beyond 150 uT",
+            &|m| m.distortion.map(|distortion| distortion as u8 as f64),
+        );
+        family(
+            "pni_distorted_samples_total",
+            "counter",
+            "Samples flagged with magnetic distortion",
+            &|m| Some(m.distorted_samples as f64),
+        );
+        out
+    }
+
+    /// Serves [Metrics::render] over HTTP on `addr` from a background thread, for Prometheus to
+    /// scrape. Every path gets the metrics. Stops when the returned server is dropped
+    pub fn serve(&self, addr: impl ToSocketAddrs) -> io::Result<MetricsServer> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let metrics = self.clone();
+        let thread = thread::spawn({
+            let stop = stop.clone();
+            move || {
+                for stream in listener.incoming() {
+                    if stop.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    // a scraper that hangs up early only loses its own response
+                    if let Ok(stream) = stream {
+                        let _ = respond(stream, &metrics);
+                    }
+                }
+            }
+        });
+        Ok(MetricsServer {
+            addr,
+            stop,
+            thread: Some(thread),
+        })
+    }
+}
+
+/// Backslashes, quotes and newlines in label values have to be escaped
+fn escape(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn respond(stream: TcpStream, metrics: &Metrics) -> io::Result<()> {
+    // read the request up to the blank line; what it asks for doesn't matter
+    let mut reader = BufReader::new(&stream);
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 0 && line != "\r\n" && line != "\n" {
+        line.clear();
+    }
+    let body = metrics.render();
+    write!(
+        &stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        body.len(),
+        body
+    )
+}
+
+/// HTTP endpoint started by [Metrics::serve]
+pub struct MetricsServer {
+    addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MetricsServer {
+    /// Address it listens on, e.g. to find the port picked when serving on port 0
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl Drop for MetricsServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        // wake the accept loop so it sees the flag
+        let mut addr = self.addr;
+        if addr.ip().is_unspecified() {
+            addr.set_ip(match addr {
+                SocketAddr::V4(_) => Ipv4Addr::LOCALHOST.into(),
+                SocketAddr::V6(_) => Ipv6Addr::LOCALHOST.into(),
+            });
+        }
+        let _ = TcpStream::connect(addr);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
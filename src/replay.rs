@@ -0,0 +1,463 @@
+use crate::acquisition::{Data, Provenance, TimestampedData};
+use crate::decode::hex;
+use crate::logger::read_log;
+
+use serialport::{ClearBuffer, DataBits, FlowControl, Parity, SerialPort, StopBits};
+use std::collections::VecDeque;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Slowest and fastest playback speeds accepted by [ReplayControl::set_speed]
+pub const MIN_SPEED: f64 = 0.1;
+pub const MAX_SPEED: f64 = 100.0;
+
+/// Plays back a recorded log as [TimestampedData] with [Provenance::Replayed], paced like the
+/// original recording, so a pipeline can be fed a captured failure instead of a live device.
+///
+/// Playback can be paused, single-stepped, sped up or slowed down, and seeked from another thread
+/// through a [ReplayControl]. Iteration ends after the last sample
+pub struct Replay {
+    samples: Vec<(SystemTime, Data)>,
+    control: ReplayControl,
+
+    /// When the previous sample was emitted, and its recorded time, to pace the next one against
+    previous: Option<(Instant, SystemTime)>,
+}
+
+#[derive(Debug)]
+struct ControlState {
+    position: usize,
+    speed: f64,
+    paused: bool,
+
+    /// Samples to emit while paused
+    steps: usize,
+
+    /// Set by seeks, so the next sample isn't paced against the one before the jump
+    jumped: bool,
+}
+
+/// Handle for steering a [Replay] while it runs. Cheap to clone; every clone controls the same
+/// replay
+#[derive(Debug, Clone)]
+pub struct ReplayControl(Arc<(Mutex<ControlState>, Condvar)>);
+
+impl Replay {
+    /// Replays a log written by [crate::logger::DataLogger]
+    pub fn from_log(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::new(read_log(path)?))
+    }
+
+    /// Replays samples with the given recorded times, which should be in order
+    pub fn new(samples: Vec<(SystemTime, Data)>) -> Self {
+        Self {
+            samples,
+            control: ReplayControl(Arc::new((
+                Mutex::new(ControlState {
+                    position: 0,
+                    speed: 1.0,
+                    paused: false,
+                    steps: 0,
+                    jumped: false,
+                }),
+                Condvar::new(),
+            ))),
+            previous: None,
+        }
+    }
+
+    pub fn control(&self) -> ReplayControl {
+        self.control.clone()
+    }
+
+    /// Number of samples in the recording
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Recorded time span from the first sample to the last
+    pub fn duration(&self) -> Duration {
+        match (self.samples.first(), self.samples.last()) {
+            (Some((first, _)), Some((last, _))) => {
+                last.duration_since(*first).unwrap_or_default()
+            }
+            _ => Duration::ZERO,
+        }
+    }
+}
+
+impl ReplayControl {
+    fn state(&self) -> MutexGuard<'_, ControlState> {
+        // the state is a few plain fields, so it's still usable if a holder panicked
+        self.0 .0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn update(&self, change: impl FnOnce(&mut ControlState)) {
+        change(&mut self.state());
+        self.0 .1.notify_all();
+    }
+
+    pub fn pause(&self) {
+        self.update(|state| state.paused = true);
+    }
+
+    pub fn resume(&self) {
+        self.update(|state| {
+            state.paused = false;
+            state.steps = 0;
+        });
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.state().paused
+    }
+
+    /// While paused, lets one more sample through immediately
+    pub fn step(&self) {
+        self.update(|state| state.steps += 1);
+    }
+
+    /// Playback speed as a multiple of the recorded rate, clamped to [MIN_SPEED]..=[MAX_SPEED]
+    pub fn set_speed(&self, speed: f64) {
+        let speed = if speed.is_nan() { 1.0 } else { speed.clamp(MIN_SPEED, MAX_SPEED) };
+        self.update(|state| state.speed = speed);
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.state().speed
+    }
+
+    /// Jumps to the sample at `index`, so it's the next one emitted
+    pub fn seek(&self, index: usize) {
+        self.update(|state| {
+            state.position = index;
+            state.jumped = true;
+        });
+    }
+
+    /// Index of the next sample to be emitted
+    pub fn position(&self) -> usize {
+        self.state().position
+    }
+}
+
+impl Iterator for Replay {
+    type Item = TimestampedData;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (lock, changed) = &*self.control.0;
+        let mut state = lock.lock().unwrap_or_else(|e| e.into_inner());
+        loop {
+            if state.jumped {
+                state.jumped = false;
+                self.previous = None;
+            }
+            let (recorded_at, _) = *self.samples.get(state.position)?;
+
+            if state.paused {
+                if state.steps == 0 {
+                    state = changed.wait(state).unwrap_or_else(|e| e.into_inner());
+                    continue;
+                }
+                state.steps -= 1;
+                break;
+            }
+
+            // wait out the recorded gap, scaled by speed, waking early for control changes
+            let due = self.previous.map(|(emitted, previous_recorded)| {
+                let gap = recorded_at.duration_since(previous_recorded).unwrap_or_default();
+                emitted + gap.div_f64(state.speed)
+            });
+            match due.map(|due| due.saturating_duration_since(Instant::now())) {
+                Some(wait) if !wait.is_zero() => {
+                    state = changed
+                        .wait_timeout(state, wait)
+                        .unwrap_or_else(|e| e.into_inner())
+                        .0;
+                }
+                _ => break,
+            }
+        }
+
+        let (recorded_at, data) = self.samples[state.position].clone();
+        state.position += 1;
+        let now = Instant::now();
+        self.previous = Some((now, recorded_at));
+        Some(TimestampedData {
+            data,
+            provenance: Provenance::Replayed,
+            received_at: now,
+            received_at_system: recorded_at,
+            estimated_sample_time: None,
+        })
+    }
+}
+
+/// One side of a scripted exchange in a [ReplayPort]
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Step {
+    /// Bytes the host must write next
+    Expect(Vec<u8>),
+
+    /// Bytes the device sends, readable once every earlier step is done
+    Respond(Vec<u8>),
+}
+
+#[derive(Debug, Default)]
+struct ScriptState {
+    steps: VecDeque<Step>,
+
+    /// Bytes written so far towards the next [Step::Expect]
+    written: Vec<u8>,
+}
+
+/// A serial port that plays a device's side of a captured exchange, for running [crate::Device]
+/// command methods without hardware. Writes must match the capture byte for byte, or fail with
+/// [io::ErrorKind::InvalidData]; reads get the device's responses in order, and time out at once
+/// when the capture has the host speaking next.
+///
+/// Captures are text, one frame per line: `>` for what the host sends, `<` for what the device
+/// answers, then the bytes in hex. `#` starts a comment:
+///
+/// ```text
+/// # SerialNumber
+/// > 00 05 34 89 22
+/// < 00 09 35 00 12 d6 87 14 b6
+/// ```
+///
+/// Clones share the script, so keep one to check [ReplayPort::is_finished] after handing the
+/// other to a device
+#[derive(Debug, Clone)]
+pub struct ReplayPort {
+    script: Arc<Mutex<ScriptState>>,
+    timeout: Duration,
+}
+
+impl ReplayPort {
+    /// Parses a capture in the format above
+    pub fn from_hex(capture: &str) -> io::Result<Self> {
+        let mut steps = VecDeque::new();
+        for (number, line) in capture.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let invalid = |reason: String| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: {}", number + 1, reason),
+                )
+            };
+            let (direction, bytes) = line.split_at(1);
+            let bytes = bytes
+                .split_whitespace()
+                .map(|byte| {
+                    u8::from_str_radix(byte, 16)
+                        .map_err(|_| invalid(format!("bad byte {:?}", byte)))
+                })
+                .collect::<io::Result<Vec<u8>>>()?;
+            steps.push_back(match direction {
+                ">" => Step::Expect(bytes),
+                "<" => Step::Respond(bytes),
+                other => return Err(invalid(format!("expected > or <, got {:?}", other))),
+            });
+        }
+        Ok(Self {
+            script: Arc::new(Mutex::new(ScriptState {
+                steps,
+                written: Vec::new(),
+            })),
+            timeout: Duration::from_millis(100),
+        })
+    }
+
+    /// Reads a capture file in the format above
+    pub fn from_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::from_hex(&std::fs::read_to_string(path)?)
+    }
+
+    /// Whether every scripted write has been made and every response read
+    pub fn is_finished(&self) -> bool {
+        let script = self.lock();
+        script.steps.is_empty() && script.written.is_empty()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ScriptState> {
+        self.script.lock().unwrap()
+    }
+}
+
+impl From<ReplayPort> for Box<dyn SerialPort> {
+    fn from(port: ReplayPort) -> Self {
+        Box::new(port)
+    }
+}
+
+impl io::Read for ReplayPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut script = self.lock();
+        let Some(Step::Respond(bytes)) = script.steps.front_mut() else {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "capture has nothing to read"));
+        };
+        let len = buf.len().min(bytes.len());
+        buf[..len].copy_from_slice(&bytes[..len]);
+        bytes.drain(..len);
+        if bytes.is_empty() {
+            script.steps.pop_front();
+        }
+        Ok(len)
+    }
+}
+
+impl io::Write for ReplayPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut script = self.lock();
+        let ScriptState { steps, written } = &mut *script;
+        let expected = match steps.front() {
+            Some(Step::Expect(expected)) => expected,
+            Some(Step::Respond(_)) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("wrote {} before reading the response", hex(buf)),
+                ))
+            }
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("wrote {} after the end of the capture", hex(buf)),
+                ))
+            }
+        };
+        // a write may span the end of this frame and the start of the next
+        let len = buf.len().min(expected.len() - written.len());
+        written.extend_from_slice(&buf[..len]);
+        if !expected.starts_with(written) {
+            let message = format!("wrote {}, capture expects {}", hex(written), hex(expected));
+            written.clear();
+            return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+        }
+        if written.len() == expected.len() {
+            written.clear();
+            steps.pop_front();
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SerialPort for ReplayPort {
+    fn name(&self) -> Option<String> {
+        Some("replay".to_owned())
+    }
+
+    fn baud_rate(&self) -> serialport::Result<u32> {
+        Ok(38400)
+    }
+
+    fn data_bits(&self) -> serialport::Result<DataBits> {
+        Ok(DataBits::Eight)
+    }
+
+    fn flow_control(&self) -> serialport::Result<FlowControl> {
+        Ok(FlowControl::None)
+    }
+
+    fn parity(&self) -> serialport::Result<Parity> {
+        Ok(Parity::None)
+    }
+
+    fn stop_bits(&self) -> serialport::Result<StopBits> {
+        Ok(StopBits::One)
+    }
+
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn set_baud_rate(&mut self, _: u32) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn set_data_bits(&mut self, _: DataBits) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn set_flow_control(&mut self, _: FlowControl) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn set_parity(&mut self, _: Parity) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn set_stop_bits(&mut self, _: StopBits) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> serialport::Result<()> {
+        self.timeout = timeout;
+        Ok(())
+    }
+
+    fn write_request_to_send(&mut self, _: bool) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn write_data_terminal_ready(&mut self, _: bool) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn read_clear_to_send(&mut self) -> serialport::Result<bool> {
+        Ok(true)
+    }
+
+    fn read_data_set_ready(&mut self) -> serialport::Result<bool> {
+        Ok(true)
+    }
+
+    fn read_ring_indicator(&mut self) -> serialport::Result<bool> {
+        Ok(false)
+    }
+
+    fn read_carrier_detect(&mut self) -> serialport::Result<bool> {
+        Ok(true)
+    }
+
+    fn bytes_to_read(&self) -> serialport::Result<u32> {
+        Ok(match self.lock().steps.front() {
+            Some(Step::Respond(bytes)) => bytes.len() as u32,
+            _ => 0,
+        })
+    }
+
+    fn bytes_to_write(&self) -> serialport::Result<u32> {
+        Ok(0)
+    }
+
+    /// Does nothing: responses in the capture are never discarded, so a capture doesn't have to
+    /// model what clearing would have dropped
+    fn clear(&self, _: ClearBuffer) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn try_clone(&self) -> serialport::Result<Box<dyn SerialPort>> {
+        Ok(Box::new(self.clone()))
+    }
+
+    fn set_break(&self) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn clear_break(&self) -> serialport::Result<()> {
+        Ok(())
+    }
+}
@@ -1,3 +1,4 @@
+use crate::catalog::{Message, MessageId};
 use crate::{ReadError, Device};
 
 /// Represents a datastream that can emit out a `T`
@@ -15,9 +16,7 @@ impl Get<f64> for Device {
     //send a link to that
     fn get(&mut self) -> Result<f64, ReadError> {
         let mut rbuff = [0u8; 8];
-        self.serialport.read_exact(&mut rbuff)?;
-        self.read_bytes += 8;
-        self.read_checksum.update(&rbuff);
+        self.read_exact_tracked(&mut rbuff)?;
         Ok(f64::from_be_bytes(rbuff))
     }
 
@@ -31,9 +30,7 @@ impl Get<f64> for Device {
 impl Get<f32> for Device {
     fn get(&mut self) -> Result<f32, ReadError> {
         let mut rbuff = [0u8; 4];
-        self.serialport.read_exact(&mut rbuff)?;
-        self.read_bytes += 4;
-        self.read_checksum.update(&rbuff);
+        self.read_exact_tracked(&mut rbuff)?;
         Ok(f32::from_be_bytes(rbuff))
     }
 
@@ -47,9 +44,7 @@ impl Get<f32> for Device {
 impl Get<i32> for Device {
     fn get(&mut self) -> Result<i32, ReadError> {
         let mut rbuff = [0u8; 4];
-        self.serialport.read_exact(&mut rbuff)?;
-        self.read_bytes += 4;
-        self.read_checksum.update(&rbuff);
+        self.read_exact_tracked(&mut rbuff)?;
         Ok(i32::from_be_bytes(rbuff))
     }
 
@@ -63,9 +58,7 @@ impl Get<i32> for Device {
 impl Get<i16> for Device {
     fn get(&mut self) -> Result<i16, ReadError> {
         let mut rbuff = [0u8; 2];
-        self.serialport.read_exact(&mut rbuff)?;
-        self.read_bytes += 2;
-        self.read_checksum.update(&rbuff);
+        self.read_exact_tracked(&mut rbuff)?;
         Ok(i16::from_be_bytes(rbuff))
     }
 
@@ -79,9 +72,7 @@ impl Get<i16> for Device {
 impl Get<i8> for Device {
     fn get(&mut self) -> Result<i8, ReadError> {
         let mut rbuff = [0u8; 1];
-        self.serialport.read_exact(&mut rbuff)?;
-        self.read_bytes += 1;
-        self.read_checksum.update(&rbuff);
+        self.read_exact_tracked(&mut rbuff)?;
         Ok(i8::from_be_bytes(rbuff))
     }
 
@@ -95,9 +86,7 @@ impl Get<i8> for Device {
 impl Get<u32> for Device {
     fn get(&mut self) -> Result<u32, ReadError> {
         let mut rbuff = [0u8; 4];
-        self.serialport.read_exact(&mut rbuff)?;
-        self.read_bytes += 4;
-        self.read_checksum.update(&rbuff);
+        self.read_exact_tracked(&mut rbuff)?;
         Ok(u32::from_be_bytes(rbuff))
     }
 
@@ -111,9 +100,7 @@ impl Get<u32> for Device {
 impl Get<u16> for Device {
     fn get(&mut self) -> Result<u16, ReadError> {
         let mut rbuff = [0u8; 2];
-        self.serialport.read_exact(&mut rbuff)?;
-        self.read_bytes += 2;
-        self.read_checksum.update(&rbuff);
+        self.read_exact_tracked(&mut rbuff)?;
         Ok(u16::from_be_bytes(rbuff))
     }
 
@@ -127,9 +114,7 @@ impl Get<u16> for Device {
 impl Get<u8> for Device {
     fn get(&mut self) -> Result<u8, ReadError> {
         let mut rbuff = [0u8; 1];
-        self.serialport.read_exact(&mut rbuff)?;
-        self.read_bytes += 1;
-        self.read_checksum.update(&rbuff);
+        self.read_exact_tracked(&mut rbuff)?;
         Ok(rbuff[0])
     }
 
@@ -143,16 +128,17 @@ impl Get<u8> for Device {
 impl Get<bool> for Device {
     fn get(&mut self) -> Result<bool, ReadError> {
         let mut rbuff = [0u8; 1];
-        self.serialport.read_exact(&mut rbuff)?;
-        self.read_bytes += 1;
-        self.read_checksum.update(&rbuff);
+        self.read_exact_tracked(&mut rbuff)?;
         if rbuff[0] == 0 {
             Ok(false)
         } else if rbuff[0] == 1 {
             Ok(true)
+        } else if self.lenient_bools {
+            log::debug!("Reading boolean {:#04x} as true", rbuff[0]);
+            Ok(true)
         } else {
             Err(ReadError::ParseError(
-                "Boolean must be 0 for true, 1 for false and nothing else".to_string(),
+                Message::new(MessageId::InvalidBool).with("value", rbuff[0]),
             ))
         }
     }
@@ -164,8 +150,135 @@ impl Get<bool> for Device {
     }
 }
 
+impl<const N: usize> Get<[u8; N]> for Device {
+    fn get(&mut self) -> Result<[u8; N], ReadError> {
+        let mut rbuff = [0u8; N];
+        self.read_exact_tracked(&mut rbuff)?;
+        Ok(rbuff)
+    }
+
+    fn get_string(&mut self) -> Result<String, ReadError> {
+        Ok(String::from_utf8(Get::<[u8; N]>::get(self)?.into())?)
+    }
+}
+
+/// Byte strings are prefixed by a single byte count, like the other variable-length lists in the
+/// protocol (data components, FIR taps)
+impl Get<Vec<u8>> for Device {
+    fn get(&mut self) -> Result<Vec<u8>, ReadError> {
+        let count = Get::<u8>::get(self)?;
+        let mut rbuff = vec![0u8; count as usize];
+        self.read_exact_tracked(&mut rbuff)?;
+        Ok(rbuff)
+    }
+
+    fn get_string(&mut self) -> Result<String, ReadError> {
+        Ok(String::from_utf8(Get::<Vec<u8>>::get(self)?)?)
+    }
+}
+
+// Fields can also be read from a payload already in memory, e.g. a frame captured by a logic
+// analyzer, with a `&mut &[u8]` cursor that advances past each field read. Running out of bytes is
+// reported like an interrupted stream, as a PipeError with ErrorKind::UnexpectedEof
+
+macro_rules! cursor_get {
+    ($($t:ty),*) => {$(
+        impl Get<$t> for &[u8] {
+            fn get(&mut self) -> Result<$t, ReadError> {
+                Ok(<$t>::from_be_bytes(Get::<[u8; std::mem::size_of::<$t>()]>::get(self)?))
+            }
+
+            fn get_string(&mut self) -> Result<String, ReadError> {
+                Ok(String::from_utf8(Get::<$t>::get(self)?.to_be_bytes().into())?)
+            }
+        }
+    )*};
+}
+
+cursor_get!(f64, f32, i32, i16, i8, u32, u16, u8);
+
+impl<const N: usize> Get<[u8; N]> for &[u8] {
+    fn get(&mut self) -> Result<[u8; N], ReadError> {
+        let mut rbuff = [0u8; N];
+        std::io::Read::read_exact(self, &mut rbuff)?;
+        Ok(rbuff)
+    }
+
+    fn get_string(&mut self) -> Result<String, ReadError> {
+        Ok(String::from_utf8(Get::<[u8; N]>::get(self)?.into())?)
+    }
+}
+
+/// Always strict; [Device::set_lenient_bools] only applies to live reads
+impl Get<bool> for &[u8] {
+    fn get(&mut self) -> Result<bool, ReadError> {
+        match Get::<u8>::get(self)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            value => Err(ReadError::ParseError(
+                Message::new(MessageId::InvalidBool).with("value", value),
+            )),
+        }
+    }
+
+    fn get_string(&mut self) -> Result<String, ReadError> {
+        Ok(String::from_utf8(Get::<u8>::get(self)?.to_be_bytes().into())?)
+    }
+}
+
+impl Get<Vec<u8>> for &[u8] {
+    fn get(&mut self) -> Result<Vec<u8>, ReadError> {
+        let count = Get::<u8>::get(self)? as usize;
+        if self.len() < count {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
+        let (bytes, rest) = self.split_at(count);
+        *self = rest;
+        Ok(bytes.to_vec())
+    }
+
+    fn get_string(&mut self) -> Result<String, ReadError> {
+        Ok(String::from_utf8(Get::<Vec<u8>>::get(self)?)?)
+    }
+}
+
+impl Device {
+    /// Fills `buf` from the buffered rest of the frame, or the serial port once that runs out,
+    /// counting the bytes towards the current frame and feeding them to the frame checksum.
+    /// Every [Get] impl should read through this
+    pub(crate) fn read_exact_tracked(&mut self, buf: &mut [u8]) -> Result<(), ReadError> {
+        // a parser asking for more than the frame holds (e.g. a component count that's too
+        // large) must not read on into the next frame
+        let wanted = self.read_bytes as usize + buf.len();
+        if self.frame_len != 0 && wanted > self.frame_len as usize {
+            let expected = self.frame_len;
+            self.reset_frame();
+            self.stats.size_errors += 1;
+            return Err(ReadError::SizeMismatch {
+                expected,
+                actual: wanted.min(u16::MAX as usize) as u16,
+            });
+        }
+        let buffered = (self.rx_buffer.len() - self.rx_pos).min(buf.len());
+        let (from_buffer, from_port) = buf.split_at_mut(buffered);
+        from_buffer.copy_from_slice(&self.rx_buffer[self.rx_pos..self.rx_pos + buffered]);
+        self.rx_pos += buffered;
+        if self.rx_pos == self.rx_buffer.len() {
+            self.rx_buffer.clear();
+            self.rx_pos = 0;
+        }
+        if let Err(e) = self.port_read_exact(from_port) {
+            self.note_port_error(&e);
+            return Err(e.into());
+        }
+        self.read_bytes += buf.len() as u16;
+        self.read_checksum.update(buf);
+        Ok(())
+    }
+}
+
 /// Contains the device type and revision
-#[derive(Debug, Display)]
+#[derive(Debug, Display, Clone, PartialEq)]
 #[allow(unused)]
 #[display(
     fmt = "ModInfoResp {{ device_type: {}, revision: {} }}",
@@ -178,4 +291,84 @@ pub struct ModInfoResp {
 
     /// Device Version
     pub revision: String,
+
+    /// Device family, parsed from [ModInfoResp::device_type]
+    pub model: Model,
+
+    /// Firmware version, parsed from [ModInfoResp::revision]. [None] if it isn't a dotted version
+    pub firmware: Option<FirmwareVersion>,
+}
+
+impl ModInfoResp {
+    /// Identity from the raw GetModInfoResp strings, parsing the typed fields
+    pub fn new(device_type: String, revision: String) -> Self {
+        Self {
+            model: Model::from_device_type(&device_type),
+            firmware: FirmwareVersion::parse(&revision),
+            device_type,
+            revision,
+        }
+    }
+}
+
+/// Device family, as identified by GetModInfo
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Model {
+    TargetPoint3,
+    Trax,
+    Trax2,
+
+    /// A device type this library doesn't recognize. See [ModInfoResp::device_type]
+    Unknown,
+}
+
+impl Model {
+    /// Recognizes a device type string, e.g. `"TP3 "`
+    pub fn from_device_type(device_type: &str) -> Self {
+        let device_type = device_type.trim_end_matches([' ', '\0']);
+        if device_type.starts_with("TP3") {
+            Model::TargetPoint3
+        } else if device_type.starts_with("TRAX2") || device_type.starts_with("TRX2") {
+            Model::Trax2
+        } else if device_type.starts_with("TRAX") {
+            Model::Trax
+        } else {
+            Model::Unknown
+        }
+    }
+}
+
+/// Firmware version, ordered so version-dependent behavior can be gated with comparisons.
+/// Revisions are reported as e.g. `"5.04"`, which is 5.4.0
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[display(fmt = "{}.{}.{}", major, minor, patch)]
+pub struct FirmwareVersion {
+    pub major: u16,
+    pub minor: u16,
+    pub patch: u16,
+}
+
+impl FirmwareVersion {
+    pub const fn new(major: u16, minor: u16, patch: u16) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// Parses a revision string of one to three dot-separated numbers, ignoring surrounding
+    /// padding. Missing parts are 0
+    pub fn parse(revision: &str) -> Option<Self> {
+        let revision = revision.trim_matches(|c: char| c.is_whitespace() || c == '\0');
+        let mut parts = revision.split('.').map(|part| part.parse::<u16>().ok());
+        let major = parts.next()??;
+        let minor = parts.next().unwrap_or(Some(0))?;
+        let patch = parts.next().unwrap_or(Some(0))?;
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(Self::new(major, minor, patch))
+    }
 }
@@ -0,0 +1,192 @@
+// ROS uses REP-103 conventions: bodies are x forward, y left, z up (FLU), the world is east, north,
+// up (ENU), and everything is in SI units. The device, like the rest of this crate, uses x forward,
+// y right, z down (FRD) against north, east, down (NED), with accel in g and the field in µT. These
+// types mirror the ROS 2 message layouts field for field, without depending on a ROS client
+// library, so they can be copied into whichever message types a project generates.
+
+use crate::acquisition::Data;
+use crate::processing::Orientation;
+use crate::rotation;
+
+use std::f64::consts::FRAC_1_SQRT_2;
+
+/// Standard gravity, for converting accel from g to m/s²
+pub const STANDARD_GRAVITY: f64 = 9.80665;
+
+/// Pitch and roll accuracy assumed for the orientation covariance, in degrees RMS. The
+/// TargetPoint3 datasheet gives ±0.2˚ for both
+pub const DEFAULT_TILT_ACCURACY: f64 = 0.2;
+
+/// `geometry_msgs/Vector3`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Vector3 {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+/// `geometry_msgs/Quaternion`. Note the x, y, z, w field order, unlike
+/// [crate::rotation::Quaternion]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quaternion {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub w: f64,
+}
+
+impl Default for Quaternion {
+    fn default() -> Self {
+        Self {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+            w: 1.0,
+        }
+    }
+}
+
+/// Row-major 3x3 covariance, as in ROS messages. ROS reads an all-zero covariance as "unknown",
+/// and one starting with -1 as "this field has no data"
+pub type Covariance = [f64; 9];
+
+/// Covariance marking a field the device didn't report
+pub const NOT_REPORTED: Covariance = [-1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0];
+
+/// `sensor_msgs/Imu`, without the header
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Imu {
+    /// Rotation from the body (FLU) to the world (ENU)
+    pub orientation: Quaternion,
+    pub orientation_covariance: Covariance,
+
+    /// rad/s, about the body axes
+    pub angular_velocity: Vector3,
+    pub angular_velocity_covariance: Covariance,
+
+    /// m/s² along the body axes, including gravity
+    pub linear_acceleration: Vector3,
+    pub linear_acceleration_covariance: Covariance,
+}
+
+/// `sensor_msgs/MagneticField`, without the header
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MagneticField {
+    /// Tesla, along the body axes
+    pub magnetic_field: Vector3,
+    pub magnetic_field_covariance: Covariance,
+}
+
+impl From<rotation::Quaternion> for Quaternion {
+    /// Converts a rotation from FRD to NED, as used by this crate, into one from FLU to ENU
+    fn from(q: rotation::Quaternion) -> Self {
+        // NED to ENU swaps north and east and flips down, a half turn about the axis between
+        // x and y; FLU to FRD is a half turn about x
+        let ned_to_enu = [0.0, FRAC_1_SQRT_2, FRAC_1_SQRT_2, 0.0];
+        let flu_to_frd = [0.0, 1.0, 0.0, 0.0];
+        let [w, x, y, z] = mul(mul(ned_to_enu, <[f32; 4]>::from(q).map(f64::from)), flu_to_frd);
+        Quaternion { x, y, z, w }
+    }
+}
+
+/// Quaternion product in w, x, y, z order: `b` applied first, then `a`
+fn mul(a: [f64; 4], b: [f64; 4]) -> [f64; 4] {
+    [
+        a[0] * b[0] - a[1] * b[1] - a[2] * b[2] - a[3] * b[3],
+        a[0] * b[1] + a[1] * b[0] + a[2] * b[3] - a[3] * b[2],
+        a[0] * b[2] - a[1] * b[3] + a[2] * b[0] + a[3] * b[1],
+        a[0] * b[3] + a[1] * b[2] - a[2] * b[1] + a[3] * b[0],
+    ]
+}
+
+/// Vector along FRD axes, scaled, as one along FLU axes. [None] unless all three are present
+fn flu(x: Option<f32>, y: Option<f32>, z: Option<f32>, scale: f64) -> Option<Vector3> {
+    Some(Vector3 {
+        x: x? as f64 * scale,
+        y: -(y? as f64) * scale,
+        z: -(z? as f64) * scale,
+    })
+}
+
+/// Diagonal covariance from roll, pitch and yaw standard deviations in degrees
+fn angle_covariance(roll: f64, pitch: f64, yaw: f64) -> Covariance {
+    let variance = |degrees: f64| degrees.to_radians().powi(2);
+    let mut covariance = [0.0; 9];
+    covariance[0] = variance(roll);
+    covariance[4] = variance(pitch);
+    covariance[8] = variance(yaw);
+    covariance
+}
+
+impl Imu {
+    /// Converts a sample. Orientation comes from [Data::quaternion] if the device sent one,
+    /// otherwise from heading, pitch and roll (converted from mils if [Data::angle_format] says
+    /// so), otherwise from accel and mag as in [Orientation::from_data]. Yaw variance is [Data::mag_accuracy] squared and
+    /// pitch and roll use [DEFAULT_TILT_ACCURACY]; left all zero ("unknown") without
+    /// mag_accuracy. Fields the device didn't send get [NOT_REPORTED] covariances, and angular
+    /// velocity and acceleration are always "unknown" since the device doesn't estimate them
+    pub fn from_data(data: &Data) -> Self {
+        let orientation = data
+            .quaternion
+            .map(|[w, x, y, z]| rotation::Quaternion { w, x, y, z })
+            .or_else(|| {
+                let degrees = (data.heading_degrees(), data.pitch_degrees(), data.roll_degrees());
+                let orientation = match degrees {
+                    (Some(heading), Some(pitch), Some(roll)) => Orientation {
+                        heading,
+                        pitch,
+                        roll,
+                    },
+                    _ => Orientation::from_data(data)?,
+                };
+                Some(orientation.to_quaternion())
+            });
+        let angular_velocity = flu(data.gyro_x, data.gyro_y, data.gyro_z, 1.0);
+        let linear_acceleration = flu(data.accel_x, data.accel_y, data.accel_z, STANDARD_GRAVITY);
+
+        Imu {
+            orientation: orientation.map(Quaternion::from).unwrap_or_default(),
+            orientation_covariance: match (orientation, data.mag_accuracy) {
+                (None, _) => NOT_REPORTED,
+                (Some(_), Some(accuracy)) => angle_covariance(
+                    DEFAULT_TILT_ACCURACY,
+                    DEFAULT_TILT_ACCURACY,
+                    accuracy as f64,
+                ),
+                (Some(_), None) => [0.0; 9],
+            },
+            angular_velocity: angular_velocity.unwrap_or_default(),
+            angular_velocity_covariance: angular_velocity.map_or(NOT_REPORTED, |_| [0.0; 9]),
+            linear_acceleration: linear_acceleration.unwrap_or_default(),
+            linear_acceleration_covariance: linear_acceleration
+                .map_or(NOT_REPORTED, |_| [0.0; 9]),
+        }
+    }
+
+    /// Orientation-only message, with the given heading accuracy in degrees RMS
+    pub fn from_orientation(orientation: &Orientation, heading_accuracy: f64) -> Self {
+        Imu {
+            orientation: orientation.to_quaternion().into(),
+            orientation_covariance: angle_covariance(
+                DEFAULT_TILT_ACCURACY,
+                DEFAULT_TILT_ACCURACY,
+                heading_accuracy,
+            ),
+            angular_velocity: Vector3::default(),
+            angular_velocity_covariance: NOT_REPORTED,
+            linear_acceleration: Vector3::default(),
+            linear_acceleration_covariance: NOT_REPORTED,
+        }
+    }
+}
+
+impl MagneticField {
+    /// Converts the mag components of a sample, or [None] if any are missing. The covariance is
+    /// left "unknown"
+    pub fn from_data(data: &Data) -> Option<Self> {
+        Some(MagneticField {
+            magnetic_field: flu(data.mag_x, data.mag_y, data.mag_z, 1e-6)?,
+            magnetic_field_covariance: [0.0; 9],
+        })
+    }
+}
@@ -0,0 +1,210 @@
+// All rotations here use the aerospace convention this crate uses elsewhere: x forward, y right,
+// z down (NED when level and facing north), with heading, pitch and roll applied as intrinsic
+// Z-Y-X rotations. Matrices are row-major and rotate vectors from the rotated frame into the
+// reference frame, i.e. `world = matrix * body`.
+
+use crate::config::MountingRef;
+use crate::processing::{wrap_heading, Orientation};
+
+/// A unit quaternion, in the same w, x, y, z order as [crate::acquisition::Data::quaternion].
+/// Enable the `mint` feature to convert to and from `mint::Quaternion`, which nalgebra, glam and
+/// cgmath all accept
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quaternion {
+    pub w: f32,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Quaternion {
+    pub const IDENTITY: Quaternion = Quaternion {
+        w: 1.0,
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+    };
+
+    /// Composes two rotations: the result applies `other` first, then `self`
+    pub fn mul(&self, other: &Quaternion) -> Quaternion {
+        let (a, b) = (self, other);
+        Quaternion {
+            w: a.w * b.w - a.x * b.x - a.y * b.y - a.z * b.z,
+            x: a.w * b.x + a.x * b.w + a.y * b.z - a.z * b.y,
+            y: a.w * b.y - a.x * b.z + a.y * b.w + a.z * b.x,
+            z: a.w * b.z + a.x * b.y - a.y * b.x + a.z * b.w,
+        }
+    }
+
+    /// The inverse rotation
+    pub fn conjugate(&self) -> Quaternion {
+        Quaternion {
+            w: self.w,
+            x: -self.x,
+            y: -self.y,
+            z: -self.z,
+        }
+    }
+
+    /// Scales to unit length, e.g. after accumulating rounding error
+    pub fn normalize(&self) -> Quaternion {
+        let norm = (self.w * self.w + self.x * self.x + self.y * self.y + self.z * self.z).sqrt();
+        Quaternion {
+            w: self.w / norm,
+            x: self.x / norm,
+            y: self.y / norm,
+            z: self.z / norm,
+        }
+    }
+
+    pub fn to_rotation_matrix(&self) -> [[f32; 3]; 3] {
+        let Quaternion { w, x, y, z } = *self;
+        [
+            [
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y - w * z),
+                2.0 * (x * z + w * y),
+            ],
+            [
+                2.0 * (x * y + w * z),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z - w * x),
+            ],
+            [
+                2.0 * (x * z - w * y),
+                2.0 * (y * z + w * x),
+                1.0 - 2.0 * (x * x + y * y),
+            ],
+        ]
+    }
+
+    /// Rotates a vector from the rotated frame into the reference frame
+    pub fn rotate(&self, v: [f32; 3]) -> [f32; 3] {
+        let m = self.to_rotation_matrix();
+        std::array::from_fn(|i| m[i][0] * v[0] + m[i][1] * v[1] + m[i][2] * v[2])
+    }
+}
+
+impl From<[f32; 4]> for Quaternion {
+    fn from([w, x, y, z]: [f32; 4]) -> Self {
+        Quaternion { w, x, y, z }
+    }
+}
+
+impl From<Quaternion> for [f32; 4] {
+    fn from(q: Quaternion) -> Self {
+        [q.w, q.x, q.y, q.z]
+    }
+}
+
+#[cfg(feature = "mint")]
+impl From<Quaternion> for mint::Quaternion<f32> {
+    fn from(q: Quaternion) -> Self {
+        mint::Quaternion {
+            s: q.w,
+            v: mint::Vector3 {
+                x: q.x,
+                y: q.y,
+                z: q.z,
+            },
+        }
+    }
+}
+
+#[cfg(feature = "mint")]
+impl From<mint::Quaternion<f32>> for Quaternion {
+    fn from(q: mint::Quaternion<f32>) -> Self {
+        Quaternion {
+            w: q.s,
+            x: q.v.x,
+            y: q.v.y,
+            z: q.v.z,
+        }
+    }
+}
+
+/// Quaternion for heading, pitch and roll in degrees
+fn from_euler_degrees(heading: f32, pitch: f32, roll: f32) -> Quaternion {
+    let (sh, ch) = (heading.to_radians() / 2.0).sin_cos();
+    let (sp, cp) = (pitch.to_radians() / 2.0).sin_cos();
+    let (sr, cr) = (roll.to_radians() / 2.0).sin_cos();
+    Quaternion {
+        w: cr * cp * ch + sr * sp * sh,
+        x: sr * cp * ch - cr * sp * sh,
+        y: cr * sp * ch + sr * cp * sh,
+        z: cr * cp * sh - sr * sp * ch,
+    }
+}
+
+impl Orientation {
+    /// Rotation from the platform frame to the local level frame (north, east, down)
+    pub fn to_quaternion(&self) -> Quaternion {
+        from_euler_degrees(self.heading, self.pitch, self.roll)
+    }
+
+    /// Same rotation as [Orientation::to_quaternion], as a row-major matrix
+    pub fn to_rotation_matrix(&self) -> [[f32; 3]; 3] {
+        self.to_quaternion().to_rotation_matrix()
+    }
+
+    /// Inverse of [Orientation::to_quaternion]. Near ±90˚ pitch heading and roll can't be told
+    /// apart, and roll is reported as 0
+    pub fn from_quaternion(q: &Quaternion) -> Self {
+        let Quaternion { w, x, y, z } = q.normalize();
+        let sin_pitch = (2.0 * (w * y - x * z)).clamp(-1.0, 1.0);
+        let pitch = sin_pitch.asin();
+        let (heading, roll) = if sin_pitch.abs() > 0.99999 {
+            (2.0 * x.atan2(w) * -sin_pitch.signum(), 0.0)
+        } else {
+            (
+                (2.0 * (w * z + x * y)).atan2(1.0 - 2.0 * (y * y + z * z)),
+                (2.0 * (w * x + y * z)).atan2(1.0 - 2.0 * (x * x + y * y)),
+            )
+        };
+        Orientation {
+            heading: wrap_heading(heading.to_degrees()),
+            pitch: pitch.to_degrees(),
+            roll: roll.to_degrees(),
+        }
+    }
+
+    /// Rotation from the sensor's own frame to the local level frame. The device reports heading,
+    /// pitch and roll for the platform it's mounted on, already corrected for `mounting`; use this
+    /// when you need to rotate vectors measured in the sensor's frame
+    pub fn sensor_quaternion(&self, mounting: MountingRef) -> Quaternion {
+        self.to_quaternion().mul(&mounting.to_quaternion())
+    }
+}
+
+impl MountingRef {
+    /// Rotation from the sensor frame to the platform frame, following Figure 4-2 of the user
+    /// manual: XUp and YUp have that sensor axis pointing up, ZDown is upside down, and the
+    /// angle is a further clockwise turn (viewed from above) about the vertical
+    pub fn to_quaternion(&self) -> Quaternion {
+        use MountingRef::*;
+        let (turn, pitch, roll) = match self {
+            Std0 => (0.0, 0.0, 0.0),
+            Std90 => (90.0, 0.0, 0.0),
+            Std180 => (180.0, 0.0, 0.0),
+            Std270 => (270.0, 0.0, 0.0),
+            XUp0 => (0.0, 90.0, 0.0),
+            XUp90 => (90.0, 90.0, 0.0),
+            XUp180 => (180.0, 90.0, 0.0),
+            XUp270 => (270.0, 90.0, 0.0),
+            YUp0 => (0.0, 0.0, -90.0),
+            YUp90 => (90.0, 0.0, -90.0),
+            YUp180 => (180.0, 0.0, -90.0),
+            YUp270 => (270.0, 0.0, -90.0),
+            ZDown0 => (0.0, 0.0, 180.0),
+            ZDown90 => (90.0, 0.0, 180.0),
+            ZDown180 => (180.0, 0.0, 180.0),
+            ZDown270 => (270.0, 0.0, 180.0),
+        };
+        from_euler_degrees(turn, pitch, roll)
+    }
+
+    /// Same rotation as [MountingRef::to_quaternion], as a row-major matrix
+    pub fn to_rotation_matrix(&self) -> [[f32; 3]; 3] {
+        self.to_quaternion().to_rotation_matrix()
+    }
+}
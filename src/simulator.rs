@@ -0,0 +1,693 @@
+// A TargetPoint3 in software, for integration tests that want realistic frame sequences without
+// the hardware. Unlike MockTransport it keeps the device's state: live and saved settings, the
+// continuous-mode schedule, sleep and wake, and the calibration point count. Measurements come
+// from a configured orientation plus noise, with the accelerometer and magnetometer vectors
+// derived from the same orientation so tilt-compensation code sees consistent inputs.
+
+use crate::acquisition::{DataID, SampleTiming};
+use crate::calibration::CalScore;
+use crate::codec::{self, CodecError, FRAME_OVERHEAD};
+use crate::command::Command;
+use crate::config::{Baud, ConfigID, ConfigPair, MountingRef};
+use crate::processing::Orientation;
+use crate::transport::Transport;
+
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+/// Time the simulated device spends measuring each sample in continuous mode, on top of
+/// SampleDelay and sending the frame
+pub const ACQUISITION_TIME: Duration = Duration::from_millis(30);
+
+/// How long [Simulator::serve] waits for the host when nothing is scheduled
+const IDLE_POLL: Duration = Duration::from_millis(100);
+
+const MILS_PER_DEGREE: f32 = 6400.0 / 360.0;
+
+/// What the simulated device reports about itself and the world around it
+#[derive(Debug, Clone)]
+pub struct SimulatorConfig {
+    pub serial_number: u32,
+
+    /// Firmware version in GetModInfo, as the device spells it, e.g. `5.04`
+    pub firmware: String,
+
+    /// How the device is held. Move it between samples with [Simulator::set_orientation]
+    pub orientation: Orientation,
+
+    /// Standard deviation of the Gaussian noise on heading, pitch and roll, in degrees. The
+    /// accelerometer and magnetometer readings follow the noisy angles
+    pub noise: f32,
+
+    /// Earth's field at the device, in µT
+    pub field_strength: f32,
+
+    /// Angle of the field below horizontal, in degrees
+    pub inclination: f32,
+
+    /// Board temperature in °C
+    pub temperature: f32,
+
+    /// Seed for the noise, so runs can be repeated
+    pub seed: u64,
+
+    /// How often a point is taken during a calibration with UserCalAutoSampling on
+    pub cal_point_interval: Duration,
+
+    /// Score sent after the last calibration point
+    pub cal_score: CalScore,
+}
+
+impl Default for SimulatorConfig {
+    fn default() -> Self {
+        Self {
+            serial_number: 1234567,
+            firmware: "5.04".to_owned(),
+            orientation: Orientation {
+                heading: 0.0,
+                pitch: 0.0,
+                roll: 0.0,
+            },
+            noise: 0.1,
+            field_strength: 50.0,
+            inclination: 60.0,
+            temperature: 25.0,
+            seed: 1,
+            cal_point_interval: Duration::from_secs(1),
+            cal_score: CalScore {
+                mag_cal_score: 0.4,
+                accel_cal_score: 0.3,
+                distribution_error: 0.0,
+                tilt_error: 0.0,
+                tilt_range: 35.0,
+            },
+        }
+    }
+}
+
+/// Everything Save stores and a power cycle restores
+#[derive(Debug, Clone)]
+struct Settings {
+    /// SetConfig payloads, by config ID
+    config: BTreeMap<u8, Vec<u8>>,
+
+    /// Component IDs from SetDataComponents
+    components: Vec<u8>,
+
+    /// SetAcqParams payload
+    acq_params: Vec<u8>,
+
+    /// FIR taps from SetFIRFilters, by axis selector
+    fir_filters: BTreeMap<[u8; 2], Vec<f64>>,
+
+    /// Whether the device streams on power-up
+    continuous: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        let defaults = [
+            ConfigPair::Declination(0.0),
+            ConfigPair::TrueNorth(false),
+            ConfigPair::BigEndian(true),
+            ConfigPair::MountingRef(MountingRef::Std0),
+            ConfigPair::UserCalNumPoints(12),
+            ConfigPair::UserCalAutoSampling(true),
+            ConfigPair::BaudRate(Baud::B38400),
+            ConfigPair::MilOut(false),
+            ConfigPair::HPRDuringCal(true),
+            ConfigPair::MagCoeffSet(0),
+            ConfigPair::AccelCoeffSet(0),
+        ];
+        Self {
+            config: defaults
+                .into_iter()
+                .map(|pair| (u8::from(pair.id()), Vec::from(pair)))
+                .collect(),
+            components: vec![DataID::Heading as u8, DataID::Pitch as u8, DataID::Roll as u8],
+            // polled mode, no flushing, no delay
+            acq_params: vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+            fir_filters: BTreeMap::new(),
+            continuous: false,
+        }
+    }
+}
+
+impl Settings {
+    fn config(&self, id: ConfigID) -> Option<ConfigPair> {
+        ConfigPair::parse(self.config.get(&u8::from(id))?).ok()
+    }
+
+    fn sample_delay(&self) -> f32 {
+        match self.acq_params.get(6..10) {
+            Some(&[a, b, c, d]) => f32::from_be_bytes([a, b, c, d]),
+            _ => 0.0,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Calibration {
+    points: u32,
+    target: u32,
+    auto: bool,
+    next_point: Instant,
+}
+
+/// A simulated TargetPoint3, speaking the binary protocol over any [Transport].
+///
+/// It answers GetModInfo, SerialNumber, GetData, Set/GetConfig, Set/GetAcqParams,
+/// Set/GetFIRFilters, Save, PowerDown and the factory coefficient and calibration commands, and
+/// streams in continuous mode at the rate SampleDelay, the baud rate and [ACQUISITION_TIME] give.
+/// Settings are volatile until Save, and PowerDown puts it to sleep until the host sends
+/// anything, when it answers PowerUpDone and restores the saved settings. It always answers
+/// big-endian, whatever BigEndian is set to.
+///
+/// Use it directly as a [Transport] for [crate::Device::from_transport], where reads wait for
+/// the next scheduled sample like a real port would, or [Simulator::serve] it on a port such as
+/// one end of a pseudo-terminal, as the `tp3-sim` binary does
+#[derive(Debug)]
+pub struct Simulator {
+    config: SimulatorConfig,
+    rng: u64,
+    live: Settings,
+    saved: Settings,
+    asleep: bool,
+    streaming: bool,
+    calibrated: bool,
+    calibration: Option<Calibration>,
+    next_sample: Option<Instant>,
+
+    /// Bytes of a frame the host is still writing
+    written: Vec<u8>,
+
+    /// Frames for the host to read, with when their last byte is off the line
+    pending: VecDeque<(Instant, Vec<u8>)>,
+
+    /// When the line finishes sending what's queued
+    line_free: Instant,
+
+    /// Read timeout, when used as a [Transport]
+    timeout: Duration,
+}
+
+impl Simulator {
+    pub fn new(config: SimulatorConfig) -> Self {
+        Self {
+            // xorshift gets stuck at zero
+            rng: config.seed.max(1),
+            config,
+            live: Settings::default(),
+            saved: Settings::default(),
+            asleep: false,
+            streaming: false,
+            calibrated: false,
+            calibration: None,
+            next_sample: None,
+            written: Vec::new(),
+            pending: VecDeque::new(),
+            line_free: Instant::now(),
+            timeout: Duration::from_secs(1),
+        }
+    }
+
+    /// Moves the simulated device
+    pub fn set_orientation(&mut self, orientation: Orientation) {
+        self.config.orientation = orientation;
+    }
+
+    /// Whether the device is in continuous mode
+    pub fn is_streaming(&self) -> bool {
+        self.streaming
+    }
+
+    /// Whether the device is powered down
+    pub fn is_asleep(&self) -> bool {
+        self.asleep
+    }
+
+    /// Takes bytes from the host, acting on every complete frame. Frames with a bad checksum are
+    /// dropped, as the device does
+    pub fn receive(&mut self, bytes: &[u8]) {
+        if self.asleep {
+            // whatever woke the device is lost
+            self.asleep = false;
+            self.written.clear();
+            self.live = self.saved.clone();
+            self.queue(Command::PowerUpDone, &[]);
+            if self.live.continuous {
+                self.start_streaming(Instant::now());
+            }
+            return;
+        }
+        self.written.extend_from_slice(bytes);
+        loop {
+            let (command, payload, len) = match codec::decode_frame(&self.written) {
+                Ok((frame, len)) => (frame.command, frame.payload.to_vec(), len),
+                Err(CodecError::Incomplete) => break,
+                Err(e) => {
+                    log::debug!("Simulator dropped {} bytes: {}", self.written.len(), e);
+                    self.written.clear();
+                    break;
+                }
+            };
+            self.written.drain(..len);
+            match Command::try_from(command) {
+                Ok(command) => self.handle(command, &payload),
+                Err(e) => log::debug!("Simulator ignored frame: {}", e),
+            }
+        }
+    }
+
+    /// Takes whatever the device has sent by now, including samples and calibration points that
+    /// have come due. Frames take as long to arrive as they would at the configured baud rate
+    pub fn output(&mut self) -> Vec<u8> {
+        let now = Instant::now();
+        self.poll(now);
+        let mut output = Vec::new();
+        while let Some(frame) = self.take_ready(now) {
+            output.extend(frame);
+        }
+        output
+    }
+
+    /// When the device next has something to send unprompted, if anything is scheduled
+    pub fn next_event(&self) -> Option<Instant> {
+        let next_point = self
+            .calibration
+            .as_ref()
+            .filter(|calibration| calibration.auto)
+            .map(|calibration| calibration.next_point);
+        let next_frame = self.pending.front().map(|(ready, _)| *ready);
+        [self.next_sample, next_point, next_frame].into_iter().flatten().min()
+    }
+
+    /// Answers the host on `port` until it's closed or fails
+    pub fn serve(&mut self, port: &mut dyn Transport) -> io::Result<()> {
+        let mut buf = [0u8; 256];
+        loop {
+            let wait = match self.next_event() {
+                Some(at) => at.saturating_duration_since(Instant::now()),
+                None => IDLE_POLL,
+            };
+            port.set_timeout(wait.max(Duration::from_millis(1)))?;
+            match port.read(&mut buf) {
+                Ok(0) => return Ok(()),
+                Ok(len) => self.receive(&buf[..len]),
+                Err(e)
+                    if e.kind() == io::ErrorKind::TimedOut
+                        || e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+            let output = self.output();
+            if !output.is_empty() {
+                port.write_all(&output)?;
+                port.flush()?;
+            }
+        }
+    }
+
+    fn handle(&mut self, command: Command, payload: &[u8]) {
+        let now = Instant::now();
+        match command {
+            Command::GetModInfo => {
+                let mut info = *b"TP3     ";
+                for (byte, firmware) in info[4..].iter_mut().zip(self.config.firmware.bytes()) {
+                    *byte = firmware;
+                }
+                self.queue(Command::GetModInfoResp, &info);
+            }
+            Command::SerialNumber => {
+                let serial_number = self.config.serial_number.to_be_bytes();
+                self.queue(Command::SerialNumberResp, &serial_number);
+            }
+            Command::SetDataComponents => {
+                self.live.components = payload.get(1..).unwrap_or_default().to_vec();
+            }
+            Command::GetData => {
+                let sample = self.sample();
+                self.queue_at(Command::GetDataResp, &sample, now + ACQUISITION_TIME);
+            }
+            Command::SetConfig => {
+                if ConfigPair::parse(payload).is_ok() {
+                    self.live.config.insert(payload[0], payload.to_vec());
+                    self.queue(Command::SetConfigDone, &[]);
+                }
+            }
+            Command::GetConfig => {
+                // the response carries the value alone, without the ID SetConfig has
+                if let Some(setting) = payload.first().and_then(|id| self.live.config.get(id)) {
+                    let value = setting[1..].to_vec();
+                    self.queue(Command::GetConfigResp, &value);
+                }
+            }
+            Command::SetAcqParams => {
+                if payload.len() == 10 {
+                    self.live.acq_params = payload.to_vec();
+                    self.queue(Command::SetAcqParamsDone, &[]);
+                }
+            }
+            Command::GetAcqParams => {
+                let params = self.live.acq_params.clone();
+                self.queue(Command::GetAcqParamsResp, &params);
+            }
+            Command::SetFIRFilters => {
+                if let Some((axis, taps)) = parse_fir_filters(payload) {
+                    self.live.fir_filters.insert(axis, taps);
+                    self.queue(Command::SetFIRFiltersDone, &[]);
+                }
+            }
+            Command::GetFIRFilters => {
+                if let &[a, b] = payload {
+                    let taps = self.live.fir_filters.get(&[a, b]).cloned().unwrap_or_default();
+                    let mut response = vec![a, b, taps.len() as u8];
+                    response.extend(taps.iter().flat_map(|tap| tap.to_be_bytes()));
+                    self.queue(Command::GetFIRFiltersResp, &response);
+                }
+            }
+            Command::Save => {
+                self.saved = self.live.clone();
+                self.queue(Command::SaveDone, &[0, 0]);
+            }
+            Command::StartContinuousMode => {
+                self.live.continuous = true;
+                self.start_streaming(now);
+            }
+            Command::StopContinuousMode => {
+                self.live.continuous = false;
+                self.streaming = false;
+                self.next_sample = None;
+            }
+            Command::PowerDown => {
+                self.queue(Command::PowerDownDone, &[]);
+                self.asleep = true;
+                self.streaming = false;
+                self.next_sample = None;
+                self.calibration = None;
+            }
+            Command::StartCal => {
+                let target = match self.live.config(ConfigID::UserCalNumPoints) {
+                    Some(ConfigPair::UserCalNumPoints(points)) => points,
+                    _ => 12,
+                };
+                let auto = !matches!(
+                    self.live.config(ConfigID::UserCalAutoSampling),
+                    Some(ConfigPair::UserCalAutoSampling(false))
+                );
+                self.calibration = Some(Calibration {
+                    points: 0,
+                    target,
+                    auto,
+                    next_point: now + self.config.cal_point_interval,
+                });
+                self.queue(Command::UserCalSampleCount, &0u32.to_be_bytes());
+            }
+            Command::TakeUserCalSample => self.take_cal_point(now),
+            Command::StopCal => self.calibration = None,
+            Command::FactoryMagCoeff => {
+                self.calibrated = false;
+                self.queue(Command::FactoryMagCoeffDone, &[]);
+            }
+            Command::FactorylAccelCoeff => self.queue(Command::FactoryAccelCoeffDone, &[]),
+            Command::CopyCoeffSet => self.queue(Command::CopyCoeffSetDone, &[]),
+            // responses, and commands a TargetPoint3 doesn't answer
+            _ => log::debug!("Simulator ignored {:?}", command),
+        }
+    }
+
+    fn start_streaming(&mut self, now: Instant) {
+        self.streaming = true;
+        self.next_sample = Some(now + self.sample_period());
+    }
+
+    fn take_cal_point(&mut self, now: Instant) {
+        let Some(calibration) = self.calibration.as_mut() else {
+            return;
+        };
+        calibration.points += 1;
+        calibration.next_point = now + self.config.cal_point_interval;
+        if calibration.points < calibration.target {
+            let points = calibration.points.to_be_bytes();
+            self.queue(Command::UserCalSampleCount, &points);
+            return;
+        }
+        self.calibration = None;
+        self.calibrated = true;
+        let score = self.config.cal_score;
+        let mut payload = Vec::new();
+        for value in [
+            score.mag_cal_score,
+            0.0,
+            score.accel_cal_score,
+            score.distribution_error,
+            score.tilt_error,
+            score.tilt_range,
+        ] {
+            payload.extend(value.to_be_bytes());
+        }
+        self.queue(Command::UserCalScore, &payload);
+    }
+
+    /// Queues samples and calibration points that are due by `now`
+    fn poll(&mut self, now: Instant) {
+        while let Some(at) = self.next_sample.filter(|&at| at <= now) {
+            let sample = self.sample();
+            self.queue_at(Command::GetDataResp, &sample, at);
+            // fall behind rather than burst if the host stopped reading for a while
+            self.next_sample = Some((at + self.sample_period()).max(now));
+        }
+        let point_due = matches!(
+            &self.calibration,
+            Some(calibration) if calibration.auto && calibration.next_point <= now
+        );
+        if point_due {
+            self.take_cal_point(now);
+        }
+    }
+
+    fn sample_period(&self) -> Duration {
+        let timing = SampleTiming {
+            sample_delay: Duration::from_secs_f32(self.live.sample_delay().max(0.0)),
+            fir_taps: 0,
+            baud_rate: self.baud_rate_bps(),
+            acquisition_time: ACQUISITION_TIME,
+            host_latency: Duration::ZERO,
+        };
+        timing.sample_period(self.sample_len() + FRAME_OVERHEAD)
+    }
+
+    /// GetDataResp payload size for the components set
+    fn sample_len(&self) -> usize {
+        1 + self
+            .live
+            .components
+            .iter()
+            .map(|&id| 1 + codec::component_value_len(id).unwrap_or(0))
+            .sum::<usize>()
+    }
+
+    /// A GetDataResp payload of the components set, measured now
+    fn sample(&mut self) -> Vec<u8> {
+        let Orientation {
+            heading,
+            pitch,
+            roll,
+        } = self.config.orientation;
+        let noisy = Orientation {
+            heading: crate::heading::wrap(heading + self.noise()),
+            pitch: (pitch + self.noise()).clamp(-90.0, 90.0),
+            roll: crate::heading::wrap_signed(roll + self.noise()),
+        };
+        let rotation = noisy.to_quaternion();
+        let to_body = rotation.conjugate();
+        let accel = to_body.rotate([0.0, 0.0, 1.0]);
+        let inclination = self.config.inclination.to_radians();
+        let field = [
+            self.config.field_strength * inclination.cos(),
+            0.0,
+            self.config.field_strength * inclination.sin(),
+        ];
+        let mag = to_body.rotate(field);
+
+        let mut output = noisy;
+        if let Some(ConfigPair::TrueNorth(true)) = self.live.config(ConfigID::TrueNorth) {
+            if let Some(ConfigPair::Declination(declination)) =
+                self.live.config(ConfigID::Declination)
+            {
+                output.heading = crate::heading::wrap(output.heading + declination);
+            }
+        }
+        if let Some(ConfigPair::MilOut(true)) = self.live.config(ConfigID::MilOut) {
+            output.heading *= MILS_PER_DEGREE;
+            output.pitch *= MILS_PER_DEGREE;
+            output.roll *= MILS_PER_DEGREE;
+        }
+        let distortion = mag.iter().any(|axis| axis.abs() > 150.0);
+        let temperature = self.config.temperature + self.noise() * 0.1;
+
+        let components = self.live.components.clone();
+        let mut payload = vec![components.len() as u8];
+        for id in components {
+            payload.push(id);
+            let floats: Vec<f32> = match DataID::try_from(id) {
+                Ok(DataID::Heading) => vec![output.heading],
+                Ok(DataID::Pitch) => vec![output.pitch],
+                Ok(DataID::Roll) => vec![output.roll],
+                Ok(DataID::Temperature) => vec![temperature],
+                Ok(DataID::AccelX) => vec![accel[0]],
+                Ok(DataID::AccelY) => vec![accel[1]],
+                Ok(DataID::AccelZ) => vec![accel[2]],
+                Ok(DataID::MagX) => vec![mag[0]],
+                Ok(DataID::MagY) => vec![mag[1]],
+                Ok(DataID::MagZ) => vec![mag[2]],
+                Ok(DataID::MagAccuracy) => vec![self.config.noise.max(0.3)],
+                Ok(DataID::GyroX | DataID::GyroY | DataID::GyroZ) => vec![0.0],
+                Ok(DataID::Quaternion) => <[f32; 4]>::from(rotation).to_vec(),
+                Ok(DataID::Distortion) => {
+                    payload.push(distortion as u8);
+                    continue;
+                }
+                Ok(DataID::CalStatus) => {
+                    payload.push(self.calibrated as u8);
+                    continue;
+                }
+                // 1 is the best accuracy band
+                Ok(DataID::HeadingStatus) => {
+                    payload.push(1);
+                    continue;
+                }
+                Err(_) => continue,
+            };
+            payload.extend(floats.iter().flat_map(|value| value.to_be_bytes()));
+        }
+        payload
+    }
+
+    /// Gaussian noise with the configured standard deviation, by Box-Muller on xorshift64*
+    fn noise(&mut self) -> f32 {
+        if self.config.noise == 0.0 {
+            return 0.0;
+        }
+        let u1 = self.uniform().max(f64::MIN_POSITIVE);
+        let u2 = self.uniform();
+        let normal = (-2.0 * u1.ln()).sqrt() * (std::f64::consts::TAU * u2).cos();
+        normal as f32 * self.config.noise
+    }
+
+    /// Uniform in [0, 1)
+    fn uniform(&mut self) -> f64 {
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        (self.rng.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// The next frame, if it's arrived by `now`
+    fn take_ready(&mut self, now: Instant) -> Option<Vec<u8>> {
+        match self.pending.front() {
+            Some((ready, _)) if *ready <= now => self.pending.pop_front().map(|(_, frame)| frame),
+            _ => None,
+        }
+    }
+
+    fn queue(&mut self, command: Command, payload: &[u8]) {
+        self.queue_at(command, payload, Instant::now());
+    }
+
+    /// Queues a frame the device starts sending at `at`, or once the line is free
+    fn queue_at(&mut self, command: Command, payload: &[u8], at: Instant) {
+        let mut frame = vec![0u8; payload.len() + FRAME_OVERHEAD];
+        // the largest payload here, a sample of every component, is far below the frame limit
+        codec::encode_frame(u8::from(command), payload, &mut frame).unwrap();
+        let timing = SampleTiming {
+            sample_delay: Duration::ZERO,
+            fir_taps: 0,
+            baud_rate: self.baud_rate_bps(),
+            acquisition_time: Duration::ZERO,
+            host_latency: Duration::ZERO,
+        };
+        let ready = at.max(self.line_free) + timing.transmission_time(frame.len());
+        self.line_free = ready;
+        self.pending.push_back((ready, frame));
+    }
+
+    fn baud_rate_bps(&self) -> u32 {
+        match self.live.config(ConfigID::BaudRate) {
+            Some(ConfigPair::BaudRate(baud)) => baud.bits_per_second(),
+            _ => 38400,
+        }
+    }
+}
+
+/// Axis selector and taps of a SetFIRFilters payload
+fn parse_fir_filters(payload: &[u8]) -> Option<([u8; 2], Vec<f64>)> {
+    let (&[a, b, count], taps) = payload.split_first_chunk::<3>()?;
+    if taps.len() != count as usize * 8 {
+        return None;
+    }
+    let taps = taps
+        .chunks_exact(8)
+        .map(|tap| f64::from_be_bytes(tap.try_into().unwrap()))
+        .collect();
+    Some(([a, b], taps))
+}
+
+impl Read for Simulator {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            let now = Instant::now();
+            self.poll(now);
+            if let Some(mut frame) = self.take_ready(now) {
+                let len = buf.len().min(frame.len());
+                buf[..len].copy_from_slice(&frame[..len]);
+                if len < frame.len() {
+                    // the rest has already arrived too
+                    frame.drain(..len);
+                    self.pending.push_front((now, frame));
+                }
+                return Ok(len);
+            }
+            if now >= deadline {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "simulator has nothing to send",
+                ));
+            }
+            let wake = self.next_event().map_or(deadline, |at| at.min(deadline));
+            std::thread::sleep(wake.saturating_duration_since(now));
+        }
+    }
+}
+
+impl Write for Simulator {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.receive(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for Simulator {
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.timeout = timeout;
+        Ok(())
+    }
+
+    fn clear_input(&mut self) -> io::Result<()> {
+        self.pending.clear();
+        Ok(())
+    }
+
+    fn baud_rate(&self) -> io::Result<u32> {
+        Ok(self.baud_rate_bps())
+    }
+}
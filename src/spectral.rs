@@ -0,0 +1,144 @@
+use crate::acquisition::Data;
+use crate::processing::unwrap_headings;
+
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+
+/// One-sided power spectral density of a signal
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spectrum {
+    /// Centre frequency of each bin in Hz, from 0 up to the Nyquist frequency
+    pub frequencies: Vec<f32>,
+
+    /// Power in each bin, in (input units)² per Hz
+    pub power: Vec<f32>,
+}
+
+impl Spectrum {
+    /// Width of each frequency bin in Hz
+    pub fn resolution(&self) -> f32 {
+        self.frequencies.get(1).copied().unwrap_or(0.0)
+    }
+
+    /// The `count` strongest local maxima as (frequency, power), strongest first. The DC bin is
+    /// skipped, since the mean is removed before the transform
+    pub fn peaks(&self, count: usize) -> Vec<(f32, f32)> {
+        let mut peaks: Vec<(f32, f32)> = (1..self.power.len())
+            .filter(|&i| {
+                let left = self.power[i - 1];
+                let right = self.power.get(i + 1).copied().unwrap_or(f32::NEG_INFINITY);
+                self.power[i] > left && self.power[i] >= right
+            })
+            .map(|i| (self.frequencies[i], self.power[i]))
+            .collect();
+        peaks.sort_by(|a, b| b.1.total_cmp(&a.1));
+        peaks.truncate(count);
+        peaks
+    }
+
+    /// Frequency of the strongest peak, if there is one
+    pub fn dominant_frequency(&self) -> Option<f32> {
+        self.peaks(1).first().map(|(frequency, _)| *frequency)
+    }
+
+    /// Total power between `low` and `high` Hz (inclusive), e.g. to compare the noise a FIR
+    /// filter would have to reject against the signal it should keep
+    pub fn band_power(&self, low: f32, high: f32) -> f32 {
+        let resolution = self.resolution();
+        self.frequencies
+            .iter()
+            .zip(&self.power)
+            .filter(|(f, _)| (low..=high).contains(*f))
+            .map(|(_, p)| p * resolution)
+            .sum()
+    }
+}
+
+/// Estimates the power spectral density of `samples`, taken at `sample_rate` Hz, using Welch's
+/// method: the window is cut into segments of `segment_len` samples overlapping by half, each
+/// segment has its mean removed and a Hann window applied, and the periodograms are averaged.
+/// Longer segments resolve frequencies more finely, shorter ones average away more noise.
+///
+/// If `samples` is shorter than `segment_len` a single segment of the whole window is used.
+/// Returns an empty spectrum for fewer than 2 samples
+pub fn power_spectral_density(samples: &[f32], sample_rate: f32, segment_len: usize) -> Spectrum {
+    let segment_len = segment_len.min(samples.len());
+    if segment_len < 2 {
+        return Spectrum {
+            frequencies: Vec::new(),
+            power: Vec::new(),
+        };
+    }
+
+    let window: Vec<f32> = (0..segment_len)
+        .map(|i| {
+            let phase = 2.0 * std::f32::consts::PI * i as f32 / segment_len as f32;
+            0.5 - 0.5 * phase.cos()
+        })
+        .collect();
+    let window_power: f32 = window.iter().map(|w| w * w).sum();
+
+    let fft = FftPlanner::new().plan_fft_forward(segment_len);
+    let bins = segment_len / 2 + 1;
+    let mut power = vec![0.0f32; bins];
+    let mut buffer = vec![Complex::new(0.0f32, 0.0); segment_len];
+
+    let step = (segment_len / 2).max(1);
+    let mut segments = 0;
+    let mut start = 0;
+    while start + segment_len <= samples.len() {
+        let segment = &samples[start..start + segment_len];
+        let mean = segment.iter().sum::<f32>() / segment_len as f32;
+        for ((out, sample), w) in buffer.iter_mut().zip(segment).zip(&window) {
+            *out = Complex::new((sample - mean) * w, 0.0);
+        }
+        fft.process(&mut buffer);
+        for (p, c) in power.iter_mut().zip(&buffer) {
+            *p += c.norm_sqr();
+        }
+        segments += 1;
+        start += step;
+    }
+
+    let scale = 1.0 / (sample_rate * window_power * segments as f32);
+    for (i, p) in power.iter_mut().enumerate() {
+        *p *= scale;
+        // fold the negative frequencies in, except for DC and (for even lengths) Nyquist
+        if i != 0 && !(segment_len.is_multiple_of(2) && i == bins - 1) {
+            *p *= 2.0;
+        }
+    }
+
+    Spectrum {
+        frequencies: (0..bins)
+            .map(|i| i as f32 * sample_rate / segment_len as f32)
+            .collect(),
+        power,
+    }
+}
+
+/// Spectrum of the heading over a window of samples. Headings are unwrapped first so a jump
+/// across north doesn't show up as broadband noise. Samples without a heading are skipped, so
+/// all samples should contain one if `sample_rate` is to be accurate
+pub fn heading_spectrum(data: &[Data], sample_rate: f32, segment_len: usize) -> Spectrum {
+    let headings: Vec<f32> = data.iter().filter_map(|d| d.heading).collect();
+    let unwrapped: Vec<f32> = unwrap_headings(&headings)
+        .into_iter()
+        .map(|h| h as f32)
+        .collect();
+    power_spectral_density(&unwrapped, sample_rate, segment_len)
+}
+
+/// Spectra of accel x, y and z over a window of samples, for finding vibration that couples into
+/// the heading. Samples missing an axis are skipped for that axis
+pub fn accel_spectra(data: &[Data], sample_rate: f32, segment_len: usize) -> [Spectrum; 3] {
+    let axis = |get: fn(&Data) -> Option<f32>| {
+        let samples: Vec<f32> = data.iter().filter_map(get).collect();
+        power_spectral_density(&samples, sample_rate, segment_len)
+    };
+    [
+        axis(|d| d.accel_x),
+        axis(|d| d.accel_y),
+        axis(|d| d.accel_z),
+    ]
+}
@@ -0,0 +1,168 @@
+// The serial port is full duplex, but Device needs &mut self for both directions, so a thread
+// blocked reading a stream holds off a thread that wants to stop it. Splitting clones the port
+// handle: reading state (the frame parser, unsolicited queue and stats) stays with the Reader, and
+// the Writer only encodes and sends frames.
+
+use crate::acquisition::{Data, TimestampedData};
+use crate::codec;
+use crate::command::Command;
+use crate::connection::{is_port_gone, write_frame_to, WriteStrategy};
+use crate::dispatch::{Events, Response};
+use crate::frame::{LinkStats, RawFrame};
+use crate::transport::Transport;
+use crate::{Device, ReadError, WriteError};
+
+use std::io::{self, Write};
+use std::time::Duration;
+
+/// Receiving half of a split [Device]. See [Device::split]
+pub struct Reader {
+    device: Device,
+}
+
+/// Sending half of a split [Device]. See [Device::split]
+pub struct Writer {
+    port: Box<dyn Transport>,
+
+    /// Whether continuous mode was last started or stopped through this writer, to hand back on
+    /// [Device::unsplit]
+    streaming: Option<bool>,
+
+    /// The device's [WriteStrategy] when it was split
+    write_strategy: WriteStrategy,
+
+    /// Set once a write finds the port gone, after which nothing more is written. Handed back
+    /// on [Device::unsplit], where the device's reconnect policy can take over
+    port_lost: bool,
+}
+
+impl Device {
+    /// Splits the device into halves that can be used from different threads, e.g. one draining
+    /// a continuous stream while another sends StopContinuousMode. Both halves use the same
+    /// serial port through cloned handles.
+    ///
+    /// Request/response commands need both halves, so they aren't available until the halves are
+    /// rejoined with [Device::unsplit]
+    pub fn split(self) -> Result<(Reader, Writer), serialport::Error> {
+        let port = self.transport.try_clone()?;
+        let writer = Writer {
+            port,
+            streaming: None,
+            write_strategy: self.write_strategy,
+            port_lost: self.port_lost,
+        };
+        Ok((Reader { device: self }, writer))
+    }
+
+    /// Rejoins the halves from [Device::split]
+    pub fn unsplit(reader: Reader, writer: Writer) -> Device {
+        let mut device = reader.device;
+        if let Some(streaming) = writer.streaming {
+            device.streaming = streaming;
+        }
+        if writer.port_lost {
+            device.note_port_error(&io::ErrorKind::NotConnected.into());
+        }
+        device
+    }
+}
+
+impl Reader {
+    /// See [Device::iter]
+    pub fn iter(&mut self) -> impl Iterator<Item = Result<Data, ReadError>> + '_ {
+        self.device.iter()
+    }
+
+    /// See [Device::iter_timestamped]
+    pub fn iter_timestamped(
+        &mut self,
+        sample_delay: Option<f32>,
+    ) -> impl Iterator<Item = Result<TimestampedData, ReadError>> + '_ {
+        self.device.iter_timestamped(sample_delay)
+    }
+
+    /// See [Device::events]
+    pub fn events(&mut self) -> Events<'_> {
+        self.device.events()
+    }
+
+    /// See [Device::read_response]
+    pub fn read_response(&mut self) -> Result<Response, ReadError> {
+        self.device.read_response()
+    }
+
+    /// See [Device::read_frame]
+    pub fn read_frame(&mut self) -> Result<RawFrame<'_>, ReadError> {
+        self.device.read_frame()
+    }
+
+    /// See [Device::take_unsolicited]
+    pub fn take_unsolicited(&mut self) -> Vec<Response> {
+        self.device.take_unsolicited()
+    }
+
+    /// See [Device::sync_to_frame_boundary]
+    pub fn sync_to_frame_boundary(&mut self) -> Result<usize, ReadError> {
+        self.device.sync_to_frame_boundary()
+    }
+
+    /// See [Device::drain_frames]
+    pub fn drain_frames(&mut self, quiet_period: Duration) -> Result<usize, ReadError> {
+        self.device.drain_frames(quiet_period)
+    }
+
+    /// See [Device::stats]
+    pub fn stats(&self) -> LinkStats {
+        self.device.stats()
+    }
+}
+
+impl Writer {
+    /// Sends a frame, flushed as the device's [WriteStrategy] says. The response, if any,
+    /// arrives on the [Reader]
+    pub fn write_frame(
+        &mut self,
+        command: Command,
+        payload: Option<&[u8]>,
+    ) -> Result<(), WriteError> {
+        if self.port_lost {
+            return Err(WriteError::PipeError(io::ErrorKind::NotConnected.into()));
+        }
+        let payload = payload.unwrap_or(&[]);
+        let mut frame = vec![0u8; payload.len() + codec::FRAME_OVERHEAD];
+        codec::encode_frame(u8::from(command), payload, &mut frame)?;
+        let result = write_frame_to(&mut *self.port, &frame, self.write_strategy);
+        if let Err(WriteError::PipeError(e) | WriteError::PartialWrite { error: e, .. }) = &result {
+            if is_port_gone(e) && !self.port_lost {
+                log::warn!("Serial port lost: {}", e);
+                self.port_lost = true;
+            }
+        }
+        result
+    }
+
+    /// Pushes out frames written under [WriteStrategy::Batched]. See [Device::flush]
+    pub fn flush(&mut self) -> Result<(), WriteError> {
+        Ok(self.port.flush()?)
+    }
+
+    /// Sends StartContinuousMode
+    pub fn start_continuous_mode(&mut self) -> Result<(), WriteError> {
+        self.write_frame(Command::StartContinuousMode, None)?;
+        self.streaming = Some(true);
+        Ok(())
+    }
+
+    /// Sends StopContinuousMode. Frames already in flight still arrive on the [Reader]; drain
+    /// them with [Reader::drain_frames]
+    pub fn stop_continuous_mode(&mut self) -> Result<(), WriteError> {
+        self.write_frame(Command::StopContinuousMode, None)?;
+        self.streaming = Some(false);
+        Ok(())
+    }
+
+    /// Sends GetData. The sample arrives on the [Reader], e.g. from [Reader::read_response]
+    pub fn request_data(&mut self) -> Result<(), WriteError> {
+        self.write_frame(Command::GetData, None)
+    }
+}
@@ -0,0 +1,189 @@
+// Device only needs a byte pipe with a read timeout, so the link is behind a trait rather than
+// tied to serialport. A local serial port (including a PTY) is one implementation; a TCP serial
+// server or a test double is another, and the frame logic doesn't know the difference.
+
+use serialport::{ClearBuffer, SerialPort};
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// A byte link to a device, with a read timeout. Reads that time out must fail with
+/// [io::ErrorKind::TimedOut], which is how [crate::Device] tells a quiet line from a broken one.
+///
+/// Only the timeout is required; the rest have defaults for links that have no such thing
+pub trait Transport: Read + Write + Send {
+    /// How long a read waits for data
+    fn timeout(&self) -> Duration;
+
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()>;
+
+    /// Discards bytes received but not read yet, to restart on a frame boundary after a garbled
+    /// response. Does nothing by default
+    fn clear_input(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Name of the serial port underneath, which reconnecting re-opens unless
+    /// [Transport::reopen] is implemented. None by default
+    fn name(&self) -> Option<String> {
+        None
+    }
+
+    /// Line rate in bits per second, for sample timing estimates. Unsupported by default
+    fn baud_rate(&self) -> io::Result<u32> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "link has no baud rate"))
+    }
+
+    /// A second handle on the same link, for [crate::Device::split]. Unsupported by default
+    fn try_clone(&self) -> io::Result<Box<dyn Transport>> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "link can't be cloned"))
+    }
+
+    /// Opens the link again after it was lost, for [crate::Device::reconnect]. Unsupported by
+    /// default, in which case a link with a [Transport::name] is re-opened as a serial port of
+    /// that name and one without is only woken again
+    fn reopen(&mut self) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "link can't be re-opened"))
+    }
+}
+
+impl Transport for Box<dyn SerialPort> {
+    fn timeout(&self) -> Duration {
+        SerialPort::timeout(self.as_ref())
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        Ok(SerialPort::set_timeout(self.as_mut(), timeout)?)
+    }
+
+    fn clear_input(&mut self) -> io::Result<()> {
+        Ok(self.clear(ClearBuffer::Input)?)
+    }
+
+    fn name(&self) -> Option<String> {
+        SerialPort::name(self.as_ref())
+    }
+
+    fn baud_rate(&self) -> io::Result<u32> {
+        Ok(SerialPort::baud_rate(self.as_ref())?)
+    }
+
+    fn try_clone(&self) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new(SerialPort::try_clone(self.as_ref())?))
+    }
+}
+
+/// Stands in for a serial port that has been closed so it can be opened again, which ports opened
+/// for exclusive use need. Keeps the port's name and timeout for the reopen
+pub(crate) struct Closed {
+    pub(crate) name: Option<String>,
+    pub(crate) timeout: Duration,
+}
+
+impl Read for Closed {
+    fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+        Err(io::ErrorKind::NotConnected.into())
+    }
+}
+
+impl Write for Closed {
+    fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+        Err(io::ErrorKind::NotConnected.into())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for Closed {
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.timeout = timeout;
+        Ok(())
+    }
+
+    fn name(&self) -> Option<String> {
+        self.name.clone()
+    }
+}
+
+/// A device behind a TCP serial server (a Moxa NPort or `ser2net` in raw mode, for instance),
+/// which passes bytes through to the device's serial line unchanged.
+///
+/// The server sets the baud rate, so [Transport::baud_rate] is unsupported and sample timing
+/// has to be measured rather than estimated
+#[derive(Debug)]
+pub struct TcpTransport {
+    stream: TcpStream,
+    timeout: Duration,
+}
+
+impl TcpTransport {
+    /// Connects to the serial server, with a read timeout of one second
+    pub fn connect(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        Self::new(TcpStream::connect(addr)?)
+    }
+
+    /// Uses an already connected stream, with a read timeout of one second
+    pub fn new(stream: TcpStream) -> io::Result<Self> {
+        // frames are small and latency matters more than packet count
+        stream.set_nodelay(true)?;
+        let mut transport = Self {
+            stream,
+            timeout: Duration::ZERO,
+        };
+        transport.set_timeout(Duration::from_secs(1))?;
+        Ok(transport)
+    }
+}
+
+impl Read for TcpTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.stream.read(buf) {
+            // Unix reports an expired socket timeout as WouldBlock
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                Err(io::Error::new(io::ErrorKind::TimedOut, e))
+            }
+            Ok(0) if !buf.is_empty() => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "serial server closed the connection",
+            )),
+            result => result,
+        }
+    }
+}
+
+impl Write for TcpTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl Transport for TcpTransport {
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        // a zero socket timeout is an error rather than a poll
+        self.stream
+            .set_read_timeout(Some(timeout.max(Duration::from_millis(1))))?;
+        self.timeout = timeout;
+        Ok(())
+    }
+
+    fn try_clone(&self) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new(Self {
+            stream: self.stream.try_clone()?,
+            timeout: self.timeout,
+        }))
+    }
+}
@@ -0,0 +1,472 @@
+// Spherical harmonic synthesis as described in "The US/UK World Magnetic Model", NOAA Technical
+// Report, section 1.2. Coefficients aren't bundled, since each model is only valid for five years:
+// load the current WMM.COF from https://www.ncei.noaa.gov/products/world-magnetic-model instead.
+// IGRF uses the same synthesis, with coefficients tabulated every five years back to 1900.
+
+use crate::config::ConfigPair;
+use crate::declination::{DeclinationError, DeclinationSource, Location};
+use crate::{Device, RWError};
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Geomagnetic reference radius, km
+const REFERENCE_RADIUS: f64 = 6371.2;
+
+/// WGS-84 semi-major axis, km
+const WGS84_A: f64 = 6378.137;
+
+/// WGS-84 flattening
+const WGS84_F: f64 = 1.0 / 298.257223563;
+
+/// Years a model is intended to be used for after its epoch
+const VALIDITY_YEARS: f64 = 5.0;
+
+/// Main field and secular variation coefficients of a World Magnetic Model release
+#[derive(Debug, Clone)]
+pub struct MagneticModel {
+    name: String,
+    epoch: f64,
+    max_degree: usize,
+
+    /// Indexed `[n][m]`, in nT (and nT/year for the secular variation)
+    g: Vec<Vec<f64>>,
+    h: Vec<Vec<f64>>,
+    g_dot: Vec<Vec<f64>>,
+    h_dot: Vec<Vec<f64>>,
+}
+
+/// Magnetic field vector at a location, in nT, in the local north, east, down frame
+#[derive(Debug, Display, Clone, Copy, PartialEq)]
+#[display(fmt = "MagneticField {{ north: {}, east: {}, down: {} }}", north, east, down)]
+pub struct MagneticField {
+    pub north: f64,
+    pub east: f64,
+    pub down: f64,
+}
+
+impl MagneticField {
+    /// Angle from true north to magnetic north in degrees, positive east. This is the value
+    /// [ConfigPair::Declination] expects
+    pub fn declination(&self) -> f64 {
+        self.east.atan2(self.north).to_degrees()
+    }
+
+    /// Angle of the field below horizontal in degrees
+    pub fn inclination(&self) -> f64 {
+        self.down.atan2(self.horizontal_intensity()).to_degrees()
+    }
+
+    pub fn horizontal_intensity(&self) -> f64 {
+        self.north.hypot(self.east)
+    }
+
+    pub fn total_intensity(&self) -> f64 {
+        self.horizontal_intensity().hypot(self.down)
+    }
+}
+
+impl MagneticModel {
+    /// Parses a coefficient file in the format NOAA distributes (WMM.COF): a header line with the
+    /// epoch and model name, then `n m g h g_dot h_dot` lines, ended by a line of 9s
+    pub fn from_cof(input: impl BufRead) -> io::Result<Self> {
+        let mut lines = input.lines();
+        let header = lines
+            .next()
+            .transpose()?
+            .ok_or_else(|| invalid("empty coefficient file".to_string()))?;
+        let mut header_fields = header.split_whitespace();
+        let epoch = parse(header_fields.next(), "epoch")?;
+        let name = header_fields.next().unwrap_or_default().to_string();
+
+        let mut rows = Vec::new();
+        for line in lines {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if line.starts_with("9999") {
+                break;
+            }
+            let mut fields = line.split_whitespace();
+            let n: usize = parse(fields.next(), "n")?;
+            let m: usize = parse(fields.next(), "m")?;
+            if m > n || n == 0 {
+                return Err(invalid(format!("invalid degree/order {} {}", n, m)));
+            }
+            let values: [f64; 4] = [
+                parse(fields.next(), "g")?,
+                parse(fields.next(), "h")?,
+                parse(fields.next(), "g_dot")?,
+                parse(fields.next(), "h_dot")?,
+            ];
+            rows.push((n, m, values));
+        }
+
+        Self::from_rows(name, epoch, rows)
+    }
+
+    /// Builds a model from `(n, m, [g, h, g_dot, h_dot])` rows
+    fn from_rows(name: String, epoch: f64, rows: Vec<(usize, usize, [f64; 4])>) -> io::Result<Self> {
+        let max_degree = rows
+            .iter()
+            .map(|(n, _, _)| *n)
+            .max()
+            .ok_or_else(|| invalid("no coefficients".to_string()))?;
+        let table = || vec![vec![0.0; max_degree + 1]; max_degree + 1];
+        let mut model = MagneticModel {
+            name,
+            epoch,
+            max_degree,
+            g: table(),
+            h: table(),
+            g_dot: table(),
+            h_dot: table(),
+        };
+        for (n, m, [g, h, g_dot, h_dot]) in rows {
+            model.g[n][m] = g;
+            model.h[n][m] = h;
+            model.g_dot[n][m] = g_dot;
+            model.h_dot[n][m] = h_dot;
+        }
+        Ok(model)
+    }
+
+    /// Reads a WMM.COF file from disk. See [MagneticModel::from_cof]
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::from_cof(BufReader::new(File::open(path)?))
+    }
+
+    /// Model name from the coefficient file, e.g. `WMM-2025`
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Decimal year the main field coefficients apply to
+    pub fn epoch(&self) -> f64 {
+        self.epoch
+    }
+
+    /// Whether `decimal_year` falls within the five years the model is valid for. Results outside
+    /// that window degrade quickly as the secular variation is extrapolated
+    pub fn is_valid_at(&self, decimal_year: f64) -> bool {
+        (self.epoch..self.epoch + VALIDITY_YEARS).contains(&decimal_year)
+    }
+
+    /// Field at a WGS-84 latitude/longitude in degrees and height above the ellipsoid in km
+    pub fn field(
+        &self,
+        latitude: f64,
+        longitude: f64,
+        altitude_km: f64,
+        decimal_year: f64,
+    ) -> MagneticField {
+        let dt = decimal_year - self.epoch;
+
+        // geodetic to geocentric spherical coordinates
+        let lat = latitude.to_radians();
+        let lon = longitude.to_radians();
+        let e2 = WGS84_F * (2.0 - WGS84_F);
+        let rc = WGS84_A / (1.0 - e2 * lat.sin().powi(2)).sqrt();
+        let p = (rc + altitude_km) * lat.cos();
+        let z = (rc * (1.0 - e2) + altitude_km) * lat.sin();
+        let r = p.hypot(z);
+        let geocentric_lat = (z / r).asin();
+
+        // Schmidt semi-normalized associated Legendre functions of cos(colatitude), and their
+        // derivatives with respect to colatitude
+        let cos_theta = geocentric_lat.sin();
+        let sin_theta = geocentric_lat.cos();
+        let size = self.max_degree + 1;
+        let mut legendre = vec![vec![0.0; size]; size];
+        let mut d_legendre = vec![vec![0.0; size]; size];
+        legendre[0][0] = 1.0;
+        for n in 1..size {
+            for m in 0..=n {
+                if m == n {
+                    legendre[n][n] = sin_theta * legendre[n - 1][n - 1];
+                    d_legendre[n][n] =
+                        sin_theta * d_legendre[n - 1][n - 1] + cos_theta * legendre[n - 1][n - 1];
+                } else {
+                    let k = if n > 1 {
+                        (((n - 1) * (n - 1)) as f64 - (m * m) as f64)
+                            / ((2 * n - 1) * (2 * n - 3)) as f64
+                    } else {
+                        0.0
+                    };
+                    let (p2, dp2) = if n > 1 {
+                        (legendre[n - 2][m], d_legendre[n - 2][m])
+                    } else {
+                        (0.0, 0.0)
+                    };
+                    legendre[n][m] = cos_theta * legendre[n - 1][m] - k * p2;
+                    d_legendre[n][m] = cos_theta * d_legendre[n - 1][m]
+                        - sin_theta * legendre[n - 1][m]
+                        - k * dp2;
+                }
+            }
+        }
+        // convert from Gauss to Schmidt normalization
+        let mut schmidt = vec![vec![0.0; size]; size];
+        schmidt[0][0] = 1.0;
+        for n in 1..size {
+            schmidt[n][0] = schmidt[n - 1][0] * (2 * n - 1) as f64 / n as f64;
+            for m in 1..=n {
+                let double_m1 = if m == 1 { 2.0 } else { 1.0 };
+                schmidt[n][m] = schmidt[n][m - 1]
+                    * (((n - m + 1) as f64 * double_m1) / (n + m) as f64).sqrt();
+            }
+        }
+
+        let (mut x, mut y, mut z) = (0.0, 0.0, 0.0);
+        for n in 1..size {
+            let radius_ratio = (REFERENCE_RADIUS / r).powi(n as i32 + 2);
+            for m in 0..=n {
+                let g = self.g[n][m] + dt * self.g_dot[n][m];
+                let h = self.h[n][m] + dt * self.h_dot[n][m];
+                let (sin_ml, cos_ml) = (m as f64 * lon).sin_cos();
+                let p = schmidt[n][m] * legendre[n][m];
+                let dp = schmidt[n][m] * d_legendre[n][m];
+
+                x += radius_ratio * (g * cos_ml + h * sin_ml) * dp;
+                y += radius_ratio * m as f64 * (g * sin_ml - h * cos_ml) * p;
+                z -= radius_ratio * (n + 1) as f64 * (g * cos_ml + h * sin_ml) * p;
+            }
+        }
+        // Y is undefined at the geographic poles; the small offset keeps it finite
+        y /= sin_theta.max(1e-10);
+
+        // rotate from geocentric back to geodetic north/down
+        let delta = geocentric_lat - lat;
+        MagneticField {
+            north: x * delta.cos() - z * delta.sin(),
+            east: y,
+            down: x * delta.sin() + z * delta.cos(),
+        }
+    }
+
+    /// Declination in degrees, positive east. See [MagneticModel::field]
+    pub fn declination(
+        &self,
+        latitude: f64,
+        longitude: f64,
+        altitude_km: f64,
+        decimal_year: f64,
+    ) -> f64 {
+        self.field(latitude, longitude, altitude_km, decimal_year)
+            .declination()
+    }
+}
+
+/// Converts a time to a decimal year (e.g. 2025.5 for early July 2025), as used by
+/// [MagneticModel::field]
+pub fn decimal_year(time: SystemTime) -> f64 {
+    let seconds = match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_secs_f64(),
+        Err(before) => -before.duration().as_secs_f64(),
+    };
+    let days = (seconds / 86400.0).floor() as i64;
+    let year = civil_year(days);
+    let start = days_from_civil(year);
+    let length = days_from_civil(year + 1) - start;
+    year as f64 + (seconds / 86400.0 - start as f64) / length as f64
+}
+
+/// Year containing the given day, counted from 1970-01-01 (proleptic Gregorian)
+fn civil_year(days: i64) -> i64 {
+    let mut year = 1970 + days.div_euclid(366);
+    while days_from_civil(year + 1) <= days {
+        year += 1;
+    }
+    year
+}
+
+/// Days from 1970-01-01 to January 1st of `year`
+fn days_from_civil(year: i64) -> i64 {
+    let y = year - 1;
+    365 * (year - 1970) + (y.div_euclid(4) - y.div_euclid(100) + y.div_euclid(400))
+        - (1969 / 4 - 1969 / 100 + 1969 / 400)
+}
+
+impl DeclinationSource for MagneticModel {
+    /// Fails outside the model's five-year validity window rather than extrapolating
+    fn declination(&self, location: &Location, time: SystemTime) -> Result<f64, DeclinationError> {
+        let year = decimal_year(time);
+        if !self.is_valid_at(year) {
+            return Err(DeclinationError::OutOfRange {
+                source: self.name.clone(),
+                year,
+            });
+        }
+        Ok(MagneticModel::declination(
+            self,
+            location.latitude,
+            location.longitude,
+            location.altitude_km,
+            year,
+        ))
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+/// International Geomagnetic Reference Field: main field coefficients every five years from 1900,
+/// plus secular variation for the five years after the last. Less accurate than the current WMM
+/// for the present day, but covers past dates too, e.g. for reprocessing old logs
+#[derive(Debug, Clone)]
+pub struct Igrf {
+    /// Epoch of each coefficient column, oldest first
+    epochs: Vec<f64>,
+
+    /// `(n, m, is_h, value per epoch, secular variation)`
+    rows: Vec<(usize, usize, bool, Vec<f64>, f64)>,
+}
+
+impl Igrf {
+    /// Parses a coefficient table in the format IAGA distributes (e.g. igrf14coeffs.txt): a
+    /// `g/h n m` header line listing the epochs, then one `g` or `h` line per coefficient with a
+    /// value per epoch and the secular variation last
+    pub fn from_table(input: impl BufRead) -> io::Result<Self> {
+        let mut epochs = Vec::new();
+        let mut rows = Vec::new();
+        for line in input.lines() {
+            let line = line?;
+            let mut fields = line.split_whitespace();
+            match fields.next() {
+                Some("g/h") => {
+                    // the last column is labelled with the secular variation's span, e.g. 2025-30
+                    epochs = fields.skip(2).filter_map(|f| f.parse().ok()).collect();
+                }
+                Some(kind @ ("g" | "h")) => {
+                    let n: usize = parse(fields.next(), "n")?;
+                    let m: usize = parse(fields.next(), "m")?;
+                    if m > n || n == 0 {
+                        return Err(invalid(format!("invalid degree/order {} {}", n, m)));
+                    }
+                    let mut values = fields
+                        .map(|f| f.parse().map_err(|_| invalid(format!("invalid coefficient {}", f))))
+                        .collect::<io::Result<Vec<f64>>>()?;
+                    if values.len() != epochs.len() + 1 {
+                        return Err(invalid(format!(
+                            "{} {} {} has {} values for {} epochs",
+                            kind,
+                            n,
+                            m,
+                            values.len(),
+                            epochs.len()
+                        )));
+                    }
+                    let secular_variation = values.pop().unwrap_or_default();
+                    rows.push((n, m, kind == "h", values, secular_variation));
+                }
+                _ => {}
+            }
+        }
+        if epochs.is_empty() || rows.is_empty() {
+            return Err(invalid("no coefficients".to_string()));
+        }
+        Ok(Self { epochs, rows })
+    }
+
+    /// Reads an IGRF coefficient table from disk. See [Igrf::from_table]
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::from_table(BufReader::new(File::open(path)?))
+    }
+
+    /// First and last years covered
+    pub fn range(&self) -> (f64, f64) {
+        let last = self.epochs[self.epochs.len() - 1];
+        (self.epochs[0], last + VALIDITY_YEARS)
+    }
+
+    /// Model for the five-year interval containing `decimal_year`, interpolating between epochs
+    /// (or extrapolating with the secular variation after the last)
+    pub fn model_at(&self, decimal_year: f64) -> Option<MagneticModel> {
+        let (first, end) = self.range();
+        if !(first..end).contains(&decimal_year) {
+            return None;
+        }
+        let index = self.epochs.iter().rposition(|epoch| *epoch <= decimal_year)?;
+        let epoch = self.epochs[index];
+        let mut coefficients = std::collections::BTreeMap::new();
+        for (n, m, is_h, values, secular_variation) in &self.rows {
+            let rate = match self.epochs.get(index + 1) {
+                Some(next) => (values[index + 1] - values[index]) / (next - epoch),
+                None => *secular_variation,
+            };
+            let entry = coefficients.entry((*n, *m)).or_insert([0.0; 4]);
+            if *is_h {
+                entry[1] = values[index];
+                entry[3] = rate;
+            } else {
+                entry[0] = values[index];
+                entry[2] = rate;
+            }
+        }
+        let rows = coefficients
+            .into_iter()
+            .map(|((n, m), values)| (n, m, values))
+            .collect();
+        MagneticModel::from_rows(format!("IGRF-{}", epoch), epoch, rows).ok()
+    }
+}
+
+impl DeclinationSource for Igrf {
+    fn declination(&self, location: &Location, time: SystemTime) -> Result<f64, DeclinationError> {
+        let year = decimal_year(time);
+        let model = self.model_at(year).ok_or_else(|| DeclinationError::OutOfRange {
+            source: self.name(),
+            year,
+        })?;
+        Ok(model.declination(
+            location.latitude,
+            location.longitude,
+            location.altitude_km,
+            year,
+        ))
+    }
+
+    fn name(&self) -> String {
+        let (first, end) = self.range();
+        format!("IGRF {}-{}", first, end)
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn parse<T: std::str::FromStr>(field: Option<&str>, name: &str) -> io::Result<T> {
+    field
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| invalid(format!("missing or invalid {} in coefficient file", name)))
+}
+
+impl Device {
+    /// Computes the declination at a location for the current date and sets
+    /// [ConfigPair::Declination] to it, returning the value set. As with setting it directly,
+    /// declination only affects heading once [ConfigPair::TrueNorth] is enabled, and needs
+    /// [Device::save] to persist across power cycles. Unlike [Device::set_declination_from], this
+    /// uses the model even outside its validity window
+    ///
+    /// # Arguments
+    /// * `latitude`, `longitude` - WGS-84 position in degrees
+    /// * `altitude_km` - height above the WGS-84 ellipsoid in km
+    pub fn set_declination_from_location(
+        &mut self,
+        model: &MagneticModel,
+        latitude: f64,
+        longitude: f64,
+        altitude_km: f64,
+    ) -> Result<f32, RWError> {
+        let year = decimal_year(SystemTime::now());
+        let declination = model.declination(latitude, longitude, altitude_km, year) as f32;
+        self.set_config(ConfigPair::Declination(declination))?;
+        Ok(declination)
+    }
+}
@@ -0,0 +1,219 @@
+use crate::Device;
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::marker::PhantomData;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+
+/// How urgently a job submitted to a [DeviceWorker] should run. Higher priorities always run
+/// first; jobs of equal priority run in the order they were submitted
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    /// Bulk work like logging polls, which can wait behind anything else
+    Routine,
+
+    /// Operations a user is waiting on, like config changes
+    Interactive,
+
+    /// Multi-command sequences that mustn't be interleaved with anything, like calibration. See
+    /// [DeviceWorker::exclusive]
+    Exclusive,
+}
+
+type Job = Box<dyn FnOnce(&mut Device) + Send>;
+
+struct QueuedJob {
+    priority: Priority,
+
+    /// Submission order, so equal priorities are first in first out
+    sequence: u64,
+
+    job: Job,
+}
+
+impl PartialEq for QueuedJob {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for QueuedJob {}
+
+impl PartialOrd for QueuedJob {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for QueuedJob {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.sequence.cmp(&self.sequence))
+    }
+}
+
+#[derive(Default)]
+struct Queue {
+    jobs: BinaryHeap<QueuedJob>,
+    next_sequence: u64,
+    shutdown: bool,
+}
+
+/// Owns a [Device] on a background thread and runs commands against it from a prioritized
+/// queue, so interactive operations stay responsive while bulk logging keeps the queue full.
+///
+/// Jobs are closures given `&mut Device`. Each runs to completion before the next starts, so a
+/// job is never interleaved with another at the frame level
+pub struct DeviceWorker {
+    queue: Arc<(Mutex<Queue>, Condvar)>,
+    thread: Option<JoinHandle<Device>>,
+}
+
+impl DeviceWorker {
+    /// Moves `device` onto a new background thread
+    pub fn spawn(mut device: Device) -> Self {
+        let queue = Arc::new((Mutex::new(Queue::default()), Condvar::new()));
+        let worker_queue = queue.clone();
+        let thread = thread::spawn(move || {
+            let (lock, ready) = &*worker_queue;
+            loop {
+                let job = {
+                    let mut queue = lock.lock().unwrap();
+                    loop {
+                        if queue.shutdown {
+                            return device;
+                        }
+                        if let Some(job) = queue.jobs.pop() {
+                            break job;
+                        }
+                        queue = ready.wait(queue).unwrap();
+                    }
+                };
+                // a panicking job drops its result sender, which its caller sees as an error;
+                // the worker itself keeps serving the queue
+                let _ = panic::catch_unwind(AssertUnwindSafe(|| (job.job)(&mut device)));
+            }
+        });
+
+        Self {
+            queue,
+            thread: Some(thread),
+        }
+    }
+
+    /// Queues `job` and returns a receiver for its result. The receiver errors if the worker is
+    /// shut down before the job runs
+    pub fn submit<R, F>(&self, priority: Priority, job: F) -> Receiver<R>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut Device) -> R + Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel();
+        self.push(
+            priority,
+            Box::new(move |device| {
+                let _ = tx.send(job(device));
+            }),
+        );
+        rx
+    }
+
+    /// Queues `job` and blocks until it has run. Returns [None] if the worker shut down first
+    pub fn run<R, F>(&self, priority: Priority, job: F) -> Option<R>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut Device) -> R + Send + 'static,
+    {
+        self.submit(priority, job).recv().ok()
+    }
+
+    /// Waits for the device to be free, then holds it until the returned session is dropped.
+    /// Nothing else runs in between, however urgent, so use this for sequences such as user
+    /// calibration that span several commands and possibly user interaction
+    pub fn exclusive(&self) -> ExclusiveSession<'_> {
+        let (job_tx, job_rx) = mpsc::channel::<Job>();
+        let (started_tx, started_rx) = mpsc::channel();
+        self.push(
+            Priority::Exclusive,
+            Box::new(move |device| {
+                let _ = started_tx.send(());
+                // runs until the session (and so the sender) is dropped
+                for job in job_rx {
+                    job(device);
+                }
+            }),
+        );
+        let _ = started_rx.recv();
+        ExclusiveSession {
+            jobs: job_tx,
+            _worker: PhantomData,
+        }
+    }
+
+    /// Stops the background thread once the job currently running (if any) finishes, and hands
+    /// the device back. Jobs still queued are dropped
+    pub fn shutdown(mut self) -> Device {
+        self.stop().expect("worker thread panicked")
+    }
+
+    fn push(&self, priority: Priority, job: Job) {
+        let (lock, ready) = &*self.queue;
+        let mut queue = lock.lock().unwrap();
+        let sequence = queue.next_sequence;
+        queue.next_sequence += 1;
+        queue.jobs.push(QueuedJob {
+            priority,
+            sequence,
+            job,
+        });
+        ready.notify_one();
+    }
+
+    fn stop(&mut self) -> Option<Device> {
+        let (lock, ready) = &*self.queue;
+        {
+            let mut queue = lock.lock().unwrap();
+            queue.shutdown = true;
+            queue.jobs.clear();
+        }
+        ready.notify_one();
+        self.thread.take()?.join().ok()
+    }
+}
+
+impl Drop for DeviceWorker {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Sole access to a [DeviceWorker]'s device, from [DeviceWorker::exclusive]. Queued jobs resume
+/// once this is dropped
+pub struct ExclusiveSession<'a> {
+    jobs: Sender<Job>,
+
+    /// Keeps the worker from being shut down, which would wait on this session forever
+    _worker: PhantomData<&'a DeviceWorker>,
+}
+
+impl ExclusiveSession<'_> {
+    /// Runs `job` on the worker thread and waits for its result
+    pub fn run<R, F>(&self, job: F) -> R
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut Device) -> R + Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel();
+        self.jobs
+            .send(Box::new(move |device| {
+                let _ = tx.send(job(device));
+            }))
+            .expect("worker thread stopped during an exclusive session");
+        rx.recv()
+            .expect("worker thread stopped during an exclusive session")
+    }
+}